@@ -0,0 +1,82 @@
+#![feature(generic_const_exprs)]
+use criterion::{criterion_group, criterion_main, Criterion};
+use num_bigint::BigUint;
+use summa_backend::{
+    apis::round::Snapshot,
+    merkle_sum_tree::{Entry, MerkleSumTree},
+};
+
+const SAMPLE_SIZE: usize = 10;
+const LEVELS: usize = 4;
+const N_CURRENCIES: usize = 2;
+const N_BYTES: usize = 8;
+
+const CSV_PATH: &str = "../csv/entry_16.csv";
+const PARAMS_PATH: &str = "ptau/hermez-raw-11";
+
+fn build_snapshot_from_csv(_c: &mut Criterion) {
+    let mut criterion = Criterion::default().sample_size(SAMPLE_SIZE);
+
+    // The setup artifacts are saved to a temporary file, so that the full reconstruction loads them rather than generating them
+    let snapshot =
+        Snapshot::<LEVELS, N_CURRENCIES, N_BYTES>::from_csv(CSV_PATH, PARAMS_PATH).unwrap();
+    let setup_artifacts_path = std::env::temp_dir().join(format!(
+        "summa_bench_round_from_previous_{}.setup",
+        std::process::id()
+    ));
+    snapshot
+        .save_setup_artifacts(&setup_artifacts_path)
+        .unwrap();
+
+    let bench_name = format!(
+        "build snapshot from csv for 2 power of {} entries with {} currencies",
+        LEVELS, N_CURRENCIES
+    );
+
+    criterion.bench_function(&bench_name, |b| {
+        b.iter(|| {
+            let mst = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(CSV_PATH).unwrap();
+            Snapshot::<LEVELS, N_CURRENCIES, N_BYTES>::new_with_setup_artifacts(
+                Box::new(mst),
+                PARAMS_PATH,
+                &setup_artifacts_path,
+            )
+            .unwrap();
+        })
+    });
+
+    std::fs::remove_file(setup_artifacts_path).unwrap();
+}
+
+fn build_snapshot_from_previous(_c: &mut Criterion) {
+    let mut criterion = Criterion::default().sample_size(SAMPLE_SIZE);
+
+    let snapshot =
+        Snapshot::<LEVELS, N_CURRENCIES, N_BYTES>::from_csv(CSV_PATH, PARAMS_PATH).unwrap();
+
+    let updated_entries = [(
+        3,
+        Entry::new(
+            "updated_user".to_string(),
+            [BigUint::from(1000u32), BigUint::from(2000u32)],
+        ),
+    )];
+
+    let bench_name = format!(
+        "build snapshot from previous with 1 updated entry for 2 power of {} entries with {} currencies",
+        LEVELS, N_CURRENCIES
+    );
+
+    criterion.bench_function(&bench_name, |b| {
+        b.iter(|| {
+            Snapshot::from_previous(&snapshot, &updated_entries).unwrap();
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    build_snapshot_from_csv,
+    build_snapshot_from_previous
+);
+criterion_main!(benches);
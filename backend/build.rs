@@ -2,6 +2,9 @@ use ethers::prelude::Abigen;
 use std::{fs::OpenOptions, io::Write, path::PathBuf};
 
 fn main() {
+    // The bindings only depend on the ABIs copied by `contracts/scripts/deploy.ts`
+    println!("cargo:rerun-if-changed=src/contracts/abi");
+
     let contracts = [
         (
             "src/contracts/generated/summa_contract.rs",
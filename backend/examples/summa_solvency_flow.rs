@@ -109,7 +109,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let user_name = "dxGaEAii".to_string();
     let balances = vec!["11888".to_string(), "41163".to_string()];
 
-    let leaf_hash = public_inputs[0];
+    let leaf_hash = public_inputs[1];
     assert_eq!(
         leaf_hash,
         leaf_hash_from_inputs::<N_CURRENCIES>(user_name.clone(), balances.clone())
@@ -119,7 +119,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let commitment = summa_contract.commitments(snapshot_time).call().await?;
 
     // Match the `mst_root` with the `root_hash` derived from the proof.
-    assert_eq!(commitment, public_inputs[1]);
+    assert_eq!(commitment, public_inputs[2]);
 
     // Validate the inclusion proof using the contract verifier.
     let proof = inclusion_proof.get_proof();
@@ -1,30 +1,49 @@
 #!/bin/bash
 set -e
 
-# Build the verifier contracts
+# Run from the backend directory, wherever the script is called from
+cd "$(dirname "$0")/.."
+
+# Build the verifier contracts, writing `contracts/src/InclusionVerifier.sol`
 echo "1. Building verifier contracts"
 cd ../zk_prover
 cargo run --release --example gen_inclusion_verifier
 
 # Generate Commitment for Merkle Sum Tree
 echo "2. Generate Commitment for Merkle Sum Tree"
-cd ../zk_prover
 cargo run --release --example gen_commitment
 
-# Deploy contracts to local environment
-echo "3. Deploying contracts to local environment"
+# Generate the inclusion proof calldata read by the contract tests
+echo "3. Generate inclusion proof calldata"
+cargo run --release --example gen_inclusion_proof
+
+# The verifier and the calldata must agree on the number of public inputs, otherwise the verifier rejects every proof
+VERIFIER_NUM_INSTANCES=$((16#$(grep -oP '0x[0-9a-f]{64}(?=\) // num_instances)' ../contracts/src/InclusionVerifier.sol | cut -c3-)))
+CALLDATA_NUM_INSTANCES=$(jq '.public_inputs | length' examples/inclusion_proof_solidity_calldata.json)
+if [ "$VERIFIER_NUM_INSTANCES" -ne "$CALLDATA_NUM_INSTANCES" ]; then
+    echo "InclusionVerifier.sol expects $VERIFIER_NUM_INSTANCES public inputs but the calldata has $CALLDATA_NUM_INSTANCES"
+    exit 1
+fi
+
+# Compile the regenerated verifier and check it against the contract tests
+echo "4. Compiling and testing contracts"
 cd ../contracts
 npm install
+npx hardhat compile
+npx hardhat test
+
+# Deploy contracts to local environment, copying their ABIs to `backend/src/contracts/abi`
+echo "5. Deploying contracts to local environment"
 npx hardhat node &
 HARDHAT_PID=$!
+trap 'kill $HARDHAT_PID' EXIT
 sleep 5
 npx hardhat run scripts/deploy.ts --network localhost
 
-# Generate interface files for Backend
-echo "4. Generating interface files for Backend"
+# Generate interface files for Backend, `build.rs` reruns as the ABIs changed
+echo "6. Generating interface files for Backend"
 cd ../backend
 cargo build
 
 # Wrap up
-echo "Terminate hardhat node"
-kill $HARDHAT_PID
+echo "Updated InclusionVerifier.sol, the calldata and the backend bindings, commit them together"
@@ -0,0 +1,158 @@
+use std::error::Error;
+
+use ethers::types::{Address, U256};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use summa_solvency::merkle_sum_tree::{utils::normalize_balance, Cryptocurrency, Tree};
+
+/// A cryptocurrency whose balances are in the tree, described by its name, the name and the id of the chain it lives on, the address of its token contract, if any, and its number of decimals.
+/// The name and the chain name are the ones labelling the balances of the tree, as submitted to the Summa contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamedAsset {
+    pub name: String,
+    pub chain: String,
+    pub chain_id: u64,
+    pub token_address: Option<Address>,
+    pub decimals: u8,
+}
+
+/// The cryptocurrencies of a round, in the same order as the balances of the entries of the tree,
+/// so that the balances and the root balances can be referred to by name rather than by position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetConfig<const N_CURRENCIES: usize> {
+    assets: [NamedAsset; N_CURRENCIES],
+}
+
+impl<const N_CURRENCIES: usize> AssetConfig<N_CURRENCIES> {
+    pub fn new(assets: [NamedAsset; N_CURRENCIES]) -> Self {
+        Self { assets }
+    }
+
+    /// Returns the config of the cryptocurrencies labelling the balances of `tree`, as held by a snapshot until `with_asset_config` sets the full config.
+    /// The tree doesn't record the chain id, the token address nor the decimals of a cryptocurrency, so that they are left as 0, none and 0, the balances being normalized as they are.
+    /// The balances of a tree without labels get empty names.
+    pub fn from_tree(tree: &dyn Tree<N_CURRENCIES>) -> Self {
+        let cryptocurrencies = tree.cryptocurrencies();
+        Self {
+            assets: std::array::from_fn(|index| {
+                let (name, chain) = cryptocurrencies
+                    .get(index)
+                    .map_or((String::new(), String::new()), |cryptocurrency| {
+                        (cryptocurrency.name.clone(), cryptocurrency.chain.clone())
+                    });
+                NamedAsset {
+                    name,
+                    chain,
+                    chain_id: 0,
+                    token_address: None,
+                    decimals: 0,
+                }
+            }),
+        }
+    }
+
+    pub fn assets(&self) -> &[NamedAsset; N_CURRENCIES] {
+        &self.assets
+    }
+
+    /// Returns the names of the cryptocurrencies, in the same order as the balances of the entries
+    pub fn names(&self) -> Vec<&str> {
+        self.assets
+            .iter()
+            .map(|asset| asset.name.as_str())
+            .collect()
+    }
+
+    /// Returns the cryptocurrencies labelling the balances, as submitted to the Summa contract along with the root of the tree
+    pub fn cryptocurrencies(&self) -> Vec<Cryptocurrency> {
+        self.assets
+            .iter()
+            .map(|asset| Cryptocurrency {
+                name: asset.name.clone(),
+                chain: asset.chain.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the index of the balances of the cryptocurrency named `name`, if any
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.assets.iter().position(|asset| asset.name == name)
+    }
+
+    /// Returns the largest number of decimals among the cryptocurrencies, namely the common precision of the balances normalized by `normalize_balance`
+    pub fn max_decimals(&self) -> u8 {
+        self.assets
+            .iter()
+            .map(|asset| asset.decimals)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Scales `balance` of the cryptocurrency at `asset_index`, expressed with its own decimals, to the largest number of decimals among the cryptocurrencies,
+    /// as `Entry::new_normalized` does, so that the balances of different cryptocurrencies can be compared or summed up.
+    /// Panics if `asset_index` doesn't point to a cryptocurrency of the config.
+    pub fn normalize_balance(&self, balance: BigUint, asset_index: usize) -> BigUint {
+        normalize_balance(
+            &balance,
+            self.assets[asset_index].decimals,
+            self.max_decimals(),
+        )
+    }
+
+    /// Checks that the cryptocurrencies labelling the balances of `tree` are named as in the config, on the same chains and in the same order, e.g. to catch two swapped columns of the CSV file of the tree.
+    /// A tree without labels can't be checked and is accepted.
+    pub fn check_tree(&self, tree: &dyn Tree<N_CURRENCIES>) -> Result<(), Box<dyn Error>> {
+        for (index, (asset, cryptocurrency)) in self
+            .assets
+            .iter()
+            .zip(tree.cryptocurrencies().iter())
+            .enumerate()
+        {
+            if asset.name != cryptocurrency.name || asset.chain != cryptocurrency.chain {
+                return Err(format!(
+                    "The balances at index {} are {} on {} in the tree but {} on {} in the asset config",
+                    index, cryptocurrency.name, cryptocurrency.chain, asset.name, asset.chain
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The public inputs of an inclusion proof, with the root balances labelled by the names of their cryptocurrencies, as returned by `MstInclusionProof::annotate_inputs`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnnotatedPublicInputs {
+    pub leaf_hash: U256,
+    pub root_hash: U256,
+    pub root_balances: Vec<(String, U256)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named_asset(name: &str, decimals: u8) -> NamedAsset {
+        NamedAsset {
+            name: name.to_string(),
+            chain: "ETH".to_string(),
+            chain_id: 1,
+            token_address: None,
+            decimals,
+        }
+    }
+
+    #[test]
+    fn test_normalize_balance() {
+        let asset_config = AssetConfig::new([named_asset("ETH", 18), named_asset("USDC", 6)]);
+        assert_eq!(asset_config.max_decimals(), 18);
+
+        // 1 ETH and 1 USDC, namely 1 000 000 of its smallest unit, share the same normalized value
+        let one_eth = BigUint::from(10u32).pow(18);
+        assert_eq!(asset_config.normalize_balance(one_eth.clone(), 0), one_eth);
+        assert_eq!(
+            asset_config.normalize_balance(BigUint::from(1_000_000u32), 1),
+            one_eth
+        );
+    }
+}
@@ -1,8 +1,12 @@
 use std::{error::Error, fs::File, path::Path};
 
-use ethers::{abi::AbiEncode, types::Bytes};
+use ethers::{
+    abi::AbiEncode,
+    types::{Address, Bytes},
+};
 use serde::{Deserialize, Serialize};
 
+use super::asset_config::{AssetConfig, NamedAsset};
 use crate::contracts::generated::summa_contract::AddressOwnershipProof;
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -46,6 +50,63 @@ pub fn parse_signature_csv<P: AsRef<Path>>(
     Ok(address_ownership_proofs)
 }
 
+/// A line of the asset CSV file, as read by `parse_asset_csv_named`
+#[derive(Debug, Deserialize)]
+struct AssetRecord {
+    name: String,
+    chain: String,
+    chain_id: u64,
+    token_address: Option<String>,
+    decimals: u8,
+}
+
+/// Parses the asset CSV file stored at `path` into the configuration of the `N_CURRENCIES` cryptocurrencies of a round. The CSV file must be formatted as follows:
+///
+/// `name;chain;chain_id;token_address;decimals`
+///
+/// `USDT;ETH;1;0xdAC17F958D2ee523a2206206994597C13D831ec7;6`
+///
+/// The name and the chain are the ones labelling the balances in the CSV file of the tree. The token address is left empty for a native cryptocurrency. The lines must be in the same order as the balances of the entries of the tree.
+pub fn parse_asset_csv_named<P: AsRef<Path>, const N_CURRENCIES: usize>(
+    path: P,
+) -> Result<AssetConfig<N_CURRENCIES>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let mut rdr = csv::ReaderBuilder::new().delimiter(b';').from_reader(file);
+
+    let mut assets = Vec::<NamedAsset>::new();
+
+    for result in rdr.deserialize() {
+        let record: AssetRecord = result?;
+
+        let token_address = match record.token_address {
+            Some(token_address) => Some(
+                token_address
+                    .parse::<Address>()
+                    .map_err(|e| format!("Invalid token address of {}: {}", record.name, e))?,
+            ),
+            None => None,
+        };
+
+        assets.push(NamedAsset {
+            name: record.name,
+            chain: record.chain,
+            chain_id: record.chain_id,
+            token_address,
+            decimals: record.decimals,
+        });
+    }
+
+    let assets: [NamedAsset; N_CURRENCIES] = assets.try_into().map_err(|assets: Vec<_>| {
+        format!(
+            "Expected {} assets but found {}",
+            N_CURRENCIES,
+            assets.len()
+        )
+    })?;
+
+    Ok(AssetConfig::new(assets))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,4 +126,33 @@ mod tests {
 
         assert_eq!(address_ownership[0], first_address_ownership);
     }
+
+    #[test]
+    fn test_parse_asset_csv_named() {
+        let asset_config = parse_asset_csv_named::<_, 2>("../csv/assets.csv").unwrap();
+
+        assert_eq!(asset_config.names(), vec!["ETH", "USDT"]);
+        assert_eq!(asset_config.index_of("USDT"), Some(1));
+        assert_eq!(asset_config.index_of("BTC"), None);
+
+        let eth = &asset_config.assets()[0];
+        assert_eq!(eth.chain, "ETH");
+        assert_eq!(eth.chain_id, 1);
+        assert_eq!(eth.token_address, None);
+        assert_eq!(eth.decimals, 18);
+
+        let usdt = &asset_config.assets()[1];
+        assert_eq!(
+            usdt.token_address,
+            Some(
+                "0xdAC17F958D2ee523a2206206994597C13D831ec7"
+                    .parse()
+                    .unwrap()
+            )
+        );
+        assert_eq!(usdt.decimals, 6);
+
+        // The number of assets must match the number of cryptocurrencies of the tree
+        assert!(parse_asset_csv_named::<_, 3>("../csv/assets.csv").is_err());
+    }
 }
@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+/// How much a `HealthIssue` affects the service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    /// The service keeps working, e.g. the commitment of the round has not been dispatched yet
+    Warning,
+    /// The service can't dispatch commitments or serve proofs until the issue is fixed
+    Critical,
+}
+
+/// The state of the subsystems of a round, as returned by `Round::health_check` when all of them are functional
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemStatus {
+    pub ethereum_connected: bool,
+    pub contract_reachable: bool,
+    pub mst_root_matches_commitment: bool,
+    pub proof_generation_operational: bool,
+    /// The time taken by the Ethereum node to answer a request for the latest block number
+    pub latency_ms: u64,
+}
+
+/// A subsystem found not functional by `Round::health_check`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthIssue {
+    pub component: String,
+    pub description: String,
+    pub severity: Severity,
+}
+
+impl HealthIssue {
+    pub const ETHEREUM: &'static str = "ethereum";
+    pub const CONTRACT: &'static str = "contract";
+    pub const COMMITMENT: &'static str = "commitment";
+    pub const PROVER: &'static str = "prover";
+
+    pub(crate) fn new(component: &str, description: String, severity: Severity) -> Self {
+        Self {
+            component: component.to_string(),
+            description,
+            severity,
+        }
+    }
+}
@@ -1,6 +1,13 @@
 pub mod address_ownership;
+pub mod asset_config;
 pub mod csv_parser;
+pub mod health;
+pub mod onchain_assets;
+pub mod proof_queue;
+pub mod proof_store;
+pub mod rate_limiter;
 pub mod round;
+pub mod round_registry;
 
 use ethers::types::U256;
 use num_bigint::BigUint;
@@ -0,0 +1,189 @@
+use std::error::Error;
+
+use ethers::{
+    providers::{Middleware, MiddlewareError},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, Bytes,
+        TransactionRequest, U256,
+    },
+};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use num_bigint::BigUint;
+use summa_solvency::merkle_sum_tree::utils::fp_to_big_uint;
+
+// The selector of `balanceOf(address)` of the ERC20 standard
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+
+/// Where the balance of an asset is held: in native ETH, or in the ERC20 token deployed at the given address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetSource {
+    Native,
+    Erc20(Address),
+}
+
+/// An asset whose sum is fetched from the chain, described by its name, where its balance is held and the custody addresses of the exchange holding it.
+///
+/// # Fields
+///
+/// * `name`: The name of the asset, e.g. "ETH"
+/// * `source`: Whether the asset is native ETH or an ERC20 token, along with the address of its contract
+/// * `custody_addresses`: The addresses of the exchange whose balances are summed up into the asset sum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetDefinition {
+    pub name: String,
+    pub source: AssetSource,
+    pub custody_addresses: Vec<Address>,
+}
+
+/// The reason why the asset sums couldn't be fetched from the chain
+#[derive(Debug)]
+pub enum OnchainAssetError {
+    /// The RPC node couldn't be reached or failed to answer
+    RpcUnreachable(String),
+    /// The contract of the asset has no code at the queried block, reverted on `balanceOf` or didn't return a single uint256
+    NotErc20 { asset: String, contract: Address },
+    /// The sum of the balances of the asset doesn't fit in U256, or exceeds the modulus of the field of the circuit
+    BalanceOverflow { asset: String },
+}
+
+impl std::fmt::Display for OnchainAssetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OnchainAssetError::RpcUnreachable(e) => write!(f, "RPC node unreachable: {}", e),
+            OnchainAssetError::NotErc20 { asset, contract } => write!(
+                f,
+                "The contract {:?} of {} is not an ERC20 token",
+                contract, asset
+            ),
+            OnchainAssetError::BalanceOverflow { asset } => write!(
+                f,
+                "The sum of the balances of {} doesn't fit in a field element",
+                asset
+            ),
+        }
+    }
+}
+
+impl Error for OnchainAssetError {}
+
+/// The asset sums of an exchange fetched from the chain, along with the block number at which the balances were read.
+///
+/// # Fields
+///
+/// * `block_number`: The number of the block at which the balances of the custody addresses were read
+/// * `asset_sums`: The sum of the balances of the custody addresses of each asset, in the same order as the asset definitions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnchainAssets<const N_CURRENCIES: usize> {
+    pub block_number: u64,
+    pub asset_sums: [BigUint; N_CURRENCIES],
+}
+
+/// Fetches the sum of the balances of the custody addresses of each asset of `asset_definitions` at `block_number`, or at the latest block if `None`.
+/// The native balances are read by `eth_getBalance` and the ERC20 balances by calling `balanceOf`, all of them at the same block so that the sums are consistent.
+pub async fn fetch_onchain_assets<M: Middleware, const N_CURRENCIES: usize>(
+    provider: &M,
+    asset_definitions: &[AssetDefinition; N_CURRENCIES],
+    block_number: Option<u64>,
+) -> Result<OnchainAssets<N_CURRENCIES>, OnchainAssetError> {
+    // Pin the latest block, so that all balances are read from the same state
+    let block_number = match block_number {
+        Some(block_number) => block_number,
+        None => provider
+            .get_block_number()
+            .await
+            .map_err(|e| OnchainAssetError::RpcUnreachable(e.to_string()))?
+            .as_u64(),
+    };
+    let block = BlockId::Number(BlockNumber::Number(block_number.into()));
+
+    // The largest value a field element can hold
+    let field_max = fp_to_big_uint(-Fp::one());
+
+    let mut asset_sums: Vec<BigUint> = Vec::with_capacity(N_CURRENCIES);
+    for definition in asset_definitions.iter() {
+        let mut sum = U256::zero();
+        for custody_address in &definition.custody_addresses {
+            let balance = match definition.source {
+                AssetSource::Native => provider
+                    .get_balance(*custody_address, Some(block))
+                    .await
+                    .map_err(|e| OnchainAssetError::RpcUnreachable(e.to_string()))?,
+                AssetSource::Erc20(contract) => {
+                    erc20_balance_of(
+                        provider,
+                        &definition.name,
+                        contract,
+                        *custody_address,
+                        block,
+                    )
+                    .await?
+                }
+            };
+            sum = sum
+                .checked_add(balance)
+                .ok_or_else(|| OnchainAssetError::BalanceOverflow {
+                    asset: definition.name.clone(),
+                })?;
+        }
+
+        let mut bytes = [0u8; 32];
+        sum.to_little_endian(&mut bytes);
+        let sum = BigUint::from_bytes_le(&bytes);
+        if sum > field_max {
+            return Err(OnchainAssetError::BalanceOverflow {
+                asset: definition.name.clone(),
+            });
+        }
+        asset_sums.push(sum);
+    }
+
+    Ok(OnchainAssets {
+        block_number,
+        // There is one sum per asset definition
+        asset_sums: asset_sums.try_into().unwrap(),
+    })
+}
+
+/// Calls `balanceOf(owner)` on the ERC20 `contract` at `block`, checking that the contract exists and returns a single uint256
+async fn erc20_balance_of<M: Middleware>(
+    provider: &M,
+    asset: &str,
+    contract: Address,
+    owner: Address,
+    block: BlockId,
+) -> Result<U256, OnchainAssetError> {
+    let not_erc20 = || OnchainAssetError::NotErc20 {
+        asset: asset.to_string(),
+        contract,
+    };
+
+    let code = provider
+        .get_code(contract, Some(block))
+        .await
+        .map_err(|e| OnchainAssetError::RpcUnreachable(e.to_string()))?;
+    if code.is_empty() {
+        return Err(not_erc20());
+    }
+
+    let mut data = BALANCE_OF_SELECTOR.to_vec();
+    data.extend_from_slice(&[0u8; 12]);
+    data.extend_from_slice(owner.as_bytes());
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(contract)
+        .data(Bytes::from(data))
+        .into();
+
+    let output = provider.call(&tx, Some(block)).await.map_err(|e| {
+        // A JSON-RPC error response means that the node has been reached and the call reverted
+        if e.as_error_response().is_some() {
+            not_erc20()
+        } else {
+            OnchainAssetError::RpcUnreachable(e.to_string())
+        }
+    })?;
+    if output.len() != 32 {
+        return Err(not_erc20());
+    }
+
+    Ok(U256::from_big_endian(&output))
+}
@@ -0,0 +1,407 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures::{stream, StreamExt};
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
+
+use super::round::{MstInclusionProof, RoundError, Snapshot};
+
+/// A request for an inclusion proof, queued in a `ProofQueue`
+struct ProofJob {
+    user_index: usize,
+    priority: u8,
+    submitted_at: Instant,
+    // The order of submission, breaking the ties between jobs submitted at the same instant
+    sequence: u64,
+    result_tx: oneshot::Sender<Result<MstInclusionProof, RoundError>>,
+}
+
+impl Ord for ProofJob {
+    /// The job of highest priority is the greatest, and the jobs of the same priority are ordered by submission, the oldest being the greatest
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.submitted_at.cmp(&self.submitted_at))
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for ProofJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ProofJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ProofJob {}
+
+/// Queue of the inclusion proof requests of a snapshot, served by the workers started by `start_worker` from the highest priority to the lowest,
+/// and in the order of submission for the same priority. The requests of a high priority, e.g. the ones of users waiting for their proof,
+/// overtake the queued requests of a lower priority, e.g. the ones of a batch precomputing the proofs of all the users with `stream_proofs`.
+/// The workers share the snapshot, and thus its setup artifacts, and generate each proof on the blocking thread pool of the runtime.
+///
+/// The workers stop once the cancellation token returned by `cancellation_token` is cancelled, e.g. by `shutdown`.
+/// The proofs being generated are completed and sent, whereas the queued requests are dropped, so that their receivers return an error.
+pub struct ProofQueue<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> {
+    snapshot: Arc<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>>,
+    inner: Arc<Mutex<BinaryHeap<ProofJob>>>,
+    // Wakes up a worker waiting for a job
+    job_notify: Arc<Notify>,
+    next_sequence: AtomicU64,
+    cancellation_token: CancellationToken,
+}
+
+impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    ProofQueue<LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+    [(); N_CURRENCIES + 2]: Sized,
+{
+    /// Returns a queue of the inclusion proofs of `snapshot`, whose requests are served once `start_worker` is called
+    pub fn new(snapshot: Arc<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>>) -> Self {
+        Self {
+            snapshot,
+            inner: Arc::new(Mutex::new(BinaryHeap::new())),
+            job_notify: Arc::new(Notify::new()),
+            next_sequence: AtomicU64::new(0),
+            cancellation_token: CancellationToken::new(),
+        }
+    }
+
+    /// Queues the generation of the inclusion proof of the user at `user_index` with `priority`, the higher being served first, and returns immediately.
+    /// The proof is received from the returned receiver, which returns an error if the queue is shut down before the proof is generated.
+    /// An out of range user index isn't queued, the receiver getting an `InvalidUserIndex` error right away.
+    pub fn submit(
+        &self,
+        user_index: usize,
+        priority: u8,
+    ) -> oneshot::Receiver<Result<MstInclusionProof, RoundError>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        if let Err(e) = self.snapshot.check_user_index(user_index) {
+            let _ = result_tx.send(Err(e));
+            return result_rx;
+        }
+
+        let mut jobs = self.inner.lock().unwrap();
+        // The job of a queue that is shut down would never be served, so that its sender is dropped right away.
+        // The token is checked under the lock the workers clear the queue with, so that a job can't be queued right after they have stopped
+        if self.cancellation_token.is_cancelled() {
+            return result_rx;
+        }
+        jobs.push(ProofJob {
+            user_index,
+            priority,
+            submitted_at: Instant::now(),
+            sequence: self.next_sequence.fetch_add(1, AtomicOrdering::Relaxed),
+            result_tx,
+        });
+        drop(jobs);
+        self.job_notify.notify_one();
+
+        result_rx
+    }
+
+    /// Submits the inclusion proofs of the users at `user_indices` with `priority`, and returns a stream yielding each `(user_index, proof)` pair as soon as it is generated,
+    /// thus not necessarily in the order of `user_indices`. The proofs are submitted as the stream is polled, so that at most `max_pending` of them are queued or wait to be received
+    /// rather than holding the proofs of all the users in memory, and a request of a higher priority submitted in the meantime is served first.
+    /// A failing proof is yielded as an error without stopping the next ones, while dropping the stream stops the submissions.
+    ///
+    /// Returns an `InvalidConcurrency` error if `max_pending` is 0. Must be called within a Tokio runtime.
+    pub fn stream_proofs(
+        self: Arc<Self>,
+        user_indices: impl IntoIterator<Item = usize>,
+        priority: u8,
+        max_pending: usize,
+    ) -> Result<ReceiverStream<Result<(usize, MstInclusionProof), RoundError>>, RoundError> {
+        if max_pending == 0 {
+            return Err(RoundError::InvalidConcurrency);
+        }
+        let (proof_sender, proof_receiver) = mpsc::channel(max_pending);
+        let user_indices: Vec<usize> = user_indices.into_iter().collect();
+
+        tokio::spawn(async move {
+            let mut proofs = stream::iter(user_indices)
+                .map(|user_index| {
+                    let result_rx = self.submit(user_index, priority);
+                    async move {
+                        result_rx
+                            .await
+                            .unwrap_or_else(|_| Err(stopped()))
+                            .map(|proof| (user_index, proof))
+                    }
+                })
+                .buffer_unordered(max_pending);
+
+            while let Some(result) = proofs.next().await {
+                // Waits while the channel is full, and fails once the caller has dropped the stream
+                if proof_sender.send(result).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(ReceiverStream::new(proof_receiver))
+    }
+
+    /// Returns the number of queued requests, not counting the ones being served
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the token stopping the workers of the queue when cancelled
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation_token.clone()
+    }
+
+    /// Stops the workers of the queue, as cancelling the token returned by `cancellation_token` does
+    pub fn shutdown(&self) {
+        self.cancellation_token.cancel();
+    }
+
+    /// Spawns `worker_count` tasks on the tokio runtime generating the inclusion proofs of the queued requests, each on a blocking thread.
+    /// Returns the handles of the tasks, which complete once the queue is shut down and their current proof is sent.
+    pub fn start_worker(&self, worker_count: usize) -> Vec<JoinHandle<()>> {
+        assert!(
+            worker_count > 0,
+            "the queue should have at least one worker"
+        );
+
+        (0..worker_count)
+            .map(|_| {
+                let snapshot = Arc::clone(&self.snapshot);
+                let inner = Arc::clone(&self.inner);
+                let job_notify = Arc::clone(&self.job_notify);
+                let cancellation_token = self.cancellation_token.clone();
+
+                tokio::spawn(async move {
+                    loop {
+                        let job = {
+                            let mut jobs = inner.lock().unwrap();
+                            if cancellation_token.is_cancelled() {
+                                // Dropping the queued jobs lets their requesters know that they won't be served
+                                jobs.clear();
+                                break;
+                            }
+                            jobs.pop()
+                        };
+                        let job = match job {
+                            Some(job) => job,
+                            None => {
+                                tokio::select! {
+                                    _ = job_notify.notified() => {}
+                                    _ = cancellation_token.cancelled() => {}
+                                }
+                                continue;
+                            }
+                        };
+
+                        // A panicking proof generation shouldn't take the worker down
+                        let snapshot = Arc::clone(&snapshot);
+                        let user_index = job.user_index;
+                        let result = tokio::task::spawn_blocking(move || {
+                            panic::catch_unwind(AssertUnwindSafe(|| {
+                                snapshot.generate_proof_of_inclusion(user_index)
+                            }))
+                            .unwrap_or_else(|_| Err(panicked()))
+                        })
+                        .await
+                        .unwrap_or_else(|_| Err(panicked()));
+
+                        // The requester may have stopped waiting for the proof
+                        let _ = job.result_tx.send(result);
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// The error yielded by `stream_proofs` for a request dropped by a queue that is shut down
+fn stopped() -> RoundError {
+    RoundError::ProofGeneration("The proof queue was shut down before generating the proof".into())
+}
+
+/// The error sent for a proof generation that panicked
+fn panicked() -> RoundError {
+    RoundError::ProofGeneration("The proof generation panicked".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::U256;
+    use halo2_proofs::dev::MockProver;
+    use summa_solvency::{
+        circuits::{
+            merkle_sum_tree::MstInclusionCircuit, utils::field_element_to_solidity_calldata,
+            WithInstances,
+        },
+        merkle_sum_tree::{MerkleSumTree, Tree},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_proof_queue_priority() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot =
+            Arc::new(Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap());
+
+        let queue = ProofQueue::new(Arc::clone(&snapshot));
+
+        // The low priority jobs are submitted first, before the worker starts
+        let receivers: Vec<_> = [(0, 0), (1, 0), (2, 255), (3, 255)]
+            .into_iter()
+            .map(|(user_index, priority)| (user_index, queue.submit(user_index, priority)))
+            .collect();
+        assert_eq!(queue.len(), 4);
+
+        // The users are sent in the order in which their proof is received
+        let (order_tx, mut order_rx) = mpsc::unbounded_channel();
+        for (user_index, receiver) in receivers {
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let proof = receiver.await.unwrap().unwrap();
+                order_tx.send((user_index, proof)).unwrap();
+            });
+        }
+        drop(order_tx);
+
+        let workers = queue.start_worker(1);
+
+        let mut order = vec![];
+        while let Some((user_index, proof)) = order_rx.recv().await {
+            assert_eq!(
+                proof.get_public_inputs()[1],
+                field_element_to_solidity_calldata(
+                    snapshot.mst.get_entry(user_index).compute_leaf().hash
+                )
+            );
+            order.push(user_index);
+        }
+        assert_eq!(order, vec![2, 3, 0, 1]);
+
+        // An out of range user index is rejected before it is queued
+        assert!(matches!(
+            queue.submit(16, 0).await.unwrap(),
+            Err(RoundError::InvalidUserIndex { index: 16, max: 15 })
+        ));
+        assert!(queue.submit(15, 0).await.unwrap().is_ok());
+
+        queue.shutdown();
+        for worker in workers {
+            worker.await.unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_proof_queue_shutdown() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot =
+            Arc::new(Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap());
+
+        let queue = ProofQueue::new(snapshot);
+        let workers = queue.start_worker(1);
+
+        let first_receiver = queue.submit(0, 0);
+        let pending_receivers: Vec<_> = (1..4)
+            .map(|user_index| queue.submit(user_index, 0))
+            .collect();
+
+        // Wait for the worker to take the first job before shutting down
+        while queue.len() == 4 {
+            tokio::task::yield_now().await;
+        }
+        queue.cancellation_token().cancel();
+
+        for worker in workers {
+            worker.await.unwrap();
+        }
+
+        // The proof being generated is completed, whereas the queued jobs are dropped
+        assert!(first_receiver.await.unwrap().is_ok());
+        for receiver in pending_receivers {
+            assert!(receiver.await.is_err());
+        }
+        assert!(queue.is_empty());
+
+        // The jobs submitted after the shutdown are never served
+        assert!(queue.submit(0, 255).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stream_proofs() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Arc::new(
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap(),
+        );
+        let queue = Arc::new(ProofQueue::new(snapshot));
+        let workers = queue.start_worker(2);
+
+        // At least one proof is pending at a time
+        assert!(matches!(
+            Arc::clone(&queue).stream_proofs(0..16, 0, 0),
+            Err(RoundError::InvalidConcurrency)
+        ));
+
+        // The out of range user index fails without ending the stream
+        let results: Vec<_> = Arc::clone(&queue)
+            .stream_proofs((0..16).chain([16, 15]), 0, 2)
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(results.len(), 18);
+        assert_eq!(
+            results
+                .iter()
+                .filter(|result| matches!(
+                    result,
+                    Err(RoundError::InvalidUserIndex { index: 16, max: 15 })
+                ))
+                .count(),
+            1
+        );
+
+        // The proofs are yielded in the order they are generated
+        let mut proofs: Vec<(usize, MstInclusionProof)> =
+            results.into_iter().filter_map(Result::ok).collect();
+        proofs.sort_by_key(|(user_index, _)| *user_index);
+        let user_indices: Vec<usize> = proofs.iter().map(|(user_index, _)| *user_index).collect();
+        assert_eq!(user_indices, (0..16).chain([15]).collect::<Vec<_>>());
+
+        for (user_index, proof) in proofs {
+            let circuit =
+                MstInclusionCircuit::<4, 2, 8>::init(mst.generate_proof(user_index).unwrap());
+            let instances = circuit.instances();
+            MockProver::run(11, &circuit, instances.clone())
+                .unwrap()
+                .assert_satisfied();
+
+            let expected_public_inputs: Vec<U256> = instances[0]
+                .iter()
+                .map(|instance| field_element_to_solidity_calldata(*instance))
+                .collect();
+            assert_eq!(proof.get_public_inputs(), &expected_public_inputs);
+        }
+
+        queue.shutdown();
+        for worker in workers {
+            worker.await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use super::round::MstInclusionProof;
+
+/// Storage of the inclusion proofs generated by a `Round`, indexed by the index of the user and the timestamp of the round.
+/// A store is shared by the requests served by the round, so that it synchronizes its own state rather than being borrowed mutably.
+pub trait ProofStore: Send + Sync {
+    /// Stores the proof of the user at `user_index` for the round at `timestamp`, replacing any proof previously stored for them
+    fn save(
+        &self,
+        user_index: usize,
+        timestamp: u64,
+        proof: MstInclusionProof,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Returns the proof stored for the user at `user_index` for the round at `timestamp`, if any
+    fn get(&self, user_index: usize, timestamp: u64) -> Option<MstInclusionProof>;
+}
+
+/// A `ProofStore` that keeps the proofs in memory, so that they are lost when the store is dropped
+#[derive(Default)]
+pub struct InMemoryProofStore {
+    proofs: Mutex<HashMap<(usize, u64), MstInclusionProof>>,
+}
+
+impl InMemoryProofStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProofStore for InMemoryProofStore {
+    fn save(
+        &self,
+        user_index: usize,
+        timestamp: u64,
+        proof: MstInclusionProof,
+    ) -> Result<(), Box<dyn Error>> {
+        self.proofs
+            .lock()
+            .unwrap()
+            .insert((user_index, timestamp), proof);
+        Ok(())
+    }
+
+    fn get(&self, user_index: usize, timestamp: u64) -> Option<MstInclusionProof> {
+        self.proofs
+            .lock()
+            .unwrap()
+            .get(&(user_index, timestamp))
+            .cloned()
+    }
+}
+
+/// The number of subdirectories among which the proofs of a round are spread by `FileProofStore`
+const USER_BUCKETS: usize = 256;
+
+/// Numbers the temporary files written by `FileProofStore::save`, so that concurrent saves of the same proof don't write to the same file
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A `ProofStore` that writes each proof to a file, serialized with `MstInclusionProof::to_bytes_versioned`.
+/// The proofs are stored at `{directory}/{timestamp}/{user_index % 256}/{user_index}.bin`, so that no directory holds more than the proofs of a round
+/// spread among 256 subdirectories, rather than the proofs of every user and round in a single directory.
+/// The proofs of the directory are indexed when the store is opened, so that they are kept across restarts of the backend,
+/// but a proof is only read from its file when it is requested, so that the memory used by the store doesn't grow with the number of proofs.
+pub struct FileProofStore {
+    directory: PathBuf,
+    // The users and rounds whose proof has a file in the directory, so that a missing proof is answered without touching the disk
+    index: Mutex<HashSet<(usize, u64)>>,
+}
+
+impl FileProofStore {
+    /// Opens the store at `directory`, creating the directory if it doesn't exist and indexing the proofs it already contains.
+    /// The proofs stored in the previous layout, namely as `proof_{timestamp}_{user_index}.bin` files at the top of the directory, are moved to their path in the current layout.
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<Self, Box<dyn Error>> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+
+        let mut store = Self {
+            directory,
+            index: Mutex::new(HashSet::new()),
+        };
+        store.migrate_flat_files()?;
+
+        for timestamp in store.list_rounds()? {
+            for user_index in store.list_users_for_round(timestamp)? {
+                store
+                    .index
+                    .get_mut()
+                    .unwrap()
+                    .insert((user_index, timestamp));
+            }
+        }
+
+        Ok(store)
+    }
+
+    /// Returns the path of the file of the proof of the user at `user_index` for the round at `timestamp`
+    pub fn proof_path(&self, user_index: usize, timestamp: u64) -> PathBuf {
+        self.round_directory(timestamp)
+            .join((user_index % USER_BUCKETS).to_string())
+            .join(format!("{}.bin", user_index))
+    }
+
+    /// Returns the directory of the proofs of the round at `timestamp`
+    fn round_directory(&self, timestamp: u64) -> PathBuf {
+        self.directory.join(timestamp.to_string())
+    }
+
+    /// Returns the timestamps of the rounds for which the store holds proofs, in increasing order.
+    /// Entries of the directory that aren't named as rounds, e.g. left by the operator, are ignored.
+    pub fn list_rounds(&self) -> Result<Vec<u64>, Box<dyn Error>> {
+        let mut timestamps = vec![];
+        for dir_entry in fs::read_dir(&self.directory)? {
+            let dir_entry = dir_entry?;
+            if !dir_entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(timestamp) = dir_entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+            {
+                timestamps.push(timestamp);
+            }
+        }
+
+        timestamps.sort_unstable();
+        Ok(timestamps)
+    }
+
+    /// Returns the indexes of the users whose proofs are stored for the round at `timestamp`, in increasing order, none if the store has no proof for the round.
+    /// Files that aren't named as proofs or that don't lie in the subdirectory of their user are ignored.
+    pub fn list_users_for_round(&self, timestamp: u64) -> Result<Vec<usize>, Box<dyn Error>> {
+        let round_directory = self.round_directory(timestamp);
+        if !round_directory.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut user_indexes = vec![];
+        for bucket_entry in fs::read_dir(&round_directory)? {
+            let bucket_entry = bucket_entry?;
+            if !bucket_entry.file_type()?.is_dir() {
+                continue;
+            }
+            let bucket = match bucket_entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<usize>().ok())
+            {
+                Some(bucket) => bucket,
+                None => continue,
+            };
+
+            for dir_entry in fs::read_dir(bucket_entry.path())? {
+                let path = dir_entry?.path();
+                let user_index = match path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_suffix(".bin"))
+                    .and_then(|name| name.parse::<usize>().ok())
+                {
+                    Some(user_index) if user_index % USER_BUCKETS == bucket => user_index,
+                    _ => continue,
+                };
+                user_indexes.push(user_index);
+            }
+        }
+
+        user_indexes.sort_unstable();
+        Ok(user_indexes)
+    }
+
+    /// Moves the proof files written in the previous flat layout to their path in the current layout
+    fn migrate_flat_files(&self) -> Result<(), Box<dyn Error>> {
+        for dir_entry in fs::read_dir(&self.directory)? {
+            let path = dir_entry?.path();
+            let (user_index, timestamp) = match Self::parse_flat_file_name(&path) {
+                Some(key) => key,
+                None => continue,
+            };
+
+            let proof_path = self.proof_path(user_index, timestamp);
+            if let Some(bucket_directory) = proof_path.parent() {
+                fs::create_dir_all(bucket_directory)?;
+            }
+            fs::rename(&path, &proof_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Parses the timestamp and the user index from a file name of the previous flat layout, `proof_{timestamp}_{user_index}.bin`
+    fn parse_flat_file_name(path: &Path) -> Option<(usize, u64)> {
+        let name = path.file_name()?.to_str()?;
+        let (timestamp, user_index) = name
+            .strip_prefix("proof_")?
+            .strip_suffix(".bin")?
+            .split_once('_')?;
+
+        Some((user_index.parse().ok()?, timestamp.parse().ok()?))
+    }
+}
+
+impl ProofStore for FileProofStore {
+    fn save(
+        &self,
+        user_index: usize,
+        timestamp: u64,
+        proof: MstInclusionProof,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = self.proof_path(user_index, timestamp);
+        if let Some(bucket_directory) = path.parent() {
+            fs::create_dir_all(bucket_directory)?;
+        }
+        write_atomically(&path, &proof.to_bytes_versioned()?)?;
+        self.index.lock().unwrap().insert((user_index, timestamp));
+        Ok(())
+    }
+
+    /// Reads the proof from its file. A proof file that can't be read or deserialized, e.g. truncated by a crash while it was written,
+    /// is logged as a warning and dropped from the index, so that the proof is generated again rather than the request failing.
+    fn get(&self, user_index: usize, timestamp: u64) -> Option<MstInclusionProof> {
+        if !self
+            .index
+            .lock()
+            .unwrap()
+            .contains(&(user_index, timestamp))
+        {
+            return None;
+        }
+
+        let path = self.proof_path(user_index, timestamp);
+        let proof = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| {
+                MstInclusionProof::from_bytes_versioned(&bytes).map_err(|e| e.to_string())
+            });
+        match proof {
+            Ok(proof) => Some(proof),
+            Err(e) => {
+                log::warn!("skipping the invalid proof file {}: {}", path.display(), e);
+                self.index.lock().unwrap().remove(&(user_index, timestamp));
+                None
+            }
+        }
+    }
+}
+
+/// Writes `bytes` to a temporary file next to `path`, then renames it to `path`, so that a crash while writing leaves either the previous file or the new one but never a truncated file.
+/// The temporary file isn't named as a proof, so that `FileProofStore::list_users_for_round` ignores it if it is left behind.
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut temp_name = path
+        .file_name()
+        .ok_or("The proof path has no file name")?
+        .to_os_string();
+    temp_name.push(format!(
+        ".tmp-{}-{}",
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::round::Snapshot;
+    use summa_solvency::merkle_sum_tree::MerkleSumTree;
+
+    #[test]
+    fn test_file_proof_store() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+        let proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+
+        let directory = std::env::temp_dir().join("summa_test_file_proof_store");
+        let _ = fs::remove_dir_all(&directory);
+
+        let store = FileProofStore::open(&directory).unwrap();
+        assert!(store.get(0, 1).is_none());
+        store.save(0, 1, proof.clone()).unwrap();
+        assert!(store.proof_path(0, 1).exists());
+
+        // The proof is written to a temporary file renamed into place, which is not left behind
+        let bucket_directory = store.proof_path(0, 1).parent().unwrap().to_path_buf();
+        assert_eq!(fs::read_dir(&bucket_directory).unwrap().count(), 1);
+
+        // A temporary file left by a crash while saving is not taken for a proof
+        fs::write(bucket_directory.join("0.bin.tmp-1-0"), b"trunc").unwrap();
+        assert_eq!(store.list_users_for_round(1).unwrap(), vec![0]);
+
+        // A store reopened on the same directory finds the proof, but only for the same user and round
+        let reopened_store = FileProofStore::open(&directory).unwrap();
+        let stored_proof = reopened_store.get(0, 1).unwrap();
+        assert_eq!(stored_proof.get_proof(), proof.get_proof());
+        assert_eq!(stored_proof.get_public_inputs(), proof.get_public_inputs());
+        assert_eq!(stored_proof.get_metadata(), proof.get_metadata());
+        assert!(reopened_store.get(1, 1).is_none());
+        assert!(reopened_store.get(0, 2).is_none());
+
+        // A corrupted proof file is skipped rather than failing to open the store or to serve the other proofs
+        fs::write(reopened_store.proof_path(256, 1), b"truncated").unwrap();
+        let reopened_store = FileProofStore::open(&directory).unwrap();
+        assert!(reopened_store.get(0, 1).is_some());
+        assert!(reopened_store.get(256, 1).is_none());
+
+        // The proofs are read from their files on request rather than kept in memory
+        fs::remove_file(reopened_store.proof_path(0, 1)).unwrap();
+        assert!(reopened_store.get(0, 1).is_none());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_file_proof_store_layout() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+        let proof = serde_json::to_value(snapshot.generate_proof_of_inclusion(0).unwrap()).unwrap();
+
+        let directory = std::env::temp_dir().join("summa_test_file_proof_store_layout");
+        let _ = fs::remove_dir_all(&directory);
+
+        // Each stored proof records its user and round, so that a proof served for another user or round is caught
+        let proof_of = |user_index: usize, timestamp: u64| {
+            let mut proof = proof.clone();
+            proof["user_index"] = user_index.into();
+            proof["metadata"]["generated_at"] = timestamp.into();
+            serde_json::from_value::<MstInclusionProof>(proof).unwrap()
+        };
+
+        let store = FileProofStore::open(&directory).unwrap();
+        for timestamp in 1..=10 {
+            for user_index in 0..1000 {
+                store
+                    .save(user_index, timestamp, proof_of(user_index, timestamp))
+                    .unwrap();
+            }
+        }
+        assert!(directory.join("3").join("232").join("488.bin").exists());
+
+        let reopened_store = FileProofStore::open(&directory).unwrap();
+        assert_eq!(
+            reopened_store.list_rounds().unwrap(),
+            (1..=10).collect::<Vec<u64>>()
+        );
+        for timestamp in 1..=10 {
+            assert_eq!(
+                reopened_store.list_users_for_round(timestamp).unwrap(),
+                (0..1000).collect::<Vec<usize>>()
+            );
+            for user_index in 0..1000 {
+                let stored_proof = reopened_store.get(user_index, timestamp).unwrap();
+                assert_eq!(stored_proof.get_user_index(), Some(user_index));
+                assert_eq!(stored_proof.get_metadata().generated_at, timestamp);
+                assert_eq!(
+                    stored_proof.get_proof(),
+                    proof_of(user_index, timestamp).get_proof()
+                );
+            }
+        }
+        assert!(reopened_store.list_users_for_round(11).unwrap().is_empty());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_file_proof_store_migration() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+        let proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+
+        let directory = std::env::temp_dir().join("summa_test_file_proof_store_migration");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+
+        // A proof written in the flat layout is moved to its user subdirectory
+        fs::write(
+            directory.join("proof_1_300.bin"),
+            proof.to_bytes_versioned().unwrap(),
+        )
+        .unwrap();
+        fs::write(directory.join("notes.txt"), "kept as is").unwrap();
+
+        let store = FileProofStore::open(&directory).unwrap();
+        assert!(!directory.join("proof_1_300.bin").exists());
+        assert!(store.proof_path(300, 1).exists());
+        assert_eq!(store.get(300, 1).unwrap().get_proof(), proof.get_proof());
+        assert_eq!(store.list_rounds().unwrap(), vec![1]);
+        assert_eq!(store.list_users_for_round(1).unwrap(), vec![300]);
+        assert!(directory.join("notes.txt").exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
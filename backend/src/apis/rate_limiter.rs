@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The length of the window in which the requests of a user are counted
+const WINDOW_SECS: u64 = 60;
+
+/// The error returned when a user has already made the maximum number of requests of the current window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitExceeded {
+    pub username: String,
+    /// The number of seconds until the window of the user ends and they can make requests again
+    pub retry_after_secs: u64,
+}
+
+impl std::fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Too many proof requests from {}, retry after {} seconds",
+            self.username, self.retry_after_secs
+        )
+    }
+}
+
+impl std::error::Error for RateLimitExceeded {}
+
+/// Limits the number of requests of each user to `max_requests_per_minute` per window of 60 seconds, starting at their first request of the window
+pub struct RateLimiter {
+    max_requests_per_minute: u32,
+    // A single lock is kept rather than a sharded map such as `DashMap`: a check holds it for a few map operations only, next to a proof taking seconds,
+    // and the pruning of the expired windows has to go over the whole map anyway.
+    windows: Mutex<Windows>,
+}
+
+#[derive(Default)]
+struct Windows {
+    // The start of the current window of each user, in seconds since the unix epoch, and the number of requests they made in it
+    by_user: HashMap<String, (u64, u32)>,
+    // The time of the last pruning of the expired windows
+    last_pruned: u64,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests_per_minute: u32) -> Self {
+        Self {
+            max_requests_per_minute,
+            windows: Mutex::new(Windows::default()),
+        }
+    }
+
+    /// Counts a request of `username`, failing if they have already made `max_requests_per_minute` requests in their current window
+    pub fn check(&self, username: &str) -> Result<(), RateLimitExceeded> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time should be after the unix epoch")
+            .as_secs();
+        self.check_at(username, now)
+    }
+
+    fn check_at(&self, username: &str, now: u64) -> Result<(), RateLimitExceeded> {
+        let mut windows = self.windows.lock().unwrap();
+
+        // Drop the windows that have ended, at most once per window, so that the map only holds the users who made a request recently
+        if now >= windows.last_pruned + WINDOW_SECS {
+            windows
+                .by_user
+                .retain(|_, (window_start, _)| now < *window_start + WINDOW_SECS);
+            windows.last_pruned = now;
+        }
+
+        let (window_start, count) = windows
+            .by_user
+            .entry(username.to_string())
+            .or_insert((now, 0));
+
+        if now >= *window_start + WINDOW_SECS {
+            *window_start = now;
+            *count = 0;
+        }
+
+        if *count >= self.max_requests_per_minute {
+            return Err(RateLimitExceeded {
+                username: username.to_string(),
+                retry_after_secs: *window_start + WINDOW_SECS - now,
+            });
+        }
+
+        *count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(5);
+
+        for second in 0..5 {
+            assert!(limiter.check_at("alice", 1000 + second).is_ok());
+        }
+        assert_eq!(
+            limiter.check_at("alice", 1010),
+            Err(RateLimitExceeded {
+                username: "alice".to_string(),
+                retry_after_secs: 50
+            })
+        );
+
+        // The requests of the other users are counted separately
+        assert!(limiter.check_at("bob", 1010).is_ok());
+
+        // A new window starts 60 seconds after the first request of the previous one
+        assert!(limiter.check_at("alice", 1059).is_err());
+        assert!(limiter.check_at("alice", 1060).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limiter_prunes_expired_windows() {
+        let limiter = RateLimiter::new(5);
+
+        for user in 0..100 {
+            assert!(limiter.check_at(&format!("user_{}", user), 1000).is_ok());
+        }
+        assert_eq!(limiter.windows.lock().unwrap().by_user.len(), 100);
+
+        // Once their windows have ended, the users who made no other request are forgotten
+        assert!(limiter.check_at("alice", 1060).is_ok());
+        let windows = limiter.windows.lock().unwrap();
+        assert_eq!(windows.by_user.len(), 1);
+        assert!(windows.by_user.contains_key("alice"));
+    }
+}
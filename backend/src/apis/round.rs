@@ -1,54 +1,1028 @@
-use ethers::types::{Bytes, U256};
+use ethers::{
+    abi::AbiEncode,
+    providers::Middleware,
+    types::{Address, Bytes, U256},
+};
 use halo2_proofs::{
-    halo2curves::bn256::{Bn256, G1Affine},
-    plonk::{ProvingKey, VerifyingKey},
-    poly::kzg::commitment::ParamsKZG,
+    circuit::Layouter,
+    halo2curves::bn256::{Bn256, Fr as Fp, G1Affine},
+    plonk::{Circuit, ConstraintSystem, Error as PlonkError, ProvingKey, VerifyingKey},
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+    SerdeFormat,
 };
+use lru::LruCache;
+use num_bigint::{BigInt, BigUint};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
 
-use crate::contracts::{generated::summa_contract::summa::Cryptocurrency, signer::SummaSigner};
+use super::asset_config::{AnnotatedPublicInputs, AssetConfig, NamedAsset};
+use super::health::{HealthIssue, Severity, SystemStatus};
+use super::onchain_assets::{fetch_onchain_assets, AssetDefinition, OnchainAssets};
+use super::proof_store::ProofStore;
+use super::rate_limiter::RateLimiter;
+use crate::contracts::{
+    generated::summa_contract::summa::{Cryptocurrency, SubmitCommitmentCall},
+    signer::{FeeConfig, RetryConfig, SummaSigner},
+};
+use crate::error::BackendError;
 use summa_solvency::{
     circuits::{
+        balance_threshold::BalanceThresholdCircuit,
+        dynamic_inclusion::DynamicMstInclusionCircuit,
         merkle_sum_tree::MstInclusionCircuit,
-        utils::{gen_proof_solidity_calldata, generate_setup_artifacts},
+        setup_cache::CachedSetupArtifacts,
+        solvency::SolvencyCircuit,
+        types::{ConstraintViolation, TranscriptKind, ViolationKind},
+        utils::{
+            field_element_to_solidity_calldata, full_verifier_with_transcript,
+            gen_proof_solidity_calldata, generate_setup_artifacts, preflight_check, read_params_k,
+            read_setup_artifacts, read_setup_artifacts_encrypted, write_setup_artifacts,
+            write_setup_artifacts_encrypted, write_verifier_params, ProofArtifact,
+        },
+        WithInstances,
     },
-    merkle_sum_tree::Tree,
+    merkle_sum_tree::{utils::serde_helpers, Entry, MerkleSumTree, Node, Tree},
 };
 
+/// The calldata of the proofs, re-exported so that the proofs served by the backend and the calldata parsed by verification tools share the same type
+pub use summa_solvency::circuits::types::SolidityCalldata;
+
 pub(crate) type SetupArtifacts = (
     ParamsKZG<Bn256>,
     ProvingKey<G1Affine>,
     VerifyingKey<G1Affine>,
 );
 
+/// The reason why a round or a snapshot couldn't be built, committed or proven, wrapping the underlying error where there is one
+#[derive(Debug)]
+pub enum RoundError {
+    /// The entries couldn't be parsed from the csv file
+    CsvParse(Box<dyn Error + Send + Sync>),
+    /// The tree couldn't be updated with the entries of the new round
+    TreeUpdate(Box<dyn Error + Send + Sync>),
+    /// The params couldn't be read or are too small for the circuit
+    ParamsLoad(Box<dyn Error + Send + Sync>),
+    /// The setup artifacts couldn't be loaded or generated
+    Keygen(Box<dyn Error + Send + Sync>),
+    /// The proof couldn't be generated or doesn't match the setup artifacts of the snapshot
+    ProofGeneration(Box<dyn Error + Send + Sync>),
+    /// The generated proof couldn't be stored in the proof store of the round
+    ProofStore(Box<dyn Error + Send + Sync>),
+    /// The transaction to the Summa contract couldn't be sent or failed
+    ContractCall(Box<dyn Error + Send + Sync>),
+    /// The user index is beyond `max`, the index of the last leaf of the tree
+    InvalidUserIndex { index: usize, max: usize },
+    /// No entry of the tree has the username
+    UserNotFound(String),
+    /// The proof generation has been cancelled through its cancellation token before the proof was returned
+    Cancelled,
+    /// The root hash among the public inputs of the proof, `found`, isn't the root hash of the snapshot, `expected`, e.g. the proof was generated for another round
+    RootMismatch { expected: U256, found: U256 },
+    /// The `k` given for the params, `expected`, isn't the `k` read from the header of the params file, `found`
+    ParamsKMismatch { expected: u32, found: u32 },
+    /// The number of proofs to generate concurrently is 0
+    InvalidConcurrency,
+    /// The witness of the inclusion proof violates the constraints of the circuit, as found by the preflight check before proving
+    ConstraintViolations(Vec<ConstraintViolation>),
+}
+
+impl std::fmt::Display for RoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoundError::CsvParse(e) => write!(f, "Failed to parse the csv file: {}", e),
+            RoundError::TreeUpdate(e) => write!(f, "Failed to update the tree: {}", e),
+            RoundError::ParamsLoad(e) => write!(f, "Failed to load the params: {}", e),
+            RoundError::Keygen(e) => write!(f, "Failed to load the setup artifacts: {}", e),
+            RoundError::ProofGeneration(e) => write!(f, "Failed to generate the proof: {}", e),
+            RoundError::ProofStore(e) => write!(f, "Failed to store the proof: {}", e),
+            RoundError::ContractCall(e) => write!(f, "Failed to call the Summa contract: {}", e),
+            RoundError::InvalidUserIndex { index, max } => write!(
+                f,
+                "The user index {} is out of range, the last user is at index {}",
+                index, max
+            ),
+            RoundError::UserNotFound(username) => {
+                write!(f, "The user {} is not in the tree", username)
+            }
+            RoundError::Cancelled => write!(f, "The proof generation has been cancelled"),
+            RoundError::RootMismatch { expected, found } => write!(
+                f,
+                "The proof is for the root {:#x} but the committed root is {:#x}",
+                found, expected
+            ),
+            RoundError::ParamsKMismatch { expected, found } => write!(
+                f,
+                "The params file has k = {} but k = {} was expected",
+                found, expected
+            ),
+            RoundError::InvalidConcurrency => {
+                write!(f, "At least one proof should be generated at a time")
+            }
+            RoundError::ConstraintViolations(violations) => {
+                write!(
+                    f,
+                    "The witness doesn't satisfy the constraints of the inclusion circuit: "
+                )?;
+                for (i, violation) in violations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", violation)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RoundError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RoundError::CsvParse(e)
+            | RoundError::TreeUpdate(e)
+            | RoundError::ParamsLoad(e)
+            | RoundError::Keygen(e)
+            | RoundError::ProofGeneration(e)
+            | RoundError::ProofStore(e)
+            | RoundError::ContractCall(e) => Some(e.as_ref()),
+            RoundError::InvalidUserIndex { .. }
+            | RoundError::UserNotFound(_)
+            | RoundError::Cancelled
+            | RoundError::RootMismatch { .. }
+            | RoundError::ParamsKMismatch { .. }
+            | RoundError::InvalidConcurrency
+            | RoundError::ConstraintViolations(_) => None,
+        }
+    }
+}
+
+/// Version of the proof metadata layout. It should be increased whenever the circuit or the layout of the public inputs changes.
+pub const PROOF_METADATA_VERSION: u32 = 1;
+
+/// Describes the configuration under which an inclusion proof was generated.
+///
+/// # Fields
+///
+/// * `version`: The version of the proof metadata layout, see `PROOF_METADATA_VERSION`
+/// * `generated_at`: The unix timestamp, in seconds, at which the proof was generated
+/// * `k`: The size of the circuit, where 2^k is the number of rows
+/// * `levels`: The number of levels of the merkle sum tree
+/// * `n_currencies`: The number of currencies of the merkle sum tree
+/// * `n_bytes`: The number of bytes in which the balances lie
+/// * `vk_digest`: The SHA-256 digest of the verifying key serialized in raw bytes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProofMetadata {
+    pub version: u32,
+    pub generated_at: u64,
+    pub k: u32,
+    pub levels: usize,
+    pub n_currencies: usize,
+    pub n_bytes: usize,
+    pub vk_digest: [u8; 32],
+}
+
+/// Returns the SHA-256 digest of the verifying key serialized in raw bytes
+pub fn vk_digest(vk: &VerifyingKey<G1Affine>) -> [u8; 32] {
+    Sha256::digest(vk.to_bytes(SerdeFormat::RawBytes)).into()
+}
+
+/// Version of the byte format of `MstInclusionProof`. It should be increased whenever the layout of the serialized proof changes,
+/// along with a new version-specific deserializer in `MstInclusionProof::from_bytes_versioned`.
+pub const PROOF_FORMAT_VERSION: u8 = 2;
+
+/// Inclusion proof of the entry of a user, as downloaded by the user.
+/// The calldata is flattened in the JSON serialization, so that the proof can be parsed as a `SolidityCalldata` by a verification tool.
+/// The index and the username of the user are recorded by `Snapshot::generate_proof_of_inclusion`, so that a client can cross-check them, but not by the previous formats nor by a `ProofBundle`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MstInclusionProof {
+    format_version: u8,
+    #[serde(flatten)]
+    calldata: SolidityCalldata,
+    metadata: ProofMetadata,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(skip)]
+    is_legacy: bool,
+}
+
+impl MstInclusionProof {
+    /// Serializes the proof with the current format, namely a leading byte equal to `PROOF_FORMAT_VERSION` followed by the version-specific payload
+    pub fn to_bytes_versioned(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bytes = vec![PROOF_FORMAT_VERSION];
+        bytes.extend(self.v2_to_bytes()?);
+        Ok(bytes)
+    }
+
+    /// Deserializes a proof serialized by `to_bytes_versioned`, dispatching to the deserializer of the version given by the leading byte.
+    /// A proof serialized with a previous version is flagged as legacy: it can still be inspected, but it may not verify against the current circuit.
+    pub fn from_bytes_versioned(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Self::from_bytes_with_current_version(bytes, PROOF_FORMAT_VERSION)
+    }
+
+    fn from_bytes_with_current_version(
+        bytes: &[u8],
+        current_version: u8,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (&version, payload) = bytes.split_first().ok_or("Empty proof bytes")?;
+        if version > current_version {
+            return Err(format!("Unsupported proof format version {}", version).into());
+        }
+
+        let mut proof = match version {
+            1 => Self::v1_from_bytes(payload)?,
+            2 => Self::v2_from_bytes(payload)?,
+            _ => return Err(format!("Unsupported proof format version {}", version).into()),
+        };
+        proof.format_version = version;
+        proof.is_legacy = version < current_version;
+
+        Ok(proof)
+    }
+
+    /// The v1 payload is the JSON serialization of the proof, in which the proof calldata lies under `proof_calldata`
+    fn v1_from_bytes(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let proof: MstInclusionProofV1 = serde_json::from_slice(payload)?;
+        Ok(MstInclusionProof {
+            format_version: 1,
+            calldata: SolidityCalldata {
+                proof: proof.proof_calldata,
+                public_inputs: proof.public_inputs,
+            },
+            metadata: proof.metadata,
+            user_index: None,
+            username: None,
+            is_legacy: false,
+        })
+    }
+
+    /// The v2 payload is the JSON serialization of the proof, in which the calldata is flattened as `proof` and `public_inputs`
+    fn v2_to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    fn v2_from_bytes(payload: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(payload)?)
+    }
+
+    pub fn get_format_version(&self) -> u8 {
+        self.format_version
+    }
+
+    /// Returns true if the proof has been deserialized from a previous format version, in which case it may not verify against the current circuit
+    pub fn is_legacy(&self) -> bool {
+        self.is_legacy
+    }
+
+    pub fn get_public_inputs(&self) -> &Vec<U256> {
+        &self.calldata.public_inputs
+    }
+
+    pub fn get_proof(&self) -> &Bytes {
+        &self.calldata.proof
+    }
+
+    /// Returns the calldata of the proof, to be passed to the `verifyProof` function of the Solidity verifier
+    pub fn get_calldata(&self) -> &SolidityCalldata {
+        &self.calldata
+    }
+
+    pub fn get_metadata(&self) -> &ProofMetadata {
+        &self.metadata
+    }
+
+    /// Returns the index of the user the proof was generated for, if recorded
+    pub fn get_user_index(&self) -> Option<usize> {
+        self.user_index
+    }
+
+    /// Returns the username of the user the proof was generated for, if recorded
+    pub fn get_username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    /// Returns true if the proof was generated with a proving key matching the given verifying key
+    pub fn verify_vk_matches(&self, vk: &VerifyingKey<G1Affine>) -> bool {
+        self.metadata.vk_digest == vk_digest(vk)
+    }
+
+    /// Returns the length in bytes of the proof calldata
+    pub fn calldata_bytes(&self) -> usize {
+        self.calldata.proof.len()
+    }
+
+    /// Returns the number of public inputs of the proof
+    pub fn public_input_count(&self) -> usize {
+        self.calldata.public_inputs.len()
+    }
+
+    /// Returns the public inputs of the proof with the root balances labelled by the names of the cryptocurrencies of `asset_config`.
+    /// Returns an error if the number of root balances doesn't match the number of cryptocurrencies of `asset_config`.
+    pub fn annotate_inputs<const N_CURRENCIES: usize>(
+        &self,
+        asset_config: &AssetConfig<N_CURRENCIES>,
+    ) -> Result<AnnotatedPublicInputs, Box<dyn Error>> {
+        let public_inputs = self.get_public_inputs();
+        if public_inputs.len() != 3 + N_CURRENCIES {
+            return Err(format!(
+                "Expected {} public inputs but found {}",
+                3 + N_CURRENCIES,
+                public_inputs.len()
+            )
+            .into());
+        }
+
+        Ok(AnnotatedPublicInputs {
+            leaf_hash: public_inputs[1],
+            root_hash: public_inputs[2],
+            root_balances: asset_config
+                .names()
+                .into_iter()
+                .map(String::from)
+                .zip(public_inputs[3..].iter().copied())
+                .collect(),
+        })
+    }
+
+    /// Returns the sizes of the proof, to be tracked by the operators of the proof service
+    pub fn summary(&self) -> ProofSummary {
+        ProofSummary {
+            calldata_bytes: self.calldata_bytes(),
+            public_input_count: self.public_input_count(),
+            // The public inputs are the version of their layout, the leaf hash, the root hash and one root balance per cryptocurrency
+            valid_public_input_layout: self.public_input_count() == 3 + self.metadata.n_currencies,
+        }
+    }
+
+    /// Serializes the proof as JSON with the following stable schema, meant to be fed into verification tools written in any language:
+    ///
+    /// ```json
+    /// {
+    ///   "format_version": <integer>,
+    ///   "proof": "0x<hex>",
+    ///   "public_inputs": ["<decimal>", ...],
+    ///   "metadata": { "version": <integer>, "generated_at": <integer>, "k": <integer>, "levels": <integer>, "n_currencies": <integer>, "n_bytes": <integer>, "vk_digest": [<byte>, ...] },
+    ///   "user_index": <integer>,
+    ///   "username": "<string>"
+    /// }
+    /// ```
+    /// where the public inputs are the version of their layout, the leaf hash, the root hash and the root balances, encoded as decimal strings, and `user_index` and `username` are only present if recorded.
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(&MstInclusionProofJson::from(
+            self,
+        ))?)
+    }
+
+    /// Deserializes a proof serialized by `to_json`, so that the proof is the same as the serialized one
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        let proof: MstInclusionProofJson = serde_json::from_str(json)?;
+        Ok(proof.into())
+    }
+}
+
+/// The layout of `MstInclusionProof` in the JSON schema of `MstInclusionProof::to_json`
+#[derive(Serialize, Deserialize)]
+struct MstInclusionProofJson {
+    format_version: u8,
+    proof: Bytes,
+    #[serde(with = "u256_decimal_vec")]
+    public_inputs: Vec<U256>,
+    metadata: ProofMetadata,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+}
+
+impl From<&MstInclusionProof> for MstInclusionProofJson {
+    fn from(proof: &MstInclusionProof) -> Self {
+        MstInclusionProofJson {
+            format_version: proof.format_version,
+            proof: proof.calldata.proof.clone(),
+            public_inputs: proof.calldata.public_inputs.clone(),
+            metadata: proof.metadata.clone(),
+            user_index: proof.user_index,
+            username: proof.username.clone(),
+        }
+    }
+}
+
+impl From<MstInclusionProofJson> for MstInclusionProof {
+    fn from(proof: MstInclusionProofJson) -> Self {
+        MstInclusionProof {
+            format_version: proof.format_version,
+            calldata: SolidityCalldata {
+                proof: proof.proof,
+                public_inputs: proof.public_inputs,
+            },
+            metadata: proof.metadata,
+            user_index: proof.user_index,
+            username: proof.username,
+            is_legacy: proof.format_version < PROOF_FORMAT_VERSION,
+        }
+    }
+}
+
+/// Serializes U256 values as decimal strings, as in the JSON schema of `MstInclusionProof::to_json`
+mod u256_decimal {
+    use ethers::types::U256;
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        U256::from_dec_str(&value).map_err(D::Error::custom)
+    }
+}
+
+/// Serializes vectors of U256 values as vectors of decimal strings, see `u256_decimal`
+mod u256_decimal_vec {
+    use ethers::types::U256;
+    use serde::{de::Error as _, ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(values: &[U256], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(values.len()))?;
+        for value in values {
+            seq.serialize_element(&value.to_string())?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<U256>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| U256::from_dec_str(value).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// Serializes an `MstInclusionProof` with the JSON schema of `MstInclusionProof::to_json`
+mod mst_inclusion_proof_json {
+    use super::{MstInclusionProof, MstInclusionProofJson};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        proof: &MstInclusionProof,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        MstInclusionProofJson::from(proof).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<MstInclusionProof, D::Error> {
+        Ok(MstInclusionProofJson::deserialize(deserializer)?.into())
+    }
+}
+
+/// The self-contained file handed to a user after a round, as written by `Round::export_proof_bundle`, from which the user can verify the inclusion of their entry with a verifier tool.
+///
+/// # Fields
+///
+/// * `timestamp`: The timestamp of the round, under which the commitment has been submitted to the Summa contract
+/// * `contract_address`: The address of the Summa contract holding the commitment
+/// * `leaf_hash`: The hash of the leaf of the user, namely the public input #1 of the proof, as a decimal string
+/// * `cryptocurrencies`: The cryptocurrencies labelling the root balances of the public inputs, in the same order
+/// * `proof`: The inclusion proof of the user, with the JSON schema of `MstInclusionProof::to_json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserProofBundle {
+    pub timestamp: u64,
+    pub contract_address: Address,
+    #[serde(with = "u256_decimal")]
+    pub leaf_hash: U256,
+    pub cryptocurrencies: Vec<summa_solvency::merkle_sum_tree::Cryptocurrency>,
+    #[serde(with = "mst_inclusion_proof_json")]
+    pub proof: MstInclusionProof,
+}
+
+impl UserProofBundle {
+    /// Reads a bundle written by `Round::export_proof_bundle`
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
+
+/// The layout of `MstInclusionProof` in the v1 format
+#[derive(Deserialize)]
+struct MstInclusionProofV1 {
     public_inputs: Vec<U256>,
     proof_calldata: Bytes,
+    metadata: ProofMetadata,
 }
 
-impl MstInclusionProof {
+/// Sizes of an inclusion proof, as returned by `MstInclusionProof::summary`.
+///
+/// # Fields
+///
+/// * `calldata_bytes`: The length in bytes of the proof calldata
+/// * `public_input_count`: The number of public inputs
+/// * `valid_public_input_layout`: Whether the number of public inputs is the expected `3 + N_CURRENCIES`, namely the version of the layout, the leaf hash, the root hash and the root balances
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofSummary {
+    pub calldata_bytes: usize,
+    pub public_input_count: usize,
+    pub valid_public_input_layout: bool,
+}
+
+impl std::fmt::Display for ProofSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "calldata: {} bytes, public inputs: {} ({} layout)",
+            self.calldata_bytes,
+            self.public_input_count,
+            if self.valid_public_input_layout {
+                "valid"
+            } else {
+                "invalid"
+            }
+        )
+    }
+}
+
+/// Compact collection of inclusion proofs generated with the same setup, meant to be distributed as a single file.
+///
+/// The format version and the metadata are stored once in a shared header, with only the generation time kept per proof,
+/// and the longest prefix shared by the calldata of all the proofs is stored once, each proof keeping the remaining bytes only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofBundle {
+    format_version: u8,
+    metadata: ProofMetadata,
+    shared_calldata_prefix: Bytes,
+    proofs: Vec<BundledProof>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundledProof {
+    public_inputs: Vec<U256>,
+    calldata_suffix: Bytes,
+    generated_at: u64,
+}
+
+impl ProofBundle {
+    /// Bundles the proofs, which must share the format version and the metadata, apart from the generation time.
+    /// Returns an error if there are no proofs or if a proof doesn't match the first one.
+    pub fn new(proofs: Vec<MstInclusionProof>) -> Result<ProofBundle, Box<dyn Error>> {
+        let first_proof = proofs
+            .first()
+            .ok_or("Cannot bundle an empty list of proofs")?;
+
+        for (index, proof) in proofs.iter().enumerate() {
+            let metadata = ProofMetadata {
+                generated_at: first_proof.metadata.generated_at,
+                ..proof.metadata.clone()
+            };
+            if proof.format_version != first_proof.format_version
+                || metadata != first_proof.metadata
+            {
+                return Err(format!(
+                    "Proof {} was not generated with the same setup as the first proof",
+                    index
+                )
+                .into());
+            }
+        }
+
+        let prefix_len = proofs
+            .iter()
+            .fold(first_proof.get_proof().len(), |len, proof| {
+                first_proof.get_proof()[..len]
+                    .iter()
+                    .zip(proof.get_proof().iter())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            });
+
+        Ok(ProofBundle {
+            format_version: first_proof.format_version,
+            metadata: first_proof.metadata.clone(),
+            shared_calldata_prefix: Bytes::from(first_proof.get_proof()[..prefix_len].to_vec()),
+            proofs: proofs
+                .iter()
+                .map(|proof| BundledProof {
+                    public_inputs: proof.get_public_inputs().clone(),
+                    calldata_suffix: Bytes::from(proof.get_proof()[prefix_len..].to_vec()),
+                    generated_at: proof.metadata.generated_at,
+                })
+                .collect(),
+        })
+    }
+
+    /// Returns the proof at `index`, rebuilt from the shared header, or `None` if the index is out of bounds
+    pub fn get(&self, index: usize) -> Option<MstInclusionProof> {
+        let bundled_proof = self.proofs.get(index)?;
+
+        let mut proof_calldata = self.shared_calldata_prefix.to_vec();
+        proof_calldata.extend_from_slice(&bundled_proof.calldata_suffix);
+
+        Some(MstInclusionProof {
+            format_version: self.format_version,
+            calldata: SolidityCalldata {
+                proof: Bytes::from(proof_calldata),
+                public_inputs: bundled_proof.public_inputs.clone(),
+            },
+            metadata: ProofMetadata {
+                generated_at: bundled_proof.generated_at,
+                ..self.metadata.clone()
+            },
+            user_index: None,
+            username: None,
+            is_legacy: false,
+        })
+    }
+
+    /// Returns an iterator over the proofs of the bundle, in the order they have been bundled
+    pub fn iter(&self) -> impl Iterator<Item = MstInclusionProof> + '_ {
+        (0..self.proofs.len()).filter_map(|index| self.get(index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
+    }
+}
+
+/// Proof that the asset sums of the CEX cover the root balances of the committed merkle sum tree, submitted alongside the commitment.
+/// The public inputs are the root hash and the asset sums.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolvencyProof {
+    #[serde(flatten)]
+    calldata: SolidityCalldata,
+}
+
+impl SolvencyProof {
+    pub fn get_public_inputs(&self) -> &Vec<U256> {
+        &self.calldata.public_inputs
+    }
+
+    pub fn get_proof(&self) -> &Bytes {
+        &self.calldata.proof
+    }
+
+    /// Returns the calldata of the proof, to be passed to the `verifyProof` function of the Solidity verifier
+    pub fn get_calldata(&self) -> &SolidityCalldata {
+        &self.calldata
+    }
+}
+
+/// Proof that the balance of a user in a cryptocurrency is above a threshold, without revealing the balance.
+/// The public inputs are the leaf hash of the user, the root hash and the thresholds, the one of `asset_index` being the proven threshold and the others zero.
+/// The calldata is verified by the Solidity verifier of `BalanceThresholdCircuit`, which can be generated as in the `gen_inclusion_verifier` example.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceRangeProof {
+    asset_index: usize,
+    #[serde(flatten)]
+    calldata: SolidityCalldata,
+}
+
+impl BalanceRangeProof {
+    pub fn get_asset_index(&self) -> usize {
+        self.asset_index
+    }
+
     pub fn get_public_inputs(&self) -> &Vec<U256> {
-        &self.public_inputs
+        &self.calldata.public_inputs
     }
 
     pub fn get_proof(&self) -> &Bytes {
-        &self.proof_calldata
+        &self.calldata.proof
+    }
+
+    /// Returns the calldata of the proof, to be passed to the `verifyProof` function of the Solidity verifier
+    pub fn get_calldata(&self) -> &SolidityCalldata {
+        &self.calldata
+    }
+}
+
+/// Proof that the balances of a user changed by `delta` between two snapshots, made of the inclusion proofs of the entry of the user in both snapshots.
+/// Each inclusion proof verifies independently against the commitment of its snapshot.
+///
+/// # Fields
+///
+/// * `user_index`: The index of the entry of the user, which is the same in both snapshots
+/// * `old_balances`: The balances of the user in the previous snapshot
+/// * `new_balances`: The balances of the user in the current snapshot
+/// * `delta`: The change of each balance, namely `new_balances[i] - old_balances[i]`
+/// * `old_inclusion_proof`: The inclusion proof of the entry of the user in the previous snapshot
+/// * `new_inclusion_proof`: The inclusion proof of the entry of the user in the current snapshot
+/// * `generated_at`: The unix timestamp, in seconds, at which both inclusion proofs were requested
+#[derive(Debug, Clone)]
+pub struct BalanceDeltaProof<const N_CURRENCIES: usize> {
+    pub user_index: usize,
+    pub old_balances: [BigUint; N_CURRENCIES],
+    pub new_balances: [BigUint; N_CURRENCIES],
+    pub delta: [BigInt; N_CURRENCIES],
+    pub old_inclusion_proof: MstInclusionProof,
+    pub new_inclusion_proof: MstInclusionProof,
+    pub generated_at: u64,
+}
+
+/// Version of the schema of the audit report of a snapshot. It should be increased whenever the layout of `AuditReport` changes.
+pub const AUDIT_SCHEMA_VERSION: u32 = 1;
+
+/// Self-contained description of the state of a snapshot, exported for auditors by `Snapshot::to_audit_json`.
+///
+/// JSON schema:
+/// ```json
+/// {
+///   "schema_version": 1,
+///   "depth": <integer>,
+///   "root": { "hash": "<hex>", "balances": ["<hex>", ...] },
+///   "cryptocurrencies": [{ "name": "<string>", "chain": "<string>" }, ...],
+///   "entries": [{ "hashed_username": "<decimal>", "balances": ["<decimal>", ...], "username": "<string>", "leaf_hash": "<hex>" }, ...]
+/// }
+/// ```
+/// where `<hex>` is a field element encoded as a 0x-prefixed, big-endian, 32-byte hex string and `<decimal>` is an unsigned integer encoded as a decimal string.
+/// The root balances are listed in the same order as the cryptocurrencies, and the entries include the zero entries padding the tree up to 2^depth leaves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuditReport<const N_CURRENCIES: usize> {
+    schema_version: u32,
+    depth: usize,
+    root: Node<N_CURRENCIES>,
+    cryptocurrencies: Vec<summa_solvency::merkle_sum_tree::Cryptocurrency>,
+    entries: Vec<AuditEntry<N_CURRENCIES>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuditEntry<const N_CURRENCIES: usize> {
+    #[serde(flatten)]
+    entry: Entry<N_CURRENCIES>,
+    #[serde(with = "serde_helpers::fp_hex")]
+    leaf_hash: Fp,
+}
+
+/// The commitment of a round to be submitted to the Summa contract, as returned by `Round::build_commitment`, so that it can be reviewed before `dispatch_commitment` sends it.
+///
+/// # Fields
+///
+/// * `mst_root`: The hash of the root of the tree
+/// * `root_sums`: The balances of the root of the tree, in the same order as the cryptocurrencies
+/// * `cryptocurrencies`: The cryptocurrencies of the tree
+/// * `timestamp`: The timestamp of the round
+/// * `calldata`: The ABI encoded call to `submitCommitment` with the above arguments, namely the transaction data sent to the Summa contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitmentPayload {
+    pub mst_root: U256,
+    pub root_sums: Vec<U256>,
+    pub cryptocurrencies: Vec<summa_solvency::merkle_sum_tree::Cryptocurrency>,
+    pub timestamp: U256,
+    pub calldata: Bytes,
+}
+
+impl CommitmentPayload {
+    /// Returns the cryptocurrencies of the payload as the tuples expected by the Summa contract
+    fn contract_cryptocurrencies(&self) -> Vec<Cryptocurrency> {
+        self.cryptocurrencies
+            .iter()
+            .map(|cryptocurrency| Cryptocurrency {
+                name: cryptocurrency.name.clone(),
+                chain: cryptocurrency.chain.clone(),
+            })
+            .collect()
     }
 }
 
+/// Version of the layout of the state written by `Round::save`. It should be increased whenever the layout of `RoundState` or of the files it refers to changes.
+pub const ROUND_STATE_VERSION: u32 = 2;
+
+/// The name of the file holding the `RoundState` in the directory of a saved round
+const ROUND_STATE_FILE: &str = "round.json";
+/// The name of the file holding the audit report of the tree, as exported by `Snapshot::to_audit_json`, in the directory of a saved round
+const ROUND_TREE_FILE: &str = "tree.json";
+/// The name of the file holding the setup artifacts of the inclusion circuit, as written by `write_setup_artifacts_encrypted`, in the directory of a saved round
+const ROUND_SETUP_ARTIFACTS_FILE: &str = "inclusion.setup";
+
+/// The state of a round saved by `Round::save`, referring to the files of its tree and of its setup artifacts in the same directory.
+/// The root of the tree is recorded, so that `Round::load` can check that the restored tree is the one of the saved round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RoundState<const N_CURRENCIES: usize> {
+    version: u32,
+    timestamp: u64,
+    root: Node<N_CURRENCIES>,
+    // The assets of the asset config, which can't be serialized as an array of `N_CURRENCIES` assets, none for a round saved before the config was always set
+    assets: Option<Vec<NamedAsset>>,
+    dynamic_levels: Option<usize>,
+    preflight_check: bool,
+    k: u32,
+    tree_file: String,
+    setup_artifacts_file: String,
+}
+
 pub struct Snapshot<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> {
     pub mst: Box<dyn Tree<N_CURRENCIES>>,
-    trusted_setup: SetupArtifacts,
+    // Shared with the snapshots built from this one by `from_previous` or `new_with_setup_of`, e.g. by the rounds of a `RoundRegistry`
+    trusted_setup: Arc<SetupArtifacts>,
+    // The number of levels selected at runtime by `new_dynamic`, in which case `LEVELS` is ignored
+    dynamic_levels: Option<usize>,
+    // The cryptocurrencies of the tree, labelling the balances of the commitment, as set by `with_asset_config` or read from the tree by default
+    asset_config: AssetConfig<N_CURRENCIES>,
+    // The asset sums read from the chain and the block they were read at, if built by `new_with_onchain_assets`
+    onchain_assets: Option<OnchainAssets<N_CURRENCIES>>,
+    // Whether the witness of each inclusion proof is checked with the MockProver before proving, set by `with_preflight_check`
+    preflight_check: bool,
+    // The index of the entry of each username, built from the tree when the snapshot is created
+    user_indexes: HashMap<String, usize>,
+    // The inclusion proofs generated by `generate_proof_of_inclusion`, along with the number of lookups served or not by the cache
+    proof_cache: Mutex<ProofCache>,
+    // Notified whenever the generation of a proof ends, so that the requests waiting for the same proof check the cache again
+    proof_generated: Condvar,
+}
+
+/// The number of inclusion proofs cached by a snapshot unless set otherwise by `Snapshot::with_proof_cache_capacity`
+pub const DEFAULT_INCLUSION_PROOF_CACHE_CAPACITY: usize = 1024;
+
+/// The interval at which a request waiting for the proof of the same user to be generated checks whether it is cancelled
+const PROOF_WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The least recently used inclusion proofs of a snapshot, keyed by the index of their user, along with the users whose proof is being generated.
+/// A capacity of 0 disables the cache.
+struct ProofCache {
+    proofs: Option<LruCache<usize, MstInclusionProof>>,
+    in_flight: HashSet<usize>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ProofCache {
+    fn new(capacity: usize) -> Self {
+        ProofCache {
+            proofs: NonZeroUsize::new(capacity).map(LruCache::new),
+            in_flight: HashSet::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+impl Default for ProofCache {
+    fn default() -> Self {
+        ProofCache::new(DEFAULT_INCLUSION_PROOF_CACHE_CAPACITY)
+    }
+}
+
+/// Marks the proof of a user as being generated, until it is dropped, so that concurrent requests for the same user wait for it rather than running the prover again.
+/// The mark is removed even if the prover panics, so that the waiting requests don't wait forever.
+struct InFlightProof<'a> {
+    cache: &'a Mutex<ProofCache>,
+    generated: &'a Condvar,
+    user_index: usize,
+}
+
+impl Drop for InFlightProof<'_> {
+    fn drop(&mut self) {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.in_flight.remove(&self.user_index);
+        self.generated.notify_all();
+    }
+}
+
+/// Usage of the cache of the inclusion proofs of a snapshot, as returned by `Snapshot::proof_cache_stats`.
+///
+/// # Fields
+///
+/// * `cached_proofs`: The number of proofs in the cache
+/// * `hits`: The number of proofs returned from the cache
+/// * `misses`: The number of proofs that weren't in the cache, namely the number of times the prover was run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofCacheStats {
+    pub cached_proofs: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Maps the username of each entry of `mst` to its index, skipping the zero entries padding the tree.
+/// If several entries share a username, the first one is kept.
+fn index_users<const N_CURRENCIES: usize>(mst: &dyn Tree<N_CURRENCIES>) -> HashMap<String, usize> {
+    let padding = Entry::<N_CURRENCIES>::zero_entry();
+    let mut user_indexes = HashMap::new();
+    for index in 0..1usize << *mst.depth() {
+        let entry = mst.get_entry(index);
+        if *entry != padding {
+            user_indexes
+                .entry(entry.username().to_string())
+                .or_insert(index);
+        }
+    }
+    user_indexes
+}
+
+/// Wraps a failure of the signer into a `RoundError::ContractCall`.
+/// The `BackendError`s are kept as they are, so that e.g. a rejected commitment can be told apart.
+fn contract_call_error(error: Box<dyn Error>) -> RoundError {
+    RoundError::ContractCall(match error.downcast::<BackendError>() {
+        Ok(error) => error,
+        Err(error) => error.to_string().into(),
+    })
+}
+
+/// Wraps a circuit so that the prover checks `cancellation_token` right before and right after synthesizing the witness of the circuit,
+/// failing the synthesis once the token is cancelled. The wrapped circuit has the same constraint system, so that it is proven with the proving key of the circuit.
+struct CancellableCircuit<C> {
+    circuit: C,
+    cancellation_token: CancellationToken,
+}
+
+impl<C: Circuit<Fp>> Circuit<Fp> for CancellableCircuit<C> {
+    type Config = C::Config;
+    type FloorPlanner = C::FloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        CancellableCircuit {
+            circuit: self.circuit.without_witnesses(),
+            cancellation_token: self.cancellation_token.clone(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        C::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        layouter: impl Layouter<Fp>,
+    ) -> Result<(), PlonkError> {
+        if self.cancellation_token.is_cancelled() {
+            return Err(PlonkError::Synthesis);
+        }
+        self.circuit.synthesize(config, layouter)?;
+        if self.cancellation_token.is_cancelled() {
+            return Err(PlonkError::Synthesis);
+        }
+        Ok(())
+    }
+}
+
+impl<C: WithInstances> WithInstances for CancellableCircuit<C> {
+    fn num_instances(&self) -> usize {
+        self.circuit.num_instances()
+    }
+
+    fn instances(&self) -> Vec<Vec<Fp>> {
+        self.circuit.instances()
+    }
+}
+
+/// Generates the calldata of the proof of `circuit` with `trusted_setup`, returning `RoundError::Cancelled` if the proof fails because `cancellation_token` has been cancelled, see `CancellableCircuit`
+fn prove_cancellable<C: Circuit<Fp> + WithInstances>(
+    trusted_setup: &SetupArtifacts,
+    circuit: C,
+    cancellation_token: &CancellationToken,
+) -> Result<SolidityCalldata, RoundError> {
+    let circuit = CancellableCircuit {
+        circuit,
+        cancellation_token: cancellation_token.clone(),
+    };
+
+    gen_proof_solidity_calldata(&trusted_setup.0, &trusted_setup.1, circuit).map_err(|e| {
+        if cancellation_token.is_cancelled() {
+            RoundError::Cancelled
+        } else {
+            RoundError::ProofGeneration(e.into())
+        }
+    })
 }
 
 pub struct Round<'a, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> {
     timestamp: u64,
-    snapshot: Snapshot<LEVELS, N_CURRENCIES, N_BYTES>,
+    // Shared with the blocking threads generating the proofs requested by `get_proof_of_inclusion_async`
+    snapshot: Arc<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>>,
     signer: &'a SummaSigner,
+    // The store in which the generated inclusion proofs are cached, if any
+    proof_store: Option<Box<dyn ProofStore>>,
+    // Bounds the number of proofs generated at once by `get_proof_of_inclusion_async`
+    proof_permits: Arc<Semaphore>,
+    // How the commitment transaction is sent again by `dispatch_commitment` after a transient failure
+    retry_config: RetryConfig,
+    // The fees and the gas limit of the commitment transaction sent by `dispatch_commitment`
+    fee_config: FeeConfig,
+    // How long `dispatch_commitment` waits for the event of the submitted commitment, if it waits for it at all
+    commitment_confirmation: Option<Duration>,
+    // The last inclusion proof generated by `health_check`, or the reason it couldn't be generated, along with when it was generated
+    proof_check: tokio::sync::Mutex<Option<(Instant, Result<MstInclusionProof, String>)>>,
+    // How long the result of the proof generated by `health_check` is reused before a new proof is generated
+    proof_check_interval: Duration,
 }
 
+/// How long `Round::health_check` reuses the inclusion proof it generated unless set otherwise by `Round::with_proof_check_interval`
+pub const DEFAULT_PROOF_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
 impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
     Round<'_, LEVELS, N_CURRENCIES, N_BYTES>
 where
@@ -60,66 +1034,677 @@ where
         mst: Box<dyn Tree<N_CURRENCIES>>,
         params_path: &str,
         timestamp: u64,
-    ) -> Result<Round<'a, LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    ) -> Result<Round<'a, LEVELS, N_CURRENCIES, N_BYTES>, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        Self::new_with_proof_store(signer, mst, params_path, timestamp, None)
+    }
+
+    /// Creates a round as `new` does, caching the inclusion proofs generated by `get_proof_of_inclusion` in `proof_store`, if any.
+    /// A proof already stored for a user and the timestamp of the round is returned without running the prover again.
+    pub fn new_with_proof_store<'a>(
+        signer: &'a SummaSigner,
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        params_path: &str,
+        timestamp: u64,
+        proof_store: Option<Box<dyn ProofStore>>,
+    ) -> Result<Round<'a, LEVELS, N_CURRENCIES, N_BYTES>, RoundError>
     where
         [(); N_CURRENCIES + 2]: Sized,
     {
         Ok(Round {
             timestamp,
-            snapshot: Snapshot::<LEVELS, N_CURRENCIES, N_BYTES>::new(mst, params_path).unwrap(),
+            snapshot: Arc::new(Snapshot::<LEVELS, N_CURRENCIES, N_BYTES>::new(
+                mst,
+                params_path,
+            )?),
             signer: &signer,
+            proof_store,
+            proof_permits: Arc::new(Semaphore::new(
+                thread::available_parallelism().map_or(1, |threads| threads.get()),
+            )),
+            retry_config: RetryConfig::default(),
+            fee_config: FeeConfig::default(),
+            commitment_confirmation: None,
+            proof_check: tokio::sync::Mutex::new(None),
+            proof_check_interval: DEFAULT_PROOF_CHECK_INTERVAL,
         })
     }
 
-    pub fn get_timestamp(&self) -> u64 {
-        self.timestamp
+    /// Creates a round of `mst` at `timestamp` as `new` does, sharing the setup artifacts of `other`, see `Snapshot::new_with_setup_of`
+    pub fn new_with_setup_of<'a>(
+        signer: &'a SummaSigner,
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        timestamp: u64,
+        other: &Round<'_, LEVELS, N_CURRENCIES, N_BYTES>,
+    ) -> Result<Round<'a, LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn Error>> {
+        Ok(Round {
+            timestamp,
+            snapshot: Arc::new(Snapshot::new_with_setup_of(mst, &other.snapshot)?),
+            signer,
+            proof_store: None,
+            proof_permits: Arc::new(Semaphore::new(
+                thread::available_parallelism().map_or(1, |threads| threads.get()),
+            )),
+            retry_config: RetryConfig::default(),
+            fee_config: FeeConfig::default(),
+            commitment_confirmation: None,
+            proof_check: tokio::sync::Mutex::new(None),
+            proof_check_interval: DEFAULT_PROOF_CHECK_INTERVAL,
+        })
     }
 
-    pub async fn dispatch_commitment(&mut self) -> Result<(), Box<dyn Error>> {
-        let root_str = format!("{:?}", self.snapshot.mst.root().hash);
-        let mst_root = U256::from_str_radix(&root_str, 16).unwrap();
-
-        let mut root_sums = Vec::<U256>::new();
-
-        for balance in self.snapshot.mst.root().balances.iter() {
-            let fp_str = format!("{:?}", balance);
-            root_sums.push(U256::from_str_radix(&fp_str, 16).unwrap());
-        }
+    /// Creates the round following `previous` at `timestamp`, whose snapshot is built by `Snapshot::from_previous` with `updated_entries`,
+    /// e.g. after a few balances changed, without parsing the csv file nor generating the setup artifacts again.
+    /// The round keeps the signer, the retry policy and the fees of `previous`, and shares its bound on the number of proofs generated at once.
+    /// The proof store of `previous` isn't shared, the proofs of the new round being generated again.
+    pub fn new_from_previous<'a>(
+        previous: &Round<'a, LEVELS, N_CURRENCIES, N_BYTES>,
+        updated_entries: &[(usize, Entry<N_CURRENCIES>)],
+        timestamp: u64,
+    ) -> Result<Round<'a, LEVELS, N_CURRENCIES, N_BYTES>, RoundError> {
+        Ok(Round {
+            timestamp,
+            snapshot: Arc::new(Snapshot::from_previous(
+                &previous.snapshot,
+                updated_entries,
+            )?),
+            signer: previous.signer,
+            proof_store: None,
+            proof_permits: Arc::clone(&previous.proof_permits),
+            retry_config: previous.retry_config.clone(),
+            fee_config: previous.fee_config.clone(),
+            commitment_confirmation: previous.commitment_confirmation,
+            proof_check: tokio::sync::Mutex::new(None),
+            proof_check_interval: previous.proof_check_interval,
+        })
+    }
+
+    /// Saves the state of the round to `dir`, which is created if needed, so that the round can be restored by `load` after a restart of the backend,
+    /// e.g. to keep serving the proofs of a commitment already submitted to the Summa contract. The following files are written:
+    /// * the audit report of the tree, see `Snapshot::to_audit_json`
+    /// * the setup artifacts of the inclusion circuit, params included, so that the keys aren't generated again. They are encrypted with `passphrase`
+    ///   as done by `write_setup_artifacts_encrypted`, so that the proving key can't be used by whoever gets hold of the directory.
+    /// * the state of the round, namely its timestamp, the root of its tree, its asset config and the files above, written last so that a partially saved round can't be loaded
+    ///
+    /// The proof store, the retry policy and the fees of the round aren't saved.
+    pub fn save(&self, dir: &Path, passphrase: &str) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(dir)?;
+
+        let snapshot = &self.snapshot;
+        std::fs::write(dir.join(ROUND_TREE_FILE), snapshot.to_audit_json()?)?;
+
+        let (params, pk, vk) = snapshot.trusted_setup.as_ref();
+        let setup_artifacts_path = dir.join(ROUND_SETUP_ARTIFACTS_FILE);
+        match snapshot.dynamic_levels {
+            None => {
+                write_setup_artifacts_encrypted::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+                    &setup_artifacts_path,
+                    params.k(),
+                    params,
+                    pk,
+                    vk,
+                    passphrase,
+                )?
+            }
+            Some(_) => {
+                write_setup_artifacts_encrypted::<DynamicMstInclusionCircuit<N_CURRENCIES, N_BYTES>>(
+                    &setup_artifacts_path,
+                    params.k(),
+                    params,
+                    pk,
+                    vk,
+                    passphrase,
+                )?
+            }
+        }
+
+        let state = RoundState::<N_CURRENCIES> {
+            version: ROUND_STATE_VERSION,
+            timestamp: self.timestamp,
+            root: snapshot.mst.root().clone(),
+            assets: Some(snapshot.asset_config.assets().to_vec()),
+            dynamic_levels: snapshot.dynamic_levels,
+            preflight_check: snapshot.preflight_check,
+            k: params.k(),
+            tree_file: ROUND_TREE_FILE.to_string(),
+            setup_artifacts_file: ROUND_SETUP_ARTIFACTS_FILE.to_string(),
+        };
+        std::fs::write(
+            dir.join(ROUND_STATE_FILE),
+            serde_json::to_string_pretty(&state)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Restores the round saved by `save` in `dir`, sending its transactions with `signer`, so that it serves the same proofs as the saved round.
+    /// The tree is rebuilt from its audit report and the setup artifacts are decrypted with `passphrase` from their file, without generating the keys again.
+    ///
+    /// Returns a `BackendError::InvalidSnapshot` if the state has been saved with another layout,
+    /// or if the root of the restored tree doesn't match the root recorded when the round was saved,
+    /// and a `DecryptionFailed` error if `passphrase` isn't the one the round was saved with.
+    pub fn load<'a>(
+        dir: &Path,
+        signer: &'a SummaSigner,
+        passphrase: &str,
+    ) -> Result<Round<'a, LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        let state: RoundState<N_CURRENCIES> =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(ROUND_STATE_FILE))?)?;
+
+        if state.version != ROUND_STATE_VERSION {
+            return Err(BackendError::InvalidSnapshot(format!(
+                "Unsupported round state version {}",
+                state.version
+            ))
+            .into());
+        }
+
+        let mst = Snapshot::<LEVELS, N_CURRENCIES, N_BYTES>::tree_from_audit_json(
+            &std::fs::read_to_string(dir.join(&state.tree_file))?,
+        )?;
+        if *mst.root() != state.root {
+            return Err(BackendError::InvalidSnapshot(
+                "The root of the restored tree doesn't match the root of the saved round"
+                    .to_string(),
+            )
+            .into());
+        }
+
+        let setup_artifacts_path = dir.join(&state.setup_artifacts_file);
+        let trusted_setup = match state.dynamic_levels {
+            None => read_setup_artifacts_encrypted::<
+                MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>,
+            >(&setup_artifacts_path, state.k, passphrase)?,
+            Some(_) => read_setup_artifacts_encrypted::<
+                DynamicMstInclusionCircuit<N_CURRENCIES, N_BYTES>,
+            >(&setup_artifacts_path, state.k, passphrase)?,
+        };
+
+        let user_indexes = index_users(&mst);
+        let asset_config = AssetConfig::from_tree(&mst);
+        let mut snapshot = Snapshot {
+            mst: Box::new(mst),
+            trusted_setup: Arc::new(trusted_setup),
+            dynamic_levels: state.dynamic_levels,
+            asset_config,
+            onchain_assets: None,
+            preflight_check: state.preflight_check,
+            user_indexes,
+            proof_cache: Mutex::new(ProofCache::default()),
+            proof_generated: Condvar::new(),
+        };
+        if let Some(assets) = state.assets {
+            let assets: [NamedAsset; N_CURRENCIES] = assets.try_into().map_err(|_| {
+                BackendError::InvalidSnapshot(format!(
+                    "The saved asset config doesn't have {} assets",
+                    N_CURRENCIES
+                ))
+            })?;
+            snapshot = snapshot.with_asset_config(AssetConfig::new(assets))?;
+        }
+
+        Ok(Round {
+            timestamp: state.timestamp,
+            snapshot: Arc::new(snapshot),
+            signer,
+            proof_store: None,
+            proof_permits: Arc::new(Semaphore::new(
+                thread::available_parallelism().map_or(1, |threads| threads.get()),
+            )),
+            retry_config: RetryConfig::default(),
+            fee_config: FeeConfig::default(),
+            commitment_confirmation: None,
+            proof_check: tokio::sync::Mutex::new(None),
+            proof_check_interval: DEFAULT_PROOF_CHECK_INTERVAL,
+        })
+    }
+
+    /// Sets how `dispatch_commitment` retries the commitment transaction after a transient failure, as `RetryConfig::default` does by default
+    pub fn with_retry_config(mut self, retry_config: RetryConfig) -> Self {
+        self.retry_config = retry_config;
+        self
+    }
+
+    /// Sets how long `health_check` reuses the inclusion proof it generated, `DEFAULT_PROOF_CHECK_INTERVAL` by default.
+    /// A zero interval generates a proof on every check.
+    pub fn with_proof_check_interval(mut self, interval: Duration) -> Self {
+        self.proof_check_interval = interval;
+        self
+    }
+
+    /// Sets the fees and the gas limit of the commitment transaction sent by `dispatch_commitment`, which are filled by the node by default
+    pub fn with_fee_config(mut self, fee_config: FeeConfig) -> Self {
+        self.fee_config = fee_config;
+        self
+    }
+
+    /// Makes `dispatch_commitment` wait for the event emitted by the Summa contract for the submitted commitment, for at most `timeout`, rather than returning once the transaction is mined
+    pub fn with_commitment_confirmation(mut self, timeout: Duration) -> Self {
+        self.commitment_confirmation = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of proofs generated at once by `get_proof_of_inclusion_async`, as many as the available CPUs by default.
+    /// Each prover allocates its own buffers, so that the limit bounds the memory used by the requests served at once.
+    pub fn with_max_concurrent_proofs(mut self, max_concurrent_proofs: usize) -> Self {
+        assert!(
+            max_concurrent_proofs > 0,
+            "at least one proof should be generated at once"
+        );
+        self.proof_permits = Arc::new(Semaphore::new(max_concurrent_proofs));
+        self
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Returns the root of the tree of the round, whose hash and balances are submitted by `dispatch_commitment`
+    pub fn get_root(&self) -> &Node<N_CURRENCIES> {
+        self.snapshot.mst.root()
+    }
+
+    /// Sets the cryptocurrencies of the round, see `Snapshot::with_asset_config`
+    pub fn with_asset_config(
+        mut self,
+        asset_config: AssetConfig<N_CURRENCIES>,
+    ) -> Result<Self, Box<dyn Error>> {
+        // The snapshot is only shared while a proof is being generated, which can't happen as the round is owned here
+        let snapshot = Arc::try_unwrap(self.snapshot).map_err(|_| {
+            BackendError::InvalidSnapshot(
+                "The snapshot is shared with a proof being generated".to_string(),
+            )
+        })?;
+        self.snapshot = Arc::new(snapshot.with_asset_config(asset_config)?);
+        Ok(self)
+    }
+
+    pub fn get_asset_config(&self) -> &AssetConfig<N_CURRENCIES> {
+        self.snapshot.get_asset_config()
+    }
+
+    /// Returns the number of the block at which the asset sums of the round have been read from the chain, if its snapshot has been built by `Snapshot::new_with_onchain_assets`.
+    /// The commitment submitted to the Summa contract doesn't carry it, so that it is kept by the round along with the commitment.
+    pub fn get_onchain_block_number(&self) -> Option<u64> {
+        self.snapshot
+            .get_onchain_assets()
+            .map(|onchain_assets| onchain_assets.block_number)
+    }
+
+    /// Returns the commitment that `dispatch_commitment` would submit to the Summa contract, see `Snapshot::build_commitment`,
+    /// e.g. to review it before sending the transaction
+    pub fn build_commitment(&self) -> CommitmentPayload {
+        self.snapshot.build_commitment(self.get_timestamp())
+    }
+
+    /// Verifies `proof` natively against the setup artifacts and the root of the round, see `Snapshot::verify_proof_of_inclusion`
+    pub fn verify_proof_of_inclusion(&self, proof: &MstInclusionProof) -> Result<bool, RoundError> {
+        self.snapshot.verify_proof_of_inclusion(proof)
+    }
+
+    /// Returns the gas estimated by the node for the commitment transaction that `dispatch_commitment` would send, e.g. to check its cost before broadcasting it
+    pub async fn estimate_commitment_gas(&self) -> Result<U256, RoundError> {
+        let payload = self.build_commitment();
 
         self.signer
-            .submit_commitment(
-                mst_root,
-                root_sums,
-                self.snapshot
-                    .mst
-                    .cryptocurrencies()
-                    .iter()
-                    .map(|cryptocurrency| Cryptocurrency {
-                        name: cryptocurrency.name.clone(),
-                        chain: cryptocurrency.chain.clone(),
-                    })
-                    .collect::<Vec<Cryptocurrency>>()
-                    .as_slice()
-                    .try_into()
-                    .unwrap(),
-                U256::from(self.get_timestamp()),
+            .estimate_commitment_gas(
+                payload.mst_root,
+                payload.root_sums.clone(),
+                payload.contract_cryptocurrencies(),
+                payload.timestamp,
             )
-            .await?;
+            .await
+            .map_err(contract_call_error)
+    }
 
-        Ok(())
+    /// Submits the commitment of the round to the Summa contract, with the retry policy and the fees the round is configured with.
+    /// Returns a `RoundError::ContractCall` wrapping a `BackendError::GasLimitExceeded`, without sending any transaction,
+    /// if the estimated gas exceeds the gas limit set by `with_fee_config`.
+    ///
+    /// If the round has been configured by `with_commitment_confirmation`, the commitment event emitted by the contract is awaited once the transaction is mined,
+    /// and the number of the block the commitment has been mined in is returned. A `RoundError::ContractCall` wrapping a `BackendError::CommitmentNotObserved` is returned
+    /// if the event isn't observed in time, and a `RoundError::RootMismatch` if the observed commitment isn't the one of the round. Otherwise, `None` is returned.
+    pub async fn dispatch_commitment(&mut self) -> Result<Option<u64>, RoundError> {
+        let payload = self.build_commitment();
+
+        // The events are read from the latest block before the submission, so that the event of the commitment can't be missed
+        let watcher = match self.commitment_confirmation {
+            Some(timeout) => {
+                let from_block = self
+                    .signer
+                    .block_number()
+                    .await
+                    .map_err(contract_call_error)?;
+                Some((
+                    self.signer.commitment_watcher().from_block(from_block),
+                    timeout,
+                ))
+            }
+            None => None,
+        };
+
+        let result = self
+            .signer
+            .submit_commitment_with_retry(
+                payload.mst_root,
+                payload.root_sums.clone(),
+                payload.contract_cryptocurrencies(),
+                payload.timestamp,
+                &self.retry_config,
+                &self.fee_config,
+            )
+            .await
+            .map_err(contract_call_error);
+
+        #[cfg(feature = "metrics")]
+        crate::metrics_server::record_commitment_dispatch(
+            result.is_ok(),
+            self.snapshot.user_count(),
+        );
+
+        result?;
+
+        match watcher {
+            Some((watcher, timeout)) => {
+                let event = watcher
+                    .await_commitment(payload.timestamp, timeout)
+                    .await
+                    .map_err(|e| RoundError::ContractCall(Box::new(e)))?;
+                if event.mst_root != payload.mst_root {
+                    return Err(RoundError::RootMismatch {
+                        expected: payload.mst_root,
+                        found: event.mst_root,
+                    });
+                }
+                Ok(Some(event.block_number))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_proof_of_inclusion(&self, user_index: usize) -> Result<MstInclusionProof, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        if let Some(proof) = self
+            .proof_store
+            .as_ref()
+            .and_then(|store| store.get(user_index, self.timestamp))
+        {
+            return Ok(proof);
+        }
+
+        let proof = self.snapshot.generate_proof_of_inclusion(user_index)?;
+
+        if let Some(store) = self.proof_store.as_ref() {
+            store
+                .save(user_index, self.timestamp, proof.clone())
+                .map_err(|e| RoundError::ProofStore(e.to_string().into()))?;
+        }
+
+        Ok(proof)
+    }
+
+    /// Returns the proof of inclusion of the user at `user_index` as `Snapshot::generate_proof_of_inclusion` does, without blocking the async runtime.
+    /// The proof is generated on a blocking thread of the tokio runtime, once one of the permits bounding the proofs generated at once is available, see `with_max_concurrent_proofs`.
+    /// Unlike `get_proof_of_inclusion`, the proof store of the round isn't used, the proofs being cached by the snapshot instead.
+    pub async fn get_proof_of_inclusion_async(
+        &self,
+        user_index: usize,
+    ) -> Result<MstInclusionProof, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        self.get_proof_of_inclusion_with_cancellation(user_index, CancellationToken::new())
+            .await
+    }
+
+    /// Returns the proof of inclusion of the user at `user_index` as `get_proof_of_inclusion_async` does, returning `RoundError::Cancelled` once `cancellation_token` is cancelled,
+    /// e.g. by the HTTP handler when the client disconnects. A request waiting for a proof permit returns right away,
+    /// while the blocking thread generating the proof stops at its next checkpoint, see `Snapshot::generate_proof_of_inclusion_with_cancellation`.
+    pub async fn get_proof_of_inclusion_with_cancellation(
+        &self,
+        user_index: usize,
+        cancellation_token: CancellationToken,
+    ) -> Result<MstInclusionProof, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        let _permit = tokio::select! {
+            permit = self.proof_permits.acquire() => {
+                permit.map_err(|e| RoundError::ProofGeneration(e.to_string().into()))?
+            }
+            _ = cancellation_token.cancelled() => return Err(RoundError::Cancelled),
+        };
+
+        let snapshot = Arc::clone(&self.snapshot);
+        tokio::task::spawn_blocking(move || {
+            snapshot.generate_proof_of_inclusion_with_cancellation(user_index, &cancellation_token)
+        })
+        .await
+        .unwrap_or_else(|_| {
+            Err(RoundError::ProofGeneration(
+                "The proof generation panicked".into(),
+            ))
+        })
+    }
+
+    /// Writes the inclusion proof of the user at `user_index`, as returned by `get_proof_of_inclusion`, to `proof_<timestamp>_<user_index>.json` in `out_dir`,
+    /// along with the timestamp of the round, the address of the Summa contract and the cryptocurrencies of the tree, see `UserProofBundle`.
+    /// Returns the path of the written file.
+    pub fn export_proof_bundle(
+        &self,
+        user_index: usize,
+        out_dir: &Path,
+    ) -> Result<PathBuf, Box<dyn Error>>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        let proof = self.get_proof_of_inclusion(user_index)?;
+
+        let bundle = UserProofBundle {
+            timestamp: self.timestamp,
+            contract_address: self.signer.get_summa_address(),
+            leaf_hash: proof.get_public_inputs()[1],
+            cryptocurrencies: self.snapshot.asset_config.cryptocurrencies(),
+            proof,
+        };
+
+        std::fs::create_dir_all(out_dir)?;
+        let path = out_dir.join(format!("proof_{}_{}.json", self.timestamp, user_index));
+        std::fs::write(&path, serde_json::to_string_pretty(&bundle)?)?;
+        Ok(path)
+    }
+
+    /// Returns the proof of inclusion of the user named `username` as `get_proof_of_inclusion` does, the index of the user being resolved from the tree of the snapshot.
+    /// Returns a `UserNotFound` error if no entry of the tree has that username.
+    pub fn get_proof_of_inclusion_by_username(
+        &self,
+        username: &str,
+    ) -> Result<MstInclusionProof, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        let user_index = self.snapshot.user_index(username)?;
+        self.get_proof_of_inclusion(user_index)
     }
 
-    pub fn get_proof_of_inclusion(
+    /// Returns the proof of inclusion of the user at `user_index` as `get_proof_of_inclusion` does, once `limiter` has counted the request of that user.
+    /// The requests are counted under the username of the entry at `user_index`, so that a caller can't spread its requests over several keys.
+    /// Returns a `RateLimitExceeded` error, without generating the proof, if the user has made too many requests.
+    pub fn get_proof_of_inclusion_rate_limited(
         &self,
         user_index: usize,
-    ) -> Result<MstInclusionProof, &'static str>
+        limiter: &RateLimiter,
+    ) -> Result<MstInclusionProof, Box<dyn Error>>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        self.snapshot.check_user_index(user_index)?;
+        limiter.check(self.snapshot.mst.get_entry(user_index).username())?;
+        Ok(self.get_proof_of_inclusion(user_index)?)
+    }
+
+    /// Checks that the Ethereum node is connected, that the Summa contract is deployed, that the root of the tree is committed at the timestamp of the round
+    /// and that an inclusion proof can be generated and is accepted by the deployed contract once the root is committed.
+    /// The proof is generated on the blocking thread pool while the contract is queried, and is reused for `with_proof_check_interval`, so that frequent checks don't keep the prover busy.
+    /// The reported latency is the one of a single request to the node.
+    /// Returns the state of the subsystems if all of them are functional, otherwise the issues found.
+    pub async fn health_check(&self) -> Result<SystemStatus, Vec<HealthIssue>>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        let mst_root = field_element_to_solidity_calldata(self.snapshot.mst.root().hash);
+
+        // The latency of the node is measured from the request for the block number, not from the start of the check
+        let block_number = async {
+            let start = Instant::now();
+            self.signer
+                .block_number()
+                .await
+                .map(|_| start.elapsed().as_millis() as u64)
+        };
+
+        let (block_number, contract_deployed, has_commitment, proof_check) = tokio::join!(
+            block_number,
+            self.signer.is_contract_deployed(),
+            self.signer
+                .has_commitment(mst_root, U256::from(self.timestamp)),
+            self.checked_inclusion_proof(),
+        );
+
+        // The deployed verifier can only check the proof against a committed root
+        let proof_check = match (proof_check, &has_commitment) {
+            (Ok(proof), Ok(true)) => match self
+                .signer
+                .verify_inclusion_proof(
+                    proof.get_proof().clone(),
+                    proof.get_public_inputs().clone(),
+                    U256::from(self.timestamp),
+                )
+                .await
+            {
+                Ok(true) => Ok(()),
+                Ok(false) => {
+                    Err("The inclusion proof is rejected by the deployed verifier".to_string())
+                }
+                Err(e) => Err(format!(
+                    "The inclusion proof can't be verified by the deployed verifier: {}",
+                    e
+                )),
+            },
+            (proof_check, _) => proof_check.map(|_| ()),
+        };
+
+        let mut issues = Vec::new();
+
+        let latency_ms = match block_number {
+            Ok(latency_ms) => latency_ms,
+            Err(e) => {
+                issues.push(HealthIssue::new(
+                    HealthIssue::ETHEREUM,
+                    format!("The Ethereum node is unreachable: {}", e),
+                    Severity::Critical,
+                ));
+                0
+            }
+        };
+
+        match contract_deployed {
+            Ok(true) => {}
+            Ok(false) => issues.push(HealthIssue::new(
+                HealthIssue::CONTRACT,
+                format!(
+                    "No contract is deployed at {:#x}",
+                    self.signer.get_summa_address()
+                ),
+                Severity::Critical,
+            )),
+            Err(e) => issues.push(HealthIssue::new(
+                HealthIssue::CONTRACT,
+                format!("The Summa contract can't be reached: {}", e),
+                Severity::Critical,
+            )),
+        }
+
+        // A round whose commitment has not been dispatched yet is not broken, hence the warning
+        match has_commitment {
+            Ok(true) => {}
+            Ok(false) => issues.push(HealthIssue::new(
+                HealthIssue::COMMITMENT,
+                format!(
+                    "The root {:#x} is not committed at timestamp {}",
+                    mst_root, self.timestamp
+                ),
+                Severity::Warning,
+            )),
+            Err(e) => issues.push(HealthIssue::new(
+                HealthIssue::COMMITMENT,
+                format!(
+                    "The commitment can't be read from the Summa contract: {}",
+                    e
+                ),
+                Severity::Critical,
+            )),
+        }
+
+        if let Err(description) = proof_check {
+            issues.push(HealthIssue::new(
+                HealthIssue::PROVER,
+                description,
+                Severity::Critical,
+            ));
+        }
+
+        if !issues.is_empty() {
+            return Err(issues);
+        }
+
+        Ok(SystemStatus {
+            ethereum_connected: true,
+            contract_reachable: true,
+            mst_root_matches_commitment: true,
+            proof_generation_operational: true,
+            latency_ms,
+        })
+    }
+
+    /// Returns the inclusion proof of the first user generated by the last check, or the reason it couldn't be generated, generating it again once `proof_check_interval` has elapsed.
+    /// The lock is held while the proof is generated, so that concurrent checks wait for the same proof rather than generating their own.
+    async fn checked_inclusion_proof(&self) -> Result<MstInclusionProof, String>
     where
         [(); N_CURRENCIES + 2]: Sized,
     {
-        Ok(self
-            .snapshot
-            .generate_proof_of_inclusion(user_index)
-            .unwrap())
+        let mut proof_check = self.proof_check.lock().await;
+        if let Some((checked_at, result)) = proof_check.as_ref() {
+            if checked_at.elapsed() < self.proof_check_interval {
+                return result.clone();
+            }
+        }
+
+        // The proof is generated on the blocking thread pool, taking one of the permits of the proofs requested by `get_proof_of_inclusion_async`,
+        // so that it doesn't stall the runtime while the requests to the node are pending
+        let snapshot = Arc::clone(&self.snapshot);
+        let result = async {
+            let _permit = self
+                .proof_permits
+                .acquire()
+                .await
+                .map_err(|e| RoundError::ProofGeneration(Box::new(e)))?;
+            tokio::task::spawn_blocking(move || snapshot.check_proof_generation())
+                .await
+                .map_err(|e| RoundError::ProofGeneration(Box::new(e)))?
+        }
+        .await
+        .map_err(|e| e.to_string());
+
+        *proof_check = Some((Instant::now(), result.clone()));
+        result
     }
 }
 
@@ -129,47 +1714,2050 @@ where
     [usize; N_CURRENCIES + 1]: Sized,
     [usize; N_CURRENCIES + 2]: Sized,
 {
+    /// Builds a snapshot of the tree, generating the setup artifacts of the inclusion circuit from the params at `params_path`.
+    /// The `k` of the params is read from the header of the params file, so that the file can have any name.
+    /// Use `new_with_setup_artifacts` to load the setup artifacts saved by `save_setup_artifacts` instead.
     pub fn new(
         mst: Box<dyn Tree<N_CURRENCIES>>,
         params_path: &str,
-    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn std::error::Error>> {
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, RoundError> {
+        let k =
+            read_params_k(params_path).map_err(|e| RoundError::ParamsLoad(e.to_string().into()))?;
+        Self::new_with_checked_k(mst, params_path, k)
+    }
+
+    /// Builds a snapshot of the tree as `new` does, with params of the given `k`.
+    /// Returns a `ParamsKMismatch` error if `k` isn't the `k` read from the header of the params file, e.g. when the params file of a deployment has been replaced.
+    pub fn new_with_k(
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        params_path: &str,
+        k: u32,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, RoundError> {
+        let params_k =
+            read_params_k(params_path).map_err(|e| RoundError::ParamsLoad(e.to_string().into()))?;
+        if params_k != k {
+            return Err(RoundError::ParamsKMismatch {
+                expected: k,
+                found: params_k,
+            });
+        }
+        Self::new_with_checked_k(mst, params_path, k)
+    }
+
+    // Builds the snapshot once `k` has been read from the header of the params file and, if given explicitly, checked against it
+    fn new_with_checked_k(
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        params_path: &str,
+        k: u32,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, RoundError> {
         let mst_inclusion_circuit =
             MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
 
-        // get k from ptau file name
-        let parts: Vec<&str> = params_path.split("-").collect();
-        let last_part = parts.last().unwrap();
-        let k = last_part.parse::<u32>().unwrap();
-
         let mst_inclusion_setup_artifacts: SetupArtifacts =
-            generate_setup_artifacts(k, Some(params_path), mst_inclusion_circuit).unwrap();
+            generate_setup_artifacts(k, Some(params_path), mst_inclusion_circuit)
+                .map_err(|e| RoundError::Keygen(e.to_string().into()))?;
+
+        let user_indexes = index_users(mst.as_ref());
+        let asset_config = AssetConfig::from_tree(mst.as_ref());
 
         Ok(Snapshot {
             mst,
-            trusted_setup: mst_inclusion_setup_artifacts,
+            trusted_setup: Arc::new(mst_inclusion_setup_artifacts),
+            dynamic_levels: None,
+            asset_config,
+            onchain_assets: None,
+            preflight_check: false,
+            user_indexes,
+            proof_cache: Mutex::new(ProofCache::default()),
+            proof_generated: Condvar::new(),
         })
     }
 
-    pub fn generate_proof_of_inclusion(
-        &self,
-        user_index: usize,
-    ) -> Result<MstInclusionProof, &'static str>
-    where
-        [(); N_CURRENCIES + 2]: Sized,
-    {
-        let merkle_proof = self.mst.generate_proof(user_index).unwrap();
-        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+    /// Builds a snapshot of the tree as `new` does, loading the setup artifacts written by `save_setup_artifacts` at `setup_artifacts_path` rather than generating them.
+    /// Returns a `Keygen` error if the artifacts have been generated for another circuit or for params of another `k` than the params at `params_path`.
+    pub fn new_with_setup_artifacts(
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        params_path: &str,
+        setup_artifacts_path: &Path,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, RoundError> {
+        let k =
+            read_params_k(params_path).map_err(|e| RoundError::ParamsLoad(e.to_string().into()))?;
 
-        // Currently, default manner of generating a inclusion proof for solidity-verifier.
-        let calldata = gen_proof_solidity_calldata(
+        let mst_inclusion_setup_artifacts: SetupArtifacts = read_setup_artifacts::<
+            MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>,
+        >(setup_artifacts_path, k)
+        .map_err(|e| RoundError::Keygen(e.to_string().into()))?;
+
+        let user_indexes = index_users(mst.as_ref());
+        let asset_config = AssetConfig::from_tree(mst.as_ref());
+
+        Ok(Snapshot {
+            mst,
+            trusted_setup: Arc::new(mst_inclusion_setup_artifacts),
+            dynamic_levels: None,
+            asset_config,
+            onchain_assets: None,
+            preflight_check: false,
+            user_indexes,
+            proof_cache: Mutex::new(ProofCache::default()),
+            proof_generated: Condvar::new(),
+        })
+    }
+
+    /// Returns the index of the entry of the user named `username`, or a `UserNotFound` error if no entry of the tree has that username.
+    /// If several entries share the username, the first one is returned.
+    pub fn user_index(&self, username: &str) -> Result<usize, RoundError> {
+        self.user_indexes
+            .get(username)
+            .copied()
+            .ok_or_else(|| RoundError::UserNotFound(username.to_string()))
+    }
+
+    /// Builds a snapshot of the tree of the entries parsed from the csv file at `csv_path`, as `new` does
+    pub fn from_csv(
+        csv_path: &str,
+        params_path: &str,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, RoundError> {
+        let mst = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(csv_path)
+            .map_err(|e| RoundError::CsvParse(e.to_string().into()))?;
+        Self::new(Box::new(mst), params_path)
+    }
+
+    /// Builds a snapshot of the tree as `new` does, along with the asset sums of the exchange read from the chain rather than from a file:
+    /// the native balances or the ERC20 balances of the custody addresses of each asset of `asset_definitions`, in the same order as the cryptocurrencies of the tree,
+    /// are read through `provider` at `block_number`, or at the latest block if `None`, see `fetch_onchain_assets`.
+    /// The block number is recorded along with the sums, see `get_onchain_assets`, as the Summa contract doesn't store it with the commitment.
+    ///
+    /// Returns an `OnchainAssetError` if the RPC node can't be reached, if the contract of an asset is not an ERC20 token or if an asset sum doesn't fit in a field element.
+    pub async fn new_with_onchain_assets<M: Middleware>(
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        params_path: &str,
+        asset_definitions: &[AssetDefinition; N_CURRENCIES],
+        provider: &M,
+        block_number: Option<u64>,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn Error>> {
+        let onchain_assets =
+            fetch_onchain_assets(provider, asset_definitions, block_number).await?;
+
+        let mut snapshot = Self::new(mst, params_path)?;
+        snapshot.onchain_assets = Some(onchain_assets);
+        Ok(snapshot)
+    }
+
+    /// Returns the asset sums read from the chain and the number of the block they were read at, if the snapshot has been built by `new_with_onchain_assets`
+    pub fn get_onchain_assets(&self) -> Option<&OnchainAssets<N_CURRENCIES>> {
+        self.onchain_assets.as_ref()
+    }
+
+    /// Returns the commitment of the tree at `timestamp` to be submitted to the Summa contract: the root hash, the root balances and the cryptocurrencies of the tree,
+    /// along with the calldata of the `submitCommitment` call. It needs no signer nor chain, so that the commitment can be checked before being dispatched.
+    pub fn build_commitment(&self, timestamp: u64) -> CommitmentPayload {
+        let root = self.mst.root();
+        let mst_root = field_element_to_solidity_calldata(root.hash);
+        let root_sums = root
+            .balances
+            .iter()
+            .map(|balance| field_element_to_solidity_calldata(*balance))
+            .collect::<Vec<U256>>();
+        let cryptocurrencies = self.asset_config.cryptocurrencies();
+        let timestamp = U256::from(timestamp);
+
+        let mut payload = CommitmentPayload {
+            mst_root,
+            root_sums,
+            cryptocurrencies,
+            timestamp,
+            calldata: Bytes::default(),
+        };
+        payload.calldata = SubmitCommitmentCall {
+            mst_root,
+            root_balances: payload.root_sums.clone(),
+            cryptocurrencies: payload.contract_cryptocurrencies(),
+            timestamp,
+        }
+        .encode()
+        .into();
+        payload
+    }
+
+    /// Builds a snapshot of the tree sharing the setup artifacts of `other`, e.g. the snapshot of another round of the same circuit, so that the keys are neither loaded nor generated again.
+    /// Returns an `InvalidSnapshot` error if `other` has been built by `new_dynamic` for a number of levels that differs from the depth of the tree.
+    pub fn new_with_setup_of(
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        other: &Snapshot<LEVELS, N_CURRENCIES, N_BYTES>,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn Error>> {
+        if let Some(levels) = other.dynamic_levels {
+            if *mst.depth() != levels {
+                return Err(BackendError::InvalidSnapshot(format!(
+                    "The tree has {} levels but the setup artifacts are for {} levels",
+                    mst.depth(),
+                    levels
+                ))
+                .into());
+            }
+        }
+
+        let user_indexes = index_users(mst.as_ref());
+        let asset_config = AssetConfig::from_tree(mst.as_ref());
+
+        Ok(Snapshot {
+            mst,
+            trusted_setup: Arc::clone(&other.trusted_setup),
+            dynamic_levels: other.dynamic_levels,
+            asset_config,
+            onchain_assets: None,
+            preflight_check: false,
+            user_indexes,
+            proof_cache: Mutex::new(ProofCache::default()),
+            proof_generated: Condvar::new(),
+        })
+    }
+
+    /// Builds the snapshot of the next round from `previous`, whose entries at the given indexes are replaced by `updated_entries`.
+    /// The nodes of the previous tree are copied and only the ones lying on the path of an updated entry are hashed again, see `MerkleSumTree::rebuild_with`,
+    /// while the setup artifacts, the asset config and the preflight check of the previous snapshot are reused, so that neither the csv file is parsed nor the keys generated again.
+    /// The proof cache of the new snapshot starts empty.
+    ///
+    /// Returns an `InvalidUserIndex` error if an index lies beyond the last leaf of the tree.
+    pub fn from_previous(
+        previous: &Snapshot<LEVELS, N_CURRENCIES, N_BYTES>,
+        updated_entries: &[(usize, Entry<N_CURRENCIES>)],
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, RoundError> {
+        let previous_mst = previous.mst.as_ref();
+        let depth = *previous_mst.depth();
+        let leaves = 1 << depth;
+
+        let previous_entries = (0..leaves)
+            .map(|index| previous_mst.get_entry(index).clone())
+            .collect::<Vec<Entry<N_CURRENCIES>>>();
+        let mut entries = previous_entries.clone();
+        for (index, entry) in updated_entries {
+            if *index >= leaves {
+                return Err(RoundError::InvalidUserIndex {
+                    index: *index,
+                    max: leaves - 1,
+                });
+            }
+            entries[*index] = entry.clone();
+        }
+
+        // The middle nodes are copied level by level, from level 1 up to the root, as stored by the tree
+        let nodes = (1..=depth)
+            .flat_map(|level| (0..leaves >> level).map(move |index| (level, index)))
+            .map(|(level, index)| previous_mst.get_node(level, index))
+            .collect::<Result<Vec<Node<N_CURRENCIES>>, _>>()
+            .map_err(|e| RoundError::TreeUpdate(e.to_string().into()))?;
+
+        // The entries keep their position, so that the tree isn't sorted again by `rebuild_with`
+        let mut mst = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_params(
+            previous_mst.root().clone(),
+            nodes,
+            depth,
+            previous_entries,
+            previous_mst.cryptocurrencies().to_vec(),
+            false,
+        )
+        .map_err(|e| RoundError::TreeUpdate(e.to_string().into()))?;
+        mst.rebuild_with(entries)
+            .map_err(|e| RoundError::TreeUpdate(e.to_string().into()))?;
+
+        let user_indexes = index_users(&mst);
+
+        Ok(Snapshot {
+            mst: Box::new(mst),
+            trusted_setup: Arc::clone(&previous.trusted_setup),
+            dynamic_levels: previous.dynamic_levels,
+            asset_config: previous.asset_config.clone(),
+            onchain_assets: None,
+            preflight_check: previous.preflight_check,
+            user_indexes,
+            proof_cache: Mutex::new(ProofCache::default()),
+            proof_generated: Condvar::new(),
+        })
+    }
+
+    /// Sets the cryptocurrencies of the snapshot, so that the balances of the tree can be referred to by name.
+    /// The config replaces the one read from the labels of the tree, so that the commitment and the proof bundles carry its cryptocurrencies.
+    /// Returns an error if the cryptocurrencies labelling the balances of the tree are not named as in `asset_config`, on the same chains and in the same order.
+    pub fn with_asset_config(
+        mut self,
+        asset_config: AssetConfig<N_CURRENCIES>,
+    ) -> Result<Self, Box<dyn Error>> {
+        asset_config
+            .check_tree(self.mst.as_ref())
+            .map_err(|e| BackendError::InvalidSnapshot(e.to_string()))?;
+        self.asset_config = asset_config;
+        Ok(self)
+    }
+
+    pub fn get_asset_config(&self) -> &AssetConfig<N_CURRENCIES> {
+        &self.asset_config
+    }
+
+    /// Enables or disables the check of the witness of each inclusion proof by `preflight_check_inclusion` before running the prover in `generate_proof_of_inclusion`.
+    /// The check takes seconds, whereas proving an invalid witness fails after running the whole prover for large circuits. It is disabled by default.
+    pub fn with_preflight_check(mut self, enabled: bool) -> Self {
+        self.preflight_check = enabled;
+        self
+    }
+
+    /// Sets the number of inclusion proofs cached by `generate_proof_of_inclusion`, `DEFAULT_INCLUSION_PROOF_CACHE_CAPACITY` by default, dropping the proofs cached so far.
+    /// The least recently used proof is evicted once the cache is full, and a capacity of 0 disables the cache.
+    pub fn with_proof_cache_capacity(mut self, capacity: usize) -> Self {
+        self.proof_cache = Mutex::new(ProofCache::new(capacity));
+        self
+    }
+
+    /// Writes the setup artifacts of the snapshot to `path`, so that the next snapshots built by `new_with_setup_artifacts` skip the key generation
+    pub fn save_setup_artifacts(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        write_setup_artifacts::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+            path,
+            self.trusted_setup.0.k(),
             &self.trusted_setup.0,
             &self.trusted_setup.1,
-            circuit.clone(),
-        );
+            &self.trusted_setup.2,
+        )
+    }
 
-        Ok(MstInclusionProof {
-            proof_calldata: calldata.0,
-            public_inputs: calldata.1,
+    /// Writes the setup artifacts of the snapshot to `path`, encrypted with `passphrase` as done by `write_setup_artifacts_encrypted`,
+    /// so that the proving key can be stored where it could be read by parties allowed to run the backend but not to generate proofs
+    pub fn save_setup_artifacts_encrypted(
+        &self,
+        path: &Path,
+        passphrase: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        write_setup_artifacts_encrypted::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+            path,
+            self.trusted_setup.0.k(),
+            &self.trusted_setup.0,
+            &self.trusted_setup.1,
+            &self.trusted_setup.2,
+            passphrase,
+        )
+    }
+
+    /// Builds a snapshot as `new` does, loading the setup artifacts saved by `save_setup_artifacts_encrypted` at `setup_artifacts_path` with `passphrase`.
+    /// Returns a `DecryptionFailed` error if the passphrase is wrong.
+    pub fn new_with_encrypted_setup_artifacts(
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        params_path: &str,
+        setup_artifacts_path: &Path,
+        passphrase: &str,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn std::error::Error>> {
+        let k = read_params_k(params_path)?;
+
+        let trusted_setup = read_setup_artifacts_encrypted::<
+            MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>,
+        >(setup_artifacts_path, k, passphrase)?;
+
+        let user_indexes = index_users(mst.as_ref());
+        let asset_config = AssetConfig::from_tree(mst.as_ref());
+
+        Ok(Snapshot {
+            mst,
+            trusted_setup: Arc::new(trusted_setup),
+            dynamic_levels: None,
+            asset_config,
+            onchain_assets: None,
+            preflight_check: false,
+            user_indexes,
+            proof_cache: Mutex::new(ProofCache::default()),
+            proof_generated: Condvar::new(),
+        })
+    }
+
+    /// Builds a snapshot as `new` does, loading the setup artifacts from `cache_dir` when they have already been generated for the same circuit and params.
+    pub fn new_with_setup_cache(
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        params_path: &str,
+        cache_dir: &Path,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn std::error::Error>> {
+        let mst_inclusion_circuit =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        // get k from the header of the ptau file, a k too small for the circuit being rejected by the key generation
+        let k = read_params_k(params_path)?;
+
+        let mst_inclusion_setup_artifacts: SetupArtifacts = CachedSetupArtifacts::load_or_generate(
+            cache_dir,
+            k,
+            Some(params_path),
+            mst_inclusion_circuit,
+        )?;
+
+        let user_indexes = index_users(mst.as_ref());
+        let asset_config = AssetConfig::from_tree(mst.as_ref());
+
+        Ok(Snapshot {
+            mst,
+            trusted_setup: Arc::new(mst_inclusion_setup_artifacts),
+            dynamic_levels: None,
+            asset_config,
+            onchain_assets: None,
+            preflight_check: false,
+            user_indexes,
+            proof_cache: Mutex::new(ProofCache::default()),
+            proof_generated: Condvar::new(),
         })
     }
+
+    /// Builds a snapshot whose inclusion circuit is selected at runtime for a tree of `levels` levels, so that the binary doesn't need to be recompiled when the tree grows.
+    /// `LEVELS` is ignored. Returns an error if `levels` is not one of the `SUPPORTED_LEVELS` of `DynamicMstInclusionCircuit` or if it differs from the depth of the tree.
+    pub fn new_dynamic(
+        levels: usize,
+        mst: Box<dyn Tree<N_CURRENCIES>>,
+        params_path: &str,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn Error>> {
+        let mst_inclusion_circuit =
+            DynamicMstInclusionCircuit::<N_CURRENCIES, N_BYTES>::init_empty(levels)?;
+
+        if *mst.depth() != levels {
+            return Err(BackendError::InvalidSnapshot(format!(
+                "The tree has {} levels but {} were requested",
+                mst.depth(),
+                levels
+            ))
+            .into());
+        }
+
+        // get k from the header of the ptau file, a k too small for the circuit being rejected by the key generation
+        let k = read_params_k(params_path)?;
+
+        let mst_inclusion_setup_artifacts: SetupArtifacts =
+            generate_setup_artifacts(k, Some(params_path), mst_inclusion_circuit)?;
+
+        let user_indexes = index_users(mst.as_ref());
+        let asset_config = AssetConfig::from_tree(mst.as_ref());
+
+        Ok(Snapshot {
+            mst,
+            trusted_setup: Arc::new(mst_inclusion_setup_artifacts),
+            dynamic_levels: Some(levels),
+            asset_config,
+            onchain_assets: None,
+            preflight_check: false,
+            user_indexes,
+            proof_cache: Mutex::new(ProofCache::default()),
+            proof_generated: Condvar::new(),
+        })
+    }
+
+    /// Generates a proof that the given asset sums, in the same order as the cryptocurrencies of the tree, cover the root balances of the tree.
+    /// The setup artifacts of the solvency circuit are generated from the params stored at `params_path`, downsized to the size of the circuit.
+    pub fn generate_proof_of_solvency(
+        &self,
+        asset_sums: [BigUint; N_CURRENCIES],
+        params_path: &str,
+    ) -> Result<SolvencyProof, Box<dyn Error>> {
+        let k = SolvencyCircuit::<N_CURRENCIES, N_BYTES>::constraint_count()?.min_k;
+
+        let (params, pk, _) = generate_setup_artifacts(
+            k,
+            Some(params_path),
+            SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init_empty(),
+        )?;
+
+        let circuit = SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init(self.mst.as_ref(), asset_sums);
+
+        let calldata = gen_proof_solidity_calldata(&params, &pk, circuit)?;
+
+        Ok(SolvencyProof { calldata })
+    }
+
+    /// Generates a proof that the balance of the user at `user_index` in the cryptocurrency at `asset_index` is greater than or equal to `threshold`, without revealing the balance.
+    /// The setup artifacts of the threshold circuit are generated from the params stored at `params_path`, downsized to the size of the circuit.
+    ///
+    /// Returns an error if the balance is below the threshold, as no valid proof can be generated, if the entry of the user is not salted, as its leaf hash is public,
+    /// or if the snapshot has dynamic levels.
+    pub fn generate_range_proof(
+        &self,
+        user_index: usize,
+        asset_index: usize,
+        threshold: BigUint,
+        params_path: &str,
+    ) -> Result<BalanceRangeProof, Box<dyn Error>> {
+        if self.dynamic_levels.is_some() {
+            return Err("Range proofs are not supported for snapshots with dynamic levels".into());
+        }
+        if asset_index >= N_CURRENCIES {
+            return Err(format!(
+                "The asset index {} is out of range for {} cryptocurrencies",
+                asset_index, N_CURRENCIES
+            )
+            .into());
+        }
+
+        let merkle_proof = self.mst.generate_proof(user_index)?;
+        if merkle_proof.entry.balances()[asset_index] < threshold {
+            return Err("The balance of the user is below the threshold".into());
+        }
+
+        let k = BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()?.min_k;
+
+        let (params, pk, _) = generate_setup_artifacts(
+            k,
+            Some(params_path),
+            BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+        )?;
+
+        let circuit = BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+            merkle_proof,
+            asset_index,
+            threshold,
+        )?;
+
+        let calldata = gen_proof_solidity_calldata(&params, &pk, circuit)?;
+
+        Ok(BalanceRangeProof {
+            asset_index,
+            calldata,
+        })
+    }
+
+    /// Exports the state of the snapshot, namely the root, the depth and all the leaf entries of the tree, as a pretty-printed JSON audit report following the schema documented on `AuditReport`
+    pub fn to_audit_json(&self) -> Result<String, Box<dyn Error>> {
+        let entries = (0..1usize << *self.mst.depth())
+            .map(|index| {
+                let entry = self.mst.get_entry(index).clone();
+                let leaf_hash = entry.compute_leaf().hash;
+                AuditEntry { entry, leaf_hash }
+            })
+            .collect();
+
+        let report = AuditReport::<N_CURRENCIES> {
+            schema_version: AUDIT_SCHEMA_VERSION,
+            depth: *self.mst.depth(),
+            root: self.mst.root().clone(),
+            cryptocurrencies: self.mst.cryptocurrencies().to_vec(),
+            entries,
+        };
+
+        Ok(serde_json::to_string_pretty(&report)?)
+    }
+
+    /// Loads a snapshot from an audit report exported by `to_audit_json`, rebuilding the tree from the entries of the report.
+    /// Returns an error if the leaf hashes, the depth or the root of the rebuilt tree don't match the ones of the report.
+    pub fn from_audit_json(
+        json: &str,
+        params_path: &str,
+    ) -> Result<Snapshot<LEVELS, N_CURRENCIES, N_BYTES>, Box<dyn Error>> {
+        Ok(Self::new(
+            Box::new(Self::tree_from_audit_json(json)?),
+            params_path,
+        )?)
+    }
+
+    /// Rebuilds the tree of an audit report exported by `to_audit_json`, checking it as `from_audit_json` does
+    fn tree_from_audit_json(
+        json: &str,
+    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>> {
+        let report: AuditReport<N_CURRENCIES> = serde_json::from_str(json)?;
+
+        if report.schema_version != AUDIT_SCHEMA_VERSION {
+            return Err(BackendError::InvalidSnapshot(format!(
+                "Unsupported audit report schema version {}",
+                report.schema_version
+            ))
+            .into());
+        }
+
+        let mut entries = Vec::with_capacity(report.entries.len());
+        for audit_entry in report.entries {
+            if audit_entry.entry.compute_leaf().hash != audit_entry.leaf_hash {
+                return Err(BackendError::InvalidSnapshot(format!(
+                    "Leaf hash mismatch for user {}",
+                    audit_entry.entry.username()
+                ))
+                .into());
+            }
+            entries.push(audit_entry.entry);
+        }
+
+        let mst = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            report.cryptocurrencies,
+            false,
+        )?;
+
+        if *mst.depth() != report.depth {
+            return Err(BackendError::InvalidSnapshot(format!(
+                "Expected depth {} but found {}",
+                report.depth,
+                mst.depth()
+            ))
+            .into());
+        }
+
+        if *mst.root() != report.root {
+            return Err(BackendError::InvalidSnapshot(
+                "The root of the audit report doesn't match the entries".to_string(),
+            )
+            .into());
+        }
+
+        Ok(mst)
+    }
+
+    /// Generates the proof that the balances of the user at `user_index` changed between `previous_snapshot` and this snapshot.
+    /// Returns an error if the entries at `user_index` of both snapshots don't belong to the same user, or if an inclusion proof can't be generated.
+    pub fn generate_balance_delta_proof(
+        &self,
+        user_index: usize,
+        previous_snapshot: &Snapshot<LEVELS, N_CURRENCIES, N_BYTES>,
+    ) -> Result<BalanceDeltaProof<N_CURRENCIES>, Box<dyn Error>>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        let n_entries = 1usize << (*self.mst.depth()).min(*previous_snapshot.mst.depth());
+        if user_index >= n_entries {
+            return Err(format!(
+                "The user index {} is out of the {} entries of the snapshots",
+                user_index, n_entries
+            )
+            .into());
+        }
+
+        let old_entry = previous_snapshot.mst.get_entry(user_index);
+        let new_entry = self.mst.get_entry(user_index);
+        if old_entry.username() != new_entry.username() {
+            return Err(BackendError::InvalidSnapshot(format!(
+                "The entry at index {} belongs to {} in the previous snapshot but to {} in the current one",
+                user_index,
+                old_entry.username(),
+                new_entry.username()
+            ))
+            .into());
+        }
+
+        let generated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time should be after the unix epoch")
+            .as_secs();
+
+        let old_inclusion_proof = previous_snapshot.generate_proof_of_inclusion(user_index)?;
+        let new_inclusion_proof = self.generate_proof_of_inclusion(user_index)?;
+
+        let old_balances = old_entry.balances().clone();
+        let new_balances = new_entry.balances().clone();
+        let delta = std::array::from_fn(|currency| {
+            BigInt::from(new_balances[currency].clone())
+                - BigInt::from(old_balances[currency].clone())
+        });
+
+        Ok(BalanceDeltaProof {
+            user_index,
+            old_balances,
+            new_balances,
+            delta,
+            old_inclusion_proof,
+            new_inclusion_proof,
+            generated_at,
+        })
+    }
+
+    /// Returns the inclusion proof of the user at `user_index`, running the prover only the first time the proof of the user is requested.
+    /// The proof is then cached, so that the same proof is returned to a user polling for it, and concurrent requests for the same user wait for the proof being generated rather than running the prover again.
+    /// The cache holds the `DEFAULT_INCLUSION_PROOF_CACHE_CAPACITY` most recently used proofs unless set otherwise by `with_proof_cache_capacity`, and can be emptied with `clear_proof_cache`.
+    pub fn generate_proof_of_inclusion(
+        &self,
+        user_index: usize,
+    ) -> Result<MstInclusionProof, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        self.generate_proof_of_inclusion_with_cancellation(user_index, &CancellationToken::new())
+    }
+
+    /// Returns the inclusion proof of the user at `user_index` as `generate_proof_of_inclusion` does, returning `RoundError::Cancelled` once `cancellation_token` is cancelled,
+    /// e.g. when the client requesting the proof disconnects. The prover runs on the calling thread, which returns at the first checkpoint following the cancellation.
+    ///
+    /// The token is checked at the following checkpoints:
+    /// * before the proof generation starts, so that a proof cancelled while waiting for a thread is never generated
+    /// * after the preflight check, if enabled, which can't be interrupted as it runs the MockProver
+    /// * before the prover synthesizes the witness of the circuit, once the merkle proof of the user is generated
+    /// * after the prover synthesizes the witness, before the KZG commitments are computed
+    /// * after the proof is generated, so that a proof completed after the cancellation is discarded rather than returned or cached
+    ///
+    /// The computation of the KZG commitments, of the evaluations and of the opening proof, as well as the verification of the generated proof, can't be interrupted,
+    /// as the halo2 prover doesn't yield between them. A cancellation during these phases takes effect once the proof is generated.
+    ///
+    /// A request waiting for the proof of the same user to be generated by another request is cancelled as well, within `PROOF_WAIT_POLL_INTERVAL`.
+    pub fn generate_proof_of_inclusion_with_cancellation(
+        &self,
+        user_index: usize,
+        cancellation_token: &CancellationToken,
+    ) -> Result<MstInclusionProof, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        let _in_flight = {
+            let mut cache = self.proof_cache.lock().unwrap();
+            loop {
+                if let Some(proof) = cache
+                    .proofs
+                    .as_mut()
+                    .and_then(|proofs| proofs.get(&user_index).cloned())
+                {
+                    cache.hits += 1;
+                    return Ok(proof);
+                }
+                if !cache.in_flight.contains(&user_index) {
+                    break;
+                }
+                if cancellation_token.is_cancelled() {
+                    return Err(RoundError::Cancelled);
+                }
+                // The proof of the user is being generated by another request, whose result is looked up once it ends. If it fails, this request runs the prover instead
+                cache = self
+                    .proof_generated
+                    .wait_timeout(cache, PROOF_WAIT_POLL_INTERVAL)
+                    .unwrap()
+                    .0;
+            }
+            cache.misses += 1;
+            cache.in_flight.insert(user_index);
+            InFlightProof {
+                cache: &self.proof_cache,
+                generated: &self.proof_generated,
+                user_index,
+            }
+        };
+
+        // The lock isn't held while proving, so that the proofs of other users are served meanwhile
+        let proof = self.prove_inclusion(user_index, cancellation_token)?;
+        if let Some(proofs) = self.proof_cache.lock().unwrap().proofs.as_mut() {
+            proofs.put(user_index, proof.clone());
+        }
+
+        Ok(proof)
+    }
+
+    /// Generates the inclusion proofs of the users at `user_indexes` that aren't cached yet, e.g. before the round is opened to the users.
+    /// Returns the error of the first proof that can't be generated, the proofs generated before it being cached.
+    pub fn warm_proof_cache(&self, user_indexes: &[usize]) -> Result<(), RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        for &user_index in user_indexes {
+            self.generate_proof_of_inclusion(user_index)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of cached proofs and the number of lookups served or not by the cache since the snapshot was created
+    pub fn proof_cache_stats(&self) -> ProofCacheStats {
+        let cache = self.proof_cache.lock().unwrap();
+        ProofCacheStats {
+            cached_proofs: cache.proofs.as_ref().map_or(0, |proofs| proofs.len()),
+            hits: cache.hits,
+            misses: cache.misses,
+        }
+    }
+
+    /// Removes the cached proofs, keeping the counts of hits and misses
+    pub fn clear_proof_cache(&self) {
+        if let Some(proofs) = self.proof_cache.lock().unwrap().proofs.as_mut() {
+            proofs.clear();
+        }
+    }
+
+    /// Returns an `InvalidUserIndex` error if `user_index` lies beyond the last leaf of the tree
+    pub fn check_user_index(&self, user_index: usize) -> Result<(), RoundError> {
+        let max = (1usize << *self.mst.depth()) - 1;
+        if user_index > max {
+            return Err(RoundError::InvalidUserIndex {
+                index: user_index,
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the number of users of the tree, namely its leaves apart from the zero entries padding it to a power of two
+    pub fn user_count(&self) -> usize {
+        let zero_entry = Entry::<N_CURRENCIES>::zero_entry();
+        (0..1usize << *self.mst.depth())
+            .filter(|&index| *self.mst.get_entry(index) != zero_entry)
+            .count()
+    }
+
+    /// Runs the prover for the inclusion proof of the user at `user_index`, bypassing the cache
+    fn prove_inclusion(
+        &self,
+        user_index: usize,
+        cancellation_token: &CancellationToken,
+    ) -> Result<MstInclusionProof, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        let checkpoint = || {
+            if cancellation_token.is_cancelled() {
+                Err(RoundError::Cancelled)
+            } else {
+                Ok(())
+            }
+        };
+
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+
+        self.check_user_index(user_index)?;
+
+        checkpoint()?;
+        if self.preflight_check {
+            self.preflight_check_inclusion(user_index)?;
+        }
+        checkpoint()?;
+
+        let merkle_proof = self
+            .mst
+            .generate_proof(user_index)
+            .map_err(|e| RoundError::ProofGeneration(e.to_string().into()))?;
+
+        // Currently, default manner of generating a inclusion proof for solidity-verifier.
+        let calldata = match self.dynamic_levels {
+            None => {
+                let circuit =
+                    MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+                prove_cancellable(&self.trusted_setup, circuit, cancellation_token)?
+            }
+            Some(levels) => {
+                let circuit =
+                    DynamicMstInclusionCircuit::<N_CURRENCIES, N_BYTES>::init(levels, merkle_proof)
+                        .map_err(|e| RoundError::ProofGeneration(e.to_string().into()))?;
+                prove_cancellable(&self.trusted_setup, circuit, cancellation_token)?
+            }
+        };
+
+        // Double-check that the public inputs of the generated proof match the entry of the user and the committed root before returning the calldata
+        let expected_inputs =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::expected_instances(
+                self.mst.get_entry(user_index),
+                self.mst.root(),
+            )[0]
+            .iter()
+            .map(|input| U256::from_little_endian(&input.to_bytes()))
+            .collect::<Vec<_>>();
+        if calldata.public_inputs != expected_inputs {
+            return Err(RoundError::ProofGeneration(
+                "The public inputs of the proof don't match the entry of the user and the committed root".into(),
+            ));
+        }
+        checkpoint()?;
+
+        let metadata = ProofMetadata {
+            version: PROOF_METADATA_VERSION,
+            generated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("System time should be after the unix epoch")
+                .as_secs(),
+            k: self.trusted_setup.0.k(),
+            levels: self.dynamic_levels.unwrap_or(LEVELS),
+            n_currencies: N_CURRENCIES,
+            n_bytes: N_BYTES,
+            vk_digest: vk_digest(&self.trusted_setup.2),
+        };
+
+        #[cfg(feature = "metrics")]
+        crate::metrics_server::record_proof_generated(start.elapsed());
+
+        Ok(MstInclusionProof {
+            format_version: PROOF_FORMAT_VERSION,
+            calldata,
+            metadata,
+            user_index: Some(user_index),
+            username: Some(self.mst.get_entry(user_index).username().to_string()),
+            is_legacy: false,
+        })
+    }
+
+    /// Writes `proof`, generated by `generate_proof_of_inclusion`, to `path` as a `ProofArtifact` recording its public inputs and the size and the type of the circuit of the snapshot,
+    /// so that it can be verified with the params and the verifying key of the snapshot alone
+    pub fn save_proof_artifact(
+        &self,
+        proof: &MstInclusionProof,
+        path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let instances = proof
+            .get_public_inputs()
+            .iter()
+            .map(|input| {
+                let mut bytes = [0u8; 32];
+                input.to_little_endian(&mut bytes);
+                Option::<Fp>::from(Fp::from_bytes(&bytes))
+                    .ok_or_else(|| format!("The public input {:#x} is not a field element", input))
+            })
+            .collect::<Result<Vec<Fp>, _>>()?;
+
+        let k = self.trusted_setup.0.k();
+        let proof_bytes = proof.get_proof().to_vec();
+        let artifact = match self.dynamic_levels {
+            None => ProofArtifact::new::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+                k,
+                vec![instances],
+                proof_bytes,
+                TranscriptKind::EvmKeccak,
+            ),
+            Some(_) => ProofArtifact::new::<DynamicMstInclusionCircuit<N_CURRENCIES, N_BYTES>>(
+                k,
+                vec![instances],
+                proof_bytes,
+                TranscriptKind::EvmKeccak,
+            ),
+        };
+
+        artifact.write(path)
+    }
+
+    /// Verifies `proof` natively against the params and the verifying key of the snapshot, without going through the Solidity verifier.
+    /// The public inputs of the calldata are decoded back into field elements and the root hash among them is checked against the root of the tree of the snapshot.
+    ///
+    /// Returns `Ok(false)` if the proof doesn't verify, including if its public inputs aren't field elements,
+    /// and a `RootMismatch` error if the proof commits to another root, e.g. the one of another round.
+    pub fn verify_proof_of_inclusion(&self, proof: &MstInclusionProof) -> Result<bool, RoundError> {
+        let public_inputs = proof.get_public_inputs();
+        if public_inputs.len() != N_CURRENCIES + 3 {
+            return Ok(false);
+        }
+
+        let committed_root = field_element_to_solidity_calldata(self.mst.root().hash);
+        if public_inputs[2] != committed_root {
+            return Err(RoundError::RootMismatch {
+                expected: committed_root,
+                found: public_inputs[2],
+            });
+        }
+
+        let instances = match public_inputs
+            .iter()
+            .map(|input| {
+                let mut bytes = [0u8; 32];
+                input.to_little_endian(&mut bytes);
+                Option::<Fp>::from(Fp::from_bytes(&bytes))
+            })
+            .collect::<Option<Vec<Fp>>>()
+        {
+            Some(instances) => instances,
+            None => return Ok(false),
+        };
+
+        Ok(full_verifier_with_transcript(
+            &self.trusted_setup.0,
+            &self.trusted_setup.2,
+            proof.get_proof().to_vec(),
+            vec![instances],
+            TranscriptKind::EvmKeccak,
+        ))
+    }
+
+    /// Writes the part of the params of the snapshot that the verifier uses to `path`, so that the verification bundle published along with the proofs
+    /// consists of the verifying key, these verifier params and the proof, rather than the full params
+    pub fn save_verifier_params(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        write_verifier_params(&self.trusted_setup.0, path)
+    }
+
+    /// Checks with the MockProver, at the `k` of the setup artifacts, that the witness of the inclusion proof of the user at `user_index` satisfies the constraints of the circuit.
+    /// Returns a `ConstraintViolations` error with the violated constraints otherwise, e.g. the range check of a balance exceeding `N_BYTES` bytes,
+    /// and an `InvalidUserIndex` error if `user_index` lies beyond the last leaf of the tree.
+    pub fn preflight_check_inclusion(&self, user_index: usize) -> Result<(), RoundError> {
+        self.check_user_index(user_index)?;
+        let merkle_proof = self
+            .mst
+            .generate_proof(user_index)
+            .map_err(|e| RoundError::ProofGeneration(e.to_string().into()))?;
+        let k = self.trusted_setup.0.k();
+
+        match self.dynamic_levels {
+            None => preflight_check(
+                k,
+                &MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof),
+            ),
+            Some(levels) => {
+                match DynamicMstInclusionCircuit::<N_CURRENCIES, N_BYTES>::init(
+                    levels,
+                    merkle_proof,
+                ) {
+                    Ok(circuit) => preflight_check(k, &circuit),
+                    Err(e) => Err(vec![ConstraintViolation {
+                        kind: ViolationKind::Synthesis,
+                        gate_name: format!("synthesis ({})", e),
+                        region: None,
+                        row: None,
+                        cell_values: vec![],
+                    }]),
+                }
+            }
+        }
+        .map_err(RoundError::ConstraintViolations)
+    }
+
+    /// Generates the inclusion proof of the first user, checking that the prover works. Whether the proof is accepted by the deployed verifier is checked by `Round::health_check`.
+    pub fn check_proof_generation(&self) -> Result<MstInclusionProof, RoundError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        // the prover is run even if the proof is cached, so that the check reflects the current state of the prover
+        self.prove_inclusion(0, &CancellationToken::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::csv_parser::parse_asset_csv_named;
+    use ethers::abi::AbiDecode;
+    use halo2_proofs::dev::MockProver;
+    use std::sync::mpsc;
+    use summa_solvency::{
+        circuits::{
+            types::{CircuitError, DecryptionFailed},
+            utils::{
+                field_element_to_solidity_calldata, read_verifier_params, verify_inclusion_proof,
+            },
+        },
+        merkle_sum_tree::{utils::fp_to_big_uint, MerkleSumTree},
+    };
+
+    #[test]
+    fn test_proof_metadata() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+
+        let first_proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+        let second_proof = snapshot.generate_proof_of_inclusion(1).unwrap();
+
+        let metadata = first_proof.get_metadata();
+        assert_eq!(metadata.version, PROOF_METADATA_VERSION);
+        assert_eq!(metadata.k, 11);
+        assert_eq!(metadata.levels, 4);
+        assert_eq!(metadata.n_currencies, 2);
+        assert_eq!(metadata.n_bytes, 8);
+
+        // The digest should be stable across proofs generated with the same setup artifacts
+        assert_eq!(metadata.vk_digest, second_proof.get_metadata().vk_digest);
+        assert!(first_proof.verify_vk_matches(&snapshot.trusted_setup.2));
+        assert!(second_proof.verify_vk_matches(&snapshot.trusted_setup.2));
+
+        // A verifying key of a circuit with different dimensions shouldn't match
+        let other_snapshot = Snapshot::<3, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+        assert!(!first_proof.verify_vk_matches(&other_snapshot.trusted_setup.2));
+    }
+
+    #[test]
+    fn test_proof_summary() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+
+        let proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+        let summary = proof.summary();
+
+        assert!(summary.calldata_bytes > 0);
+        assert_eq!(summary.calldata_bytes, proof.get_proof().len());
+        assert_eq!(summary.public_input_count, 2 + 2);
+        assert!(summary.valid_public_input_layout);
+        assert_eq!(
+            summary.to_string(),
+            format!(
+                "calldata: {} bytes, public inputs: 4 (valid layout)",
+                summary.calldata_bytes
+            )
+        );
+
+        // A proof missing a root balance doesn't have the expected layout
+        let mut truncated_proof = proof.clone();
+        truncated_proof.calldata.public_inputs.pop();
+        assert!(!truncated_proof.summary().valid_public_input_layout);
+    }
+
+    #[test]
+    fn test_proof_format_versioning() {
+        let proof = MstInclusionProof {
+            format_version: PROOF_FORMAT_VERSION,
+            calldata: SolidityCalldata {
+                proof: Bytes::from(vec![0xde, 0xad, 0xbe, 0xef]),
+                public_inputs: vec![U256::from(1), U256::from(2), U256::from(3), U256::from(4)],
+            },
+            metadata: ProofMetadata {
+                version: PROOF_METADATA_VERSION,
+                generated_at: 1_700_000_000,
+                k: 11,
+                levels: 4,
+                n_currencies: 2,
+                n_bytes: 8,
+                vk_digest: [7u8; 32],
+            },
+            user_index: None,
+            username: None,
+            is_legacy: false,
+        };
+
+        let bytes = proof.to_bytes_versioned().unwrap();
+        assert_eq!(bytes[0], PROOF_FORMAT_VERSION);
+
+        // A proof serialized with the current version is not legacy
+        let deserialized_proof = MstInclusionProof::from_bytes_versioned(&bytes).unwrap();
+        assert_eq!(
+            deserialized_proof.get_public_inputs(),
+            proof.get_public_inputs()
+        );
+        assert_eq!(deserialized_proof.get_proof(), proof.get_proof());
+        assert_eq!(deserialized_proof.get_metadata(), proof.get_metadata());
+        assert_eq!(deserialized_proof.get_format_version(), 2);
+        assert!(!deserialized_proof.is_legacy());
+
+        // The JSON payload can be parsed as the calldata of the Solidity verifier
+        let calldata: SolidityCalldata = serde_json::from_slice(&bytes[1..]).unwrap();
+        assert_eq!(&calldata, proof.get_calldata());
+
+        // Once the format version is increased, the v2 proof is deserialized by the v2 deserializer and flagged as legacy
+        let legacy_proof =
+            MstInclusionProof::from_bytes_with_current_version(&bytes, PROOF_FORMAT_VERSION + 1)
+                .unwrap();
+        assert_eq!(legacy_proof.get_format_version(), 2);
+        assert_eq!(legacy_proof.get_public_inputs(), proof.get_public_inputs());
+        assert!(legacy_proof.is_legacy());
+
+        // A v1 proof, whose calldata lies under `proof_calldata`, is still deserialized and flagged as legacy
+        let mut v1_bytes = vec![1u8];
+        v1_bytes.extend(
+            serde_json::to_vec(&serde_json::json!({
+                "format_version": 1,
+                "public_inputs": proof.get_public_inputs(),
+                "proof_calldata": proof.get_proof(),
+                "metadata": proof.get_metadata(),
+            }))
+            .unwrap(),
+        );
+        let v1_proof = MstInclusionProof::from_bytes_versioned(&v1_bytes).unwrap();
+        assert_eq!(v1_proof.get_format_version(), 1);
+        assert_eq!(v1_proof.get_calldata(), proof.get_calldata());
+        assert_eq!(v1_proof.get_metadata(), proof.get_metadata());
+        assert!(v1_proof.is_legacy());
+
+        // Unknown versions are rejected
+        let mut future_bytes = bytes;
+        future_bytes[0] = PROOF_FORMAT_VERSION + 1;
+        assert!(MstInclusionProof::from_bytes_versioned(&future_bytes).is_err());
+        assert!(MstInclusionProof::from_bytes_versioned(&[]).is_err());
+    }
+
+    #[test]
+    fn test_audit_json() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+
+        let json = snapshot.to_audit_json().unwrap();
+
+        // The report is versioned and its root hash matches the root of the tree
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(report["schema_version"], AUDIT_SCHEMA_VERSION);
+        assert_eq!(report["depth"], 4);
+        assert_eq!(
+            report["root"]["hash"],
+            serde_helpers::fp_to_hex(&mst.root().hash)
+        );
+        assert_eq!(report["entries"].as_array().unwrap().len(), 16);
+        assert_eq!(
+            report["entries"][0]["username"],
+            mst.get_entry(0).username()
+        );
+
+        // Loading the report rebuilds the same tree
+        let loaded_snapshot =
+            Snapshot::<4, 2, 8>::from_audit_json(&json, "ptau/hermez-raw-11").unwrap();
+        assert_eq!(loaded_snapshot.mst.root(), mst.root());
+        assert_eq!(
+            loaded_snapshot.mst.cryptocurrencies(),
+            mst.cryptocurrencies()
+        );
+        for index in 0..16 {
+            assert_eq!(loaded_snapshot.mst.get_entry(index), mst.get_entry(index));
+        }
+        assert_eq!(loaded_snapshot.to_audit_json().unwrap(), json);
+
+        // A report whose entries don't match its leaf hashes is rejected
+        let mut tampered_report = report.clone();
+        tampered_report["entries"][3]["balances"][0] = "1".into();
+        assert!(matches!(
+            Snapshot::<4, 2, 8>::from_audit_json(
+                &tampered_report.to_string(),
+                "ptau/hermez-raw-11"
+            )
+            .err()
+            .unwrap()
+            .downcast_ref::<BackendError>(),
+            Some(BackendError::InvalidSnapshot(_))
+        ));
+
+        // A report with an unknown schema version is rejected
+        let mut future_report = report;
+        future_report["schema_version"] = (AUDIT_SCHEMA_VERSION + 1).into();
+        assert!(matches!(
+            Snapshot::<4, 2, 8>::from_audit_json(&future_report.to_string(), "ptau/hermez-raw-11")
+                .err()
+                .unwrap()
+                .downcast_ref::<BackendError>(),
+            Some(BackendError::InvalidSnapshot(_))
+        ));
+    }
+
+    #[test]
+    fn test_proof_of_solvency() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+
+        let asset_sums = mst
+            .root()
+            .balances
+            .map(summa_solvency::merkle_sum_tree::utils::fp_to_big_uint);
+
+        let proof = snapshot
+            .generate_proof_of_solvency(asset_sums, "ptau/hermez-raw-11")
+            .unwrap();
+
+        // The public inputs are the root hash and the asset sums
+        assert_eq!(proof.get_public_inputs().len(), 3);
+        assert_eq!(
+            proof.get_public_inputs()[0],
+            U256::from_little_endian(&mst.root().hash.to_bytes())
+        );
+        assert!(!proof.get_proof().is_empty());
+    }
+
+    #[test]
+    fn test_range_proof() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // The first user has a balance of 500 and the second one a balance of 50 in the first cryptocurrency, the third one being unsalted
+        let mut entries = mst.entries().to_vec();
+        for (index, balance) in [(0, 500u32), (1, 50u32)] {
+            entries[index] = Entry::new_salted(
+                entries[index].username().to_string(),
+                [BigUint::from(balance), entries[index].balances()[1].clone()],
+                Entry::<2>::random_salt(),
+            );
+        }
+        let mst =
+            MerkleSumTree::<2, 8>::from_entries(entries, mst.cryptocurrencies().to_vec(), false)
+                .unwrap();
+        let snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+
+        let proof = snapshot
+            .generate_range_proof(0, 0, BigUint::from(100u32), "ptau/hermez-raw-11")
+            .unwrap();
+
+        // The public inputs are the leaf hash, the root hash and the thresholds, but not the balance
+        assert_eq!(proof.get_asset_index(), 0);
+        assert_eq!(
+            proof.get_public_inputs(),
+            &vec![
+                field_element_to_solidity_calldata(mst.get_entry(0).compute_leaf().hash),
+                field_element_to_solidity_calldata(mst.root().hash),
+                U256::from(100),
+                U256::zero()
+            ]
+        );
+        assert!(!proof.get_proof().is_empty());
+
+        // No proof can be generated for a balance below the threshold
+        assert!(snapshot
+            .generate_range_proof(1, 0, BigUint::from(100u32), "ptau/hermez-raw-11")
+            .is_err());
+        assert!(snapshot
+            .generate_range_proof(0, 2, BigUint::from(100u32), "ptau/hermez-raw-11")
+            .is_err());
+
+        // The balance of an unsalted entry could be ground out of its public leaf hash
+        assert!(snapshot
+            .generate_range_proof(2, 0, BigUint::from(0u32), "ptau/hermez-raw-11")
+            .is_err());
+    }
+
+    #[test]
+    fn test_proof_bundle() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+
+        let proofs = [0, 5, 10, 15]
+            .iter()
+            .map(|&user_index| snapshot.generate_proof_of_inclusion(user_index).unwrap())
+            .collect::<Vec<_>>();
+        let bundle = ProofBundle::new(proofs.clone()).unwrap();
+        assert_eq!(bundle.len(), 4);
+
+        // Each proof is rebuilt as it was before being bundled, and still verifies
+        for (index, proof) in proofs.iter().enumerate() {
+            let bundled_proof = bundle.get(index).unwrap();
+            assert_eq!(bundled_proof.get_proof(), proof.get_proof());
+            assert_eq!(bundled_proof.get_public_inputs(), proof.get_public_inputs());
+            assert_eq!(bundled_proof.get_metadata(), proof.get_metadata());
+            assert!(snapshot.verify_proof_of_inclusion(&bundled_proof).unwrap());
+        }
+        assert!(bundle.get(4).is_none());
+        assert_eq!(
+            bundle
+                .iter()
+                .map(|proof| proof.get_proof().clone())
+                .collect::<Vec<_>>(),
+            proofs
+                .iter()
+                .map(|proof| proof.get_proof().clone())
+                .collect::<Vec<_>>()
+        );
+
+        // The bundle is smaller than the proofs serialized one by one, as the metadata is stored once
+        let bundle_size = serde_json::to_vec(&bundle).unwrap().len();
+        let naive_size: usize = proofs
+            .iter()
+            .map(|proof| serde_json::to_vec(proof).unwrap().len())
+            .sum();
+        assert!(bundle_size < naive_size);
+
+        // Proofs generated with a different setup can't be bundled together
+        let mut other_proofs = proofs;
+        other_proofs[3].metadata.k = 12;
+        assert!(ProofBundle::new(other_proofs).is_err());
+        assert!(ProofBundle::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_reuses_saved_setup_artifacts() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot =
+            Snapshot::<5, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+
+        let setup_artifacts_path =
+            std::env::temp_dir().join("summa_test_snapshot_setup_artifacts.setup");
+        snapshot
+            .save_setup_artifacts(&setup_artifacts_path)
+            .unwrap();
+        assert!(setup_artifacts_path.exists());
+
+        // The next snapshot loads the saved keys instead of generating them
+        let reloaded_snapshot = Snapshot::<5, 2, 8>::new_with_setup_artifacts(
+            Box::new(mst.clone()),
+            "ptau/hermez-raw-11",
+            &setup_artifacts_path,
+        )
+        .unwrap();
+        assert_eq!(
+            vk_digest(&reloaded_snapshot.trusted_setup.2),
+            vk_digest(&snapshot.trusted_setup.2)
+        );
+
+        let proof = reloaded_snapshot.generate_proof_of_inclusion(0).unwrap();
+        assert!(proof.verify_vk_matches(&snapshot.trusted_setup.2));
+
+        // The saved keys can't be loaded for a tree of another depth
+        assert!(matches!(
+            Snapshot::<4, 2, 8>::new_with_setup_artifacts(
+                Box::new(mst),
+                "ptau/hermez-raw-11",
+                &setup_artifacts_path,
+            ),
+            Err(RoundError::Keygen(_))
+        ));
+
+        std::fs::remove_file(setup_artifacts_path).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_setup_artifacts() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+
+        let path = std::env::temp_dir().join("summa_test_encrypted_setup_artifacts.bin");
+        snapshot
+            .save_setup_artifacts_encrypted(&path, "passphrase")
+            .unwrap();
+
+        let reloaded_snapshot = Snapshot::<4, 2, 8>::new_with_encrypted_setup_artifacts(
+            Box::new(mst.clone()),
+            "ptau/hermez-raw-11",
+            &path,
+            "passphrase",
+        )
+        .unwrap();
+        assert_eq!(
+            vk_digest(&reloaded_snapshot.trusted_setup.2),
+            vk_digest(&snapshot.trusted_setup.2)
+        );
+        let proof = reloaded_snapshot.generate_proof_of_inclusion(0).unwrap();
+        assert!(proof.verify_vk_matches(&snapshot.trusted_setup.2));
+
+        let error = Snapshot::<4, 2, 8>::new_with_encrypted_setup_artifacts(
+            Box::new(mst),
+            "ptau/hermez-raw-11",
+            &path,
+            "wrong passphrase",
+        )
+        .err()
+        .unwrap();
+        assert!(error.downcast_ref::<DecryptionFailed>().is_some());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_preflight_check() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // The first balance exceeds the 8 bytes of the range check
+        let mut entries = mst.entries().to_vec();
+        entries[0] = Entry::new(
+            entries[0].username().to_string(),
+            [BigUint::from(1u8) << 64, entries[0].balances()[1].clone()],
+        );
+        let mst =
+            MerkleSumTree::<2, 8>::from_entries(entries, mst.cryptocurrencies().to_vec(), false)
+                .unwrap();
+
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11")
+            .unwrap()
+            .with_preflight_check(true);
+
+        let violations = match snapshot.preflight_check_inclusion(0).unwrap_err() {
+            RoundError::ConstraintViolations(violations) => violations,
+            e => panic!("unexpected error: {}", e),
+        };
+        assert!(violations.iter().any(|violation| violation
+            .to_string()
+            .contains("assign value to perform range check")));
+
+        // The violations are carried by the error of the proof generation
+        match snapshot.generate_proof_of_inclusion(0).unwrap_err() {
+            RoundError::ConstraintViolations(proof_violations) => {
+                assert_eq!(proof_violations, violations)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        assert!(matches!(
+            snapshot.preflight_check_inclusion(16),
+            Err(RoundError::InvalidUserIndex { index: 16, max: 15 })
+        ));
+    }
+
+    #[test]
+    fn test_save_proof_artifact() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+
+        let proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+
+        let path = std::env::temp_dir().join("summa_test_save_proof_artifact.bin");
+        snapshot.save_proof_artifact(&proof, &path).unwrap();
+
+        let artifact = ProofArtifact::read(&path).unwrap();
+        assert_eq!(artifact.k, 11);
+        assert_eq!(artifact.proof_bytes, proof.get_proof().to_vec());
+        assert_eq!(
+            artifact.instances[0]
+                .iter()
+                .map(|instance| field_element_to_solidity_calldata(*instance))
+                .collect::<Vec<_>>(),
+            *proof.get_public_inputs()
+        );
+        assert!(artifact
+            .verify::<MstInclusionCircuit<4, 2, 8>>(
+                &snapshot.trusted_setup.0,
+                &snapshot.trusted_setup.2
+            )
+            .unwrap());
+
+        // The artifact verifies against the verifier params alone
+        let verifier_params_path = std::env::temp_dir().join("summa_test_save_verifier_params.bin");
+        snapshot
+            .save_verifier_params(&verifier_params_path)
+            .unwrap();
+        let verifier_params = read_verifier_params(&verifier_params_path)
+            .unwrap()
+            .to_params();
+        assert!(artifact
+            .verify::<MstInclusionCircuit<4, 2, 8>>(&verifier_params, &snapshot.trusted_setup.2)
+            .unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&verifier_params_path).unwrap();
+    }
+
+    #[test]
+    fn test_asset_config() {
+        let asset_config = parse_asset_csv_named::<_, 2>("../csv/assets.csv").unwrap();
+
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // Swapping the two cryptocurrencies of the tree changes its root hash
+        let swapped_entries = mst
+            .entries()
+            .iter()
+            .map(|entry| {
+                let [first_balance, second_balance] = entry.balances().clone();
+                Entry::new(
+                    entry.username().to_string(),
+                    [second_balance, first_balance],
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut swapped_cryptocurrencies = mst.cryptocurrencies().to_vec();
+        swapped_cryptocurrencies.swap(0, 1);
+        let swapped_mst =
+            MerkleSumTree::<2, 8>::from_entries(swapped_entries, swapped_cryptocurrencies, false)
+                .unwrap();
+        assert_ne!(swapped_mst.root().hash, mst.root().hash);
+
+        // The swapped tree doesn't match the asset config, its default config being read from its labels
+        let swapped_snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(swapped_mst), "ptau/hermez-raw-11").unwrap();
+        assert_eq!(
+            swapped_snapshot.get_asset_config().names(),
+            vec!["USDT", "ETH"]
+        );
+        assert!(matches!(
+            swapped_snapshot
+                .with_asset_config(asset_config.clone())
+                .err()
+                .unwrap()
+                .downcast_ref::<BackendError>(),
+            Some(BackendError::InvalidSnapshot(_))
+        ));
+
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11")
+            .unwrap()
+            .with_asset_config(asset_config.clone())
+            .unwrap();
+        assert_eq!(snapshot.get_asset_config(), &asset_config);
+        assert_eq!(
+            snapshot.build_commitment(1).cryptocurrencies,
+            asset_config.cryptocurrencies()
+        );
+
+        // The root balances of a proof are labelled with the names of the cryptocurrencies
+        let proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+        let annotated_inputs = proof.annotate_inputs(&asset_config).unwrap();
+        assert_eq!(annotated_inputs.leaf_hash, proof.get_public_inputs()[1]);
+        assert_eq!(annotated_inputs.root_hash, proof.get_public_inputs()[2]);
+        assert_eq!(
+            annotated_inputs.root_balances,
+            vec![
+                ("ETH".to_string(), U256::from(556862)),
+                ("USDT".to_string(), U256::from(556862)),
+            ]
+        );
+
+        let three_assets = AssetConfig::new([
+            asset_config.assets()[0].clone(),
+            asset_config.assets()[1].clone(),
+            asset_config.assets()[0].clone(),
+        ]);
+        assert!(proof.annotate_inputs(&three_assets).is_err());
+    }
+
+    #[test]
+    fn test_balance_delta_proof() {
+        let previous_mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // The first user deposits on the first cryptocurrency and withdraws on the second one
+        let mut entries = previous_mst.entries().to_vec();
+        let old_balances = entries[0].balances().clone();
+        let new_balances = [
+            &old_balances[0] + BigUint::from(1000u32),
+            &old_balances[1] - BigUint::from(1000u32),
+        ];
+        entries[0] = Entry::new(entries[0].username().to_string(), new_balances.clone());
+        let current_mst = MerkleSumTree::<2, 8>::from_entries(
+            entries,
+            previous_mst.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+
+        let previous_snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(previous_mst), "ptau/hermez-raw-11").unwrap();
+        let current_snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(current_mst), "ptau/hermez-raw-11").unwrap();
+
+        let delta_proof = current_snapshot
+            .generate_balance_delta_proof(0, &previous_snapshot)
+            .unwrap();
+
+        assert_eq!(delta_proof.user_index, 0);
+        assert_eq!(delta_proof.old_balances, old_balances);
+        assert_eq!(delta_proof.new_balances, new_balances);
+        for currency in 0..2 {
+            assert_eq!(
+                delta_proof.delta[currency],
+                BigInt::from(new_balances[currency].clone())
+                    - BigInt::from(old_balances[currency].clone())
+            );
+        }
+        assert_eq!(delta_proof.delta[0], BigInt::from(1000));
+        assert_eq!(delta_proof.delta[1], BigInt::from(-1000));
+
+        // Each inclusion proof verifies against its own snapshot only
+        let verify = |snapshot: &Snapshot<4, 2, 8>, proof: &MstInclusionProof| {
+            let instances: Vec<Fp> = proof
+                .get_public_inputs()
+                .iter()
+                .map(|input| {
+                    let mut bytes = [0u8; 32];
+                    input.to_little_endian(&mut bytes);
+                    Fp::from_bytes(&bytes).unwrap()
+                })
+                .collect();
+            let vk_bytes = snapshot.trusted_setup.2.to_bytes(SerdeFormat::RawBytes);
+            let verified = verify_inclusion_proof::<2, 8>(
+                &vk_bytes,
+                &snapshot.trusted_setup.0,
+                proof.get_proof(),
+                &instances,
+            )
+            .unwrap();
+            let committed_root = field_element_to_solidity_calldata(snapshot.mst.root().hash);
+            verified && proof.get_public_inputs()[2] == committed_root
+        };
+        assert!(verify(&previous_snapshot, &delta_proof.old_inclusion_proof));
+        assert!(verify(&current_snapshot, &delta_proof.new_inclusion_proof));
+        assert!(!verify(&current_snapshot, &delta_proof.old_inclusion_proof));
+
+        assert!(current_snapshot
+            .generate_balance_delta_proof(16, &previous_snapshot)
+            .is_err());
+    }
+
+    #[test]
+    fn test_dynamic_snapshot() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let snapshot =
+            Snapshot::<4, 2, 8>::new_dynamic(4, Box::new(mst.clone()), "ptau/hermez-raw-11")
+                .unwrap();
+        let proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+        assert_eq!(proof.get_metadata().levels, 4);
+
+        // The dynamic circuit has the same verifying key as the static one
+        let static_snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+        assert!(proof.verify_vk_matches(&static_snapshot.trusted_setup.2));
+
+        // Unsupported levels and levels that don't match the depth of the tree are rejected
+        assert!(matches!(
+            Snapshot::<4, 2, 8>::new_dynamic(5, Box::new(mst.clone()), "ptau/hermez-raw-11")
+                .err()
+                .unwrap()
+                .downcast_ref::<CircuitError>(),
+            Some(CircuitError::InvalidWitness(_))
+        ));
+        assert!(matches!(
+            Snapshot::<4, 2, 8>::new_dynamic(8, Box::new(mst), "ptau/hermez-raw-11")
+                .err()
+                .unwrap()
+                .downcast_ref::<BackendError>(),
+            Some(BackendError::InvalidSnapshot(_))
+        ));
+    }
+
+    #[test]
+    fn test_proof_of_inclusion_by_username() {
+        let snapshot =
+            Snapshot::<4, 2, 8>::from_csv("../csv/entry_16.csv", "ptau/hermez-raw-11").unwrap();
+
+        assert_eq!(snapshot.user_index("dxGaEAii").unwrap(), 0);
+        assert_eq!(snapshot.user_index("MBlfbBGI").unwrap(), 1);
+
+        // The proof records the user it was generated for, whose leaf hash is the public input #1
+        let proof = snapshot
+            .generate_proof_of_inclusion(snapshot.user_index("MBlfbBGI").unwrap())
+            .unwrap();
+        assert_eq!(proof.get_user_index(), Some(1));
+        assert_eq!(proof.get_username(), Some("MBlfbBGI"));
+        assert_eq!(
+            proof.get_public_inputs()[1],
+            field_element_to_solidity_calldata(snapshot.mst.get_entry(1).compute_leaf().hash)
+        );
+
+        assert!(matches!(
+            snapshot.user_index("unknown").unwrap_err(),
+            RoundError::UserNotFound(username) if username == "unknown"
+        ));
+    }
+
+    #[test]
+    fn test_user_count() {
+        let snapshot =
+            Snapshot::<4, 2, 8>::from_csv("../csv/entry_16.csv", "ptau/hermez-raw-11").unwrap();
+        assert_eq!(snapshot.user_count(), 16);
+
+        // The zero entries padding the tree aren't users
+        let snapshot =
+            Snapshot::<4, 2, 8>::from_csv("../csv/entry_13.csv", "ptau/hermez-raw-11").unwrap();
+        assert_eq!(snapshot.user_count(), 12);
+    }
+
+    #[test]
+    fn test_proof_cache() {
+        let snapshot =
+            Snapshot::<4, 2, 8>::from_csv("../csv/entry_16.csv", "ptau/hermez-raw-11").unwrap();
+
+        // The prover is only run for the first request, the second one returning the same randomized proof
+        let first_proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+        let second_proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+        assert_eq!(first_proof.get_proof(), second_proof.get_proof());
+        assert_eq!(
+            first_proof.get_metadata().generated_at,
+            second_proof.get_metadata().generated_at
+        );
+        assert_eq!(
+            snapshot.proof_cache_stats(),
+            ProofCacheStats {
+                cached_proofs: 1,
+                hits: 1,
+                misses: 1,
+            }
+        );
+
+        // Warming the cache only proves the users that aren't cached yet
+        snapshot.warm_proof_cache(&[0, 1]).unwrap();
+        snapshot.generate_proof_of_inclusion(1).unwrap();
+        assert_eq!(
+            snapshot.proof_cache_stats(),
+            ProofCacheStats {
+                cached_proofs: 2,
+                hits: 3,
+                misses: 2,
+            }
+        );
+
+        // The failed proofs aren't cached
+        assert!(snapshot.warm_proof_cache(&[16]).is_err());
+        assert_eq!(snapshot.proof_cache_stats().cached_proofs, 2);
+
+        // Once the cache is cleared, the prover runs again
+        snapshot.clear_proof_cache();
+        let third_proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+        assert_ne!(third_proof.get_proof(), first_proof.get_proof());
+        assert_eq!(snapshot.proof_cache_stats().misses, 4);
+
+        // The least recently used proof is evicted once the cache is full
+        let snapshot = Snapshot::<4, 2, 8>::from_csv("../csv/entry_16.csv", "ptau/hermez-raw-11")
+            .unwrap()
+            .with_proof_cache_capacity(2);
+        snapshot.warm_proof_cache(&[0, 1]).unwrap();
+        snapshot.generate_proof_of_inclusion(0).unwrap();
+        snapshot.generate_proof_of_inclusion(2).unwrap();
+        snapshot.generate_proof_of_inclusion(0).unwrap();
+        snapshot.generate_proof_of_inclusion(1).unwrap();
+        assert_eq!(
+            snapshot.proof_cache_stats(),
+            ProofCacheStats {
+                cached_proofs: 2,
+                hits: 2,
+                misses: 4,
+            }
+        );
+
+        // A capacity of 0 disables the cache
+        let snapshot = snapshot.with_proof_cache_capacity(0);
+        snapshot.generate_proof_of_inclusion(0).unwrap();
+        snapshot.generate_proof_of_inclusion(0).unwrap();
+        assert_eq!(
+            snapshot.proof_cache_stats(),
+            ProofCacheStats {
+                cached_proofs: 0,
+                hits: 0,
+                misses: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_proof_cache_concurrent_requests() {
+        let snapshot = Arc::new(
+            Snapshot::<4, 2, 8>::from_csv("../csv/entry_16.csv", "ptau/hermez-raw-11").unwrap(),
+        );
+
+        // Concurrent requests for the same user run the prover once, the other ones waiting for its proof
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let snapshot = Arc::clone(&snapshot);
+                thread::spawn(move || snapshot.generate_proof_of_inclusion(3).unwrap())
+            })
+            .collect();
+        let proofs: Vec<_> = workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect();
+
+        for proof in &proofs[1..] {
+            assert_eq!(proof.get_proof(), proofs[0].get_proof());
+        }
+        assert_eq!(
+            snapshot.proof_cache_stats(),
+            ProofCacheStats {
+                cached_proofs: 1,
+                hits: 3,
+                misses: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_proof_cancellation() {
+        let snapshot = Arc::new(
+            Snapshot::<4, 2, 8>::from_csv("../csv/entry_16.csv", "ptau/hermez-raw-11").unwrap(),
+        );
+
+        // The proof is generated by a worker thread, which sends its result on the output channel
+        let spawn_worker = |cancellation_token: CancellationToken| {
+            let snapshot = Arc::clone(&snapshot);
+            let (sender, receiver) = mpsc::channel();
+            let worker = thread::spawn(move || {
+                let result =
+                    snapshot.generate_proof_of_inclusion_with_cancellation(0, &cancellation_token);
+                if let Ok(proof) = result {
+                    sender.send(proof.get_proof().clone()).unwrap();
+                    return Ok(());
+                }
+                result.map(|_| ())
+            });
+            (worker, receiver)
+        };
+
+        // A proof cancelled mid-generation terminates the worker thread without sending any proof bytes
+        let cancellation_token = CancellationToken::new();
+        let (worker, receiver) = spawn_worker(cancellation_token.clone());
+        thread::sleep(std::time::Duration::from_millis(20));
+        cancellation_token.cancel();
+        let cancelled_at = Instant::now();
+        let result = worker.join().unwrap();
+        assert!(matches!(result, Err(RoundError::Cancelled)));
+        assert!(cancelled_at.elapsed() < std::time::Duration::from_millis(500));
+        assert!(receiver.try_recv().is_err());
+
+        // A proof cancelled before it starts is never generated
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+        let (worker, receiver) = spawn_worker(cancellation_token);
+        assert!(matches!(worker.join().unwrap(), Err(RoundError::Cancelled)));
+        assert!(receiver.try_recv().is_err());
+
+        // The cancelled proofs aren't cached, and the proof is generated once the token isn't cancelled
+        assert_eq!(snapshot.proof_cache_stats().cached_proofs, 0);
+        let (worker, receiver) = spawn_worker(CancellationToken::new());
+        worker.join().unwrap().unwrap();
+        assert!(!receiver.try_recv().unwrap().is_empty());
+        assert_eq!(snapshot.proof_cache_stats().cached_proofs, 1);
+    }
+
+    #[test]
+    fn test_snapshot_from_previous() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+
+        // Without any change, the next snapshot has the same tree
+        let next_snapshot = Snapshot::from_previous(&snapshot, &[]).unwrap();
+        assert_eq!(next_snapshot.mst.root(), snapshot.mst.root());
+        assert_eq!(
+            next_snapshot.trusted_setup.2.transcript_repr(),
+            snapshot.trusted_setup.2.transcript_repr()
+        );
+
+        // The updated tree is the same as the one built from scratch with the updated entries
+        let updated_entry = Entry::new(
+            "new_user".to_string(),
+            [BigUint::from(1000u32), BigUint::from(2000u32)],
+        );
+        let next_snapshot =
+            Snapshot::from_previous(&snapshot, &[(3, updated_entry.clone())]).unwrap();
+
+        let mut entries = mst.entries().to_vec();
+        entries[3] = updated_entry;
+        let rebuilt_mst =
+            MerkleSumTree::<2, 8>::from_entries(entries, mst.cryptocurrencies().to_vec(), false)
+                .unwrap();
+        assert_eq!(next_snapshot.mst.root(), rebuilt_mst.root());
+        assert_ne!(next_snapshot.mst.root(), snapshot.mst.root());
+        assert_eq!(next_snapshot.user_index("new_user").unwrap(), 3);
+
+        // The proofs of the next snapshot are generated with the reused setup artifacts
+        let proof = next_snapshot.generate_proof_of_inclusion(3).unwrap();
+        assert!(proof.verify_vk_matches(&snapshot.trusted_setup.2));
+
+        assert!(matches!(
+            Snapshot::from_previous(&snapshot, &[(16, Entry::zero_entry())]).err(),
+            Some(RoundError::InvalidUserIndex { index: 16, max: 15 })
+        ));
+    }
+
+    #[test]
+    fn test_build_commitment() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+
+        let payload = snapshot.build_commitment(1);
+
+        let root = snapshot.mst.root();
+        let expected_root = U256::from_little_endian(&fp_to_big_uint(root.hash).to_bytes_le());
+        assert_eq!(payload.mst_root, expected_root);
+        assert_eq!(payload.root_sums.len(), 2);
+        assert_eq!(
+            payload.root_sums[0],
+            U256::from_little_endian(&fp_to_big_uint(root.balances[0]).to_bytes_le())
+        );
+        assert_eq!(payload.cryptocurrencies, snapshot.mst.cryptocurrencies());
+        assert_eq!(payload.timestamp, U256::from(1));
+
+        // The calldata is the call to `submitCommitment` with the arguments of the payload
+        let call = SubmitCommitmentCall::decode(&payload.calldata).unwrap();
+        assert_eq!(call.mst_root, payload.mst_root);
+        assert_eq!(call.root_balances, payload.root_sums);
+        assert_eq!(
+            call.cryptocurrencies[0].name,
+            payload.cryptocurrencies[0].name
+        );
+        assert_eq!(call.timestamp, payload.timestamp);
+
+        // The payload can be written out for review
+        let json = serde_json::to_string(&payload).unwrap();
+        assert_eq!(
+            serde_json::from_str::<CommitmentPayload>(&json).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_verify_proof_of_inclusion() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+
+        let proof = snapshot.generate_proof_of_inclusion(0).unwrap();
+        assert!(snapshot.verify_proof_of_inclusion(&proof).unwrap());
+
+        // A single flipped byte of the proof makes the verification fail
+        let mut tampered_proof = proof.clone();
+        let mut proof_bytes = tampered_proof.calldata.proof.to_vec();
+        proof_bytes[100] ^= 1;
+        tampered_proof.calldata.proof = proof_bytes.into();
+        assert!(!snapshot.verify_proof_of_inclusion(&tampered_proof).unwrap());
+
+        // The proof of the previous round doesn't pass the root cross-check of the next one, although both share the same verifying key
+        let updated_entry = Entry::new(
+            "new_user".to_string(),
+            [BigUint::from(1000u32), BigUint::from(2000u32)],
+        );
+        let next_snapshot = Snapshot::from_previous(&snapshot, &[(3, updated_entry)]).unwrap();
+        assert!(matches!(
+            next_snapshot.verify_proof_of_inclusion(&proof),
+            Err(RoundError::RootMismatch { found, .. }) if found == proof.get_public_inputs()[2]
+        ));
+        let next_proof = next_snapshot.generate_proof_of_inclusion(0).unwrap();
+        assert!(next_snapshot
+            .verify_proof_of_inclusion(&next_proof)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_proof_json() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+        let proof = snapshot.generate_proof_of_inclusion(1).unwrap();
+
+        // The public inputs are decimal strings and the proof is a 0x-prefixed hex string
+        let json = proof.to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["public_inputs"][0].as_str().unwrap(),
+            proof.get_public_inputs()[0].to_string()
+        );
+        assert!(value["proof"].as_str().unwrap().starts_with("0x"));
+        assert_eq!(value["user_index"], 1);
+
+        let decoded_proof = MstInclusionProof::from_json(&json).unwrap();
+        assert_eq!(
+            decoded_proof.to_bytes_versioned().unwrap(),
+            proof.to_bytes_versioned().unwrap()
+        );
+        assert_eq!(decoded_proof.to_json().unwrap(), json);
+        assert!(!decoded_proof.is_legacy);
+        assert!(snapshot.verify_proof_of_inclusion(&decoded_proof).unwrap());
+
+        // A public input that isn't a decimal string is rejected
+        let hex_json = json.replace(
+            &format!("\"{}\"", proof.get_public_inputs()[0]),
+            &format!("\"{:#x}\"", proof.get_public_inputs()[0]),
+        );
+        assert!(MstInclusionProof::from_json(&hex_json).is_err());
+    }
+
+    #[test]
+    fn test_round_errors() {
+        assert!(matches!(
+            Snapshot::<4, 2, 8>::from_csv("../csv/missing.csv", "ptau/hermez-raw-11").unwrap_err(),
+            RoundError::CsvParse(_)
+        ));
+
+        assert!(matches!(
+            Snapshot::<4, 2, 8>::from_csv("../csv/entry_16.csv", "ptau/missing").unwrap_err(),
+            RoundError::ParamsLoad(_)
+        ));
+
+        let snapshot =
+            Snapshot::<4, 2, 8>::from_csv("../csv/entry_16.csv", "ptau/hermez-raw-11").unwrap();
+        match snapshot.generate_proof_of_inclusion(16).unwrap_err() {
+            RoundError::InvalidUserIndex { index, max } => {
+                assert_eq!(index, 16);
+                assert_eq!(max, 15);
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_params_k() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // The params file keeps working under the name of the Hermez ceremony
+        let snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), "ptau/hermez-raw-11").unwrap();
+        assert_eq!(snapshot.trusted_setup.0.k(), 11);
+
+        // k is read from the header of a renamed params file, whose name doesn't end with k.
+        // The copy is removed when the test ends, even if an assertion fails
+        struct RemovedOnDrop(PathBuf);
+        impl Drop for RemovedOnDrop {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_file(&self.0);
+            }
+        }
+        let renamed_params_file = RemovedOnDrop(std::env::temp_dir().join(format!(
+            "summa_test_snapshot_params_k_{}.srs",
+            std::process::id()
+        )));
+        std::fs::copy("ptau/hermez-raw-11", &renamed_params_file.0).unwrap();
+        let renamed_params_path = renamed_params_file.0.to_str().unwrap();
+
+        let snapshot =
+            Snapshot::<4, 2, 8>::new(Box::new(mst.clone()), renamed_params_path).unwrap();
+        assert_eq!(snapshot.trusted_setup.0.k(), 11);
+
+        let snapshot =
+            Snapshot::<4, 2, 8>::new_with_k(Box::new(mst.clone()), renamed_params_path, 11)
+                .unwrap();
+        assert_eq!(snapshot.trusted_setup.0.k(), 11);
+
+        // An explicit k that isn't the one of the params file is rejected
+        match Snapshot::<4, 2, 8>::new_with_k(Box::new(mst), renamed_params_path, 12).unwrap_err() {
+            RoundError::ParamsKMismatch { expected, found } => {
+                assert_eq!(expected, 12);
+                assert_eq!(found, 11);
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+    }
 }
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use ethers::types::U256;
+use summa_solvency::circuits::utils::field_element_to_solidity_calldata;
+
+use super::round::{MstInclusionProof, Round, RoundError};
+
+/// The reason why a round of a `RoundRegistry` couldn't be added or served
+#[derive(Debug)]
+pub enum RegistryError {
+    /// A round has already been registered at the timestamp
+    DuplicateRound(u64),
+    /// No round has ever been registered at the timestamp
+    UnknownRound(u64),
+    /// The round exists, but its tree and setup artifacts have been evicted, so that its proofs are no longer served
+    RoundEvicted(EvictedRound),
+    /// The round couldn't generate the proof
+    Round(RoundError),
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::DuplicateRound(timestamp) => {
+                write!(
+                    f,
+                    "A round is already registered at timestamp {}",
+                    timestamp
+                )
+            }
+            RegistryError::UnknownRound(timestamp) => {
+                write!(f, "No round is registered at timestamp {}", timestamp)
+            }
+            RegistryError::RoundEvicted(round) => write!(
+                f,
+                "The round at timestamp {} exists but its proofs are no longer served",
+                round.timestamp
+            ),
+            RegistryError::Round(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for RegistryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RegistryError::Round(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// What is kept of a round evicted from a `RoundRegistry`, enough to tell the users that the round exists and which root it committed.
+///
+/// # Fields
+///
+/// * `timestamp`: The timestamp of the round
+/// * `root_hash`: The hash of the root of the tree of the round, as submitted to the Summa contract
+/// * `root_balances`: The balances of the root of the tree of the round, as submitted to the Summa contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvictedRound {
+    pub timestamp: u64,
+    pub root_hash: U256,
+    pub root_balances: Vec<U256>,
+}
+
+enum RegisteredRound<'a, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> {
+    Served(Round<'a, LEVELS, N_CURRENCIES, N_BYTES>),
+    Evicted(EvictedRound),
+}
+
+/// The rounds of an exchange, keyed by their timestamp, so that the users can ask for a proof against a past commitment rather than only against the latest one.
+///
+/// The rounds of a registry share the same circuit, so that a round built by `Round::new_with_setup_of` from another round of the registry
+/// shares its setup artifacts rather than holding a copy of its own. Evicting a round frees its tree, while its setup artifacts are freed along with the last round sharing them.
+#[derive(Default)]
+pub struct RoundRegistry<'a, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> {
+    rounds: BTreeMap<u64, RegisteredRound<'a, LEVELS, N_CURRENCIES, N_BYTES>>,
+}
+
+impl<'a, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    RoundRegistry<'a, LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub fn new() -> Self {
+        RoundRegistry {
+            rounds: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `round` at its timestamp. Returns a `DuplicateRound` error if a round, even evicted, is already registered at the same timestamp.
+    pub fn add_round(
+        &mut self,
+        round: Round<'a, LEVELS, N_CURRENCIES, N_BYTES>,
+    ) -> Result<(), RegistryError> {
+        let timestamp = round.get_timestamp();
+        if self.rounds.contains_key(&timestamp) {
+            return Err(RegistryError::DuplicateRound(timestamp));
+        }
+
+        self.rounds
+            .insert(timestamp, RegisteredRound::Served(round));
+        Ok(())
+    }
+
+    /// Returns the round registered at `timestamp`, or a `RoundEvicted` error carrying what is kept of it if it has been evicted
+    pub fn get_round(
+        &self,
+        timestamp: u64,
+    ) -> Result<&Round<'a, LEVELS, N_CURRENCIES, N_BYTES>, RegistryError> {
+        match self.rounds.get(&timestamp) {
+            Some(RegisteredRound::Served(round)) => Ok(round),
+            Some(RegisteredRound::Evicted(round)) => {
+                Err(RegistryError::RoundEvicted(round.clone()))
+            }
+            None => Err(RegistryError::UnknownRound(timestamp)),
+        }
+    }
+
+    /// Returns the round registered at `timestamp` as `get_round` does, mutably, e.g. to dispatch its commitment
+    pub fn get_round_mut(
+        &mut self,
+        timestamp: u64,
+    ) -> Result<&mut Round<'a, LEVELS, N_CURRENCIES, N_BYTES>, RegistryError> {
+        match self.rounds.get_mut(&timestamp) {
+            Some(RegisteredRound::Served(round)) => Ok(round),
+            Some(RegisteredRound::Evicted(round)) => {
+                Err(RegistryError::RoundEvicted(round.clone()))
+            }
+            None => Err(RegistryError::UnknownRound(timestamp)),
+        }
+    }
+
+    /// Returns the served round with the latest timestamp, if any
+    pub fn latest(&self) -> Option<&Round<'a, LEVELS, N_CURRENCIES, N_BYTES>> {
+        self.rounds.values().rev().find_map(|round| match round {
+            RegisteredRound::Served(round) => Some(round),
+            RegisteredRound::Evicted(_) => None,
+        })
+    }
+
+    /// Returns the timestamps of the registered rounds, evicted ones included, in increasing order
+    pub fn timestamps(&self) -> Vec<u64> {
+        self.rounds.keys().copied().collect()
+    }
+
+    /// Returns the proof of inclusion of the user at `user_index` in the round registered at `timestamp`, see `Round::get_proof_of_inclusion`
+    pub fn get_proof_of_inclusion(
+        &self,
+        timestamp: u64,
+        user_index: usize,
+    ) -> Result<MstInclusionProof, RegistryError>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        self.get_round(timestamp)?
+            .get_proof_of_inclusion(user_index)
+            .map_err(RegistryError::Round)
+    }
+
+    /// Evicts the round registered at `timestamp`, dropping its tree and its share of the setup artifacts, while keeping its root so that `get_round` can report it.
+    /// Evicting a round already evicted does nothing. Returns an `UnknownRound` error if no round is registered at `timestamp`.
+    pub fn evict(&mut self, timestamp: u64) -> Result<(), RegistryError> {
+        let registered_round = self
+            .rounds
+            .get_mut(&timestamp)
+            .ok_or(RegistryError::UnknownRound(timestamp))?;
+
+        if let RegisteredRound::Served(round) = registered_round {
+            let root = round.get_root();
+            let evicted_round = EvictedRound {
+                timestamp,
+                root_hash: field_element_to_solidity_calldata(root.hash),
+                root_balances: root
+                    .balances
+                    .iter()
+                    .map(|balance| field_element_to_solidity_calldata(*balance))
+                    .collect(),
+            };
+            *registered_round = RegisteredRound::Evicted(evicted_round);
+        }
+        Ok(())
+    }
+
+    /// Evicts the rounds registered before `timestamp`, as `evict` does, returning the timestamps of the rounds newly evicted
+    pub fn evict_older_than(&mut self, timestamp: u64) -> Vec<u64> {
+        let served_timestamps: Vec<u64> = self
+            .rounds
+            .range(..timestamp)
+            .filter(|(_, round)| matches!(round, RegisteredRound::Served(_)))
+            .map(|(timestamp, _)| *timestamp)
+            .collect();
+
+        for timestamp in &served_timestamps {
+            // The timestamps have just been read from the registry
+            self.evict(*timestamp).unwrap();
+        }
+        served_timestamps
+    }
+}
@@ -1,3 +1,4 @@
 pub mod generated;
 pub mod mock;
 pub mod signer;
+pub mod watcher;
@@ -1,15 +1,176 @@
 use ethers::{
+    core::rand::{thread_rng, Rng},
     prelude::SignerMiddleware,
     providers::{Http, Middleware, Provider},
     signers::{LocalWallet, Signer},
-    types::{Address, U256},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockNumber, Bytes,
+        Eip1559TransactionRequest, U256, U64,
+    },
 };
 use serde_json::Value;
-use std::{error::Error, fs::File, io::BufReader, path::Path, str::FromStr, sync::Arc};
+use std::{
+    error::Error, fs::File, future::Future, io::BufReader, path::Path, str::FromStr, sync::Arc,
+    time::Duration,
+};
 use tokio::sync::Mutex;
 
 use super::generated::summa_contract::{AddressOwnershipProof, Cryptocurrency};
+use super::watcher::CommitmentWatcher;
 use crate::contracts::generated::summa_contract::Summa;
+use crate::error::BackendError;
+
+/// How a transaction that failed for a transient reason, e.g. an unreachable node or a nonce taken by another process, is sent again.
+/// The delay before the attempt following attempt `n` is `base_delay * 2^(n - 1)`, plus a random jitter of at most `max_jitter`.
+///
+/// # Fields
+///
+/// * `max_attempts`: The maximum number of times the transaction is sent, including the first attempt
+/// * `base_delay`: The delay before the second attempt
+/// * `max_jitter`: The maximum random delay added to each delay, so that processes retrying at once don't collide again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_jitter: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_jitter: Duration::from_millis(250),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Sends the transaction once, without retrying it
+    pub fn no_retry() -> Self {
+        RetryConfig {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the delay to wait after the failed attempt `attempt`, starting from 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let jitter = if self.max_jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            thread_rng().gen_range(Duration::ZERO..=self.max_jitter)
+        };
+        backoff.saturating_add(jitter)
+    }
+}
+
+/// The reason why an attempt to send a transaction failed, which tells whether it is worth sending it again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmissionError {
+    /// A transient failure, e.g. the node is unreachable or the transaction was dropped. The transaction is sent again with the same nonce,
+    /// so that it can't be mined twice if the failed attempt reached the mempool after all
+    Retryable(String),
+    /// The nonce has been used by another transaction of the account. The transaction is sent again with the next nonce
+    NonceTooLow(String),
+    /// The transaction can't succeed, e.g. it reverted. It isn't sent again
+    Fatal(String),
+}
+
+impl SubmissionError {
+    /// Classifies the failure of an attempt from the error message of the node and whether the transaction reverted
+    pub fn classify(message: String, reverted: bool) -> Self {
+        if reverted {
+            SubmissionError::Fatal(message)
+        } else if message.to_lowercase().contains("nonce too low") {
+            SubmissionError::NonceTooLow(message)
+        } else {
+            SubmissionError::Retryable(message)
+        }
+    }
+}
+
+/// Runs `submit` with `nonce` until it succeeds, fails with a `SubmissionError::Fatal` or has been attempted `config.max_attempts` times,
+/// waiting `config.delay` between two attempts. The nonce is kept on a retryable failure and incremented when it is too low.
+pub async fn submit_with_retry<F, Fut>(
+    config: &RetryConfig,
+    mut nonce: U256,
+    mut submit: F,
+) -> Result<(), BackendError>
+where
+    F: FnMut(U256) -> Fut,
+    Fut: Future<Output = Result<(), SubmissionError>>,
+{
+    let mut attempt = 1;
+    loop {
+        let error = match submit(nonce).await {
+            Ok(()) => return Ok(()),
+            Err(SubmissionError::Fatal(reason)) => {
+                return Err(BackendError::ContractCallFailed(reason))
+            }
+            Err(error) => error,
+        };
+
+        if attempt >= config.max_attempts {
+            let reason = match error {
+                SubmissionError::Retryable(reason)
+                | SubmissionError::NonceTooLow(reason)
+                | SubmissionError::Fatal(reason) => reason,
+            };
+            return Err(BackendError::ContractCallFailed(format!(
+                "{} after {} attempts",
+                reason, attempt
+            )));
+        }
+        if let SubmissionError::NonceTooLow(_) = error {
+            nonce += U256::one();
+        }
+
+        tokio::time::sleep(config.delay(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// The fees and the gas limit of the commitment transaction. The fields left to `None` are filled by the node, as they are by default.
+///
+/// # Fields
+///
+/// * `max_fee_per_gas`: The maximum fee paid per unit of gas, base fee included
+/// * `max_priority_fee_per_gas`: The maximum tip paid per unit of gas to the block producer
+/// * `gas_limit_override`: The gas limit of the transaction, instead of the gas estimated by the node. The transaction isn't sent if the estimated gas exceeds it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeeConfig {
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub gas_limit_override: Option<U256>,
+}
+
+impl FeeConfig {
+    /// Sets the fees and the gas limit of the config on `tx`.
+    /// As the contract calls are built as legacy transactions, `tx` is turned into an EIP-1559 transaction if any of the fees is set.
+    pub fn apply(&self, tx: &mut TypedTransaction) {
+        if self.max_fee_per_gas.is_some() || self.max_priority_fee_per_gas.is_some() {
+            *tx = TypedTransaction::Eip1559(Eip1559TransactionRequest {
+                from: tx.from().copied(),
+                to: tx.to().cloned(),
+                gas: tx.gas().copied(),
+                value: tx.value().copied(),
+                data: tx.data().cloned(),
+                nonce: tx.nonce().copied(),
+                chain_id: tx.chain_id(),
+                max_fee_per_gas: self.max_fee_per_gas,
+                max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+                ..Default::default()
+            });
+        }
+        if let Some(gas_limit) = self.gas_limit_override {
+            tx.set_gas(gas_limit);
+        }
+    }
+}
 
 pub enum AddressInput {
     Address(Address),
@@ -28,15 +189,24 @@ impl SummaSigner {
     /// * `signer_key` - The private key of wallet that will interact with the chain on behalf of the exchange
     /// * `url` -  The endpoint for connecting to the node
     /// * `address` - The address of the Summa contract
+    ///
+    /// Returns a `BackendError::SignerError` if the private key, the url or the deployment file are invalid
     pub async fn new(
         signer_key: &str,
         url: &str,
         address_input: AddressInput,
     ) -> Result<Self, Box<dyn Error>> {
-        let wallet: LocalWallet = LocalWallet::from_str(signer_key).unwrap();
+        let wallet: LocalWallet = LocalWallet::from_str(signer_key)
+            .map_err(|e| BackendError::SignerError(e.to_string()))?;
 
-        let provider = Arc::new(Provider::try_from(url)?);
-        let chain_id = provider.get_chainid().await?.as_u64();
+        let provider = Arc::new(
+            Provider::try_from(url).map_err(|e| BackendError::SignerError(e.to_string()))?,
+        );
+        let chain_id = provider
+            .get_chainid()
+            .await
+            .map_err(contract_call_failed)?
+            .as_u64();
         let client = Arc::new(SignerMiddleware::new(
             provider,
             wallet.with_chain_id(chain_id),
@@ -44,10 +214,7 @@ impl SummaSigner {
 
         let address = match address_input {
             AddressInput::Address(address) => address,
-            AddressInput::Path(path) => {
-                let address = Self::get_deployment_address(path, chain_id).unwrap();
-                address
-            }
+            AddressInput::Path(path) => Self::get_deployment_address(path, chain_id)?,
         };
 
         Ok(Self {
@@ -60,26 +227,35 @@ impl SummaSigner {
         self.summa_contract.address()
     }
 
+    /// Returns a watcher of the commitments submitted to the Summa contract, reading the events through the client of the signer
+    pub fn commitment_watcher(
+        &self,
+    ) -> CommitmentWatcher<SignerMiddleware<Arc<Provider<Http>>, LocalWallet>> {
+        CommitmentWatcher::new(self.summa_contract.address(), self.summa_contract.client())
+    }
+
     fn get_deployment_address<P: AsRef<Path>>(
         path: P,
         chain_id: u64,
     ) -> Result<Address, Box<dyn Error>> {
         // Open file in RO mode with buffer
-        let file = File::open(path)?;
+        let file = File::open(path).map_err(|e| BackendError::SignerError(e.to_string()))?;
         let reader = BufReader::new(file);
 
         // Read the JSON contents of the file
-        let payload: Value = serde_json::from_reader(reader)?;
+        let payload: Value = serde_json::from_reader(reader)
+            .map_err(|e| BackendError::SignerError(e.to_string()))?;
 
         // Retrieve the contract address from the deployments.json file
-        let deployment_address: &Value = &payload.as_object().unwrap()
-            [chain_id.to_string().as_str()]
-        .as_object()
-        .unwrap()["address"];
+        let summa_address = payload[chain_id.to_string().as_str()]["address"]
+            .as_str()
+            .ok_or_else(|| {
+                BackendError::SignerError(format!("No deployment found for chain {}", chain_id))
+            })?;
 
-        let summa_address: &str = deployment_address.as_str().unwrap();
-
-        let address: Address = summa_address.parse().unwrap();
+        let address: Address = summa_address
+            .parse()
+            .map_err(|_| BackendError::SignerError(format!("Invalid address {}", summa_address)))?;
 
         Ok(address)
     }
@@ -95,15 +271,89 @@ impl SummaSigner {
             .submit_proof_of_address_ownership(address_ownership_proofs);
 
         // To prevent nonce collision, we lock the nonce before sending the transaction
-        let tx = submit_proof_of_address_ownership.send().await?;
+        let tx = submit_proof_of_address_ownership
+            .send()
+            .await
+            .map_err(contract_call_failed)?;
 
         // Wait for the pending transaction to be mined
-        tx.await?;
+        tx.await.map_err(contract_call_failed)?;
 
         drop(lock_guard);
         Ok(())
     }
 
+    /// Returns the number of the latest block known by the node
+    pub async fn block_number(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(self
+            .summa_contract
+            .client()
+            .get_block_number()
+            .await
+            .map_err(contract_call_failed)?
+            .as_u64())
+    }
+
+    /// Returns whether there is a contract deployed at the address of the Summa contract
+    pub async fn is_contract_deployed(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let code = self
+            .summa_contract
+            .client()
+            .get_code(self.summa_contract.address(), None)
+            .await
+            .map_err(contract_call_failed)?;
+        Ok(!code.is_empty())
+    }
+
+    /// Returns whether the Summa contract stores a commitment of `root` at `timestamp`
+    pub async fn has_commitment(
+        &self,
+        root: U256,
+        timestamp: U256,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let committed_root = self
+            .summa_contract
+            .commitments(timestamp)
+            .call()
+            .await
+            .map_err(contract_call_failed)?;
+        Ok(committed_root == root)
+    }
+
+    /// Returns whether the Summa contract accepts the inclusion `proof` of `public_inputs` against the commitment at `timestamp`,
+    /// the proof being checked by the inclusion verifier the contract was deployed with
+    pub async fn verify_inclusion_proof(
+        &self,
+        proof: Bytes,
+        public_inputs: Vec<U256>,
+        timestamp: U256,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self
+            .summa_contract
+            .verify_inclusion_proof(proof, public_inputs, timestamp)
+            .call()
+            .await
+            .map_err(contract_call_failed)?)
+    }
+
+    /// Returns the gas estimated by the node for the transaction submitting the commitment of `mst_root` at `timestamp`, as `submit_commitment` would send it
+    pub async fn estimate_commitment_gas(
+        &self,
+        mst_root: U256,
+        root_sums: Vec<U256>,
+        cryptocurrencies: Vec<Cryptocurrency>,
+        timestamp: U256,
+    ) -> Result<U256, Box<dyn std::error::Error>> {
+        Ok(self
+            .summa_contract
+            .submit_commitment(mst_root, root_sums, cryptocurrencies, timestamp)
+            .estimate_gas()
+            .await
+            .map_err(contract_call_failed)?)
+    }
+
+    /// Submits the commitment of `mst_root` at `timestamp` to the Summa contract, retrying it as `RetryConfig::default` does.
+    /// Returns a `BackendError::CommitmentRejected` without sending any transaction if the same commitment has already been submitted.
     pub async fn submit_commitment(
         &self,
         mst_root: U256,
@@ -111,23 +361,219 @@ impl SummaSigner {
         cryptocurrencies: Vec<Cryptocurrency>,
         timestamp: U256,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let lock_guard = self.nonce_lock.lock().await;
-
-        let submit_liability_commitment = &self.summa_contract.submit_commitment(
+        self.submit_commitment_with_retry(
             mst_root,
             root_sums,
             cryptocurrencies,
             timestamp,
-        );
+            &RetryConfig::default(),
+            &FeeConfig::default(),
+        )
+        .await
+    }
+
+    /// Submits the commitment as `submit_commitment` does, retrying the transaction as configured by `retry_config`.
+    /// The transaction is sent with the pending nonce of the account, which is kept on a transient failure and incremented when another transaction took it.
+    /// A reverted transaction is not sent again.
+    ///
+    /// The fees and the gas limit of the transaction are set by `fee_config`. If it overrides the gas limit,
+    /// a `BackendError::GasLimitExceeded` is returned without sending any transaction when the estimated gas exceeds the limit.
+    pub async fn submit_commitment_with_retry(
+        &self,
+        mst_root: U256,
+        root_sums: Vec<U256>,
+        cryptocurrencies: Vec<Cryptocurrency>,
+        timestamp: U256,
+        retry_config: &RetryConfig,
+        fee_config: &FeeConfig,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let lock_guard = self.nonce_lock.lock().await;
+
+        // The check is done under the lock, so that two concurrent submissions of the same commitment can't both pass it
+        if self.has_commitment(mst_root, timestamp).await? {
+            return Err(BackendError::CommitmentRejected {
+                root: mst_root,
+                timestamp,
+            }
+            .into());
+        }
+
+        if let Some(gas_limit) = fee_config.gas_limit_override {
+            let estimated_gas = self
+                .estimate_commitment_gas(
+                    mst_root,
+                    root_sums.clone(),
+                    cryptocurrencies.clone(),
+                    timestamp,
+                )
+                .await?;
+            if estimated_gas > gas_limit {
+                return Err(BackendError::GasLimitExceeded {
+                    estimated_gas,
+                    gas_limit,
+                }
+                .into());
+            }
+        }
+
+        let client = self.summa_contract.client();
+        let nonce = client
+            .get_transaction_count(client.address(), Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(contract_call_failed)?;
 
         // To prevent nonce collision, we lock the nonce before sending the transaction
-        let tx = submit_liability_commitment.send().await?;
+        submit_with_retry(retry_config, nonce, |nonce| {
+            let mut submit_liability_commitment = self
+                .summa_contract
+                .submit_commitment(
+                    mst_root,
+                    root_sums.clone(),
+                    cryptocurrencies.clone(),
+                    timestamp,
+                )
+                .nonce(nonce);
+            fee_config.apply(&mut submit_liability_commitment.tx);
 
-        // Wait for the pending transaction to be mined
-        tx.await?;
+            async move {
+                // A previous attempt that seemed to fail may have been mined after all
+                if let Ok(true) = self.has_commitment(mst_root, timestamp).await {
+                    return Ok(());
+                }
+
+                let tx = submit_liability_commitment
+                    .send()
+                    .await
+                    .map_err(|e| SubmissionError::classify(e.to_string(), e.is_revert()))?;
+
+                // Wait for the pending transaction to be mined
+                match tx.await {
+                    Ok(Some(receipt)) if receipt.status == Some(U64::one()) => Ok(()),
+                    Ok(Some(_)) => Err(SubmissionError::Fatal(
+                        "The commitment transaction reverted".to_string(),
+                    )),
+                    Ok(None) => Err(SubmissionError::Retryable(
+                        "The commitment transaction was dropped from the mempool".to_string(),
+                    )),
+                    Err(e) => Err(SubmissionError::classify(e.to_string(), false)),
+                }
+            }
+        })
+        .await?;
 
         drop(lock_guard);
 
         Ok(())
     }
 }
+
+/// Wraps the error of a call to the node or to the Summa contract
+fn contract_call_failed<E: std::fmt::Display>(error: E) -> BackendError {
+    BackendError::ContractCallFailed(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_delay(max_attempts: u32) -> RetryConfig {
+        RetryConfig {
+            max_attempts,
+            base_delay: Duration::ZERO,
+            max_jitter: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_with_retry() {
+        // The node fails twice before the transaction goes through, and the nonce is kept across the attempts
+        let mut nonces = vec![];
+        let result = submit_with_retry(&no_delay(3), U256::from(7), |nonce| {
+            nonces.push(nonce);
+            let attempt = nonces.len();
+            async move {
+                if attempt < 3 {
+                    Err(SubmissionError::classify(
+                        "connection refused".to_string(),
+                        false,
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(nonces, vec![U256::from(7); 3]);
+
+        // The nonce is bumped when another transaction took it
+        let mut nonces = vec![];
+        let result = submit_with_retry(&no_delay(3), U256::from(7), |nonce| {
+            nonces.push(nonce);
+            async move {
+                if nonce == U256::from(7) {
+                    Err(SubmissionError::classify(
+                        "nonce too low: next nonce 8, tx nonce 7".to_string(),
+                        false,
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(nonces, vec![U256::from(7), U256::from(8)]);
+
+        // A reverted transaction isn't sent again
+        let mut attempts = 0;
+        let result = submit_with_retry(&no_delay(3), U256::zero(), |_| {
+            attempts += 1;
+            async {
+                Err(SubmissionError::classify(
+                    "execution reverted".to_string(),
+                    true,
+                ))
+            }
+        })
+        .await;
+        assert_eq!(
+            result,
+            Err(BackendError::ContractCallFailed(
+                "execution reverted".to_string()
+            ))
+        );
+        assert_eq!(attempts, 1);
+
+        // The last failure is returned once the attempts are exhausted
+        let mut attempts = 0;
+        let result = submit_with_retry(&no_delay(2), U256::zero(), |_| {
+            attempts += 1;
+            async { Err(SubmissionError::Retryable("timeout".to_string())) }
+        })
+        .await;
+        assert_eq!(
+            result,
+            Err(BackendError::ContractCallFailed(
+                "timeout after 2 attempts".to_string()
+            ))
+        );
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let config = RetryConfig {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_jitter: Duration::from_millis(10),
+        };
+
+        for (attempt, backoff) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = config.delay(attempt);
+            assert!(delay >= Duration::from_millis(backoff));
+            assert!(delay <= Duration::from_millis(backoff + 10));
+        }
+        assert_eq!(RetryConfig::no_retry().max_attempts, 1);
+    }
+}
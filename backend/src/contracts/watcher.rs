@@ -0,0 +1,199 @@
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256, U256},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+use super::generated::summa_contract::{Cryptocurrency, Summa};
+use crate::error::BackendError;
+
+/// The interval at which the watcher polls the node for new commitment events, unless set otherwise by `CommitmentWatcher::with_poll_interval`
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The commitment of a round as submitted to the Summa contract, decoded from a `LiabilitiesCommitmentSubmitted` event.
+///
+/// # Fields
+///
+/// * `timestamp`: The timestamp of the round the commitment has been submitted for
+/// * `mst_root`: The hash of the root of the Merkle Sum Tree of the round
+/// * `root_sums`: The balances of the root of the Merkle Sum Tree, one per cryptocurrency
+/// * `cryptocurrencies`: The cryptocurrencies labelling the root sums, in the same order
+/// * `block_number`: The number of the block in which the commitment has been mined
+/// * `transaction_hash`: The hash of the transaction that submitted the commitment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitmentEvent {
+    pub timestamp: U256,
+    pub mst_root: U256,
+    pub root_sums: Vec<U256>,
+    pub cryptocurrencies: Vec<Cryptocurrency>,
+    pub block_number: u64,
+    pub transaction_hash: H256,
+}
+
+/// Watches the commitments submitted to the Summa contract by polling the logs of the node, so that a submission can be confirmed by the event the contract emitted rather than by the receipt of the transaction alone.
+/// The Summa contract doesn't emit any event when an inclusion proof is verified, as the verification is a view call, so that only the commitments are watched.
+#[derive(Debug)]
+pub struct CommitmentWatcher<M> {
+    summa_contract: Summa<M>,
+    from_block: u64,
+    poll_interval: Duration,
+}
+
+impl<M> Clone for CommitmentWatcher<M> {
+    fn clone(&self) -> Self {
+        CommitmentWatcher {
+            summa_contract: self.summa_contract.clone(),
+            from_block: self.from_block,
+            poll_interval: self.poll_interval,
+        }
+    }
+}
+
+impl<M: Middleware> CommitmentWatcher<M> {
+    /// Creates a watcher of the Summa contract deployed at `address`, reading the events from the genesis block through `client`
+    pub fn new(address: Address, client: Arc<M>) -> Self {
+        CommitmentWatcher {
+            summa_contract: Summa::new(address, client),
+            from_block: 0,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets the first block whose events are read, e.g. the latest block before a commitment is submitted
+    pub fn from_block(mut self, block_number: u64) -> Self {
+        self.from_block = block_number;
+        self
+    }
+
+    /// Sets the interval at which the node is polled for new events, `DEFAULT_POLL_INTERVAL` by default
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Returns the commitments submitted between `from_block` and `to_block`, both included, in the order they have been mined.
+    /// If `timestamp` is set, only the commitments of the round at `timestamp` are returned, the timestamp being an indexed topic of the event.
+    pub async fn commitments(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        timestamp: Option<U256>,
+    ) -> Result<Vec<CommitmentEvent>, BackendError> {
+        let mut filter = self
+            .summa_contract
+            .liabilities_commitment_submitted_filter()
+            .from_block(from_block)
+            .to_block(to_block);
+        if let Some(timestamp) = timestamp {
+            filter = filter.topic1(timestamp);
+        }
+
+        let logs = filter
+            .query_with_meta()
+            .await
+            .map_err(|e| BackendError::ContractCallFailed(e.to_string()))?;
+
+        Ok(logs
+            .into_iter()
+            .map(|(event, meta)| CommitmentEvent {
+                timestamp: event.timestamp,
+                mst_root: event.mst_root,
+                root_sums: event.root_balances,
+                cryptocurrencies: event.cryptocurrencies,
+                block_number: meta.block_number.as_u64(),
+                transaction_hash: meta.transaction_hash,
+            })
+            .collect())
+    }
+
+    /// Waits for the first commitment of the round at `timestamp` mined from the first block of the watcher on, polling the node until it is observed.
+    /// Returns a `BackendError::CommitmentNotObserved` if no such commitment has been observed within `timeout`.
+    pub async fn await_commitment(
+        &self,
+        timestamp: U256,
+        timeout: Duration,
+    ) -> Result<CommitmentEvent, BackendError> {
+        let poll = async {
+            let mut next_block = self.from_block;
+            loop {
+                let latest_block = self.latest_block().await?;
+                if latest_block >= next_block {
+                    let events = self
+                        .commitments(next_block, latest_block, Some(timestamp))
+                        .await?;
+                    if let Some(event) = events.into_iter().next() {
+                        return Ok(event);
+                    }
+                    next_block = latest_block + 1;
+                }
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        };
+
+        tokio::time::timeout(timeout, poll)
+            .await
+            .map_err(|_| BackendError::CommitmentNotObserved { timestamp })?
+    }
+
+    /// Streams the commitments mined from the first block of the watcher on, as they are observed by polling the node, e.g. to monitor the submissions of the exchange.
+    /// The commitments are sent in the order they have been mined, and a failure to poll the node is sent as an error before the node is polled again.
+    /// The polling stops once the receiver is dropped.
+    pub fn stream(&self) -> mpsc::Receiver<Result<CommitmentEvent, BackendError>>
+    where
+        M: 'static,
+    {
+        let (sender, receiver) = mpsc::channel(64);
+        let watcher = self.clone();
+
+        tokio::spawn(async move {
+            let mut next_block = watcher.from_block;
+            loop {
+                let events = match watcher.latest_block().await {
+                    Ok(latest_block) if latest_block >= next_block => {
+                        let events = watcher.commitments(next_block, latest_block, None).await;
+                        if events.is_ok() {
+                            next_block = latest_block + 1;
+                        }
+                        events
+                    }
+                    Ok(_) => Ok(vec![]),
+                    Err(e) => Err(e),
+                };
+
+                match events {
+                    Ok(events) => {
+                        for event in events {
+                            if sender.send(Ok(event)).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        if sender.send(Err(e)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+
+                if sender.is_closed() {
+                    return;
+                }
+                tokio::time::sleep(watcher.poll_interval).await;
+            }
+        });
+
+        receiver
+    }
+
+    // Returns the number of the latest block known by the node
+    async fn latest_block(&self) -> Result<u64, BackendError> {
+        Ok(self
+            .summa_contract
+            .client()
+            .get_block_number()
+            .await
+            .map_err(|e| BackendError::ContractCallFailed(e.to_string()))?
+            .as_u64())
+    }
+}
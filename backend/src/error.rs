@@ -0,0 +1,55 @@
+use ethers::types::U256;
+
+/// The failures of the backend when it builds the snapshots of a round or interacts with the Summa contract.
+/// The functions of the backend return them boxed as `Box<dyn Error>`, from which they can be recovered with `downcast_ref::<BackendError>()`,
+/// and `Round::dispatch_commitment` returns them as the source of `RoundError::ContractCall`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendError {
+    /// The Summa contract or the node couldn't be called, or the transaction failed
+    ContractCallFailed(String),
+    /// The signer couldn't be built from its private key, the url of the node or the deployment file
+    SignerError(String),
+    /// The snapshot doesn't match the tree, the asset config, the audit report or the other snapshot it is used with
+    InvalidSnapshot(String),
+    /// The Summa contract already stores the commitment of `root` at `timestamp`, so that it is not submitted again
+    CommitmentRejected { root: U256, timestamp: U256 },
+    /// The gas estimated for the transaction exceeds the gas limit it is configured with, so that it is not sent
+    GasLimitExceeded {
+        estimated_gas: U256,
+        gas_limit: U256,
+    },
+    /// No commitment of the round at `timestamp` has been emitted by the Summa contract before the watcher timed out
+    CommitmentNotObserved { timestamp: U256 },
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::ContractCallFailed(reason) => {
+                write!(f, "Failed to call the Summa contract: {}", reason)
+            }
+            BackendError::SignerError(reason) => write!(f, "Invalid signer: {}", reason),
+            BackendError::InvalidSnapshot(reason) => write!(f, "{}", reason),
+            BackendError::CommitmentRejected { root, timestamp } => write!(
+                f,
+                "Duplicate commitment of root {:#x} at timestamp {}",
+                root, timestamp
+            ),
+            BackendError::GasLimitExceeded {
+                estimated_gas,
+                gas_limit,
+            } => write!(
+                f,
+                "The estimated gas {} exceeds the gas limit {}",
+                estimated_gas, gas_limit
+            ),
+            BackendError::CommitmentNotObserved { timestamp } => write!(
+                f,
+                "No commitment at timestamp {} has been observed on chain",
+                timestamp
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
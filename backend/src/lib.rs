@@ -1,5 +1,8 @@
 #![feature(generic_const_exprs)]
 pub mod apis;
 pub mod contracts;
+pub mod error;
+#[cfg(feature = "metrics")]
+pub mod metrics_server;
 pub mod tests;
 pub use summa_solvency::merkle_sum_tree;
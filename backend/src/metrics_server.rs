@@ -0,0 +1,174 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    task::JoinHandle,
+    time::timeout,
+};
+
+pub const PROOFS_GENERATED_TOTAL: &str = "summa_proofs_generated_total";
+pub const PROOF_GENERATION_DURATION_SECONDS: &str = "summa_proof_generation_duration_seconds";
+pub const COMMITMENT_DISPATCHES_TOTAL: &str = "summa_commitment_dispatches_total";
+pub const COMMITMENT_ERRORS_TOTAL: &str = "summa_commitment_errors_total";
+pub const MST_LEAF_COUNT: &str = "summa_mst_leaf_count";
+
+static PROMETHEUS_HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Returns the handle of the Prometheus recorder, installing it as the global recorder of the `metrics` crate on the first call
+pub fn prometheus_handle() -> &'static PrometheusHandle {
+    PROMETHEUS_HANDLE.get_or_init(|| {
+        let handle = PrometheusBuilder::new()
+            .install_recorder()
+            .expect("no other metrics recorder should be installed");
+
+        describe_counter!(
+            PROOFS_GENERATED_TOTAL,
+            "The number of inclusion proofs generated"
+        );
+        describe_histogram!(
+            PROOF_GENERATION_DURATION_SECONDS,
+            metrics::Unit::Seconds,
+            "The time taken to generate an inclusion proof"
+        );
+        describe_counter!(
+            COMMITMENT_DISPATCHES_TOTAL,
+            "The number of commitments dispatched to the Summa contract"
+        );
+        describe_counter!(
+            COMMITMENT_ERRORS_TOTAL,
+            "The number of commitments whose dispatch failed"
+        );
+        describe_gauge!(
+            MST_LEAF_COUNT,
+            "The number of user leaves of the last dispatched Merkle Sum Tree, not counting the ones padding the tree"
+        );
+
+        handle
+    })
+}
+
+/// How long a scraper has to send its request line, or to receive the response, before its connection is closed
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Binds `port` and spawns a task serving the metrics in the Prometheus text format on `/metrics`, installing the recorder if needed.
+/// Any other path is answered with a 404. Each connection is served on its own task and closed after `CONNECTION_TIMEOUT` without a request,
+/// so that an idle scraper doesn't hold back the other ones.
+///
+/// Returns an error if `port` can't be bound.
+pub async fn start(port: u16) -> std::io::Result<JoinHandle<()>> {
+    let handle = prometheus_handle();
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+
+    Ok(tokio::spawn(async move {
+        loop {
+            let mut stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(_) => continue,
+            };
+
+            tokio::spawn(async move {
+                // The request line fits in the first read, e.g. `GET /metrics HTTP/1.1`
+                let mut request = [0u8; 1024];
+                let read = match timeout(CONNECTION_TIMEOUT, stream.read(&mut request)).await {
+                    Ok(Ok(read)) => read,
+                    _ => return,
+                };
+
+                let response = if request[..read].starts_with(b"GET /metrics ") {
+                    let body = handle.render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                };
+
+                // The scraper may have disconnected, which doesn't concern the next ones
+                let _ = timeout(CONNECTION_TIMEOUT, stream.write_all(response.as_bytes())).await;
+            });
+        }
+    }))
+}
+
+/// Records the generation of an inclusion proof that took `duration`
+pub(crate) fn record_proof_generated(duration: Duration) {
+    counter!(PROOFS_GENERATED_TOTAL).increment(1);
+    histogram!(PROOF_GENERATION_DURATION_SECONDS).record(duration.as_secs_f64());
+}
+
+/// Records the dispatch of the commitment of a tree of `leaf_count` user leaves, not counting the ones padding the tree, failed or not
+pub(crate) fn record_commitment_dispatch(succeeded: bool, leaf_count: usize) {
+    counter!(COMMITMENT_DISPATCHES_TOTAL).increment(1);
+    if !succeeded {
+        counter!(COMMITMENT_ERRORS_TOTAL).increment(1);
+    }
+    gauge!(MST_LEAF_COUNT).set(leaf_count as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::round::Snapshot;
+    use summa_solvency::merkle_sum_tree::MerkleSumTree;
+    use tokio::net::TcpStream;
+
+    /// Returns the value of the counter `name` in the metrics rendered by the recorder, 0 if it hasn't been incremented yet
+    fn counter_value(name: &str) -> u64 {
+        prometheus_handle()
+            .render()
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .map_or(0, |value| value.trim().parse().unwrap())
+    }
+
+    #[test]
+    fn test_proof_generation_metrics() {
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new(Box::new(mst), "ptau/hermez-raw-11").unwrap();
+
+        let proofs_generated = counter_value(PROOFS_GENERATED_TOTAL);
+        snapshot.generate_proof_of_inclusion(0).unwrap();
+
+        // Other tests may generate proofs concurrently
+        assert!(counter_value(PROOFS_GENERATED_TOTAL) > proofs_generated);
+        assert!(prometheus_handle()
+            .render()
+            .contains(&format!("{}_count", PROOF_GENERATION_DURATION_SECONDS)));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_server() {
+        let listener = TcpListener::bind(("0.0.0.0", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // A port that is already bound is reported rather than panicking
+        assert!(start(port).await.is_err());
+        drop(listener);
+
+        let server = start(port).await.unwrap();
+
+        // An idle connection doesn't hold back the next scraper
+        let _idle_stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        timeout(Duration::from_secs(1), stream.read_to_string(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        server.abort();
+    }
+}
@@ -101,24 +101,43 @@ mod test {
     use ethers::{
         abi::AbiEncode,
         providers::{Http, Middleware, Provider},
-        types::{U256, U64},
+        types::{BlockNumber, H160, U256, U64},
         utils::to_checksum,
     };
-    use std::{convert::TryFrom, error::Error};
-    use summa_solvency::merkle_sum_tree::MerkleSumTree;
+    use num_bigint::BigUint;
+    use std::{
+        convert::TryFrom,
+        error::Error,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use summa_solvency::{circuits::types::DecryptionFailed, merkle_sum_tree::MerkleSumTree};
     use tokio::{
         join,
         time::{sleep, Duration},
     };
 
-    use crate::apis::{address_ownership::AddressOwnership, round::Round};
+    use crate::apis::{
+        address_ownership::AddressOwnership,
+        health::{HealthIssue, Severity},
+        onchain_assets::{fetch_onchain_assets, AssetDefinition, AssetSource, OnchainAssetError},
+        proof_store::InMemoryProofStore,
+        rate_limiter::{RateLimitExceeded, RateLimiter},
+        round::{Round, RoundError, Snapshot, UserProofBundle},
+        round_registry::{RegistryError, RoundRegistry},
+    };
     use crate::contracts::{
         generated::summa_contract::{
             AddressOwnershipProof, AddressOwnershipProofSubmittedFilter, Cryptocurrency,
             LiabilitiesCommitmentSubmittedFilter,
         },
-        signer::{AddressInput, SummaSigner},
+        mock::mock_erc20::MockERC20,
+        signer::{AddressInput, FeeConfig, SummaSigner},
+        watcher::CommitmentWatcher,
     };
+    use crate::error::BackendError;
     use crate::tests::initialize_test_env;
 
     #[tokio::test]
@@ -139,6 +158,32 @@ mod test {
 
         assert_eq!(contract_address, signer.get_summa_address());
 
+        // An invalid private key or a missing deployment file are rejected instead of panicking
+        let invalid_key = SummaSigner::new(
+            "not a private key",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(contract_address),
+        )
+        .await;
+        assert!(matches!(
+            invalid_key.err().unwrap().downcast_ref::<BackendError>(),
+            Some(BackendError::SignerError(_))
+        ));
+
+        let missing_deployment = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Path("./src/contracts/missing.json".into()),
+        )
+        .await;
+        assert!(matches!(
+            missing_deployment
+                .err()
+                .unwrap()
+                .downcast_ref::<BackendError>(),
+            Some(BackendError::SignerError(_))
+        ));
+
         Ok(())
     }
 
@@ -296,4 +341,778 @@ mod test {
         drop(anvil);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_duplicate_commitment() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+        let mut round = Round::<4, 2, 8>::new(&signer, Box::new(mst), params_path, 1).unwrap();
+
+        let mst_root: U256 = "0x177bf452ad139f067a64fe09fdc30aae46144d60abfa2ad9f0c70928e29a26d1"
+            .parse()
+            .unwrap();
+        assert!(!signer.has_commitment(mst_root, U256::from(1)).await?);
+
+        round.dispatch_commitment().await?;
+        assert!(signer.has_commitment(mst_root, U256::from(1)).await?);
+        assert!(!signer.has_commitment(mst_root, U256::from(2)).await?);
+
+        // The second submission of the same commitment fails without sending a transaction
+        let outer_provider: Provider<Http> = Provider::try_from(anvil.endpoint().as_str())?;
+        let block_number = outer_provider.get_block_number().await?;
+
+        match round.dispatch_commitment().await.unwrap_err() {
+            RoundError::ContractCall(e) => assert_eq!(
+                e.downcast_ref::<BackendError>(),
+                Some(&BackendError::CommitmentRejected {
+                    root: mst_root,
+                    timestamp: U256::from(1)
+                })
+            ),
+            e => panic!("Unexpected error: {}", e),
+        }
+        assert_eq!(outer_provider.get_block_number().await?, block_number);
+
+        let liability_commitment_logs = summa_contract
+            .liabilities_commitment_submitted_filter()
+            .query()
+            .await?;
+        assert_eq!(liability_commitment_logs.len(), 1);
+
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_commitment_fee_config() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+        let outer_provider: Provider<Http> = Provider::try_from(anvil.endpoint().as_str())?;
+
+        // A gas limit below the estimated gas is refused without sending any transaction
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+        let round = Round::<4, 2, 8>::new(&signer, Box::new(mst), params_path, 1).unwrap();
+        let estimated_gas = round.estimate_commitment_gas().await?;
+        assert!(estimated_gas > U256::zero());
+
+        let mut round = round.with_fee_config(FeeConfig {
+            gas_limit_override: Some(estimated_gas - 1),
+            ..Default::default()
+        });
+        let block_number = outer_provider.get_block_number().await?;
+        match round.dispatch_commitment().await.unwrap_err() {
+            RoundError::ContractCall(e) => assert_eq!(
+                e.downcast_ref::<BackendError>(),
+                Some(&BackendError::GasLimitExceeded {
+                    estimated_gas,
+                    gas_limit: estimated_gas - 1,
+                })
+            ),
+            e => panic!("Unexpected error: {}", e),
+        }
+        assert_eq!(outer_provider.get_block_number().await?, block_number);
+
+        // The configured fees and gas limit are set on the EIP-1559 transaction sent to the node
+        let fee_config = FeeConfig {
+            max_fee_per_gas: Some(U256::from(10_000_000_000u64)),
+            max_priority_fee_per_gas: Some(U256::from(1_000_000_000u64)),
+            gas_limit_override: Some(estimated_gas * 2),
+        };
+        let mut round = round.with_fee_config(fee_config.clone());
+        round.dispatch_commitment().await?;
+
+        let block = outer_provider
+            .get_block_with_txs(BlockNumber::Latest)
+            .await?
+            .unwrap();
+        assert_eq!(block.transactions.len(), 1);
+        let tx = &block.transactions[0];
+        assert_eq!(tx.to, Some(summa_contract.address()));
+        assert_eq!(tx.transaction_type, Some(U64::from(2)));
+        assert_eq!(tx.max_fee_per_gas, fee_config.max_fee_per_gas);
+        assert_eq!(
+            tx.max_priority_fee_per_gas,
+            fee_config.max_priority_fee_per_gas
+        );
+        assert_eq!(Some(tx.gas), fee_config.gas_limit_override);
+
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_round_from_previous() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+        let mut round = Round::<4, 2, 8>::new(&signer, Box::new(mst), params_path, 1).unwrap();
+        round.dispatch_commitment().await?;
+
+        // The next round without any change commits the same root at its own timestamp
+        let mut next_round = Round::new_from_previous(&round, &[], 2).unwrap();
+        assert_eq!(next_round.get_timestamp(), 2);
+        next_round.dispatch_commitment().await?;
+
+        let mst_root: U256 = "0x177bf452ad139f067a64fe09fdc30aae46144d60abfa2ad9f0c70928e29a26d1"
+            .parse()
+            .unwrap();
+        assert!(signer.has_commitment(mst_root, U256::from(1)).await?);
+        assert!(signer.has_commitment(mst_root, U256::from(2)).await?);
+
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_round() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+        let round_dir = std::env::temp_dir().join("summa_test_saved_round");
+
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+        let mut round = Round::<4, 2, 8>::new(&signer, Box::new(mst), params_path, 1).unwrap();
+        round.dispatch_commitment().await?;
+        let proof = round.get_proof_of_inclusion(0)?;
+
+        round.save(&round_dir, "passphrase")?;
+        drop(round);
+
+        // The restored round serves a proof of the same user against the committed root
+        // The proving key isn't saved in plaintext
+        let setup_artifacts = std::fs::read(round_dir.join("inclusion.setup"))?;
+        assert!(setup_artifacts.starts_with(b"SUMMAENC"));
+        assert!(matches!(
+            Round::<4, 2, 8>::load(&round_dir, &signer, "wrong passphrase")
+                .err()
+                .unwrap()
+                .downcast_ref::<DecryptionFailed>(),
+            Some(DecryptionFailed)
+        ));
+
+        let restored_round = Round::<4, 2, 8>::load(&round_dir, &signer, "passphrase")?;
+        assert_eq!(restored_round.get_timestamp(), 1);
+        let restored_proof = restored_round.get_proof_of_inclusion(0)?;
+        assert_eq!(
+            restored_proof.get_public_inputs(),
+            proof.get_public_inputs()
+        );
+
+        let verified = summa_contract
+            .verify_inclusion_proof(
+                restored_proof.get_proof().clone(),
+                restored_proof.get_public_inputs().clone(),
+                U256::from(1),
+            )
+            .await?;
+        assert!(verified);
+
+        // A tree that doesn't match the recorded root is rejected, even if it is a valid tree
+        let other_mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16_modified.csv").unwrap();
+        let other_round =
+            Round::<4, 2, 8>::new(&signer, Box::new(other_mst), params_path, 1).unwrap();
+        let other_dir = std::env::temp_dir().join("summa_test_saved_other_round");
+        other_round.save(&other_dir, "passphrase")?;
+        std::fs::copy(other_dir.join("tree.json"), round_dir.join("tree.json"))?;
+        assert!(matches!(
+            Round::<4, 2, 8>::load(&round_dir, &signer, "passphrase")
+                .err()
+                .unwrap()
+                .downcast_ref::<BackendError>(),
+            Some(BackendError::InvalidSnapshot(_))
+        ));
+
+        std::fs::remove_dir_all(&round_dir)?;
+        std::fs::remove_dir_all(&other_dir)?;
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_round_registry() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+
+        // The first round generates the setup artifacts, which are shared by the next rounds
+        let mut registry = RoundRegistry::<4, 2, 8>::new();
+        let first_mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let first_round =
+            Round::<4, 2, 8>::new(&signer, Box::new(first_mst), params_path, 1).unwrap();
+        let mut rounds = vec![];
+        for (timestamp, entry_csv) in [
+            (2, "../csv/entry_16_modified.csv"),
+            (3, "../csv/entry_16_switched_order.csv"),
+        ] {
+            let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+            rounds.push(Round::new_with_setup_of(
+                &signer,
+                Box::new(mst),
+                timestamp,
+                &first_round,
+            )?);
+        }
+        registry.add_round(first_round)?;
+        for round in rounds {
+            registry.add_round(round)?;
+        }
+        assert_eq!(registry.timestamps(), vec![1, 2, 3]);
+        assert_eq!(registry.latest().unwrap().get_timestamp(), 3);
+
+        // The proof of a user against each round is verified against the commitment of that round
+        for timestamp in [1, 2, 3] {
+            registry
+                .get_round_mut(timestamp)?
+                .dispatch_commitment()
+                .await?;
+        }
+        let mut roots = vec![];
+        for timestamp in [1, 2, 3] {
+            let proof = registry.get_proof_of_inclusion(timestamp, 0)?;
+            let verified = summa_contract
+                .verify_inclusion_proof(
+                    proof.get_proof().clone(),
+                    proof.get_public_inputs().clone(),
+                    U256::from(timestamp),
+                )
+                .await?;
+            assert!(verified);
+            roots.push(proof.get_public_inputs()[2]);
+        }
+        assert_ne!(roots[0], roots[1]);
+        assert_ne!(roots[1], roots[2]);
+
+        // A round can't be registered twice
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let duplicate_round =
+            Round::new_with_setup_of(&signer, Box::new(mst), 3, registry.latest().unwrap())?;
+        assert!(matches!(
+            registry.add_round(duplicate_round),
+            Err(RegistryError::DuplicateRound(3))
+        ));
+
+        // The evicted rounds still exist, but their proofs are no longer served
+        assert_eq!(registry.evict_older_than(3), vec![1, 2]);
+        match registry.get_proof_of_inclusion(1, 0).unwrap_err() {
+            RegistryError::RoundEvicted(evicted_round) => {
+                assert_eq!(evicted_round.timestamp, 1);
+                assert_eq!(evicted_round.root_hash, roots[0]);
+            }
+            e => panic!("Unexpected error: {}", e),
+        }
+        assert!(matches!(
+            registry.get_round(4),
+            Err(RegistryError::UnknownRound(4))
+        ));
+        assert!(registry.get_proof_of_inclusion(3, 0).is_ok());
+        assert_eq!(registry.latest().unwrap().get_timestamp(), 3);
+
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_with_onchain_assets() -> Result<(), Box<dyn Error>> {
+        let (anvil, cex_addr_1, cex_addr_2, client, summa_contract) =
+            initialize_test_env(None).await;
+
+        let mock_erc20 = MockERC20::deploy(Arc::clone(&client), ())
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        mock_erc20
+            .mint(cex_addr_1, U256::from(556863))
+            .send()
+            .await?;
+        mock_erc20
+            .mint(cex_addr_2, U256::from(556863))
+            .send()
+            .await?;
+
+        let custody_addresses = vec![cex_addr_1, cex_addr_2];
+        let asset_definitions = [
+            AssetDefinition {
+                name: "ETH".to_string(),
+                source: AssetSource::Native,
+                custody_addresses: custody_addresses.clone(),
+            },
+            AssetDefinition {
+                name: "USDT".to_string(),
+                source: AssetSource::Erc20(mock_erc20.address()),
+                custody_addresses: custody_addresses.clone(),
+            },
+        ];
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+
+        let block_number = client.get_block_number().await?.as_u64();
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+        let snapshot = Snapshot::<4, 2, 8>::new_with_onchain_assets(
+            Box::new(mst),
+            params_path,
+            &asset_definitions,
+            client.as_ref(),
+            Some(block_number),
+        )
+        .await?;
+
+        // The balances set by `initialize_test_env` and minted above are summed up over both custody addresses
+        let onchain_assets = snapshot.get_onchain_assets().unwrap();
+        assert_eq!(onchain_assets.block_number, block_number);
+        assert_eq!(
+            onchain_assets.asset_sums,
+            [BigUint::from(556864u32), BigUint::from(1113726u32)]
+        );
+
+        // The balances are read at the given block, before any of the tokens was minted
+        let onchain_assets =
+            fetch_onchain_assets(client.as_ref(), &asset_definitions, Some(block_number - 2))
+                .await?;
+        assert_eq!(
+            onchain_assets.asset_sums,
+            [BigUint::from(556864u32), BigUint::from(0u32)]
+        );
+
+        // The Summa contract has code but no `balanceOf` function, and a custody address has no code at all
+        for contract in [summa_contract.address(), cex_addr_1] {
+            let not_erc20 = [
+                asset_definitions[0].clone(),
+                AssetDefinition {
+                    name: "USDT".to_string(),
+                    source: AssetSource::Erc20(contract),
+                    custody_addresses: custody_addresses.clone(),
+                },
+            ];
+            let result = fetch_onchain_assets(client.as_ref(), &not_erc20, None).await;
+            assert!(matches!(
+                result,
+                Err(OnchainAssetError::NotErc20 { contract: c, .. }) if c == contract
+            ));
+        }
+
+        // A balance of the native asset beyond the modulus of the field can't be committed
+        client
+            .provider()
+            .request::<(H160, U256), ()>("anvil_setBalance", (cex_addr_1, U256::MAX))
+            .await?;
+        let result = fetch_onchain_assets(client.as_ref(), &asset_definitions, None).await;
+        assert!(matches!(
+            result,
+            Err(OnchainAssetError::BalanceOverflow { asset }) if asset == "ETH"
+        ));
+
+        drop(anvil);
+
+        // The node is gone
+        let result = fetch_onchain_assets(client.as_ref(), &asset_definitions, None).await;
+        assert!(matches!(result, Err(OnchainAssetError::RpcUnreachable(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_proof_bundle() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+        let round = Round::<4, 2, 8>::new(&signer, Box::new(mst), params_path, 1).unwrap();
+
+        let out_dir = std::env::temp_dir().join("summa_test_export_proof_bundle");
+        let path = round.export_proof_bundle(3, &out_dir)?;
+        assert_eq!(path, out_dir.join("proof_1_3.json"));
+
+        let bundle = UserProofBundle::read(&path)?;
+        assert_eq!(bundle.timestamp, 1);
+        assert_eq!(bundle.contract_address, summa_contract.address());
+        assert_eq!(bundle.leaf_hash, bundle.proof.get_public_inputs()[1]);
+        assert_eq!(
+            bundle
+                .cryptocurrencies
+                .iter()
+                .map(|cryptocurrency| cryptocurrency.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["ETH", "USDT"]
+        );
+        assert_eq!(bundle.proof.get_user_index(), Some(3));
+
+        // The proof read back from the file verifies against the round
+        assert!(round.verify_proof_of_inclusion(&bundle.proof)?);
+
+        std::fs::remove_dir_all(&out_dir)?;
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_round_proof_store() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+
+        let round = Round::<4, 2, 8>::new_with_proof_store(
+            &signer,
+            Box::new(mst),
+            params_path,
+            1,
+            Some(Box::new(InMemoryProofStore::new())),
+        )
+        .unwrap();
+
+        let first_proof = round.get_proof_of_inclusion(0).unwrap();
+        let second_proof = round.get_proof_of_inclusion(0).unwrap();
+
+        // The proof is randomized, so that the same proof bytes are only returned if the prover didn't run again
+        assert_eq!(first_proof.get_proof(), second_proof.get_proof());
+        assert_eq!(
+            first_proof.get_metadata().generated_at,
+            second_proof.get_metadata().generated_at
+        );
+
+        let other_proof = round.get_proof_of_inclusion(1).unwrap();
+        assert_ne!(first_proof.get_proof(), other_proof.get_proof());
+
+        // The proof of a user looked up by username is the one of its index
+        let username_proof = round
+            .get_proof_of_inclusion_by_username("dxGaEAii")
+            .unwrap();
+        assert_eq!(username_proof.get_proof(), first_proof.get_proof());
+        assert_eq!(
+            username_proof.get_public_inputs(),
+            first_proof.get_public_inputs()
+        );
+        assert_eq!(username_proof.get_user_index(), Some(0));
+        assert_eq!(username_proof.get_username(), Some("dxGaEAii"));
+
+        assert!(matches!(
+            round.get_proof_of_inclusion_by_username("unknown"),
+            Err(RoundError::UserNotFound(_))
+        ));
+
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_proof_of_inclusion_async() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+
+        let mut round = Round::<4, 2, 8>::new(&signer, Box::new(mst), params_path, 1)
+            .unwrap()
+            .with_max_concurrent_proofs(2);
+        round.dispatch_commitment().await?;
+
+        // A timer keeps ticking on the single threaded runtime of the test while the proofs are generated
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = {
+            let ticks = Arc::clone(&ticks);
+            tokio::spawn(async move {
+                loop {
+                    sleep(Duration::from_millis(10)).await;
+                    ticks.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        };
+
+        let proofs = join!(
+            round.get_proof_of_inclusion_async(0),
+            round.get_proof_of_inclusion_async(1),
+            round.get_proof_of_inclusion_async(2),
+            round.get_proof_of_inclusion_async(3)
+        );
+        ticker.abort();
+        assert!(ticks.load(Ordering::Relaxed) > 0);
+
+        for proof in [proofs.0, proofs.1, proofs.2, proofs.3] {
+            let proof = proof.unwrap();
+            let verified = summa_contract
+                .verify_inclusion_proof(
+                    proof.get_proof().clone(),
+                    proof.get_public_inputs().clone(),
+                    U256::from(1),
+                )
+                .await?;
+            assert!(verified);
+        }
+
+        assert!(matches!(
+            round.get_proof_of_inclusion_async(16).await,
+            Err(RoundError::InvalidUserIndex { index: 16, max: 15 })
+        ));
+
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_proof_of_inclusion() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+
+        // The proofs are stored, so that only the first request runs the prover
+        let round = Round::<4, 2, 8>::new_with_proof_store(
+            &signer,
+            Box::new(mst),
+            params_path,
+            1,
+            Some(Box::new(InMemoryProofStore::new())),
+        )
+        .unwrap();
+
+        let limiter = RateLimiter::new(5);
+        for _ in 0..5 {
+            assert!(round
+                .get_proof_of_inclusion_rate_limited(0, &limiter)
+                .is_ok());
+        }
+
+        let error = round
+            .get_proof_of_inclusion_rate_limited(0, &limiter)
+            .unwrap_err();
+        let error = error.downcast_ref::<RateLimitExceeded>().unwrap();
+        assert_eq!(error.username, "dxGaEAii");
+        assert!(error.retry_after_secs > 0 && error.retry_after_secs <= 60);
+
+        // Another user isn't limited by the requests of the first one
+        assert!(round
+            .get_proof_of_inclusion_rate_limited(1, &limiter)
+            .is_ok());
+
+        drop(anvil);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_health_check() -> Result<(), Box<dyn Error>> {
+        let (anvil, cex_addr_1, _, _, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let params_path = "ptau/hermez-raw-11";
+        let entry_csv = "../csv/entry_16.csv";
+
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+        let mut round = Round::<4, 2, 8>::new(&signer, Box::new(mst), params_path, 1).unwrap();
+
+        // The only issue of a round not dispatched yet is the missing commitment
+        let issues = round.health_check().await.unwrap_err();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].component, HealthIssue::COMMITMENT);
+        assert_eq!(issues[0].severity, Severity::Warning);
+
+        round.dispatch_commitment().await?;
+
+        let status = round.health_check().await.unwrap();
+        assert!(status.ethereum_connected);
+        assert!(status.contract_reachable);
+        assert!(status.mst_root_matches_commitment);
+        assert!(status.proof_generation_operational);
+
+        // A signer pointing to an account without code can't reach the contract, nor read the commitment
+        let misconfigured_signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(cex_addr_1),
+        )
+        .await?;
+        let mst = MerkleSumTree::<2, 8>::from_csv(entry_csv).unwrap();
+        let misconfigured_round =
+            Round::<4, 2, 8>::new(&misconfigured_signer, Box::new(mst), params_path, 1).unwrap();
+
+        let issues = misconfigured_round.health_check().await.unwrap_err();
+        let components: Vec<&str> = issues
+            .iter()
+            .map(|issue| issue.component.as_str())
+            .collect();
+        assert_eq!(
+            components,
+            vec![HealthIssue::CONTRACT, HealthIssue::COMMITMENT]
+        );
+        assert!(issues
+            .iter()
+            .all(|issue| issue.severity == Severity::Critical));
+
+        // Once the node is down, every check involving it fails, while the proofs can still be generated
+        drop(anvil);
+
+        let issues = round.health_check().await.unwrap_err();
+        let components: Vec<&str> = issues
+            .iter()
+            .map(|issue| issue.component.as_str())
+            .collect();
+        assert_eq!(
+            components,
+            vec![
+                HealthIssue::ETHEREUM,
+                HealthIssue::CONTRACT,
+                HealthIssue::COMMITMENT
+            ]
+        );
+        assert!(issues
+            .iter()
+            .all(|issue| issue.severity == Severity::Critical));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_commitment_watcher() -> Result<(), Box<dyn Error>> {
+        let (anvil, _, _, client, summa_contract) = initialize_test_env(None).await;
+
+        let signer = SummaSigner::new(
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+            anvil.endpoint().as_str(),
+            AddressInput::Address(summa_contract.address()),
+        )
+        .await?;
+
+        let mst = MerkleSumTree::<2, 8>::from_csv("../csv/entry_16.csv").unwrap();
+        let mut round = Round::<4, 2, 8>::new(&signer, Box::new(mst), "ptau/hermez-raw-11", 1)
+            .unwrap()
+            .with_commitment_confirmation(Duration::from_secs(10));
+        let payload = round.build_commitment();
+
+        let watcher = CommitmentWatcher::new(summa_contract.address(), client)
+            .with_poll_interval(Duration::from_millis(50));
+        let mut events = watcher.stream();
+
+        // No commitment has been submitted yet
+        match watcher
+            .await_commitment(U256::from(1), Duration::from_millis(200))
+            .await
+            .unwrap_err()
+        {
+            BackendError::CommitmentNotObserved { timestamp } => {
+                assert_eq!(timestamp, U256::from(1))
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+
+        // The round waits for the event of its commitment and returns the block it has been mined in
+        let block_number = round.dispatch_commitment().await?.unwrap();
+
+        let event = watcher
+            .await_commitment(U256::from(1), Duration::from_secs(10))
+            .await?;
+        assert_eq!(event.block_number, block_number);
+        assert_eq!(event.timestamp, U256::from(1));
+        assert_eq!(event.mst_root, payload.mst_root);
+        assert_eq!(event.root_sums, payload.root_sums);
+        assert_eq!(
+            event.cryptocurrencies,
+            vec![
+                Cryptocurrency {
+                    name: "ETH".to_string(),
+                    chain: "ETH".to_string(),
+                },
+                Cryptocurrency {
+                    name: "USDT".to_string(),
+                    chain: "ETH".to_string(),
+                },
+            ]
+        );
+
+        // The stream observes the same commitment
+        let streamed_event = tokio::time::timeout(Duration::from_secs(10), events.recv())
+            .await?
+            .unwrap()?;
+        assert_eq!(streamed_event, event);
+
+        // The commitments of a block range can be queried at once
+        let commitments = watcher.commitments(0, block_number, None).await?;
+        assert_eq!(commitments, vec![event]);
+
+        Ok(())
+    }
 }
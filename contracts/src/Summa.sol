@@ -9,6 +9,11 @@ import "@openzeppelin/contracts/access/Ownable.sol";
 import "./interfaces/IVerifier.sol";
 
 contract Summa is Ownable {
+    /**
+     * @dev The version of the layout of the public inputs of the inclusion circuit, exposed as its first public input
+     */
+    uint256 public constant INCLUSION_CIRCUIT_VERSION = 2;
+
     /**
      * @dev Struct representing the configuration of the Summa instance
      * @param mstLevels The number of levels of the Merkle sum tree
@@ -188,7 +193,7 @@ contract Summa is Ownable {
     /**
      * Verify the proof of user inclusion into the liabilities tree
      * @param proof ZK proof
-     * @param publicInputs proof inputs
+     * @param publicInputs proof inputs, namely the version of their layout, the leaf hash, the MST root and the root balances
      */
     function verifyInclusionProof(
         bytes memory proof,
@@ -196,12 +201,16 @@ contract Summa is Ownable {
         uint256 timestamp
     ) public view returns (bool) {
         require(
-            commitments[timestamp].mstRoot == publicInputs[1],
+            publicInputs[0] == INCLUSION_CIRCUIT_VERSION,
+            "Unsupported inclusion circuit version"
+        );
+        require(
+            commitments[timestamp].mstRoot == publicInputs[2],
             "Invalid MST root"
         );
-        for (uint i = 2; i < publicInputs.length; i++) {
+        for (uint i = 3; i < publicInputs.length; i++) {
             require(
-                commitments[timestamp].rootBalances[i - 2] == publicInputs[i],
+                commitments[timestamp].rootBalances[i - 3] == publicInputs[i],
                 "Invalid root balance"
             );
         }
@@ -35,6 +35,7 @@ describe("Summa Contract", () => {
   function verifyInclusionProof(
     summa: Summa,
     inclusionProof: string,
+    version: BigNumber,
     leafHash: BigNumber,
     mstRoot: BigNumber,
     balance1: BigNumber,
@@ -42,7 +43,7 @@ describe("Summa Contract", () => {
   ): any {
     return summa.verifyInclusionProof(
       inclusionProof,
-      [leafHash, mstRoot, balance1, balance2],
+      [version, leafHash, mstRoot, balance1, balance2],
       1693559255
     );
   }
@@ -365,6 +366,7 @@ describe("Summa Contract", () => {
     let commitmentMstRoot: BigNumber;
     let rootBalances: BigNumber[];
     let inclusionMstRoot: BigNumber;
+    let version: BigNumber;
     let leafHash: BigNumber;
     let balance1: BigNumber;
     let balance2: BigNumber;
@@ -411,10 +413,11 @@ describe("Summa Contract", () => {
       const inclusionCalldata: any = JSON.parse(inclusionJson);
 
       inclusionProof = inclusionCalldata.proof;
-      leafHash = inclusionCalldata.public_inputs[0];
-      inclusionMstRoot = inclusionCalldata.public_inputs[1];
-      balance1 = inclusionCalldata.public_inputs[2];
-      balance2 = inclusionCalldata.public_inputs[3];
+      version = inclusionCalldata.public_inputs[0];
+      leafHash = inclusionCalldata.public_inputs[1];
+      inclusionMstRoot = inclusionCalldata.public_inputs[2];
+      balance1 = inclusionCalldata.public_inputs[3];
+      balance2 = inclusionCalldata.public_inputs[4];
 
       const commitmentCalldataJson = fs.readFileSync(
         path.resolve(
@@ -436,6 +439,7 @@ describe("Summa Contract", () => {
         await verifyInclusionProof(
           summa,
           inclusionProof,
+          version,
           leafHash,
           inclusionMstRoot,
           balance1,
@@ -444,6 +448,24 @@ describe("Summa Contract", () => {
       ).to.be.equal(true);
     });
 
+    it("should not verify with another circuit version", async () => {
+      version = BigNumber.from(1);
+
+      await summa.submitProofOfAddressOwnership(ownedAddresses);
+      await submitCommitment(summa, commitmentMstRoot, rootBalances);
+      await expect(
+        verifyInclusionProof(
+          summa,
+          inclusionProof,
+          version,
+          leafHash,
+          inclusionMstRoot,
+          balance1,
+          balance2
+        )
+      ).to.be.revertedWith("Unsupported inclusion circuit version");
+    });
+
     it("should not verify with invalid MST root", async () => {
       await summa.submitProofOfAddressOwnership(ownedAddresses);
       await submitCommitment(summa, commitmentMstRoot, rootBalances);
@@ -452,6 +474,7 @@ describe("Summa Contract", () => {
         verifyInclusionProof(
           summa,
           inclusionProof,
+          version,
           leafHash,
           inclusionMstRoot,
           balance1,
@@ -466,6 +489,7 @@ describe("Summa Contract", () => {
         verifyInclusionProof(
           summa,
           inclusionProof,
+          version,
           leafHash,
           inclusionMstRoot,
           balance1,
@@ -483,6 +507,7 @@ describe("Summa Contract", () => {
         verifyInclusionProof(
           summa,
           inclusionProof,
+          version,
           leafHash,
           inclusionMstRoot,
           balance1,
@@ -500,6 +525,7 @@ describe("Summa Contract", () => {
         verifyInclusionProof(
           summa,
           inclusionProof,
+          version,
           leafHash,
           inclusionMstRoot,
           balance1,
@@ -517,6 +543,7 @@ describe("Summa Contract", () => {
         verifyInclusionProof(
           summa,
           inclusionProof,
+          version,
           leafHash,
           inclusionMstRoot,
           balance1,
@@ -0,0 +1,57 @@
+//! Helpers shared by the benches
+use rand::Rng;
+use std::fs::{create_dir_all, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// Returns the path of a csv file of `n_entries` entries with random balances of `n_currencies` cryptocurrencies, in the format of `csv/entry_16.csv`.
+/// The file is generated in `benches/csv` the first time and reused by the next runs. The balances fit in 4 bytes, so that they pass the range check of any `N_BYTES >= 4`.
+pub fn synthetic_csv(n_entries: usize, n_currencies: usize) -> PathBuf {
+    let path = PathBuf::from(format!(
+        "benches/csv/synthetic_{}_entry_{}.csv",
+        n_currencies, n_entries
+    ));
+    if path.exists() {
+        return path;
+    }
+
+    create_dir_all("benches/csv").unwrap();
+    let mut writer = BufWriter::new(File::create(&path).unwrap());
+
+    let header: Vec<String> = (0..n_currencies)
+        .map(|i| format!("balance_CURRENCY{}_ETH", i))
+        .collect();
+    writeln!(writer, "username,{}", header.join(",")).unwrap();
+
+    let mut rng = rand::thread_rng();
+    for i in 0..n_entries {
+        let balances: Vec<String> = (0..n_currencies)
+            .map(|_| rng.gen_range(0..u32::MAX).to_string())
+            .collect();
+        writeln!(writer, "user_{},{}", i, balances.join(",")).unwrap();
+    }
+    writer.flush().unwrap();
+
+    path
+}
+
+/// Resets the peak resident set size of the process to its current resident set size, so that `peak_rss_kb` measures the runs that follow only.
+/// Returns whether the peak could be reset, which requires a Linux kernel supporting `/proc/self/clear_refs`.
+pub fn reset_peak_rss() -> bool {
+    // "5" resets the peak resident set size, see proc(5)
+    std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+/// Returns the peak resident set size of the process in kilobytes since it started or since the last `reset_peak_rss`, as reported by `/proc/self/status`,
+/// or `None` on platforms without procfs
+pub fn peak_rss_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
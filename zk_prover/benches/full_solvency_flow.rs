@@ -110,7 +110,7 @@ fn generate_zk_proof_mst_inclusion_circuit(_c: &mut Criterion) {
     );
     criterion.bench_function(&bench_name, |b| {
         b.iter(|| {
-            full_prover(&params, &pk, circuit.clone(), circuit.instances());
+            full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
         })
     });
 }
@@ -134,7 +134,7 @@ fn verify_zk_proof_mst_inclusion_circuit(_c: &mut Criterion) {
 
     let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
 
-    let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances());
+    let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
 
     println!("proof size in bytes: {}", proof.len());
 
@@ -0,0 +1,84 @@
+#![feature(generic_const_exprs)]
+use num_bigint::BigUint;
+use rand::Rng;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use summa_solvency::merkle_sum_tree::{Cryptocurrency, Entry, MerkleSumTree, Node, Tree};
+
+const LEVELS: usize = 18;
+const N_CURRENCIES: usize = 4;
+const N_BYTES: usize = 8;
+
+/// Allocator that keeps track of the number of bytes currently allocated on the heap
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn main() {
+    let mut rng = rand::thread_rng();
+
+    // Generate 2^LEVELS entries with random balances in range
+    let entries: Vec<Entry<N_CURRENCIES>> = (0..2usize.pow(LEVELS as u32))
+        .map(|i| {
+            Entry::new(
+                format!("user_{}", i),
+                std::array::from_fn(|_| BigUint::from(rng.gen_range(0..u32::MAX))),
+            )
+        })
+        .collect();
+
+    let cryptocurrencies = (0..N_CURRENCIES)
+        .map(|i| Cryptocurrency {
+            name: format!("currency_{}", i),
+            chain: "ETH".to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    let entries_bytes = ALLOCATED.load(Ordering::SeqCst);
+
+    let merkle_sum_tree =
+        MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(entries, cryptocurrencies, false)
+            .unwrap();
+
+    // The entries are moved into the tree, so the difference is the memory held by the nodes
+    let nodes_bytes = ALLOCATED.load(Ordering::SeqCst) - entries_bytes;
+
+    // Memory that a tree storing every level, leaves included, in a `Vec<Vec<Node>>` would hold
+    let nested_layout_bytes = (2usize.pow(LEVELS as u32 + 1) - 1)
+        * std::mem::size_of::<Node<N_CURRENCIES>>()
+        + (LEVELS + 1) * std::mem::size_of::<Vec<Node<N_CURRENCIES>>>();
+
+    println!(
+        "Merkle sum tree of 2 power of {} entries with {} currencies, root hash {:?}",
+        LEVELS,
+        N_CURRENCIES,
+        merkle_sum_tree.root().hash
+    );
+    println!("heap bytes held by the nodes: {}", nodes_bytes);
+    println!(
+        "heap bytes held by a nested layout with stored leaves: {}",
+        nested_layout_bytes
+    );
+    println!(
+        "reduction: {:.1}%",
+        100.0 * (1.0 - nodes_bytes as f64 / nested_layout_bytes as f64)
+    );
+}
@@ -0,0 +1,143 @@
+#![feature(generic_const_exprs)]
+mod common;
+
+use common::{peak_rss_kb, reset_peak_rss, synthetic_csv};
+use criterion::{criterion_group, criterion_main, Criterion};
+use summa_solvency::{
+    circuits::merkle_sum_tree::MstInclusionCircuit,
+    circuits::{
+        utils::{full_prover, full_verifier, generate_setup_artifacts},
+        WithInstances,
+    },
+    merkle_sum_tree::{MerkleSumTree, Tree},
+};
+
+const SAMPLE_SIZE: usize = 10;
+const LEVELS: usize = 4;
+const N_CURRENCIES: usize = 2;
+const N_BYTES: usize = 8;
+
+/// The powers of 2 of the number of entries of the trees built by `build_mstree`
+const TREE_SIZES: [u32; 4] = [10, 12, 14, 16];
+
+/// The sizes of the circuits proven by `prove_mst_inclusion_circuit`, from the smallest `k` fitting the circuit upwards.
+/// The params are generated by an unsafe trusted setup, so that no ptau file is needed.
+const KS: [u32; 3] = [11, 12, 13];
+
+fn build_mstree(_c: &mut Criterion) {
+    let mut criterion = Criterion::default().sample_size(SAMPLE_SIZE);
+
+    for power in TREE_SIZES {
+        let csv_file = synthetic_csv(1 << power, N_CURRENCIES);
+
+        let bench_name = format!(
+            "build Merkle sum tree for 2 power of {} entries with {} currencies",
+            power, N_CURRENCIES
+        );
+        criterion.bench_function(&bench_name, |b| {
+            b.iter(|| {
+                MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(csv_file.to_str().unwrap())
+                    .unwrap();
+            })
+        });
+    }
+}
+
+fn inclusion_circuit() -> MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES> {
+    let csv_file = synthetic_csv(1 << LEVELS, N_CURRENCIES);
+    let merkle_sum_tree =
+        MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(csv_file.to_str().unwrap()).unwrap();
+    let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+
+    MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof)
+}
+
+fn witness_generation_mst_inclusion_circuit(_c: &mut Criterion) {
+    let mut criterion = Criterion::default().sample_size(SAMPLE_SIZE);
+
+    let circuit = inclusion_circuit();
+
+    let bench_name = format!(
+        "generate witness - tree of 2 power of {} entries with {} currencies mst inclusion circuit",
+        LEVELS, N_CURRENCIES
+    );
+    criterion.bench_function(&bench_name, |b| b.iter(|| circuit.profile_synthesis(KS[0])));
+}
+
+fn prove_mst_inclusion_circuit(_c: &mut Criterion) {
+    let mut criterion = Criterion::default().sample_size(SAMPLE_SIZE);
+
+    let circuit = inclusion_circuit();
+
+    for k in KS {
+        // The artifacts of the previous k have been dropped, so that the peak only accounts for the setup and the proofs at this k
+        let peak_reset = reset_peak_rss();
+
+        let (params, pk, _) = generate_setup_artifacts(
+            k,
+            None,
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+        )
+        .unwrap();
+
+        let bench_name = format!(
+            "generate zk proof - tree of 2 power of {} entries with {} currencies mst inclusion circuit at k = {}",
+            LEVELS, N_CURRENCIES, k
+        );
+        criterion.bench_function(&bench_name, |b| {
+            b.iter(|| {
+                full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+            })
+        });
+
+        // Without a reset, the peak is the one of the largest run of the process so far rather than the one of this k
+        match peak_rss_kb() {
+            Some(peak_rss) if peak_reset => {
+                println!("peak RSS of proving at k = {}: {} kB", k, peak_rss)
+            }
+            Some(peak_rss) => println!(
+                "peak RSS of the process after proving at k = {}: {} kB (the peak couldn't be reset)",
+                k, peak_rss
+            ),
+            None => {}
+        }
+    }
+}
+
+fn verify_mst_inclusion_circuit(_c: &mut Criterion) {
+    let mut criterion = Criterion::default().sample_size(SAMPLE_SIZE);
+
+    let circuit = inclusion_circuit();
+
+    let (params, pk, vk) = generate_setup_artifacts(
+        KS[0],
+        None,
+        MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+    )
+    .unwrap();
+    let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+
+    let bench_name = format!(
+        "verify zk proof - tree of 2 power of {} entries with {} currencies mst inclusion circuit at k = {}",
+        LEVELS, N_CURRENCIES, KS[0]
+    );
+    criterion.bench_function(&bench_name, |b| {
+        b.iter(|| {
+            assert!(full_verifier(
+                &params,
+                &vk,
+                proof.clone(),
+                circuit.instances()
+            ));
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    build_mstree,
+    witness_generation_mst_inclusion_circuit,
+    prove_mst_inclusion_circuit,
+    verify_mst_inclusion_circuit,
+);
+criterion_main!(benches);
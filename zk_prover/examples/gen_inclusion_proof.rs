@@ -1,14 +1,11 @@
 #![feature(generic_const_exprs)]
 
-use ethers::abi::parse_abi;
-use ethers::abi::Token::{Array, Bytes, Uint};
 use halo2_solidity_verifier::{compile_solidity, BatchOpenScheme::Bdfg21, Evm, SolidityGenerator};
 use serde_json::to_string_pretty;
 use std::{fs::File, io::Write};
 use summa_solvency::{
     circuits::{
         merkle_sum_tree::MstInclusionCircuit,
-        types::ProofSolidityCallData,
         utils::{gen_proof_solidity_calldata, generate_setup_artifacts},
         WithInstances,
     },
@@ -47,19 +44,12 @@ fn main() {
         .replace("Halo2Verifier", "Verifier");
     let deployment_code = compile_solidity(&verifier_solidity);
 
-    let proof_solidity_calldata = gen_proof_solidity_calldata(&params, &pk, circuit.clone());
+    let proof_solidity_calldata =
+        gen_proof_solidity_calldata(&params, &pk, circuit.clone()).unwrap();
 
-    let proof_hex_string = format!("0x{}", hex::encode(&proof_solidity_calldata.clone().0 .0));
-
-    let calldata_instances = proof_solidity_calldata.1.clone();
-
-    let data = ProofSolidityCallData {
-        proof: proof_hex_string,
-        public_inputs: proof_solidity_calldata.1,
-    };
-
-    // Serialize the data to a JSON string
-    let serialized_data = to_string_pretty(&data).expect("Failed to serialize data");
+    // Serialize the calldata to a JSON string
+    let serialized_data =
+        to_string_pretty(&proof_solidity_calldata).expect("Failed to serialize data");
 
     // Save the serialized data to a JSON file
     let mut file = File::create("./examples/inclusion_proof_solidity_calldata.json")
@@ -67,22 +57,8 @@ fn main() {
     file.write_all(serialized_data.as_bytes())
         .expect("Unable to write data to file");
 
-    let abi = parse_abi(&[
-        "function verifyProof(bytes calldata proof, uint256[] calldata instances) public returns (bool)",
-    ]).expect("Invalid ABI");
-
-    let function = abi.function("verifyProof").unwrap();
-    let calldata_encoded = function
-        .encode_input(&[
-            Bytes(proof_solidity_calldata.0.to_vec()),
-            Array(
-                calldata_instances
-                    .iter()
-                    .map(|&instance| Uint(instance))
-                    .collect(),
-            ),
-        ])
-        .unwrap();
+    // The calldata sent to the verifier is the ABI encoded call to `verifyProof`
+    let calldata_encoded = proof_solidity_calldata.to_abi_encoded().to_vec();
 
     let mut evm = Evm::default();
     let verifier_address = evm.create(deployment_code);
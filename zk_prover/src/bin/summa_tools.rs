@@ -0,0 +1,76 @@
+#![feature(generic_const_exprs)]
+
+use std::env;
+use std::error::Error;
+use std::process;
+
+use summa_solvency::circuits::utils::recommended_k_for_levels;
+use summa_solvency::merkle_sum_tree::utils::optimal_levels;
+
+const USAGE: &str = "Usage: summa-tools suggest-config --users <N> --assets <M> --bytes <B>";
+
+/// The numbers of cryptocurrencies and of bytes for which the circuit is compiled in the tool
+const SUPPORTED_ASSETS: [usize; 4] = [1, 2, 3, 4];
+const SUPPORTED_BYTES: [usize; 2] = [8, 16];
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("suggest-config") => suggest_config(&args[1..]),
+        _ => Err(USAGE.into()),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// Prints the `LEVELS` of the smallest tree fitting the users and the smallest `k` fitting its inclusion circuit
+fn suggest_config(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let users = parse_flag(args, "--users")?;
+    let assets = parse_flag(args, "--assets")?;
+    let bytes = parse_flag(args, "--bytes")?;
+
+    let levels = optimal_levels(users);
+
+    let k = match (assets, bytes) {
+        (1, 8) => recommended_k_for_levels::<1, 8>(levels)?,
+        (2, 8) => recommended_k_for_levels::<2, 8>(levels)?,
+        (3, 8) => recommended_k_for_levels::<3, 8>(levels)?,
+        (4, 8) => recommended_k_for_levels::<4, 8>(levels)?,
+        (1, 16) => recommended_k_for_levels::<1, 16>(levels)?,
+        (2, 16) => recommended_k_for_levels::<2, 16>(levels)?,
+        (3, 16) => recommended_k_for_levels::<3, 16>(levels)?,
+        (4, 16) => recommended_k_for_levels::<4, 16>(levels)?,
+        _ => {
+            return Err(format!(
+                "Unsupported configuration of {} assets and {} bytes, the supported assets are {:?} and the supported bytes are {:?}",
+                assets, bytes, SUPPORTED_ASSETS, SUPPORTED_BYTES
+            )
+            .into())
+        }
+    };
+
+    println!("LEVELS = {}", levels);
+    println!("K = {}", k);
+
+    Ok(())
+}
+
+/// Returns the value following `flag` in `args`, parsed as a number
+fn parse_flag(args: &[String], flag: &str) -> Result<usize, Box<dyn Error>> {
+    let position = args
+        .iter()
+        .position(|arg| arg == flag)
+        .ok_or_else(|| format!("Missing {}\n{}", flag, USAGE))?;
+
+    let value = args
+        .get(position + 1)
+        .ok_or_else(|| format!("Missing value for {}\n{}", flag, USAGE))?;
+
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value).into())
+}
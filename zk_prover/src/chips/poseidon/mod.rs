@@ -1,3 +1,175 @@
 pub mod hash;
 mod poseidon_params;
 pub mod poseidon_spec;
+
+use halo2_gadgets::poseidon::primitives::Spec;
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter},
+    halo2curves::bn256::Fr as Fp,
+    plonk::{Advice, Column, ConstraintSystem, Error as PlonkError, Fixed},
+};
+use std::error::Error;
+use std::fmt::Debug;
+
+use self::{hash::PoseidonChip, poseidon_spec::PoseidonSpec};
+
+/// A Poseidon specification with which the merkle sum tree and the circuits verifying its paths hash the nodes, `PoseidonSpec` being the default one.
+/// The tree and the circuit take the same specification as type parameter, so that the hashes computed off-circuit always match the ones constrained in the circuit.
+///
+/// The specification is implemented for a `Spec` of any width and rate with `impl_tree_spec!`, the width fixing the number of columns of the circuits.
+pub trait TreeSpec: Debug + Clone + Copy + Send + Sync + 'static {
+    /// The width of the permutation, namely its rate plus its capacity
+    const WIDTH: usize;
+    /// The number of field elements absorbed by each permutation
+    const RATE: usize;
+    /// The chip hashing a message of `L` field elements in the circuit
+    type Chip<const L: usize>: TreeHashChip<L>;
+
+    /// Hashes a message of `L` field elements off-circuit
+    fn hash<const L: usize>(message: [Fp; L]) -> Fp;
+
+    /// Returns the constants of the specification
+    fn params() -> PoseidonParams;
+}
+
+/// A chip hashing a message of `L` field elements in the circuit, as `TreeSpec::hash` does off-circuit
+pub trait TreeHashChip<const L: usize>: Debug + Clone {
+    /// Configures the chip with the first `WIDTH` columns of `advices` as state and the next one as partial S-box,
+    /// and the first `2 * WIDTH` columns of `fixed_columns` as round constants
+    fn configure_with_columns(
+        meta: &mut ConstraintSystem<Fp>,
+        advices: &[Column<Advice>],
+        fixed_columns: &[Column<Fixed>],
+    ) -> Self;
+
+    /// Hashes the given input cells. Returns the output cell.
+    fn hash_cells(
+        &self,
+        layouter: impl Layouter<Fp>,
+        input_cells: [AssignedCell<Fp, Fp>; L],
+    ) -> Result<AssignedCell<Fp, Fp>, PlonkError>;
+}
+
+impl<S, const WIDTH: usize, const RATE: usize, const L: usize> TreeHashChip<L>
+    for PoseidonChip<S, WIDTH, RATE, L>
+where
+    S: Spec<Fp, WIDTH, RATE> + Clone,
+{
+    fn configure_with_columns(
+        meta: &mut ConstraintSystem<Fp>,
+        advices: &[Column<Advice>],
+        fixed_columns: &[Column<Fixed>],
+    ) -> Self {
+        let config = Self::configure(
+            meta,
+            advices[0..WIDTH].try_into().unwrap(),
+            advices[WIDTH],
+            fixed_columns[0..WIDTH].try_into().unwrap(),
+            fixed_columns[WIDTH..2 * WIDTH].try_into().unwrap(),
+        );
+        Self::construct(config)
+    }
+
+    fn hash_cells(
+        &self,
+        layouter: impl Layouter<Fp>,
+        input_cells: [AssignedCell<Fp, Fp>; L],
+    ) -> Result<AssignedCell<Fp, Fp>, PlonkError> {
+        self.hash(layouter, input_cells)
+    }
+}
+
+/// Implements `TreeSpec` for a `Spec` of width `$width` and rate `$rate`, e.g. `impl_tree_spec!(PoseidonSpec, 2, 1)`
+#[macro_export]
+macro_rules! impl_tree_spec {
+    ($spec:ty, $width:expr, $rate:expr) => {
+        impl $crate::chips::poseidon::TreeSpec for $spec {
+            const WIDTH: usize = $width;
+            const RATE: usize = $rate;
+            type Chip<const L: usize> =
+                $crate::chips::poseidon::hash::PoseidonChip<$spec, $width, $rate, L>;
+
+            fn hash<const L: usize>(
+                message: [::halo2_proofs::halo2curves::bn256::Fr; L],
+            ) -> ::halo2_proofs::halo2curves::bn256::Fr {
+                ::halo2_gadgets::poseidon::primitives::Hash::<
+                    _,
+                    $spec,
+                    ::halo2_gadgets::poseidon::primitives::ConstantLength<L>,
+                    $width,
+                    $rate,
+                >::init()
+                .hash(message)
+            }
+
+            fn params() -> $crate::chips::poseidon::PoseidonParams {
+                $crate::chips::poseidon::PoseidonParams::from_spec::<$spec, $width, $rate>()
+            }
+        }
+    };
+}
+
+impl_tree_spec!(PoseidonSpec, 2, 1);
+
+/// The constants of a Poseidon specification, given at runtime rather than by a `Spec` type, e.g. to check the parameterization a deployment relies on.
+///
+/// # Fields
+///
+/// * `width`: The width of the permutation, namely its rate plus its capacity
+/// * `full_rounds`: The number of full rounds, half of them performed before the partial rounds and half after
+/// * `partial_rounds`: The number of partial rounds
+/// * `mds_matrix`: The `width` x `width` MDS matrix
+/// * `round_constants`: The `width` round constants of each of the `full_rounds + partial_rounds` rounds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoseidonParams {
+    pub width: usize,
+    pub full_rounds: usize,
+    pub partial_rounds: usize,
+    pub mds_matrix: Vec<Vec<Fp>>,
+    pub round_constants: Vec<Vec<Fp>>,
+}
+
+impl PoseidonParams {
+    /// Returns the constants of the specification `S` of width `T`
+    pub fn from_spec<S: Spec<Fp, T, RATE>, const T: usize, const RATE: usize>() -> Self {
+        let (round_constants, mds_matrix, _) = S::constants();
+        Self {
+            width: T,
+            full_rounds: S::full_rounds(),
+            partial_rounds: S::partial_rounds(),
+            mds_matrix: mds_matrix.iter().map(|row| row.to_vec()).collect(),
+            round_constants: round_constants.iter().map(|round| round.to_vec()).collect(),
+        }
+    }
+
+    /// Checks that the MDS matrix is a `width` x `width` matrix and that there are `width` round constants for each of the `full_rounds + partial_rounds` rounds,
+    /// the number of full rounds being even.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.mds_matrix.len() != self.width
+            || self.mds_matrix.iter().any(|row| row.len() != self.width)
+        {
+            return Err(format!("The MDS matrix must be a {0}x{0} matrix", self.width).into());
+        }
+        if self.full_rounds % 2 != 0 {
+            return Err(format!(
+                "The number of full rounds must be even, got {}",
+                self.full_rounds
+            )
+            .into());
+        }
+        let rounds = self.full_rounds + self.partial_rounds;
+        if self.round_constants.len() != rounds
+            || self
+                .round_constants
+                .iter()
+                .any(|round| round.len() != self.width)
+        {
+            return Err(format!(
+                "Expected {} round constants for each of the {} rounds",
+                self.width, rounds
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
@@ -6,7 +6,10 @@ use halo2_proofs::poly::Rotation;
 
 use std::fmt::Debug;
 
-use super::utils::decompose_fp_to_bytes;
+use super::utils::decompose_fp_to_chunks;
+
+/// Default width in bits of the chunks looked up by the Range Check Chip
+pub const DEFAULT_LOOKUP_BITS: usize = 8;
 
 /// Configuration for the Range Check Chip
 ///
@@ -18,12 +21,26 @@ use super::utils::decompose_fp_to_bytes;
 ///
 /// * `z`: Advice column for the value to be checked and its running sum.
 /// * `lookup_enable_selector`: Selector to enable the lookup check.
+/// * `lookup_bits`: Width in bits of the chunks in which the value is decomposed, the lookup table containing the values from `0` to `2^lookup_bits - 1`.
 ///
 /// Patterned after [halo2_gadgets](https://github.com/privacy-scaling-explorations/halo2/blob/main/halo2_gadgets/src/utilities/decompose_running_sum.rs)
 #[derive(Debug, Copy, Clone)]
 pub struct RangeCheckConfig<const N_BYTES: usize> {
     z: Column<Advice>,
     lookup_enable_selector: Selector,
+    lookup_bits: usize,
+}
+
+impl<const N_BYTES: usize> RangeCheckConfig<N_BYTES> {
+    /// Returns the width in bits of the chunks looked up by the chip
+    pub fn lookup_bits(&self) -> usize {
+        self.lookup_bits
+    }
+
+    /// Returns the number of chunks in which a value is decomposed, namely the number of rows of the running sum besides `z(0)`
+    pub fn n_chunks(&self) -> usize {
+        N_BYTES * 8 / self.lookup_bits
+    }
 }
 
 /// Helper chip that verifies that the value witnessed in a given cell lies within a given range defined by N_BYTES.
@@ -48,6 +65,9 @@ pub struct RangeCheckConfig<const N_BYTES: usize> {
 /// The constraints that are enforced are:
 /// * `z(i) - 2^8⋅z(i+1) ∈ lookup_u8_table` (enabled by lookup_enable_selector at offset [0, N_BYTES - 1])
 /// * `z(N_BYTES) == 0`
+///
+/// The chip can also be configured with wider chunks by `configure_with_lookup_bits`, e.g. 16 bits, in which case the value is decomposed in `N_BYTES * 8 / 16` chunks
+/// looked up in a table of 2^16 rows. It halves the rows of the running sum at the cost of a larger lookup table, which is worth it when `k` is large enough to fit the table.
 #[derive(Debug, Clone)]
 pub struct RangeCheckChip<const N_BYTES: usize> {
     config: RangeCheckConfig<N_BYTES>,
@@ -66,26 +86,56 @@ impl<const N_BYTES: usize> RangeCheckChip<N_BYTES> {
         lookup_u8_table: Column<Fixed>,
         lookup_enable_selector: Selector,
     ) -> RangeCheckConfig<N_BYTES> {
-        meta.annotate_lookup_any_column(lookup_u8_table, || "LOOKUP_MAXBITS_RANGE");
+        Self::configure_with_lookup_bits(
+            meta,
+            z,
+            lookup_u8_table,
+            lookup_enable_selector,
+            DEFAULT_LOOKUP_BITS,
+        )
+    }
+
+    /// Configures the Range Chip to decompose the value in chunks of `lookup_bits` bits.
+    /// `N_BYTES * 8` must be a multiple of `lookup_bits`, so that the range of the accepted values is the same for any chunk width.
+    /// Note: the lookup table should be loaded with values from `0` to `2^lookup_bits - 1` otherwise the range check will fail.
+    pub fn configure_with_lookup_bits(
+        meta: &mut ConstraintSystem<Fp>,
+        z: Column<Advice>,
+        lookup_table: Column<Fixed>,
+        lookup_enable_selector: Selector,
+        lookup_bits: usize,
+    ) -> RangeCheckConfig<N_BYTES> {
+        assert!(
+            lookup_bits > 0 && lookup_bits <= 32,
+            "the lookup bits should be between 1 and 32"
+        );
+        assert_eq!(
+            (N_BYTES * 8) % lookup_bits,
+            0,
+            "the number of bits of the range should be a multiple of the lookup bits"
+        );
+
+        meta.annotate_lookup_any_column(lookup_table, || "LOOKUP_MAXBITS_RANGE");
 
         meta.lookup_any(
-            "range u8 check for difference between each interstitial running sum output",
+            "range check for difference between each interstitial running sum output",
             |meta| {
                 let z_cur = meta.query_advice(z, Rotation::cur());
                 let z_next = meta.query_advice(z, Rotation::next());
 
                 let lookup_enable_selector = meta.query_selector(lookup_enable_selector);
-                let u8_range = meta.query_fixed(lookup_u8_table, Rotation::cur());
+                let chunk_range = meta.query_fixed(lookup_table, Rotation::cur());
 
-                let diff = z_cur - z_next * Expression::Constant(Fp::from(1 << 8));
+                let diff = z_cur - z_next * Expression::Constant(Fp::from(1 << lookup_bits));
 
-                vec![(lookup_enable_selector * diff, u8_range)]
+                vec![(lookup_enable_selector * diff, chunk_range)]
             },
         );
 
         RangeCheckConfig {
             z,
             lookup_enable_selector,
+            lookup_bits,
         }
     }
 
@@ -95,11 +145,14 @@ impl<const N_BYTES: usize> RangeCheckChip<N_BYTES> {
         mut layouter: impl Layouter<Fp>,
         value: &AssignedCell<Fp, Fp>,
     ) -> Result<(), Error> {
+        let lookup_bits = self.config.lookup_bits;
+        let n_chunks = self.config.n_chunks();
+
         layouter.assign_region(
             || "assign value to perform range check",
             |mut region| {
-                // enable the lookup at offset [0, N_BYTES - 1]
-                for i in 0..N_BYTES {
+                // enable the lookup at offset [0, n_chunks - 1]
+                for i in 0..n_chunks {
                     self.config.lookup_enable_selector.enable(&mut region, i)?;
                 }
 
@@ -111,26 +164,26 @@ impl<const N_BYTES: usize> RangeCheckChip<N_BYTES> {
                     0,
                 )?;
 
-                // Decompose the value in #N_BYTES bytes
-                let bytes = value
+                // Decompose the value in #n_chunks chunks of #lookup_bits bits
+                let chunks = value
                     .value()
                     .copied()
-                    .map(|x| decompose_fp_to_bytes(x, N_BYTES))
-                    .transpose_vec(N_BYTES);
+                    .map(|x| decompose_fp_to_chunks(x, n_chunks, lookup_bits))
+                    .transpose_vec(n_chunks);
 
                 // Initialize empty vector to store running sum values [z_0, ..., z_W].
                 let mut zs: Vec<AssignedCell<Fp, Fp>> = vec![z_0.clone()];
                 let mut z = z_0;
 
-                // Assign running sum `z_{i+1}` = (z_i - k_i) / (2^8) for i = 0..=N_BYTES - 1.
-                let two_pow_k_inv = Value::known(Fp::from(1 << 8).invert().unwrap());
+                // Assign running sum `z_{i+1}` = (z_i - k_i) / (2^lookup_bits) for i = 0..=n_chunks - 1.
+                let two_pow_k_inv = Value::known(Fp::from(1 << lookup_bits).invert().unwrap());
 
-                for (i, byte) in bytes.iter().enumerate() {
-                    // z_next = (z_cur - byte) / (2^K)
+                for (i, chunk) in chunks.iter().enumerate() {
+                    // z_next = (z_cur - chunk) / (2^K)
                     let z_next = {
                         let z_cur_val = z.value().copied();
-                        let byte = byte.map(|byte| Fp::from(byte as u64));
-                        let z_next_val = (z_cur_val - byte) * two_pow_k_inv;
+                        let chunk = chunk.map(Fp::from);
+                        let z_next_val = (z_cur_val - chunk) * two_pow_k_inv;
                         region.assign_advice(
                             || format!("z_{:?}", i + 1),
                             self.config.z,
@@ -145,7 +198,7 @@ impl<const N_BYTES: usize> RangeCheckChip<N_BYTES> {
                 }
 
                 // Constrain the final running sum output to be zero.
-                region.constrain_constant(zs[N_BYTES].cell(), Fp::from(0))?;
+                region.constrain_constant(zs[n_chunks].cell(), Fp::from(0))?;
 
                 Ok(())
             },
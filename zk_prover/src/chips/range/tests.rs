@@ -1,5 +1,5 @@
 use crate::{
-    chips::range::range_check::{RangeCheckChip, RangeCheckConfig},
+    chips::range::range_check::{RangeCheckChip, RangeCheckConfig, DEFAULT_LOOKUP_BITS},
     circuits::traits::CircuitBase,
 };
 use halo2_proofs::{
@@ -97,16 +97,22 @@ pub struct TestConfig<const N_BYTES: usize> {
 // The test circuit takes two inputs a and b.
 // It adds them together by using the add chip to produce c = a + b.
 // Performs a range check on a, b and c. Each value should lie in N_BYTES.
+// The range check chip looks up chunks of LOOKUP_BITS bits.
 #[derive(Default, Clone, Debug)]
-struct TestCircuit<const N_BYTES: usize> {
+struct TestCircuit<const N_BYTES: usize, const LOOKUP_BITS: usize = DEFAULT_LOOKUP_BITS> {
     pub a: Fp,
     pub b: Fp,
 }
 
 /// Inherit the `CircuitBase` trait for the `TestCircuit` struct.
-impl<const N_BYTES: usize> CircuitBase for TestCircuit<N_BYTES> {}
+impl<const N_BYTES: usize, const LOOKUP_BITS: usize> CircuitBase
+    for TestCircuit<N_BYTES, LOOKUP_BITS>
+{
+}
 
-impl<const N_BYTES: usize> Circuit<Fp> for TestCircuit<N_BYTES> {
+impl<const N_BYTES: usize, const LOOKUP_BITS: usize> Circuit<Fp>
+    for TestCircuit<N_BYTES, LOOKUP_BITS>
+{
     type Config = TestConfig<N_BYTES>;
     type FloorPlanner = SimpleFloorPlanner;
 
@@ -133,8 +139,13 @@ impl<const N_BYTES: usize> Circuit<Fp> for TestCircuit<N_BYTES> {
         let add_selector = meta.selector();
         let lookup_enable_selector = meta.complex_selector();
 
-        let range_check_config =
-            RangeCheckChip::<N_BYTES>::configure(meta, z, lookup_u8_table, lookup_enable_selector);
+        let range_check_config = RangeCheckChip::<N_BYTES>::configure_with_lookup_bits(
+            meta,
+            z,
+            lookup_u8_table,
+            lookup_enable_selector,
+            LOOKUP_BITS,
+        );
 
         let addchip_config = AddChip::configure(meta, a, b, c, add_selector);
 
@@ -158,7 +169,7 @@ impl<const N_BYTES: usize> Circuit<Fp> for TestCircuit<N_BYTES> {
             addchip.assign(self.a, self.b, layouter.namespace(|| "add chip"))?;
 
         // Load the lookup table
-        self.load(&mut layouter, config.lookup_u8_table)?;
+        self.load_range_table(&mut layouter, config.lookup_u8_table, LOOKUP_BITS)?;
 
         // Initiate the range check chip
         let range_chip = RangeCheckChip::construct(config.range_check_config);
@@ -185,7 +196,7 @@ impl<const N_BYTES: usize> Circuit<Fp> for TestCircuit<N_BYTES> {
 mod testing {
     use super::TestCircuit;
     use halo2_proofs::{
-        dev::{FailureLocation, MockProver, VerifyFailure},
+        dev::{CellValue, FailureLocation, MockProver, VerifyFailure},
         halo2curves::bn256::Fr as Fp,
         plonk::Any,
     };
@@ -272,6 +283,70 @@ mod testing {
         );
     }
 
+    // The same values as in `test_none_overflow_16bits` and `test_overflow_16bits`, range checked with a single 16-bit chunk
+    #[test]
+    fn test_overflow_16bits_with_16bit_lookup() {
+        let k = 17;
+
+        let circuit = TestCircuit::<2, 16> {
+            a: Fp::from((1 << 16) - 2),
+            b: Fp::from(1),
+        };
+        let prover = MockProver::run(k, &circuit, vec![]).unwrap();
+        prover.assert_satisfied();
+
+        let circuit = TestCircuit::<2, 16> {
+            a: Fp::from((1 << 16) - 2),
+            b: Fp::from(2),
+        };
+        let invalid_prover = MockProver::run(k, &circuit, vec![]).unwrap();
+        assert_eq!(
+            invalid_prover.verify(),
+            Err(vec![
+                VerifyFailure::Permutation {
+                    column: (Any::advice(), 0).into(),
+                    location: FailureLocation::InRegion {
+                        region: (4, "assign value to perform range check").into(),
+                        offset: 1
+                    }
+                },
+                VerifyFailure::Permutation {
+                    column: (Any::Fixed, 1).into(),
+                    location: FailureLocation::OutsideRegion { row: 2 }
+                },
+            ])
+        );
+    }
+
+    // Compares the rows of the running sums of an 8-byte range check decomposed in chunks of 8 and 16 bits
+    #[test]
+    fn test_rows_per_lookup_bits() {
+        // Counts the assigned cells of the running sum column
+        fn running_sum_rows(prover: &MockProver<Fp>) -> usize {
+            prover.advice()[0]
+                .iter()
+                .filter(|cell| matches!(cell, CellValue::Assigned(_)))
+                .count()
+        }
+
+        let a = Fp::from(0x1f2f3f4f);
+        let b = Fp::from(1);
+
+        let circuit_8 = TestCircuit::<8, 8> { a, b };
+        let prover_8 = MockProver::run(9, &circuit_8, vec![]).unwrap();
+        prover_8.assert_satisfied();
+
+        let circuit_16 = TestCircuit::<8, 16> { a, b };
+        let prover_16 = MockProver::run(17, &circuit_16, vec![]).unwrap();
+        prover_16.assert_satisfied();
+
+        // Each of the 3 range checked values takes 1 + 64 / LOOKUP_BITS rows
+        let rows_8 = running_sum_rows(&prover_8);
+        let rows_16 = running_sum_rows(&prover_16);
+        assert_eq!(rows_8, 3 * 9);
+        assert_eq!(rows_16, 3 * 5);
+    }
+
     #[cfg(feature = "dev-graph")]
     #[test]
     fn print_range_check_test() {
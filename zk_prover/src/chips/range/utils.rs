@@ -28,6 +28,22 @@ pub fn decompose_fp_to_bytes(value: Fp, n: usize) -> Vec<u8> {
     bytes
 }
 
+/// Converts value Fp to n chunks of `chunk_bits` bits in little endian order, the chunk_bits being at most 64.
+/// As for `decompose_fp_to_bytes`, the chunks are padded with 0s if the value is smaller than n chunks, and the most significant chunks are truncated if it is larger.
+/// Example:
+/// decompose_fp_to_chunks(0x1f2f3f, 2, 16) -> [0x2f3f, 0x1f]
+pub fn decompose_fp_to_chunks(value: Fp, n: usize, chunk_bits: usize) -> Vec<u64> {
+    let value_biguint = fp_to_big_uint(value);
+    let mask = (BigUint::from(1u8) << chunk_bits) - 1u8;
+
+    (0..n)
+        .map(|i| {
+            let chunk = (&value_biguint >> (i * chunk_bits)) & &mask;
+            chunk.to_u64_digits().first().copied().unwrap_or(0)
+        })
+        .collect()
+}
+
 pub fn pow_of_two(by: usize) -> Fp {
     let res = BigUint::from(1u8) << by;
     big_uint_to_fp(&res)
@@ -78,6 +94,18 @@ mod testing {
         assert_eq!(bytes, vec![0x3f, 0x2f]);
     }
 
+    #[test]
+    fn test_decompose_fp_to_chunks() {
+        let f = Fp::from(0x1f2f3f4f);
+        assert_eq!(
+            decompose_fp_to_chunks(f, 4, 8),
+            vec![0x4f, 0x3f, 0x2f, 0x1f]
+        );
+        assert_eq!(decompose_fp_to_chunks(f, 3, 16), vec![0x3f4f, 0x1f2f, 0x00]);
+        // the most significant chunks are truncated
+        assert_eq!(decompose_fp_to_chunks(f, 1, 16), vec![0x3f4f]);
+    }
+
     #[test]
     fn test_pow_2() {
         let pow = pow_of_two(8);
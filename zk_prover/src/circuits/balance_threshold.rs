@@ -0,0 +1,253 @@
+use crate::circuits::merkle_sum_tree::{MstInclusionCircuit, MstInclusionConfig};
+use crate::circuits::traits::{CircuitBase, CircuitId};
+use crate::circuits::types::{CircuitError, CircuitStats};
+use crate::circuits::utils::circuit_stats;
+use crate::circuits::WithInstances;
+use crate::merkle_sum_tree::utils::big_uint_to_fp;
+use crate::merkle_sum_tree::MerkleProof;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use num_bigint::BigUint;
+
+/// Circuit for verifying that the balances of an entry included in a merkle sum tree are above public thresholds, without revealing the balances.
+/// The inclusion path is verified as in `MstInclusionCircuit`, reusing its configuration and chips, but the root balances are not exposed.
+///
+/// For each cryptocurrency, the difference between the balance of the entry and the threshold is range checked to lie within N_BYTES, which enforces `balances[i] >= thresholds[i]`.
+/// The thresholds are range checked as well, so that the sum of a threshold and a difference can't overflow the field.
+/// A threshold of zero holds for any balance, so that a single cryptocurrency is checked by setting the thresholds of the others to zero, see `init`.
+///
+/// The leaf hash of the entry is public, so that the entry must be salted: a verifier could otherwise grind the balances of a known username out of the leaf hash,
+/// defeating the purpose of hiding them behind the thresholds. `init` rejects unsalted entries.
+///
+/// # Type Parameters
+///
+/// * `LEVELS`: The number of levels of the merkle sum tree
+/// * `N_CURRENCIES`: The number of currencies for which the solvency is verified.
+/// * `N_BYTES`: The number of bytes in which the balances and the thresholds should lie
+///
+/// # Fields
+///
+/// * `inclusion`: The inclusion circuit of the entry whose balances are checked
+/// * `thresholds`: The thresholds below which the balances of the entry must not be, one per cryptocurrency
+#[derive(Clone)]
+pub struct BalanceThresholdCircuit<
+    const LEVELS: usize,
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+> where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub inclusion: MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>,
+    pub thresholds: [Fp; N_CURRENCIES],
+}
+
+impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> WithInstances
+    for BalanceThresholdCircuit<LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Returns the number of public inputs of the circuit. It is {2 + N_CURRENCIES}, namely the leaf hash of the entry, the root hash of the merkle sum tree and the thresholds.
+    fn num_instances(&self) -> usize {
+        2 + N_CURRENCIES
+    }
+
+    /// Returns the values of the public inputs of the circuit, namely `[leaf_hash, root_hash, thresholds[0], ..., thresholds[N_CURRENCIES - 1]]`
+    fn instances(&self) -> Vec<Vec<Fp>> {
+        let mut instance = vec![
+            self.inclusion.entry.compute_leaf().hash,
+            self.inclusion.root.hash,
+        ];
+        instance.extend_from_slice(&self.thresholds);
+        vec![instance]
+    }
+}
+
+impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> CircuitBase
+    for BalanceThresholdCircuit<LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+}
+
+impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> CircuitId
+    for BalanceThresholdCircuit<LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    const CIRCUIT_VERSION: u32 = 1;
+
+    fn circuit_id() -> String {
+        format!(
+            "balance-threshold-v{}-levels{}-currencies{}-bytes{}",
+            Self::CIRCUIT_VERSION,
+            LEVELS,
+            N_CURRENCIES,
+            N_BYTES
+        )
+    }
+}
+
+impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    BalanceThresholdCircuit<LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub fn init_empty() -> Self {
+        Self {
+            inclusion: MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+            thresholds: [Fp::zero(); N_CURRENCIES],
+        }
+    }
+
+    /// Initializes the circuit with the merkle proof of the entry of the user and the threshold of the cryptocurrency at `asset_index`.
+    /// The thresholds of the other cryptocurrencies are set to zero.
+    /// Returns an `InvalidWitness` error if `asset_index` is not lower than `N_CURRENCIES` or if the entry is not salted, see `Entry::new_salted`.
+    pub fn init(
+        merkle_proof: MerkleProof<N_CURRENCIES>,
+        asset_index: usize,
+        threshold: BigUint,
+    ) -> Result<Self, CircuitError> {
+        if asset_index >= N_CURRENCIES {
+            return Err(CircuitError::InvalidWitness(format!(
+                "The asset index {} is out of range for {} cryptocurrencies",
+                asset_index, N_CURRENCIES
+            )));
+        }
+        if merkle_proof.entry.salt().is_none() {
+            return Err(CircuitError::InvalidWitness(
+                "The entry must be salted, as its leaf hash is public".to_string(),
+            ));
+        }
+
+        let mut thresholds = [Fp::zero(); N_CURRENCIES];
+        thresholds[asset_index] = big_uint_to_fp(&threshold);
+
+        Ok(Self {
+            inclusion: MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof),
+            thresholds,
+        })
+    }
+
+    /// Returns the number of gates and columns of the circuit and the smallest `k` such that the circuit fits in 2^k rows.
+    /// Returns an error if the circuit can't be synthesized.
+    pub fn constraint_count() -> Result<CircuitStats, CircuitError> {
+        circuit_stats(&Self::init_empty())
+    }
+}
+
+impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> Circuit<Fp>
+    for BalanceThresholdCircuit<LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    type Config = MstInclusionConfig<N_CURRENCIES, N_BYTES>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::init_empty()
+    }
+
+    /// Configures the circuit, in the same way as `MstInclusionCircuit`
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        MstInclusionConfig::<N_CURRENCIES, N_BYTES>::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        // build auxiliary chips
+        let chips = config.construct_chips();
+
+        let (leaf_hash, leaf_balances) =
+            self.inclusion.assign_leaf(&mut layouter, &config, &chips)?;
+
+        // expose the leaf hash as public input
+        self.expose_public(
+            layouter.namespace(|| "public leaf hash"),
+            &leaf_hash,
+            0,
+            config.instance,
+        )?;
+
+        // load lookup table for range check
+        self.load(&mut layouter, config.range_table_column())?;
+
+        let (root_hash, _) = self.inclusion.assign_path(
+            &mut layouter,
+            &config,
+            &chips,
+            leaf_hash,
+            leaf_balances.clone(),
+        )?;
+
+        // expose the root hash as public input, the root balances are kept private
+        self.expose_public(
+            layouter.namespace(|| "public root hash"),
+            &root_hash,
+            1,
+            config.instance,
+        )?;
+
+        for (currency, balance) in leaf_balances.iter().enumerate() {
+            // Assign the threshold and the difference between the balance and the threshold to the circuit
+            let threshold = self.assign_value_to_witness(
+                layouter.namespace(|| format!("currency {}: threshold", currency)),
+                self.thresholds[currency],
+                "threshold",
+                config.advices[1],
+            )?;
+
+            let difference = self.assign_value_to_witness(
+                layouter.namespace(|| format!("currency {}: balance minus threshold", currency)),
+                big_uint_to_fp(&self.inclusion.entry.balances()[currency])
+                    - self.thresholds[currency],
+                "balance minus threshold",
+                config.advices[1],
+            )?;
+
+            // Both are constrained to be within the range defined by N_BYTES, so that the difference can't be negative and their sum can't overflow
+            chips.range_check_chip.assign(
+                layouter.namespace(|| format!("currency {}: range check threshold", currency)),
+                &threshold,
+            )?;
+
+            chips.range_check_chip.assign(
+                layouter.namespace(|| {
+                    format!("currency {}: range check balance minus threshold", currency)
+                }),
+                &difference,
+            )?;
+
+            // The sum of the threshold and the difference must be the balance of the entry
+            let computed_balance = chips.merkle_sum_tree_chip.sum_balances_per_level(
+                layouter.namespace(|| format!("currency {}: compute balance", currency)),
+                &threshold,
+                &difference,
+            )?;
+
+            layouter.assign_region(
+                || format!("currency {}: constrain balance", currency),
+                |mut region| region.constrain_equal(computed_balance.cell(), balance.cell()),
+            )?;
+
+            // expose the threshold as public input
+            self.expose_public(
+                layouter.namespace(|| format!("public threshold {}", currency)),
+                &threshold,
+                2 + currency,
+                config.instance,
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use crate::circuits::merkle_sum_tree::{MstInclusionCircuit, MstInclusionConfig};
+use crate::circuits::traits::{CircuitBase, CircuitId};
+use crate::circuits::types::{CircuitError, CircuitStats};
+use crate::circuits::utils::circuit_stats;
+use crate::circuits::WithInstances;
+use crate::merkle_sum_tree::MerkleProof;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+
+/// Circuit for verifying the inclusion of a batch of entries inside the same merkle sum tree within a single proof.
+/// Each inclusion path is verified as in `MstInclusionCircuit`, reusing its configuration and chips, and every path is constrained to lead to the same root.
+///
+/// # Type Parameters
+///
+/// * `BATCH`: The number of entries of which the inclusion is verified
+/// * `LEVELS`: The number of levels of the merkle sum tree
+/// * `N_CURRENCIES`: The number of currencies for which the solvency is verified.
+/// * `N_BYTES`: The number of bytes in which the balances should lie
+///
+/// # Fields
+///
+/// * `paths`: The inclusion circuits of the entries of the batch, one per entry. They share the same root
+#[derive(Clone)]
+pub struct MstBatchInclusionCircuit<
+    const BATCH: usize,
+    const LEVELS: usize,
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+> where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub paths: Vec<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>,
+}
+
+impl<const BATCH: usize, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    WithInstances for MstBatchInclusionCircuit<BATCH, LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Returns the number of public inputs of the circuit. It is {1 + N_CURRENCIES + BATCH}, namely the root hash of the merkle sum tree, the root balances of the merkle sum tree and the leaf hashes to be verified inclusion of.
+    fn num_instances(&self) -> usize {
+        1 + N_CURRENCIES + BATCH
+    }
+
+    /// Returns the values of the public inputs of the circuit, namely `[root_hash, root_balance[0], ..., root_balance[N_CURRENCIES - 1], leaf_hash[0], ..., leaf_hash[BATCH - 1]]`
+    fn instances(&self) -> Vec<Vec<Fp>> {
+        let root = &self.paths[0].root;
+
+        let mut instance = vec![root.hash];
+        instance.extend_from_slice(&root.balances);
+        instance.extend(self.paths.iter().map(|path| path.entry.compute_leaf().hash));
+        vec![instance]
+    }
+}
+
+impl<const BATCH: usize, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    CircuitBase for MstBatchInclusionCircuit<BATCH, LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+}
+
+impl<const BATCH: usize, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    CircuitId for MstBatchInclusionCircuit<BATCH, LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    const CIRCUIT_VERSION: u32 = 1;
+
+    fn circuit_id() -> String {
+        format!(
+            "mst-batch-inclusion-v{}-batch{}-levels{}-currencies{}-bytes{}",
+            Self::CIRCUIT_VERSION,
+            BATCH,
+            LEVELS,
+            N_CURRENCIES,
+            N_BYTES
+        )
+    }
+}
+
+impl<const BATCH: usize, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    MstBatchInclusionCircuit<BATCH, LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub fn init_empty() -> Self {
+        Self {
+            paths: (0..BATCH)
+                .map(|_| MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty())
+                .collect(),
+        }
+    }
+
+    /// Initializes the circuit with the merkle proofs of the entries of which the inclusion is to be verified. The entries are carried by the proofs.
+    /// There must be exactly `BATCH` proofs, all of them against the same root.
+    pub fn init(merkle_proofs: Vec<MerkleProof<N_CURRENCIES>>) -> Self {
+        assert_eq!(merkle_proofs.len(), BATCH);
+        assert!(
+            merkle_proofs
+                .iter()
+                .all(|proof| proof.root == merkle_proofs[0].root),
+            "the merkle proofs should share the same root"
+        );
+
+        Self {
+            paths: merkle_proofs
+                .into_iter()
+                .map(MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init)
+                .collect(),
+        }
+    }
+
+    /// Returns the number of gates and columns of the circuit and the smallest `k` such that the circuit fits in 2^k rows.
+    /// Returns an error if the circuit can't be synthesized.
+    pub fn constraint_count() -> Result<CircuitStats, CircuitError> {
+        circuit_stats(&Self::init_empty())
+    }
+}
+
+impl<const BATCH: usize, const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    Circuit<Fp> for MstBatchInclusionCircuit<BATCH, LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    type Config = MstInclusionConfig<N_CURRENCIES, N_BYTES>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::init_empty()
+    }
+
+    /// Configures the circuit, in the same way as `MstInclusionCircuit`
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        MstInclusionConfig::<N_CURRENCIES, N_BYTES>::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let chips = config.construct_chips();
+
+        // load lookup table for range check, shared by all the paths
+        self.load(&mut layouter, config.range_table_column())?;
+
+        for (i, path) in self.paths.iter().enumerate() {
+            let mut path_layouter = layouter.namespace(|| format!("path {}", i));
+
+            let (leaf_hash, leaf_balances) =
+                path.assign_leaf(&mut path_layouter, &config, &chips)?;
+
+            // expose the leaf hash of the path as public input
+            self.expose_public(
+                path_layouter.namespace(|| "public leaf hash"),
+                &leaf_hash,
+                1 + N_CURRENCIES + i,
+                config.instance,
+            )?;
+
+            let (root_hash, root_balances) = path.assign_path(
+                &mut path_layouter,
+                &config,
+                &chips,
+                leaf_hash,
+                leaf_balances,
+            )?;
+
+            // constrain the root of every path to the same public root hash and root balances
+            self.expose_public(
+                path_layouter.namespace(|| "public root hash"),
+                &root_hash,
+                0,
+                config.instance,
+            )?;
+
+            for (currency, balance) in root_balances.iter().enumerate() {
+                self.expose_public(
+                    path_layouter.namespace(|| format!("public root balance {}", currency)),
+                    balance,
+                    1 + currency,
+                    config.instance,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
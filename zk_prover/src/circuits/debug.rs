@@ -0,0 +1,84 @@
+//! Debugging helpers to inspect the witness of a circuit, for example when `MockProver::verify()` fails.
+use crate::circuits::merkle_sum_tree::MstInclusionCircuit;
+use crate::circuits::WithInstances;
+use halo2_proofs::dev::{CellValue, MockProver};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::Error;
+use std::path::Path;
+
+/// Snapshot of the advice columns of a circuit witness.
+///
+/// # Fields
+///
+/// * `columns`: The values of the advice columns indexed by column and then by row. Unassigned cells are `None`.
+#[derive(Debug, Clone)]
+pub struct WitnessSnapshot {
+    pub columns: Vec<Vec<Option<Fp>>>,
+}
+
+impl WitnessSnapshot {
+    /// Returns the value assigned to the cell at `column` and `row`, if any
+    pub fn get_value(&self, column: usize, row: usize) -> Option<Fp> {
+        self.columns
+            .get(column)
+            .and_then(|values| values.get(row))
+            .copied()
+            .flatten()
+    }
+
+    /// Writes the snapshot to a CSV file stored at `path`. The file has a `row` column followed by a column per advice column, unassigned cells are left empty.
+    pub fn to_csv(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = csv::Writer::from_path(path)?;
+
+        let mut header = vec!["row".to_string()];
+        header.extend((0..self.columns.len()).map(|column| format!("advice_{}", column)));
+        writer.write_record(&header)?;
+
+        let n_rows = self
+            .columns
+            .iter()
+            .map(|values| values.len())
+            .max()
+            .unwrap_or(0);
+        for row in 0..n_rows {
+            let mut record = vec![row.to_string()];
+            record.extend((0..self.columns.len()).map(|column| {
+                self.get_value(column, row)
+                    .map(|value| format!("{:?}", value))
+                    .unwrap_or_default()
+            }));
+            writer.write_record(&record)?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Runs the `MockProver` on the circuit with its own public inputs and extracts the values of every advice column at each row.
+pub fn extract_witness<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>(
+    circuit: &MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>,
+    k: u32,
+) -> Result<WitnessSnapshot, Error>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    let prover = MockProver::run(k, circuit, circuit.instances())?;
+
+    let columns = prover
+        .advice()
+        .iter()
+        .map(|values| {
+            values
+                .iter()
+                .map(|cell| match cell {
+                    CellValue::Assigned(value) => Some(*value),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(WitnessSnapshot { columns })
+}
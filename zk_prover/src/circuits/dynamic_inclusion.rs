@@ -0,0 +1,174 @@
+use crate::circuits::merkle_sum_tree::{MstInclusionCircuit, MstInclusionConfig};
+use crate::circuits::traits::CircuitId;
+use crate::circuits::types::{CircuitError, CircuitStats};
+use crate::circuits::utils::{circuit_stats, MST_INCLUSION_CIRCUIT_VERSION};
+use crate::circuits::WithInstances;
+use crate::merkle_sum_tree::MerkleProof;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+
+/// The numbers of levels of the merkle sum tree supported by `DynamicMstInclusionCircuit`
+pub const SUPPORTED_LEVELS: [usize; 7] = [4, 8, 16, 20, 24, 28, 32];
+
+/// Circuit for verifying inclusion of an entry inside a merkle sum tree whose number of levels is only known at runtime.
+/// Each variant wraps a `MstInclusionCircuit` monomorphized for one of the `SUPPORTED_LEVELS`, selected at construction time.
+///
+/// The configuration of `MstInclusionCircuit` doesn't depend on the number of levels, so that all the variants share the same `MstInclusionConfig`.
+/// The circuit of a given number of levels has the same verifying key as the corresponding `MstInclusionCircuit`.
+#[derive(Clone)]
+pub enum DynamicMstInclusionCircuit<const N_CURRENCIES: usize, const N_BYTES: usize>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    Levels4(MstInclusionCircuit<4, N_CURRENCIES, N_BYTES>),
+    Levels8(MstInclusionCircuit<8, N_CURRENCIES, N_BYTES>),
+    Levels16(MstInclusionCircuit<16, N_CURRENCIES, N_BYTES>),
+    Levels20(MstInclusionCircuit<20, N_CURRENCIES, N_BYTES>),
+    Levels24(MstInclusionCircuit<24, N_CURRENCIES, N_BYTES>),
+    Levels28(MstInclusionCircuit<28, N_CURRENCIES, N_BYTES>),
+    Levels32(MstInclusionCircuit<32, N_CURRENCIES, N_BYTES>),
+}
+
+/// Applies `$body` to the `MstInclusionCircuit` wrapped by `$circuit`, bound to `$inner`
+macro_rules! delegate {
+    ($circuit:expr, $inner:ident => $body:expr) => {
+        match $circuit {
+            DynamicMstInclusionCircuit::Levels4($inner) => $body,
+            DynamicMstInclusionCircuit::Levels8($inner) => $body,
+            DynamicMstInclusionCircuit::Levels16($inner) => $body,
+            DynamicMstInclusionCircuit::Levels20($inner) => $body,
+            DynamicMstInclusionCircuit::Levels24($inner) => $body,
+            DynamicMstInclusionCircuit::Levels28($inner) => $body,
+            DynamicMstInclusionCircuit::Levels32($inner) => $body,
+        }
+    };
+}
+
+/// Builds the variant of `DynamicMstInclusionCircuit` for `$levels` levels by calling `$init` on `MstInclusionCircuit`
+macro_rules! build {
+    ($levels:expr, $init:ident ( $($arg:expr),* )) => {
+        match $levels {
+            4 => Ok(Self::Levels4(MstInclusionCircuit::$init($($arg),*))),
+            8 => Ok(Self::Levels8(MstInclusionCircuit::$init($($arg),*))),
+            16 => Ok(Self::Levels16(MstInclusionCircuit::$init($($arg),*))),
+            20 => Ok(Self::Levels20(MstInclusionCircuit::$init($($arg),*))),
+            24 => Ok(Self::Levels24(MstInclusionCircuit::$init($($arg),*))),
+            28 => Ok(Self::Levels28(MstInclusionCircuit::$init($($arg),*))),
+            32 => Ok(Self::Levels32(MstInclusionCircuit::$init($($arg),*))),
+            levels => Err(CircuitError::InvalidWitness(format!(
+                "Unsupported number of levels {}, the supported levels are {:?}",
+                levels, SUPPORTED_LEVELS
+            ))
+            .into()),
+        }
+    };
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize>
+    DynamicMstInclusionCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Returns an empty circuit for a tree of `levels` levels, or an error if `levels` is not one of the `SUPPORTED_LEVELS`
+    pub fn init_empty(levels: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        build!(levels, init_empty())
+    }
+
+    /// Initializes the circuit with the merkle proof of the entry of which the inclusion is to be verified, for a tree of `levels` levels.
+    /// Returns an error if `levels` is not one of the `SUPPORTED_LEVELS` or if the merkle proof doesn't have `levels` levels.
+    pub fn init(
+        levels: usize,
+        merkle_proof: MerkleProof<N_CURRENCIES>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if merkle_proof.path_indices.len() != levels {
+            return Err(CircuitError::InvalidWitness(format!(
+                "Expected a merkle proof of {} levels but found {}",
+                levels,
+                merkle_proof.path_indices.len()
+            ))
+            .into());
+        }
+
+        build!(levels, init(merkle_proof))
+    }
+
+    /// Returns the number of levels of the merkle sum tree verified by the circuit
+    pub fn levels(&self) -> usize {
+        delegate!(self, inner => inner.path_indices.len())
+    }
+
+    /// Returns the number of gates and columns of the circuit and the smallest `k` such that the circuit fits in 2^k rows.
+    /// Returns an error if the circuit can't be synthesized.
+    pub fn constraint_count(&self) -> Result<CircuitStats, CircuitError> {
+        circuit_stats(&self.without_witnesses())
+    }
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> WithInstances
+    for DynamicMstInclusionCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    fn num_instances(&self) -> usize {
+        delegate!(self, inner => inner.num_instances())
+    }
+
+    fn instances(&self) -> Vec<Vec<Fp>> {
+        delegate!(self, inner => inner.instances())
+    }
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> CircuitId
+    for DynamicMstInclusionCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    // The variants expose the public inputs of `MstInclusionCircuit`, so that they share its version
+    const CIRCUIT_VERSION: u32 = MST_INCLUSION_CIRCUIT_VERSION;
+
+    fn circuit_id() -> String {
+        // The number of levels is only known at runtime, so that it isn't part of the identifier
+        format!(
+            "mst-inclusion-dynamic-v{}-currencies{}-bytes{}",
+            Self::CIRCUIT_VERSION,
+            N_CURRENCIES,
+            N_BYTES
+        )
+    }
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> Circuit<Fp>
+    for DynamicMstInclusionCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    type Config = MstInclusionConfig<N_CURRENCIES, N_BYTES>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        match self {
+            Self::Levels4(inner) => Self::Levels4(inner.without_witnesses()),
+            Self::Levels8(inner) => Self::Levels8(inner.without_witnesses()),
+            Self::Levels16(inner) => Self::Levels16(inner.without_witnesses()),
+            Self::Levels20(inner) => Self::Levels20(inner.without_witnesses()),
+            Self::Levels24(inner) => Self::Levels24(inner.without_witnesses()),
+            Self::Levels28(inner) => Self::Levels28(inner.without_witnesses()),
+            Self::Levels32(inner) => Self::Levels32(inner.without_witnesses()),
+        }
+    }
+
+    /// Configures the circuit, in the same way as `MstInclusionCircuit` for any number of levels
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        MstInclusionConfig::<N_CURRENCIES, N_BYTES>::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        delegate!(self, inner => inner.synthesize(config, layouter))
+    }
+}
@@ -0,0 +1,97 @@
+//! Machine-readable report of the layout of a circuit, for example to track the growth of the circuits over time in CI.
+use crate::circuits::synthesis_observer::observe_synthesis;
+use crate::circuits::utils::RegionCounter;
+use crate::circuits::WithInstances;
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::Circuit;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// A region of the circuit and the rows spanned by its cells.
+///
+/// # Fields
+///
+/// * `name`: The name of the region, as given to `Layouter::assign_region`
+/// * `first_row`: The first row of the cells of the region. It is `None` for a region without cells
+/// * `last_row`: The last row of the cells of the region. It is `None` for a region without cells
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionExtent {
+    pub name: String,
+    pub first_row: Option<usize>,
+    pub last_row: Option<usize>,
+}
+
+/// Layout of a circuit of size `k`, as returned by `circuit_layout_report`.
+///
+/// # Fields
+///
+/// * `k`: The size of the circuit, which has 2^k rows
+/// * `used_rows`: The number of rows assigned by the circuit, including its lookup tables but not the blinding rows
+/// * `n_advice_columns`: The number of advice columns
+/// * `n_fixed_columns`: The number of fixed columns, not counting the ones added by the selector compression
+/// * `n_instance_columns`: The number of instance columns
+/// * `n_selectors`: The number of selectors
+/// * `n_gates`: The number of custom gates
+/// * `n_lookups`: The number of lookup arguments
+/// * `n_permutation_columns`: The number of columns on which equality constraints are enabled
+/// * `n_copy_constraints`: The number of pairs of cells constrained to be equal during the synthesis
+/// * `n_public_inputs`: The number of public inputs of the circuit
+/// * `regions`: The regions of the circuit, in the order in which they are assigned
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitLayoutReport {
+    pub k: u32,
+    pub used_rows: usize,
+    pub n_advice_columns: usize,
+    pub n_fixed_columns: usize,
+    pub n_instance_columns: usize,
+    pub n_selectors: usize,
+    pub n_gates: usize,
+    pub n_lookups: usize,
+    pub n_permutation_columns: usize,
+    pub n_copy_constraints: usize,
+    pub n_public_inputs: usize,
+    pub regions: Vec<RegionExtent>,
+}
+
+impl CircuitLayoutReport {
+    /// Writes the report as JSON to the file stored at `path`
+    pub fn write_json(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+/// Returns the layout of the circuit of size `k`. The columns, gates and arguments are counted from the constraint system of the circuit,
+/// while the regions and the copy constraints are recorded by synthesizing the circuit with its floor planner, without running the mock prover.
+pub fn circuit_layout_report<C: Circuit<Fp> + WithInstances>(
+    circuit: &C,
+    k: u32,
+) -> CircuitLayoutReport {
+    let (cs, counter) = observe_synthesis(circuit, None, RegionCounter::default())
+        .expect("the circuit should be synthesized");
+
+    CircuitLayoutReport {
+        k,
+        used_rows: counter.last_row.map_or(0, |last_row| last_row + 1),
+        n_advice_columns: cs.num_advice_columns(),
+        n_fixed_columns: cs.num_fixed_columns(),
+        n_instance_columns: cs.num_instance_columns(),
+        n_selectors: cs.num_selectors(),
+        n_gates: cs.gates().len(),
+        n_lookups: cs.lookups().len(),
+        n_permutation_columns: cs.permutation().get_columns().len(),
+        n_copy_constraints: counter.copies,
+        n_public_inputs: circuit.num_instances(),
+        regions: counter
+            .regions
+            .into_iter()
+            .map(|region| RegionExtent {
+                name: region.name,
+                first_row: region.rows.map(|(first_row, _)| first_row),
+                last_row: region.rows.map(|(_, last_row)| last_row),
+            })
+            .collect(),
+    }
+}
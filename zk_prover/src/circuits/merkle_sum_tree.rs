@@ -1,16 +1,27 @@
 use crate::chips::merkle_sum_tree::{MerkleSumTreeChip, MerkleSumTreeConfig};
-use crate::chips::poseidon::hash::{PoseidonChip, PoseidonConfig};
-use crate::chips::poseidon::poseidon_spec::PoseidonSpec;
-use crate::chips::range::range_check::{RangeCheckChip, RangeCheckConfig};
-use crate::circuits::traits::CircuitBase;
+use crate::chips::poseidon::{poseidon_spec::PoseidonSpec, PoseidonParams, TreeHashChip, TreeSpec};
+use crate::chips::range::range_check::{RangeCheckChip, RangeCheckConfig, DEFAULT_LOOKUP_BITS};
+use crate::circuits::synthesis_trace::{synthesis_trace, SynthesisStep};
+use crate::circuits::traits::{CircuitBase, CircuitId};
+use crate::circuits::types::{
+    CircuitError, CircuitStats, CircuitUtilization, ConstraintViolation, InstanceMismatch,
+    SynthesisProfile,
+};
+use crate::circuits::utils::{
+    check_instance_version, circuit_stats, circuit_utilization, required_k, synthesis_profile,
+    MST_INCLUSION_CIRCUIT_VERSION,
+};
+use crate::circuits::witness_check::check_witness;
 use crate::circuits::WithInstances;
 use crate::merkle_sum_tree::utils::big_uint_to_fp;
-use crate::merkle_sum_tree::{Entry, MerkleProof, Node};
+use crate::merkle_sum_tree::{Entry, ForestProof, MerkleProof, Node};
 use halo2_proofs::circuit::{AssignedCell, Layouter, SimpleFloorPlanner};
 use halo2_proofs::halo2curves::bn256::Fr as Fp;
 use halo2_proofs::plonk::{
     Advice, Circuit, Column, ConstraintSystem, Error, Fixed, Instance, Selector,
 };
+use num_bigint::BigUint;
+use std::marker::PhantomData;
 
 /// Circuit for verifying inclusion of an entry (username, balances) inside a merkle sum tree with a given root.
 ///
@@ -19,6 +30,8 @@ use halo2_proofs::plonk::{
 /// * `LEVELS`: The number of levels of the merkle sum tree. In particular, it indicates the number of hashing operations that are performed from the leaf to the root. For example a tree with 16 entries has 4 levels.
 /// * `N_CURRENCIES`: The number of currencies for which the solvency is verified.
 /// * `N_BYTES`: The number of bytes in which the balances should lie
+/// * `LOOKUP_BITS`: The width in bits of the chunks in which the range check chip decomposes the balances. Wider chunks reduce the rows used by the range checks but require a lookup table of 2^LOOKUP_BITS rows, e.g. 16 bits need `k >= 17`. `N_BYTES * 8` must be a multiple of it.
+/// * `S`: The Poseidon specification with which the leaf and the middle nodes are hashed. It must be the one of the `MerkleSumTree` the merkle proof comes from, `PoseidonSpec` by default.
 ///
 /// # Fields
 ///
@@ -27,9 +40,15 @@ use halo2_proofs::plonk::{
 /// * `sibling_leaf_node_hash_preimage`: The preimage of the hash that corresponds to the Sibling Leaf Node (part of the Merkle Proof).
 /// * `sibling_middle_node_hash_preimages`: The preimages of the hashes that corresponds to the Sibling Middle Nodes (part of the Merkle Proof).  
 /// * `root`: The root of the Merkle Sum Tree
+/// * `watermark`: The identifier of the exchange the proof is bound to, if given by `with_watermark`. The public input of the leaf hash is then `H(watermark, leaf_hash)` instead of the leaf hash, see `watermarked_leaf_hash`
 #[derive(Clone)]
-pub struct MstInclusionCircuit<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
-where
+pub struct MstInclusionCircuit<
+    const LEVELS: usize,
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+    const LOOKUP_BITS: usize = DEFAULT_LOOKUP_BITS,
+    S: TreeSpec = PoseidonSpec,
+> where
     [usize; N_CURRENCIES + 1]: Sized,
     [usize; N_CURRENCIES + 2]: Sized,
 {
@@ -38,36 +57,86 @@ where
     pub sibling_leaf_node_hash_preimage: [Fp; N_CURRENCIES + 1],
     pub sibling_middle_node_hash_preimages: Vec<[Fp; N_CURRENCIES + 2]>,
     pub root: Node<N_CURRENCIES>,
+    pub watermark: Option<[u8; 32]>,
+    _spec: PhantomData<S>,
 }
 
-impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> WithInstances
-    for MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>
+impl<
+        const LEVELS: usize,
+        const N_CURRENCIES: usize,
+        const N_BYTES: usize,
+        const LOOKUP_BITS: usize,
+        S: TreeSpec,
+    > WithInstances for MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES, LOOKUP_BITS, S>
 where
     [usize; N_CURRENCIES + 1]: Sized,
     [usize; N_CURRENCIES + 2]: Sized,
 {
-    /// Returns the number of public inputs of the circuit. It is {2 + N_CURRENCIES}, namely the leaf hash to be verified inclusion of, the root hash of the merkle sum tree and the root balances of the merkle sum tree.
+    /// Returns the number of public inputs of the circuit. It is {3 + N_CURRENCIES}, namely the version of the layout of the public inputs, the leaf hash to be verified inclusion of, the root hash of the merkle sum tree and the root balances of the merkle sum tree.
     fn num_instances(&self) -> usize {
-        2 + N_CURRENCIES
+        3 + N_CURRENCIES
     }
-    /// Returns the values of the public inputs of the circuit. Namely the leaf hash to be verified inclusion of and the root hash of the merkle sum tree.
+    /// Returns the values of the public inputs of the circuit, laid out as in `expected_instances`, the leaf hash being watermarked if the circuit has a watermark.
     fn instances(&self) -> Vec<Vec<Fp>> {
-        let mut instance = vec![self.entry.compute_leaf().hash, self.root.hash];
-        instance.extend_from_slice(&self.root.balances);
-        vec![instance]
+        let mut instances = Self::expected_instances(&self.entry, &self.root);
+        if let Some(watermark) = &self.watermark {
+            instances[0][1] = Self::watermarked_leaf_hash(watermark, instances[0][1]);
+        }
+        instances
     }
 }
 
-impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> CircuitBase
-    for MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>
+impl<
+        const LEVELS: usize,
+        const N_CURRENCIES: usize,
+        const N_BYTES: usize,
+        const LOOKUP_BITS: usize,
+        S: TreeSpec,
+    > CircuitBase for MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES, LOOKUP_BITS, S>
 where
     [usize; N_CURRENCIES + 1]: Sized,
     [usize; N_CURRENCIES + 2]: Sized,
 {
 }
 
-impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
-    MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>
+impl<
+        const LEVELS: usize,
+        const N_CURRENCIES: usize,
+        const N_BYTES: usize,
+        const LOOKUP_BITS: usize,
+        S: TreeSpec,
+    > CircuitId for MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES, LOOKUP_BITS, S>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    const CIRCUIT_VERSION: u32 = MST_INCLUSION_CIRCUIT_VERSION;
+
+    fn circuit_id() -> String {
+        // The Poseidon specification is identified by its number of full and partial rounds, its width and its rate
+        let params = S::params();
+        format!(
+            "mst-inclusion-v{}-levels{}-currencies{}-bytes{}-lookup{}-poseidon{}x{}w{}r{}",
+            Self::CIRCUIT_VERSION,
+            LEVELS,
+            N_CURRENCIES,
+            N_BYTES,
+            LOOKUP_BITS,
+            params.full_rounds,
+            params.partial_rounds,
+            S::WIDTH,
+            S::RATE
+        )
+    }
+}
+
+impl<
+        const LEVELS: usize,
+        const N_CURRENCIES: usize,
+        const N_BYTES: usize,
+        const LOOKUP_BITS: usize,
+        S: TreeSpec,
+    > MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES, LOOKUP_BITS, S>
 where
     [usize; N_CURRENCIES + 1]: Sized,
     [usize; N_CURRENCIES + 2]: Sized,
@@ -79,7 +148,163 @@ where
             sibling_leaf_node_hash_preimage: [Fp::zero(); N_CURRENCIES + 1],
             sibling_middle_node_hash_preimages: vec![[Fp::zero(); N_CURRENCIES + 2]; LEVELS],
             root: Node::init_empty(),
+            watermark: None,
+            _spec: PhantomData,
+        }
+    }
+
+    /// Returns an empty circuit verifying a path of `levels` levels instead of `LEVELS`, so that the rows of the inclusion circuit of a tree whose depth is only known at runtime can be counted,
+    /// see `recommended_k_for_levels`. The circuit is only meant to be synthesized, its `LEVELS` being meaningless.
+    pub(crate) fn init_empty_with_levels(levels: usize) -> Self {
+        Self {
+            path_indices: vec![Fp::zero(); levels],
+            sibling_middle_node_hash_preimages: vec![[Fp::zero(); N_CURRENCIES + 2]; levels],
+            ..Self::init_empty()
+        }
+    }
+
+    /// Checks that the circuit hashes with the Poseidon constants `params`, e.g. the ones of the parameterization a deployment relies on.
+    /// The constants of the Poseidon chips are fixed when the circuit is configured, namely by the specification `S`, so that they can't be overridden at runtime:
+    /// another parameterization is selected by implementing `TreeSpec` for it, see `impl_tree_spec`, and building the tree and the circuit with it.
+    ///
+    /// The structure of `params` is validated first, so that malformed constants are diagnosed as such rather than reported as different from the ones of `S`.
+    ///
+    /// Returns an `InvalidPoseidonParams` error if `params` are malformed or have another width than the one of `S`, and a `PoseidonParamsMismatch` error if they differ from the constants of `S`.
+    pub fn check_poseidon_params(&self, params: &PoseidonParams) -> Result<(), CircuitError> {
+        params
+            .validate()
+            .map_err(|e| CircuitError::InvalidPoseidonParams(e.to_string()))?;
+        if params.width != S::WIDTH {
+            return Err(CircuitError::InvalidPoseidonParams(format!(
+                "The Poseidon width of the circuit is {}, got {}",
+                S::WIDTH,
+                params.width
+            )));
         }
+        if *params != S::params() {
+            return Err(CircuitError::PoseidonParamsMismatch);
+        }
+        Ok(())
+    }
+
+    /// Binds the proofs of the circuit to the exchange identified by `watermark`, so that a leaked proving key can't be used to forge proofs attributed to another exchange.
+    /// The public input of the leaf hash becomes `H(watermark, leaf_hash)`, which a verifier recomputes from the identifier of the exchange with `watermarked_leaf_hash`.
+    ///
+    /// The watermark is assigned from a constant, so that it is fixed in the verifying key: the setup artifacts must be generated from an empty circuit with the same watermark,
+    /// and a proving key generated for a watermark can't produce a valid proof for another one.
+    pub fn with_watermark(mut self, watermark: [u8; 32]) -> Self {
+        self.watermark = Some(watermark);
+        self
+    }
+
+    /// Returns `H(watermark, leaf_hash)`, the public input of the leaf hash of a circuit with the given `watermark`.
+    /// The watermark is read as a big endian integer reduced modulo the order of the field, and hashed with the leaf hash by the Poseidon hash of the middle nodes, padded with zeros.
+    pub fn watermarked_leaf_hash(watermark: &[u8; 32], leaf_hash: Fp) -> Fp {
+        let mut preimage = [Fp::zero(); N_CURRENCIES + 2];
+        preimage[0] = watermark_to_fp(watermark);
+        preimage[1] = leaf_hash;
+        S::hash(preimage)
+    }
+
+    /// Returns the public inputs of the circuit verifying the inclusion of `entry` in a tree with the given `root`.
+    /// The layout is `[version, leaf_hash, root_hash, root_balance[0], ..., root_balance[N_CURRENCIES - 1]]`, in a single instance column,
+    /// where `version` is `MST_INCLUSION_CIRCUIT_VERSION`, see `check_instance_version`.
+    pub fn expected_instances(
+        entry: &Entry<N_CURRENCIES>,
+        root: &Node<N_CURRENCIES>,
+    ) -> Vec<Vec<Fp>> {
+        let mut instance = vec![
+            Fp::from(MST_INCLUSION_CIRCUIT_VERSION as u64),
+            entry.compute_leaf_with_spec::<S>().hash,
+            root.hash,
+        ];
+        instance.extend_from_slice(&root.balances);
+        vec![instance]
+    }
+
+    /// Checks `instances` against the public inputs of the circuit, reporting the first slot that disagrees.
+    pub fn validate_instances(&self, instances: &[Vec<Fp>]) -> Result<(), InstanceMismatch> {
+        let expected = self.instances();
+
+        if instances.len() != expected.len() {
+            return Err(InstanceMismatch::Length {
+                expected: expected.len(),
+                found: instances.len(),
+            });
+        }
+        let (expected, found) = (&expected[0], &instances[0]);
+        if found.len() != expected.len() {
+            return Err(InstanceMismatch::Length {
+                expected: expected.len(),
+                found: found.len(),
+            });
+        }
+
+        check_instance_version(instances)?;
+        if found[1] != expected[1] {
+            return Err(InstanceMismatch::LeafHash);
+        }
+        if found[2] != expected[2] {
+            return Err(InstanceMismatch::RootHash);
+        }
+        match (0..N_CURRENCIES).find(|i| found[3 + i] != expected[3 + i]) {
+            Some(index) => Err(InstanceMismatch::RootBalance(index)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the number of gates and columns of the circuit and the smallest `k` such that the circuit fits in 2^k rows.
+    /// Returns an error if the circuit can't be synthesized.
+    pub fn constraint_count() -> Result<CircuitStats, CircuitError> {
+        circuit_stats(&Self::init_empty())
+    }
+
+    /// Returns the share of the 2^`k` rows used by the circuit and the regions spanning the most rows, to choose `k` for production.
+    /// Returns an error if `k` is too large or if the circuit can't be synthesized, see `circuit_utilization`.
+    pub fn utilization_report(k: u32) -> Result<CircuitUtilization, CircuitError> {
+        circuit_utilization(&Self::init_empty(), k)
+    }
+
+    /// Returns the wall-clock time spent computing the witness of each region of the circuit of size `k`, e.g. the Poseidon permutations versus the balance accumulations,
+    /// sorted from the slowest region to the fastest. See `synthesis_profile`.
+    pub fn profile_synthesis(&self, k: u32) -> SynthesisProfile {
+        synthesis_profile(self, k)
+    }
+
+    /// Returns the gates applied while the circuit of size `k` is synthesized, in order, e.g. to follow the swap of the hashes and the range checks of the balances at each level.
+    /// See `synthesis_trace`.
+    pub fn trace_synthesis(&self, k: u32) -> Vec<SynthesisStep> {
+        synthesis_trace(self, k)
+    }
+
+    /// Returns the smallest `k` such that the circuit fits in 2^k rows, blinding rows included.
+    /// The rows are counted by a single synthesis of the empty circuit, see `required_k`.
+    /// Returns an error if the circuit can't be synthesized.
+    pub fn minimum_k() -> Result<u32, CircuitError> {
+        Ok(required_k(&Self::init_empty())?.0)
+    }
+
+    /// Returns the `k` recommended to run the circuit, namely the smallest `k` that fits the circuit with an extra bit of headroom.
+    pub fn recommended_k() -> Result<u32, CircuitError> {
+        Ok(Self::constraint_count()?.min_k + 1)
+    }
+
+    /// Checks that the witness of the circuit satisfies all the constraints of the circuit, given its own public inputs.
+    /// See `check_witness_with_instances`.
+    pub fn check_witness(&self) -> Result<(), Vec<ConstraintViolation>> {
+        self.check_witness_with_instances(self.instances())
+    }
+
+    /// Checks that the witness of the circuit satisfies all the gate constraints, lookups and equality constraints of the circuit, given the public inputs `instances`.
+    /// Unlike `preflight_check_with_instances`, it doesn't run the MockProver: the circuit is synthesized once and each constraint is evaluated only at the rows where it is enabled,
+    /// so that no column of 2^k rows is built, see `witness_check::check_witness`.
+    ///
+    /// Returns the list of the violated constraints otherwise.
+    pub fn check_witness_with_instances(
+        &self,
+        instances: Vec<Vec<Fp>>,
+    ) -> Result<(), Vec<ConstraintViolation>> {
+        check_witness(self, &instances)
     }
 
     /// Initializes the circuit with the merkle proof and the entry of the user of which the inclusion is to be verified.
@@ -99,157 +324,80 @@ where
             sibling_leaf_node_hash_preimage: merkle_proof.sibling_leaf_node_hash_preimage,
             sibling_middle_node_hash_preimages: merkle_proof.sibling_middle_node_hash_preimages,
             root: merkle_proof.root,
+            watermark: None,
+            _spec: PhantomData,
         }
     }
-}
-
-/// Configuration for the Mst Inclusion circuit
-/// # Type Parameters
-///
-/// * `N_CURRENCIES`: The number of currencies for which the solvency is verified.
-/// * `N_BYTES`: The number of bytes in which the balances should lie
-///
-/// # Fields
-///
-/// * `merkle_sum_tree_config`: Configuration for the merkle sum tree
-/// * `poseidon_entry_config`: Configuration for the poseidon hash function with WIDTH = 2 and RATE = 1 and input length of N_CURRENCIES + 1. Needed to perform the hashing from the entry to the leaf.
-/// * `poseidon_middle_config`: Configuration for the poseidon hash function with WIDTH = 2 and RATE = 1 and input length of N_CURRENCIES + 2. Needed to perform hashings from the leaf to the root.
-/// * `range_check_config`: Configuration for the range check chip
-/// * `instance`: Instance column used to store the public inputs
-/// * `advices`: Advice columns used to store the private inputs
-
-#[derive(Debug, Clone)]
-pub struct MstInclusionConfig<const N_CURRENCIES: usize, const N_BYTES: usize>
-where
-    [usize; N_CURRENCIES + 1]: Sized,
-    [usize; N_CURRENCIES + 2]: Sized,
-{
-    merkle_sum_tree_config: MerkleSumTreeConfig,
-    poseidon_entry_config: PoseidonConfig<2, 1, { N_CURRENCIES + 1 }>,
-    poseidon_middle_config: PoseidonConfig<2, 1, { N_CURRENCIES + 2 }>,
-    range_check_config: RangeCheckConfig<N_BYTES>,
-    instance: Column<Instance>,
-    advices: [Column<Advice>; 3],
-    fixed_columns: [Column<Fixed>; 5],
-}
 
-impl<const N_CURRENCIES: usize, const N_BYTES: usize> MstInclusionConfig<N_CURRENCIES, N_BYTES>
-where
-    [usize; N_CURRENCIES + 1]: Sized,
-    [usize; N_CURRENCIES + 2]: Sized,
-{
-    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
-        // the max number of advices columns needed is WIDTH + 1 given requirement of the poseidon config
-        let advices: [Column<Advice>; 3] = std::array::from_fn(|_| meta.advice_column());
-
-        // we need 2 * WIDTH fixed columns for poseidon config + 1 for the range check chip
-        let fixed_columns: [Column<Fixed>; 5] = std::array::from_fn(|_| meta.fixed_column());
-
-        // we also need 2 selectors for the MerkleSumTreeChip
-        let selectors: [Selector; 2] = std::array::from_fn(|_| meta.selector());
-
-        // we need 1 complex selector for the lookup check in the range check chip
-        let enable_lookup_selector = meta.complex_selector();
-
-        // enable constant for the fixed_column[2], this is required for the poseidon chip and the range check chip
-        meta.enable_constant(fixed_columns[2]);
-
-        let poseidon_entry_config =
-            PoseidonChip::<PoseidonSpec, 2, 1, { N_CURRENCIES + 1 }>::configure(
-                meta,
-                advices[0..2].try_into().unwrap(),
-                advices[2],
-                fixed_columns[0..2].try_into().unwrap(),
-                fixed_columns[2..4].try_into().unwrap(),
-            );
-
-        // in fact, the poseidon config requires #WIDTH advice columns for state and 1 for partial_sbox, #WIDTH fixed columns for rc_a and #WIDTH for rc_b
-        let poseidon_middle_config =
-            PoseidonChip::<PoseidonSpec, 2, 1, { N_CURRENCIES + 2 }>::configure(
-                meta,
-                advices[0..2].try_into().unwrap(),
-                advices[2],
-                fixed_columns[0..2].try_into().unwrap(),
-                fixed_columns[2..4].try_into().unwrap(),
-            );
+    /// Initializes the circuit with the proof of inclusion of an entry in a forest of Merkle Sum Trees, verifying both the proof within its sub-tree and the proof of the sub-tree root within the top-level tree.
+    /// `LEVELS` must be the depth of the forest, namely the depth of the sub-trees plus the depth of the top-level tree. The public inputs of the circuit are the version of their layout, the leaf hash, the root hash of the forest and the root balances of the forest.
+    pub fn init_forest(forest_proof: ForestProof<N_CURRENCIES>) -> Self
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        Self::init(forest_proof.to_merkle_proof())
+    }
 
-        // enable permutation for all the advice columns
-        for col in &advices {
-            meta.enable_equality(*col);
+    /// Initializes the circuit with the lowest `levels` levels of the merkle proof of `entry`. The inclusion of the entry is verified against the intermediate node reached after `levels` hashing operations from the leaf, rather than against the root of the tree.
+    /// The public inputs of the circuit are therefore the leaf hash, the intermediate node hash and the intermediate node balances.
+    /// The circuit synthesizes `levels` levels, so that its setup artifacts must be generated from a circuit of the same number of levels, e.g. with `levels = LEVELS`.
+    ///
+    /// Returns an error if `levels` is 0 or exceeds the length of the merkle proof, or if `entry` is not the entry of the merkle proof.
+    pub fn init_partial(
+        merkle_proof: MerkleProof<N_CURRENCIES>,
+        entry: Entry<N_CURRENCIES>,
+        levels: usize,
+    ) -> Result<Self, CircuitError>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if levels == 0 || levels > merkle_proof.path_indices.len() {
+            return Err(CircuitError::InvalidWitness(format!(
+                "Can't verify {} levels of a merkle proof of {} levels",
+                levels,
+                merkle_proof.path_indices.len()
+            )));
         }
-
-        // the configuration of merkle_sum_tree will always require 3 advices, no matter the number of currencies
-        let merkle_sum_tree_config = MerkleSumTreeChip::<N_CURRENCIES>::configure(
-            meta,
-            advices[0..3].try_into().unwrap(),
-            selectors[0..2].try_into().unwrap(),
-        );
-
-        let range_check_config = RangeCheckChip::<N_BYTES>::configure(
-            meta,
-            advices[0],
-            fixed_columns[4],
-            enable_lookup_selector,
-        );
-
-        let instance = meta.instance_column();
-        meta.enable_equality(instance);
-
-        Self {
-            merkle_sum_tree_config,
-            poseidon_entry_config,
-            poseidon_middle_config,
-            range_check_config,
-            instance,
-            advices,
-            fixed_columns,
+        if entry != merkle_proof.entry {
+            return Err(CircuitError::InvalidWitness(
+                "The entry is not the one of the merkle proof".to_string(),
+            ));
         }
-    }
-}
 
-impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize> Circuit<Fp>
-    for MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>
-where
-    [usize; N_CURRENCIES + 1]: Sized,
-    [usize; N_CURRENCIES + 2]: Sized,
-{
-    type Config = MstInclusionConfig<N_CURRENCIES, N_BYTES>;
-    type FloorPlanner = SimpleFloorPlanner;
+        let (subtree_root_hash, subtree_root_balances) = merkle_proof
+            .verify_partial_with_spec::<S>(levels)
+            .map_err(|e| CircuitError::InvalidWitness(e.to_string()))?;
 
-    fn without_witnesses(&self) -> Self {
-        Self::init_empty()
-    }
-
-    /// Configures the circuit
-    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
-        MstInclusionConfig::<N_CURRENCIES, N_BYTES>::configure(meta)
+        Ok(Self {
+            path_indices: merkle_proof.path_indices[..levels].to_vec(),
+            sibling_leaf_node_hash_preimage: merkle_proof.sibling_leaf_node_hash_preimage,
+            sibling_middle_node_hash_preimages: merkle_proof.sibling_middle_node_hash_preimages
+                [..levels - 1]
+                .to_vec(),
+            root: Node {
+                hash: subtree_root_hash,
+                balances: subtree_root_balances.map(|balance| big_uint_to_fp(&balance)),
+            },
+            entry,
+            watermark: None,
+            _spec: PhantomData,
+        })
     }
 
-    fn synthesize(
+    /// Assigns the entry to the witness and computes its leaf hash.
+    /// Returns the leaf hash and the assigned entry balances.
+    pub(crate) fn assign_leaf(
         &self,
-        config: Self::Config,
-        mut layouter: impl Layouter<Fp>,
-    ) -> Result<(), Error> {
-        // build auxiliary chips
-        let merkle_sum_tree_chip =
-            MerkleSumTreeChip::<N_CURRENCIES>::construct(config.merkle_sum_tree_config);
-
-        let poseidon_entry_chip =
-            PoseidonChip::<PoseidonSpec, 2, 1, { N_CURRENCIES + 1 }>::construct(
-                config.poseidon_entry_config,
-            );
-
-        let poseidon_middle_chip =
-            PoseidonChip::<PoseidonSpec, 2, 1, { N_CURRENCIES + 2 }>::construct(
-                config.poseidon_middle_config,
-            );
-
-        let range_check_chip = RangeCheckChip::<N_BYTES>::construct(config.range_check_config);
-
+        layouter: &mut impl Layouter<Fp>,
+        config: &MstInclusionConfig<N_CURRENCIES, N_BYTES, S>,
+        chips: &MstInclusionChips<N_CURRENCIES, N_BYTES, S>,
+    ) -> Result<(AssignedCell<Fp, Fp>, Vec<AssignedCell<Fp, Fp>>), Error> {
         // Assign the entry username to the witness
         let username = self.assign_value_to_witness(
             layouter.namespace(|| "assign entry username"),
-            big_uint_to_fp(&self.entry.username_as_big_uint()),
+            self.entry.leaf_username_with_spec::<S>(),
             "entry username",
             config.advices[0],
         )?;
@@ -282,23 +430,75 @@ where
             };
 
         // compute the entry hash
-        let mut current_hash = poseidon_entry_chip.hash(
+        let leaf_hash = chips.poseidon_entry_chip.hash_cells(
             layouter.namespace(|| "perform poseidon entry hash"),
             entry_hasher_input,
         )?;
 
-        // expose the first current hash, namely the leaf hash, as public input
-        self.expose_public(
-            layouter.namespace(|| "public leaf hash"),
-            &current_hash,
-            0,
-            config.instance,
+        Ok((leaf_hash, current_balances))
+    }
+
+    /// Assigns the watermark from a constant, so that it is fixed in the verifying key, and hashes it with the leaf hash returned by `assign_leaf`, as `watermarked_leaf_hash` does.
+    /// Returns the watermarked leaf hash.
+    fn assign_watermark(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        config: &MstInclusionConfig<N_CURRENCIES, N_BYTES, S>,
+        chips: &MstInclusionChips<N_CURRENCIES, N_BYTES, S>,
+        watermark: &[u8; 32],
+        leaf_hash: AssignedCell<Fp, Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let watermark = layouter.assign_region(
+            || "assign watermark",
+            |mut region| {
+                region.assign_advice_from_constant(
+                    || "watermark",
+                    config.advices[0],
+                    0,
+                    watermark_to_fp(watermark),
+                )
+            },
         )?;
 
-        // load lookup table for range check
-        self.load(&mut layouter, config.fixed_columns[4])?;
+        // pad the input of the middle node hasher with zeros, constrained to be constants
+        let mut watermark_hasher_input_vec = vec![watermark, leaf_hash];
+        for i in 0..N_CURRENCIES {
+            let padding = layouter.assign_region(
+                || format!("assign watermark padding {}", i),
+                |mut region| {
+                    region.assign_advice_from_constant(|| "zero", config.advices[0], 0, Fp::zero())
+                },
+            )?;
+            watermark_hasher_input_vec.push(padding);
+        }
 
-        for level in 0..LEVELS {
+        let watermark_hasher_input: [AssignedCell<Fp, Fp>; N_CURRENCIES + 2] =
+            match watermark_hasher_input_vec.try_into() {
+                Ok(arr) => arr,
+                Err(_) => panic!("Failed to convert Vec to Array"),
+            };
+
+        chips.poseidon_middle_chip.hash_cells(
+            layouter.namespace(|| "perform poseidon watermark hash"),
+            watermark_hasher_input,
+        )
+    }
+
+    /// Performs the hashing operations from the leaf to the root, starting from the leaf hash and balances returned by `assign_leaf`.
+    /// The lookup table of the range check chip must have been loaded before.
+    /// Returns the computed root hash and root balances.
+    pub(crate) fn assign_path(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        config: &MstInclusionConfig<N_CURRENCIES, N_BYTES, S>,
+        chips: &MstInclusionChips<N_CURRENCIES, N_BYTES, S>,
+        leaf_hash: AssignedCell<Fp, Fp>,
+        leaf_balances: Vec<AssignedCell<Fp, Fp>>,
+    ) -> Result<(AssignedCell<Fp, Fp>, Vec<AssignedCell<Fp, Fp>>), Error> {
+        let mut current_hash = leaf_hash;
+        let mut current_balances = leaf_balances;
+
+        for level in 0..self.path_indices.len() {
             let namespace_prefix = format!("level {}", level);
 
             let sibling_hash: AssignedCell<Fp, Fp>; // hash of the sibling node
@@ -340,7 +540,7 @@ where
                     };
 
                 // compute the sibling hash
-                let computed_sibling_hash = poseidon_entry_chip.hash(
+                let computed_sibling_hash = chips.poseidon_entry_chip.hash_cells(
                     layouter.namespace(|| format!("{}: perform poseidon hash", namespace_prefix)),
                     sibling_hasher_input,
                 )?;
@@ -348,7 +548,7 @@ where
                 // For level 0, perform range check on the leaf node balances and on the sibling node balances
                 for currency in 0..N_CURRENCIES {
                     // Each balance cell is constrained to be within the range defined by N_BYTES
-                    range_check_chip.assign(
+                    chips.range_check_chip.assign(
                         layouter.namespace(|| {
                             format!(
                                 "{}: currency {}: range check leaf balance",
@@ -357,7 +557,7 @@ where
                         }),
                         &current_balances[currency],
                     )?;
-                    range_check_chip.assign(
+                    chips.range_check_chip.assign(
                         layouter.namespace(|| {
                             format!(
                                 "{}: currency {}: range check sibling balance",
@@ -416,7 +616,7 @@ where
                     };
 
                 // compute the sibling hash
-                let computed_sibling_hash = poseidon_middle_chip.hash(
+                let computed_sibling_hash = chips.poseidon_middle_chip.hash_cells(
                     layouter.namespace(|| format!("{}: perform poseidon hash", namespace_prefix)),
                     sibling_hasher_input,
                 )?;
@@ -426,7 +626,7 @@ where
                     sibling_balances.iter().enumerate().take(N_CURRENCIES)
                 {
                     // Each balance cell is constrained to be within the range defined by N_BYTES
-                    range_check_chip.assign(
+                    chips.range_check_chip.assign(
                         layouter.namespace(|| {
                             format!(
                                 "{}: currency {}: range check sibling balance",
@@ -449,8 +649,8 @@ where
             )?;
 
             // For every level, perform the swap of the hashes (between `current_hash` and `sibling_hash`) according to the swap bit
-            let (hash_left_current, hash_right_current) = merkle_sum_tree_chip
-                .swap_hashes_per_level(
+            let (hash_left_current, hash_right_current) =
+                chips.merkle_sum_tree_chip.swap_hashes_per_level(
                     layouter.namespace(|| format!("{}: swap hashes", namespace_prefix)),
                     &current_hash,
                     &sibling_hash,
@@ -461,7 +661,7 @@ where
 
             // For every level, perform sum the balances `current_balances` and `sibling_balances`
             for currency in 0..N_CURRENCIES {
-                let next_balance = merkle_sum_tree_chip.sum_balances_per_level(
+                let next_balance = chips.merkle_sum_tree_chip.sum_balances_per_level(
                     layouter.namespace(|| {
                         format!(
                             "{}: currency {}: perform balance sum",
@@ -490,7 +690,7 @@ where
                 };
 
             // compute the next hash
-            let computed_hash = poseidon_middle_chip.hash(
+            let computed_hash = chips.poseidon_middle_chip.hash_cells(
                 layouter.namespace(|| format!("{}: perform poseidon hash", namespace_prefix)),
                 middle_hasher_input,
             )?;
@@ -499,23 +699,259 @@ where
             current_hash = computed_hash;
         }
 
-        // expose the last current hash, namely the root hash, as public input
+        Ok((current_hash, current_balances))
+    }
+}
+
+/// Configuration for the Mst Inclusion circuit
+/// # Type Parameters
+///
+/// * `N_CURRENCIES`: The number of currencies for which the solvency is verified.
+/// * `N_BYTES`: The number of bytes in which the balances should lie
+/// * `S`: The Poseidon specification of the tree, whose width fixes the number of columns of the circuit
+///
+/// # Fields
+///
+/// * `merkle_sum_tree_config`: Configuration for the merkle sum tree
+/// * `poseidon_entry_chip`: The Poseidon chip of `S` with an input length of N_CURRENCIES + 1. Needed to perform the hashing from the entry to the leaf.
+/// * `poseidon_middle_chip`: The Poseidon chip of `S` with an input length of N_CURRENCIES + 2. Needed to perform hashings from the leaf to the root.
+/// * `range_check_config`: Configuration for the range check chip
+/// * `instance`: Instance column used to store the public inputs
+/// * `advices`: Advice columns used to store the private inputs, `max(3, WIDTH + 1)` of them
+/// * `fixed_columns`: Fixed columns holding the round constants of the Poseidon chips, the constants and the lookup table of the range check chip, `2 * WIDTH + 1` of them
+
+#[derive(Debug, Clone)]
+pub struct MstInclusionConfig<
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+    S: TreeSpec = PoseidonSpec,
+> where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    merkle_sum_tree_config: MerkleSumTreeConfig,
+    poseidon_entry_chip: S::Chip<{ N_CURRENCIES + 1 }>,
+    poseidon_middle_chip: S::Chip<{ N_CURRENCIES + 2 }>,
+    range_check_config: RangeCheckConfig<N_BYTES>,
+    pub(crate) instance: Column<Instance>,
+    pub(crate) advices: Vec<Column<Advice>>,
+    pub(crate) fixed_columns: Vec<Column<Fixed>>,
+}
+
+/// Chips needed to verify an inclusion path, built out of the `MstInclusionConfig`
+pub(crate) struct MstInclusionChips<
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+    S: TreeSpec = PoseidonSpec,
+> where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub(crate) merkle_sum_tree_chip: MerkleSumTreeChip<N_CURRENCIES>,
+    pub(crate) poseidon_entry_chip: S::Chip<{ N_CURRENCIES + 1 }>,
+    pub(crate) poseidon_middle_chip: S::Chip<{ N_CURRENCIES + 2 }>,
+    pub(crate) range_check_chip: RangeCheckChip<N_BYTES>,
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize, S: TreeSpec>
+    MstInclusionConfig<N_CURRENCIES, N_BYTES, S>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Builds the chips needed to verify an inclusion path
+    pub(crate) fn construct_chips(&self) -> MstInclusionChips<N_CURRENCIES, N_BYTES, S> {
+        MstInclusionChips {
+            merkle_sum_tree_chip: MerkleSumTreeChip::<N_CURRENCIES>::construct(
+                self.merkle_sum_tree_config.clone(),
+            ),
+            poseidon_entry_chip: self.poseidon_entry_chip.clone(),
+            poseidon_middle_chip: self.poseidon_middle_chip.clone(),
+            range_check_chip: RangeCheckChip::<N_BYTES>::construct(self.range_check_config),
+        }
+    }
+
+    /// Returns the width in bits of the chunks looked up by the range check chip
+    pub fn lookup_bits(&self) -> usize {
+        self.range_check_config.lookup_bits()
+    }
+
+    /// Returns the fixed column holding the lookup table of the range check chip
+    pub(crate) fn range_table_column(&self) -> Column<Fixed> {
+        self.fixed_columns[2 * S::WIDTH]
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        Self::configure_with_lookup_bits(meta, DEFAULT_LOOKUP_BITS)
+    }
+
+    /// Configures the circuit with a range check chip decomposing the balances in chunks of `lookup_bits` bits, and Poseidon chips hashing with the specification `S`
+    pub fn configure_with_lookup_bits(meta: &mut ConstraintSystem<Fp>, lookup_bits: usize) -> Self {
+        // the max number of advices columns needed is WIDTH + 1 given requirement of the poseidon config, the merkle sum tree chip needing 3 of them
+        let advices: Vec<Column<Advice>> = (0..(S::WIDTH + 1).max(3))
+            .map(|_| meta.advice_column())
+            .collect();
+
+        // we need 2 * WIDTH fixed columns for poseidon config + 1 for the range check chip
+        let fixed_columns: Vec<Column<Fixed>> =
+            (0..2 * S::WIDTH + 1).map(|_| meta.fixed_column()).collect();
+
+        // we also need 2 selectors for the MerkleSumTreeChip
+        let selectors: [Selector; 2] = std::array::from_fn(|_| meta.selector());
+
+        // we need 1 complex selector for the lookup check in the range check chip
+        let enable_lookup_selector = meta.complex_selector();
+
+        // enable constant for the first rc_b column of the poseidon config, this is required for the poseidon chip and the range check chip
+        meta.enable_constant(fixed_columns[S::WIDTH]);
+
+        // in fact, the poseidon config requires #WIDTH advice columns for state and 1 for partial_sbox, #WIDTH fixed columns for rc_a and #WIDTH for rc_b
+        let poseidon_entry_chip =
+            S::Chip::<{ N_CURRENCIES + 1 }>::configure_with_columns(meta, &advices, &fixed_columns);
+
+        let poseidon_middle_chip =
+            S::Chip::<{ N_CURRENCIES + 2 }>::configure_with_columns(meta, &advices, &fixed_columns);
+
+        // enable permutation for all the advice columns
+        for col in &advices {
+            meta.enable_equality(*col);
+        }
+
+        // the configuration of merkle_sum_tree will always require 3 advices, no matter the number of currencies
+        let merkle_sum_tree_config = MerkleSumTreeChip::<N_CURRENCIES>::configure(
+            meta,
+            advices[0..3].try_into().unwrap(),
+            selectors[0..2].try_into().unwrap(),
+        );
+
+        let range_check_config = RangeCheckChip::<N_BYTES>::configure_with_lookup_bits(
+            meta,
+            advices[0],
+            fixed_columns[2 * S::WIDTH],
+            enable_lookup_selector,
+            lookup_bits,
+        );
+
+        let instance = meta.instance_column();
+        meta.enable_equality(instance);
+
+        Self {
+            merkle_sum_tree_config,
+            poseidon_entry_chip,
+            poseidon_middle_chip,
+            range_check_config,
+            instance,
+            advices,
+            fixed_columns,
+        }
+    }
+}
+
+impl<
+        const LEVELS: usize,
+        const N_CURRENCIES: usize,
+        const N_BYTES: usize,
+        const LOOKUP_BITS: usize,
+        S: TreeSpec,
+    > Circuit<Fp> for MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES, LOOKUP_BITS, S>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    type Config = MstInclusionConfig<N_CURRENCIES, N_BYTES, S>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        // the watermark is a constant of the circuit rather than a witness, so that it is kept. The number of levels of a partial circuit is kept as well
+        Self {
+            watermark: self.watermark,
+            ..Self::init_empty_with_levels(self.path_indices.len())
+        }
+    }
+
+    /// Configures the circuit
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        MstInclusionConfig::<N_CURRENCIES, N_BYTES, S>::configure_with_lookup_bits(
+            meta,
+            LOOKUP_BITS,
+        )
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        // build auxiliary chips
+        let chips = config.construct_chips();
+
+        // expose the version of the layout of the public inputs, assigned from a constant so that it is fixed in the verifying key
+        let version = layouter.assign_region(
+            || "assign circuit version",
+            |mut region| {
+                region.assign_advice_from_constant(
+                    || "circuit version",
+                    config.advices[0],
+                    0,
+                    Fp::from(MST_INCLUSION_CIRCUIT_VERSION as u64),
+                )
+            },
+        )?;
         self.expose_public(
-            layouter.namespace(|| "public root hash"),
-            &current_hash,
+            layouter.namespace(|| "public circuit version"),
+            &version,
+            0,
+            config.instance,
+        )?;
+
+        let (leaf_hash, leaf_balances) = self.assign_leaf(&mut layouter, &config, &chips)?;
+
+        // expose the leaf hash as public input, bound to the watermark if any
+        let public_leaf_hash = match &self.watermark {
+            Some(watermark) => {
+                self.assign_watermark(&mut layouter, &config, &chips, watermark, leaf_hash.clone())?
+            }
+            None => leaf_hash.clone(),
+        };
+        self.expose_public(
+            layouter.namespace(|| "public leaf hash"),
+            &public_leaf_hash,
             1,
             config.instance,
         )?;
 
-        // expose the last current balances, namely the root balances, as public input
-        for (i, balance) in current_balances.iter().enumerate() {
+        // load lookup table for range check
+        self.load_range_table(
+            &mut layouter,
+            config.range_table_column(),
+            config.lookup_bits(),
+        )?;
+
+        let (root_hash, root_balances) =
+            self.assign_path(&mut layouter, &config, &chips, leaf_hash, leaf_balances)?;
+
+        // expose the root hash as public input
+        self.expose_public(
+            layouter.namespace(|| "public root hash"),
+            &root_hash,
+            2,
+            config.instance,
+        )?;
+
+        // expose the root balances as public input
+        for (i, balance) in root_balances.iter().enumerate() {
             self.expose_public(
                 layouter.namespace(|| format!("public root balance {}", i)),
                 balance,
-                2 + i,
+                3 + i,
                 config.instance,
             )?;
         }
         Ok(())
     }
 }
+
+/// Converts a watermark to a field element, reading it as a big endian integer reduced modulo the order of the field
+fn watermark_to_fp(watermark: &[u8; 32]) -> Fp {
+    big_uint_to_fp(&BigUint::from_bytes_be(watermark))
+}
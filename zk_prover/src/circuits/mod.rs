@@ -1,8 +1,20 @@
+pub mod balance_threshold;
+pub mod batch_inclusion;
+#[cfg(feature = "debug")]
+pub mod debug;
+pub mod dynamic_inclusion;
+#[cfg(feature = "dev-stats")]
+pub mod layout_report;
 pub mod merkle_sum_tree;
+pub mod setup_cache;
+pub mod solvency;
+mod synthesis_observer;
+pub mod synthesis_trace;
 mod tests;
 pub mod traits;
 pub mod types;
 pub mod utils;
+mod witness_check;
 
 use halo2_proofs::halo2curves::bn256::Fr as Fp;
 
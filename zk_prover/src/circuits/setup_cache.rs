@@ -0,0 +1,198 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fs2::FileExt;
+use halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr as Fp, G1Affine},
+    plonk::{Circuit, ProvingKey, VerifyingKey},
+    poly::{commitment::Params, kzg::commitment::ParamsKZG},
+    SerdeFormat,
+};
+use sha2::{Digest, Sha256};
+
+use crate::circuits::traits::CircuitId;
+use crate::circuits::utils::generate_setup_artifacts;
+
+/// Version of the layout of the cached setup artifacts. It must be bumped whenever the layout of the cache entries changes, the changes of the circuits being captured by their `CircuitId`.
+pub const SETUP_CACHE_VERSION: u32 = 3;
+
+/// Interval between two attempts to acquire the lock of a cache entry
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time after which waiting for the lock of a cache entry is given up
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Cache of the setup artifacts, namely the params, the proving key and the verifying key, stored on disk to skip the key generation on later runs.
+///
+/// A cache entry is identified by a key made of `k`, the identifier of the circuit given by `CircuitId`, the SHA-256 digest of the contents of the params file, the version of the crate and `SETUP_CACHE_VERSION`.
+/// Changing any of them invalidates the entry, and the artifacts are generated again.
+pub struct CachedSetupArtifacts;
+
+impl CachedSetupArtifacts {
+    /// Returns the setup artifacts cached in `cache_dir` for the given `k`, `params_path` and circuit, generating them with `generate_setup_artifacts` and caching them if they are not found.
+    /// An advisory lock on a `.lock` file prevents concurrent processes from generating the same artifacts at the same time, see `CacheLock`.
+    pub fn load_or_generate<C: Circuit<Fp> + CircuitId>(
+        cache_dir: &Path,
+        k: u32,
+        params_path: Option<&str>,
+        circuit: C,
+    ) -> Result<
+        (
+            ParamsKZG<Bn256>,
+            ProvingKey<G1Affine>,
+            VerifyingKey<G1Affine>,
+        ),
+        Box<dyn Error>,
+    > {
+        fs::create_dir_all(cache_dir)?;
+
+        let key = Self::cache_key::<C>(k, params_path)?;
+        let path = Self::cache_path(cache_dir, &key);
+
+        let _lock = CacheLock::acquire(&path.with_extension("lock"))?;
+
+        if let Some(artifacts) = Self::load::<C>(&path, &key)? {
+            return Ok(artifacts);
+        }
+
+        let (params, pk, vk) = generate_setup_artifacts(k, params_path, circuit)?;
+        Self::store(&path, &key, &params, &pk)?;
+
+        Ok((params, pk, vk))
+    }
+
+    /// Returns the key identifying the cache entry of the circuit `C`.
+    /// The params file is identified by the SHA-256 digest of its contents, so that the same params reached through different paths share the entry
+    /// and params replaced at the same path don't reuse the proving key generated for the former ones.
+    ///
+    /// Returns an error if the params file can't be read.
+    pub fn cache_key<C: CircuitId>(
+        k: u32,
+        params_path: Option<&str>,
+    ) -> Result<String, Box<dyn Error>> {
+        let params = match params_path {
+            Some(params_path) => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut BufReader::new(File::open(params_path)?), &mut hasher)?;
+                hex::encode(hasher.finalize())
+            }
+            None => "unsafe-setup".to_string(),
+        };
+
+        Ok(format!(
+            "{}|k={}|params={}|crate={}|cache={}",
+            C::circuit_id(),
+            k,
+            params,
+            env!("CARGO_PKG_VERSION"),
+            SETUP_CACHE_VERSION
+        ))
+    }
+
+    /// Returns the path of the cache entry of `key` in `cache_dir`. Its lock is the file of the same name with the `.lock` extension
+    /// The file name is derived from the SHA-256 digest of `key`, which is stable across Rust releases unlike the `DefaultHasher`.
+    pub(crate) fn cache_path(cache_dir: &Path, key: &str) -> PathBuf {
+        let digest = Sha256::digest(key.as_bytes());
+        cache_dir.join(format!("setup-{}.bin", hex::encode(&digest[..16])))
+    }
+
+    /// Reads the cache entry stored at `path`. Returns `None` if there is no entry or if the entry has been stored with a different key.
+    fn load<C: Circuit<Fp>>(
+        path: &Path,
+        key: &str,
+    ) -> Result<
+        Option<(
+            ParamsKZG<Bn256>,
+            ProvingKey<G1Affine>,
+            VerifyingKey<G1Affine>,
+        )>,
+        Box<dyn Error>,
+    > {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut reader = BufReader::new(file);
+
+        let mut key_len = [0u8; 4];
+        reader.read_exact(&mut key_len)?;
+        let mut stored_key = vec![0u8; u32::from_le_bytes(key_len) as usize];
+        reader.read_exact(&mut stored_key)?;
+        if stored_key != key.as_bytes() {
+            return Ok(None);
+        }
+
+        let params = ParamsKZG::<Bn256>::read(&mut reader)?;
+        let pk = ProvingKey::<G1Affine>::read::<_, C>(&mut reader, SerdeFormat::RawBytes)?;
+        let vk = pk.get_vk().clone();
+
+        Ok(Some((params, pk, vk)))
+    }
+
+    /// Writes the cache entry to a temporary file first, so that a partially written entry is never read
+    fn store(
+        path: &Path,
+        key: &str,
+        params: &ParamsKZG<Bn256>,
+        pk: &ProvingKey<G1Affine>,
+    ) -> Result<(), Box<dyn Error>> {
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(&(key.len() as u32).to_le_bytes())?;
+            writer.write_all(key.as_bytes())?;
+            params.write(&mut writer)?;
+            pk.write(&mut writer, SerdeFormat::RawBytes)?;
+            writer.flush()?;
+        }
+        fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+/// Lock of a cache entry, released when dropped.
+/// It is an exclusive advisory lock (`flock` on Unix) on the `.lock` file, which the OS releases when the process holding it exits, so that a crashed process never leaves the entry locked.
+/// The lock file itself is kept, as removing it would let a process lock a new file while another one still holds the lock on the removed one.
+struct CacheLock {
+    file: File,
+}
+
+impl CacheLock {
+    fn acquire(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+
+        let start = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(CacheLock { file }),
+                Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+                    if start.elapsed() > LOCK_TIMEOUT {
+                        return Err(format!(
+                            "Timed out waiting for the setup cache lock {}",
+                            path.display()
+                        )
+                        .into());
+                    }
+                    thread::sleep(LOCK_POLL_INTERVAL);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
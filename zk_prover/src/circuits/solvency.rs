@@ -0,0 +1,246 @@
+use crate::circuits::merkle_sum_tree::MstInclusionConfig;
+use crate::circuits::traits::{CircuitBase, CircuitId};
+use crate::circuits::types::{CircuitError, CircuitStats};
+use crate::circuits::utils::{circuit_stats, required_k};
+use crate::circuits::WithInstances;
+use crate::merkle_sum_tree::utils::big_uint_to_fp;
+use crate::merkle_sum_tree::{Node, Tree};
+use halo2_proofs::circuit::{AssignedCell, Layouter, SimpleFloorPlanner};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use num_bigint::BigUint;
+
+/// Circuit for verifying that the assets of the CEX cover its liabilities, namely the root balances of a merkle sum tree with a given root hash.
+///
+/// The root is witnessed through its hash preimage, so that the root balances are bound to the root hash exposed as public input.
+/// For each cryptocurrency, the difference between the asset sum and the root balance is range checked to lie within N_BYTES, which enforces `asset_sums[i] >= root_balances[i]`.
+///
+/// # Type Parameters
+///
+/// * `N_CURRENCIES`: The number of currencies for which the solvency is verified.
+/// * `N_BYTES`: The number of bytes in which the difference between the asset sums and the root balances should lie
+///
+/// # Fields
+///
+/// * `root_hash_preimage`: The hash preimage of the root of the merkle sum tree. It is equal to `[root.balance[0], ..., root.balance[N_CURRENCIES - 1], left_child.hash, right_child.hash]`
+/// * `asset_sums`: The sums of the assets of the CEX, one per cryptocurrency
+#[derive(Clone)]
+pub struct SolvencyCircuit<const N_CURRENCIES: usize, const N_BYTES: usize>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub root_hash_preimage: [Fp; N_CURRENCIES + 2],
+    pub asset_sums: [Fp; N_CURRENCIES],
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> WithInstances
+    for SolvencyCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Returns the number of public inputs of the circuit. It is {1 + N_CURRENCIES}, namely the root hash of the merkle sum tree and the asset sums.
+    fn num_instances(&self) -> usize {
+        1 + N_CURRENCIES
+    }
+
+    /// Returns the values of the public inputs of the circuit, namely `[root_hash, asset_sums[0], ..., asset_sums[N_CURRENCIES - 1]]`
+    fn instances(&self) -> Vec<Vec<Fp>> {
+        let root = Node::<N_CURRENCIES>::middle_node_from_preimage(&self.root_hash_preimage);
+
+        let mut instance = vec![root.hash];
+        instance.extend_from_slice(&self.asset_sums);
+        vec![instance]
+    }
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> CircuitBase
+    for SolvencyCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> CircuitId
+    for SolvencyCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    const CIRCUIT_VERSION: u32 = 1;
+
+    fn circuit_id() -> String {
+        format!(
+            "solvency-v{}-currencies{}-bytes{}",
+            Self::CIRCUIT_VERSION,
+            N_CURRENCIES,
+            N_BYTES
+        )
+    }
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> SolvencyCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub fn init_empty() -> Self {
+        Self {
+            root_hash_preimage: [Fp::zero(); N_CURRENCIES + 2],
+            asset_sums: [Fp::zero(); N_CURRENCIES],
+        }
+    }
+
+    /// Initializes the circuit with the root of the merkle sum tree and the asset sums of the CEX, in the same order as the cryptocurrencies of the tree.
+    pub fn init(
+        merkle_sum_tree: &dyn Tree<N_CURRENCIES>,
+        asset_sums: [BigUint; N_CURRENCIES],
+    ) -> Self {
+        let root_hash_preimage = merkle_sum_tree
+            .get_middle_node_hash_preimage(*merkle_sum_tree.depth(), 0)
+            .expect("the root should have a hash preimage");
+
+        Self {
+            root_hash_preimage,
+            asset_sums: asset_sums.map(|asset_sum| big_uint_to_fp(&asset_sum)),
+        }
+    }
+
+    /// Returns the number of gates and columns of the circuit and the smallest `k` such that the circuit fits in 2^k rows.
+    /// Returns an error if the circuit can't be synthesized.
+    pub fn constraint_count() -> Result<CircuitStats, CircuitError> {
+        circuit_stats(&Self::init_empty())
+    }
+
+    /// Returns the smallest `k` such that the circuit fits in 2^k rows, blinding rows included, from a single synthesis of the empty circuit. See `required_k`.
+    /// Returns an error if the circuit can't be synthesized.
+    pub fn minimum_k() -> Result<u32, CircuitError> {
+        Ok(required_k(&Self::init_empty())?.0)
+    }
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> Circuit<Fp>
+    for SolvencyCircuit<N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    type Config = MstInclusionConfig<N_CURRENCIES, N_BYTES>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::init_empty()
+    }
+
+    /// Configures the circuit, in the same way as `MstInclusionCircuit`
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        MstInclusionConfig::<N_CURRENCIES, N_BYTES>::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        // build auxiliary chips
+        let chips = config.construct_chips();
+
+        // load lookup table for range check
+        self.load(&mut layouter, config.range_table_column())?;
+
+        // Assign the root balances from the root hash preimage to the circuit
+        let mut root_balances = vec![];
+        for currency in 0..N_CURRENCIES {
+            let root_balance = self.assign_value_to_witness(
+                layouter.namespace(|| format!("root balance {}", currency)),
+                self.root_hash_preimage[currency],
+                "root balance",
+                config.advices[1],
+            )?;
+            root_balances.push(root_balance);
+        }
+
+        // Assign the hashes of the children of the root from the root hash preimage to the circuit
+        let left_child_hash = self.assign_value_to_witness(
+            layouter.namespace(|| "root left child hash"),
+            self.root_hash_preimage[N_CURRENCIES],
+            "root left child hash",
+            config.advices[2],
+        )?;
+
+        let right_child_hash = self.assign_value_to_witness(
+            layouter.namespace(|| "root right child hash"),
+            self.root_hash_preimage[N_CURRENCIES + 1],
+            "root right child hash",
+            config.advices[2],
+        )?;
+
+        // create an hash_input array of length N_CURRENCIES + 2 that contains the root balances, the left child hash and the right child hash
+        let root_hasher_input_vec: Vec<AssignedCell<Fp, Fp>> = root_balances
+            .iter()
+            .chain([left_child_hash].iter())
+            .chain([right_child_hash].iter())
+            .map(|x| x.to_owned())
+            .collect();
+
+        let root_hasher_input: [AssignedCell<Fp, Fp>; N_CURRENCIES + 2] =
+            match root_hasher_input_vec.try_into() {
+                Ok(arr) => arr,
+                Err(_) => panic!("Failed to convert Vec to Array"),
+            };
+
+        // compute the root hash
+        let root_hash = chips.poseidon_middle_chip.hash(
+            layouter.namespace(|| "perform poseidon root hash"),
+            root_hasher_input,
+        )?;
+
+        // expose the root hash as public input
+        self.expose_public(
+            layouter.namespace(|| "public root hash"),
+            &root_hash,
+            0,
+            config.instance,
+        )?;
+
+        for (currency, root_balance) in root_balances.iter().enumerate() {
+            // Assign the difference between the asset sum and the root balance to the circuit
+            let difference = self.assign_value_to_witness(
+                layouter.namespace(|| format!("currency {}: assets minus liabilities", currency)),
+                self.asset_sums[currency] - self.root_hash_preimage[currency],
+                "assets minus liabilities",
+                config.advices[1],
+            )?;
+
+            // The difference is constrained to be within the range defined by N_BYTES, so that it can't be negative
+            chips.range_check_chip.assign(
+                layouter.namespace(|| {
+                    format!(
+                        "currency {}: range check assets minus liabilities",
+                        currency
+                    )
+                }),
+                &difference,
+            )?;
+
+            // The asset sum is computed as the sum of the root balance and the difference
+            let asset_sum = chips.merkle_sum_tree_chip.sum_balances_per_level(
+                layouter.namespace(|| format!("currency {}: compute asset sum", currency)),
+                root_balance,
+                &difference,
+            )?;
+
+            // expose the asset sum as public input
+            self.expose_public(
+                layouter.namespace(|| format!("public asset sum {}", currency)),
+                &asset_sum,
+                1 + currency,
+                config.instance,
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+//! Synthesis of a circuit with its floor planner outside of the mock prover and of the key generation, reporting the regions, the selectors,
+//! the cells and the copy constraints to an observer. The row counts, the synthesis profile and the synthesis trace are built on it.
+use halo2_proofs::circuit::Value;
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::{
+    Advice, Any, Assigned, Assignment, Challenge, Circuit, Column, ConstraintSystem,
+    Error as PlonkError, Fixed, FloorPlanner, Instance, Selector,
+};
+
+/// The events of a synthesis recorded by `observe_synthesis`. Every event is ignored unless overridden.
+pub(crate) trait SynthesisObserver {
+    /// Whether the values assigned to the cells are computed and passed to `assigned`, including the time spent computing the witness in the synthesis
+    const COMPUTE_VALUES: bool;
+
+    fn entered_region(&mut self, _name: String) {}
+
+    fn exited_region(&mut self) {}
+
+    fn enabled_selector(&mut self, _selector: &Selector, _row: usize) {}
+
+    /// Called for each advice or fixed cell, with its value if `COMPUTE_VALUES` is set and the value is known
+    fn assigned(&mut self, _column: Column<Any>, _row: usize, _value: Option<Fp>) {}
+
+    /// Called for each copy constraint, the right cell being copied from the left one
+    fn copied(&mut self, _left: (Column<Any>, usize), _right: (Column<Any>, usize)) {}
+}
+
+/// Synthesizes `circuit` with its floor planner and reports the synthesis to `observer`, returning the constraint system of the circuit along with the observer.
+/// If `k` is given, the synthesis fails with `NotEnoughRowsAvailable` as soon as a row beyond 2^`k` is used.
+pub(crate) fn observe_synthesis<C: Circuit<Fp>, O: SynthesisObserver>(
+    circuit: &C,
+    k: Option<u32>,
+    observer: O,
+) -> Result<(ConstraintSystem<Fp>, O), PlonkError> {
+    let mut cs = ConstraintSystem::<Fp>::default();
+    let config = C::configure(&mut cs);
+
+    let mut assignment = ObservedAssignment { k, observer };
+    C::FloorPlanner::synthesize(&mut assignment, circuit, config, cs.constants().clone())?;
+
+    Ok((cs, assignment.observer))
+}
+
+/// The `Assignment` handed to the floor planner by `observe_synthesis`
+struct ObservedAssignment<O> {
+    k: Option<u32>,
+    observer: O,
+}
+
+impl<O: SynthesisObserver> ObservedAssignment<O> {
+    fn check_row(&self, row: usize) -> Result<(), PlonkError> {
+        match self.k {
+            Some(k) if row >= 1 << k => Err(PlonkError::NotEnoughRowsAvailable { current_k: k }),
+            _ => Ok(()),
+        }
+    }
+
+    fn assign<V, VR>(&mut self, column: Column<Any>, row: usize, to: V) -> Result<(), PlonkError>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+    {
+        self.check_row(row)?;
+        let mut value = None;
+        if O::COMPUTE_VALUES {
+            let _ = to().map(|to| value = Some(to.into().evaluate()));
+        }
+        self.observer.assigned(column, row, value);
+        Ok(())
+    }
+}
+
+impl<O: SynthesisObserver> Assignment<Fp> for ObservedAssignment<O> {
+    fn enter_region<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.observer.entered_region(name_fn().into());
+    }
+
+    fn annotate_column<A, AR>(&mut self, _annotation: A, _column: Column<Any>)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+    }
+
+    fn exit_region(&mut self) {
+        self.observer.exited_region();
+    }
+
+    fn enable_selector<A, AR>(
+        &mut self,
+        _annotation: A,
+        selector: &Selector,
+        row: usize,
+    ) -> Result<(), PlonkError>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.check_row(row)?;
+        self.observer.enabled_selector(selector, row);
+        Ok(())
+    }
+
+    fn query_instance(
+        &self,
+        _column: Column<Instance>,
+        _row: usize,
+    ) -> Result<Value<Fp>, PlonkError> {
+        Ok(Value::unknown())
+    }
+
+    fn assign_advice<V, VR, A, AR>(
+        &mut self,
+        _annotation: A,
+        column: Column<Advice>,
+        row: usize,
+        to: V,
+    ) -> Result<(), PlonkError>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.assign(column.into(), row, to)
+    }
+
+    fn assign_fixed<V, VR, A, AR>(
+        &mut self,
+        _annotation: A,
+        column: Column<Fixed>,
+        row: usize,
+        to: V,
+    ) -> Result<(), PlonkError>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.assign(column.into(), row, to)
+    }
+
+    fn copy(
+        &mut self,
+        left_column: Column<Any>,
+        left_row: usize,
+        right_column: Column<Any>,
+        right_row: usize,
+    ) -> Result<(), PlonkError> {
+        self.observer
+            .copied((left_column, left_row), (right_column, right_row));
+        Ok(())
+    }
+
+    fn fill_from_row(
+        &mut self,
+        _column: Column<Fixed>,
+        _row: usize,
+        _to: Value<Assigned<Fp>>,
+    ) -> Result<(), PlonkError> {
+        Ok(())
+    }
+
+    fn get_challenge(&self, _challenge: Challenge) -> Value<Fp> {
+        Value::unknown()
+    }
+
+    fn push_namespace<NR, N>(&mut self, _name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _gadget_name: Option<String>) {}
+}
@@ -0,0 +1,256 @@
+//! Step-by-step trace of the gates applied while a circuit is synthesized, to follow how the values flow through the constraints,
+//! for example the swap of the hashes and the sum of the balances at each level of the merkle sum tree.
+use crate::circuits::synthesis_observer::{observe_synthesis, SynthesisObserver};
+use crate::merkle_sum_tree::utils::serde_helpers::{fp_from_hex, fp_to_hex};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::{Any, Circuit, Column, Expression, Selector};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+
+/// The application of the gates controlled by a selector at a row of the circuit, as returned by `synthesis_trace`.
+///
+/// # Fields
+///
+/// * `step`: The position of the step in the trace, the steps being ordered as the selectors are enabled during the synthesis
+/// * `region_name`: The name of the region in which the selector is enabled, as given to `Layouter::assign_region`
+/// * `gate_name`: The names of the gates controlled by the selector, separated by commas, e.g. `bool constraint, swap constraint`
+/// * `row`: The row at which the selector is enabled
+/// * `input_cells`: The advice cells queried by the gates that are copied from cells assigned before, e.g. the hashes entering a swap, as `(column, row, value)`
+/// * `output_cells`: The advice cells queried by the gates that are computed in the region, e.g. the swapped hashes, as `(column, row, value)`
+/// * `satisfied`: Whether all the constraints of the gates evaluate to zero at the row
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SynthesisStep {
+    pub step: usize,
+    pub region_name: String,
+    pub gate_name: String,
+    pub row: usize,
+    #[serde(with = "traced_cells_hex")]
+    pub input_cells: Vec<(usize, usize, Fp)>,
+    #[serde(with = "traced_cells_hex")]
+    pub output_cells: Vec<(usize, usize, Fp)>,
+    pub satisfied: bool,
+}
+
+/// Serializes the traced cells as `[column, row, value]` arrays, the value being a hex string
+mod traced_cells_hex {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        cells: &[(usize, usize, Fp)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        cells
+            .iter()
+            .map(|(column, row, value)| (*column, *row, fp_to_hex(value)))
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(usize, usize, Fp)>, D::Error> {
+        Vec::<(usize, usize, String)>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(column, row, hex_str)| fp_from_hex(&hex_str).map(|value| (column, row, value)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Synthesizes the circuit of size `k` with its floor planner, computing the witness of every cell, and returns the gates applied at each enabled selector, in order.
+/// Each step records the advice cells queried by the gates and whether their constraints hold, without creating any proof.
+/// The selectors that don't control any gate, such as the ones of the lookup arguments, are not traced.
+///
+/// Panics if the circuit doesn't fit in 2^`k` rows.
+pub fn synthesis_trace<C: Circuit<Fp>>(circuit: &C, k: u32) -> Vec<SynthesisStep> {
+    let (cs, tracer) = observe_synthesis(circuit, Some(k), SynthesisTracer::default())
+        .expect("the circuit should be synthesized");
+
+    let enabled: HashSet<(Selector, usize)> = tracer
+        .selectors
+        .iter()
+        .map(|(_, selector, row)| (*selector, *row))
+        .collect();
+
+    // The selectors and the advice cells, as column and rotation, queried by each gate
+    let gate_queries: Vec<(Vec<Selector>, Vec<(usize, i32)>)> = cs
+        .gates()
+        .iter()
+        .map(|gate| {
+            let mut selectors = vec![];
+            let mut cells = vec![];
+            for polynomial in gate.polynomials() {
+                let (polynomial_selectors, polynomial_cells) = queries(polynomial);
+                selectors.extend(polynomial_selectors);
+                cells.extend(polynomial_cells);
+            }
+            (selectors, cells)
+        })
+        .collect();
+
+    let mut steps = vec![];
+    for (region_name, selector, row) in tracer.selectors.iter() {
+        let gates: Vec<usize> = (0..cs.gates().len())
+            .filter(|&gate| gate_queries[gate].0.contains(selector))
+            .collect();
+        if gates.is_empty() {
+            continue;
+        }
+
+        let mut queried_cells: Vec<(usize, usize)> = vec![];
+        for &gate in gates.iter() {
+            for &(column, rotation) in gate_queries[gate].1.iter() {
+                if let Some(cell_row) = row.checked_add_signed(rotation as isize) {
+                    if !queried_cells.contains(&(column, cell_row)) {
+                        queried_cells.push((column, cell_row));
+                    }
+                }
+            }
+        }
+
+        let mut input_cells = vec![];
+        let mut output_cells = vec![];
+        for (column, cell_row) in queried_cells {
+            if let Some(value) = tracer.advice.get(&(column, cell_row)) {
+                if tracer.copied.contains(&(column, cell_row)) {
+                    input_cells.push((column, cell_row, *value));
+                } else {
+                    output_cells.push((column, cell_row, *value));
+                }
+            }
+        }
+
+        let satisfied = gates.iter().all(|&gate| {
+            cs.gates()[gate]
+                .polynomials()
+                .iter()
+                .all(|polynomial| tracer.evaluate(polynomial, *row, &enabled) == Fp::zero())
+        });
+
+        steps.push(SynthesisStep {
+            step: steps.len(),
+            region_name: region_name.clone(),
+            gate_name: gates
+                .iter()
+                .map(|&gate| cs.gates()[gate].name())
+                .collect::<Vec<_>>()
+                .join(", "),
+            row: *row,
+            input_cells,
+            output_cells,
+            satisfied,
+        });
+    }
+
+    steps
+}
+
+/// Returns the selectors and the advice cells, as column index and rotation, queried by `polynomial`
+fn queries(polynomial: &Expression<Fp>) -> (Vec<Selector>, Vec<(usize, i32)>) {
+    let none = || (Vec::<Selector>::new(), Vec::<(usize, i32)>::new());
+    polynomial.evaluate(
+        &|_| none(),
+        &|selector| (vec![selector], vec![]),
+        &|_| none(),
+        &|query| (vec![], vec![(query.column_index(), query.rotation().0)]),
+        &|_| none(),
+        &|_| none(),
+        &|queried| queried,
+        &|(mut selectors, mut cells), (other_selectors, other_cells)| {
+            selectors.extend(other_selectors);
+            cells.extend(other_cells);
+            (selectors, cells)
+        },
+        &|(mut selectors, mut cells), (other_selectors, other_cells)| {
+            selectors.extend(other_selectors);
+            cells.extend(other_cells);
+            (selectors, cells)
+        },
+        &|queried, _| queried,
+    )
+}
+
+/// Records the enabled selectors and the values of the cells of a circuit while it is synthesized, see `synthesis_trace`.
+/// The advice cells that are the target of a copy constraint are recorded as copied, namely as inputs of the gates querying them.
+#[derive(Default)]
+struct SynthesisTracer {
+    region_name: Option<String>,
+    selectors: Vec<(String, Selector, usize)>,
+    advice: HashMap<(usize, usize), Fp>,
+    fixed: HashMap<(usize, usize), Fp>,
+    copied: HashSet<(usize, usize)>,
+}
+
+impl SynthesisTracer {
+    /// Evaluates `polynomial` at `row`, the cells that are not assigned being zero
+    fn evaluate(
+        &self,
+        polynomial: &Expression<Fp>,
+        row: usize,
+        enabled: &HashSet<(Selector, usize)>,
+    ) -> Fp {
+        let value_at = |cells: &HashMap<(usize, usize), Fp>, column: usize, rotation: i32| {
+            row.checked_add_signed(rotation as isize)
+                .and_then(|cell_row| cells.get(&(column, cell_row)).copied())
+                .unwrap_or(Fp::zero())
+        };
+
+        polynomial.evaluate(
+            &|constant| constant,
+            &|selector| {
+                if enabled.contains(&(selector, row)) {
+                    Fp::one()
+                } else {
+                    Fp::zero()
+                }
+            },
+            &|query| value_at(&self.fixed, query.column_index(), query.rotation().0),
+            &|query| value_at(&self.advice, query.column_index(), query.rotation().0),
+            // the circuits only copy cells to the instance column, without querying it in their gates
+            &|_| Fp::zero(),
+            &|_| Fp::zero(),
+            &|value| -value,
+            &|a, b| a + b,
+            &|a, b| a * b,
+            &|value, scalar| value * scalar,
+        )
+    }
+}
+
+impl SynthesisObserver for SynthesisTracer {
+    const COMPUTE_VALUES: bool = true;
+
+    fn entered_region(&mut self, name: String) {
+        self.region_name = Some(name);
+    }
+
+    fn exited_region(&mut self) {
+        self.region_name = None;
+    }
+
+    fn enabled_selector(&mut self, selector: &Selector, row: usize) {
+        let region_name = self.region_name.clone().unwrap_or_default();
+        self.selectors.push((region_name, *selector, row));
+    }
+
+    fn assigned(&mut self, column: Column<Any>, row: usize, value: Option<Fp>) {
+        if let Some(value) = value {
+            match column.column_type() {
+                Any::Advice(_) => self.advice.insert((column.index(), row), value),
+                Any::Fixed => self.fixed.insert((column.index(), row), value),
+                Any::Instance => None,
+            };
+        }
+    }
+
+    fn copied(
+        &mut self,
+        _left: (Column<Any>, usize),
+        (right_column, right_row): (Column<Any>, usize),
+    ) {
+        // a cell is copied from a cell assigned before, e.g. by `AssignedCell::copy_advice`
+        if let Any::Advice(_) = right_column.column_type() {
+            self.copied.insert((right_column.index(), right_row));
+        }
+    }
+}
@@ -1,90 +1,2408 @@
 #[cfg(test)]
 mod test {
 
+    use crate::chips::poseidon::{poseidon_spec::PoseidonSpec, PoseidonParams};
+    use crate::chips::range::range_check::DEFAULT_LOOKUP_BITS;
     use crate::circuits::WithInstances;
-    use crate::merkle_sum_tree::{MerkleSumTree, Tree};
+    use crate::impl_tree_spec;
+    use crate::merkle_sum_tree::{ForestMerkleSumTree, MerkleSumTree, Tree};
     use crate::{
         circuits::{
+            balance_threshold::BalanceThresholdCircuit,
+            batch_inclusion::MstBatchInclusionCircuit,
+            dynamic_inclusion::{DynamicMstInclusionCircuit, SUPPORTED_LEVELS},
             merkle_sum_tree::MstInclusionCircuit,
-            utils::{full_prover, full_verifier, generate_setup_artifacts},
+            setup_cache::CachedSetupArtifacts,
+            solvency::SolvencyCircuit,
+            synthesis_trace::SynthesisStep,
+            traits::CircuitId,
+            types::{
+                CircuitError, DecryptionFailed, InstanceMismatch, MigrationReport, ParamsIntegrity,
+                ProverError, SolidityCalldata, TranscriptKind, VerifyError, ViolationKind,
+            },
+            utils::{
+                check_instance_version, check_params_k, circuit_stats, full_prover,
+                full_prover_with_rng, full_prover_with_transcript, full_verifier,
+                full_verifier_with_transcript, gen_proof_solidity_calldata,
+                gen_proof_solidity_calldata_watermarked, gen_proof_solidity_calldata_with_rng,
+                generate_setup_artifacts, migrate_setup_artifacts, preflight_check,
+                prove_inclusion_parallel, read_params_k, read_setup_artifacts,
+                read_setup_artifacts_encrypted, read_verifier_params, recommended_k_for_levels,
+                required_k, verify_inclusion_proof, verify_params_file, write_setup_artifacts,
+                write_setup_artifacts_encrypted, write_verifier_params, ProofArtifact, MAX_K,
+                MST_INCLUSION_CIRCUIT_VERSION,
+            },
+        },
+        merkle_sum_tree::{
+            utils::{big_uint_to_fp, fp_to_big_uint},
+            Entry, MerkleProof, Node,
         },
-        merkle_sum_tree::Entry,
     };
+    use halo2_gadgets::poseidon::primitives::{generate_constants, Spec};
+    use halo2_proofs::arithmetic::Field;
     use halo2_proofs::{
         dev::{FailureLocation, MockProver, VerifyFailure},
-        halo2curves::bn256::Fr as Fp,
-        plonk::Any,
+        halo2curves::bn256::{Bn256, Fr as Fp},
+        plonk::{keygen_vk, Any},
+        poly::{commitment::Params, kzg::commitment::ParamsKZG},
+        SerdeFormat,
     };
+    use halo2_solidity_verifier::encode_calldata;
     use num_bigint::ToBigUint;
+    use rand::rngs::{OsRng, StdRng};
+    use rand::SeedableRng;
+    use std::path::Path;
+
+    const N_CURRENCIES: usize = 2;
+    const LEVELS: usize = 4;
+    const N_BYTES: usize = 8;
+    const K: u32 = 11;
+
+    #[test]
+    fn test_valid_merkle_sum_tree() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        for user_index in 0..16 {
+            // get proof for entry ˆuser_indexˆ
+            let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
+
+            let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+            let valid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+
+            assert_eq!(circuit.instances()[0].len(), circuit.num_instances());
+            assert_eq!(circuit.instances()[0].len(), 3 + N_CURRENCIES);
+
+            valid_prover.assert_satisfied();
+        }
+    }
+
+    #[test]
+    fn test_valid_forest_merkle_sum_tree() {
+        // The forest of two sub-trees of LEVELS levels has a top-level tree of one level
+        const FOREST_LEVELS: usize = LEVELS + 1;
+
+        let forest = ForestMerkleSumTree::<LEVELS, N_CURRENCIES, N_BYTES>::from_sub_trees(vec![
+            MerkleSumTree::from_csv("../csv/entry_16.csv").unwrap(),
+            MerkleSumTree::from_csv("../csv/entry_16_switched_order.csv").unwrap(),
+        ])
+        .unwrap();
+
+        let k = MstInclusionCircuit::<FOREST_LEVELS, N_CURRENCIES, N_BYTES>::minimum_k().unwrap();
+
+        // verify the inclusion of a leaf of each sub-tree
+        for global_index in [2, 16 + 11] {
+            let forest_proof = forest.generate_proof(global_index).unwrap();
+
+            let circuit = MstInclusionCircuit::<FOREST_LEVELS, N_CURRENCIES, N_BYTES>::init_forest(
+                forest_proof,
+            );
+
+            // public input #2 is the hash of the root of the forest
+            assert_eq!(circuit.instances()[0][2], forest.root().hash);
+
+            let valid_prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+            valid_prover.assert_satisfied();
+        }
+    }
+
+    #[test]
+    fn test_valid_partial_merkle_sum_tree() {
+        const PARTIAL_LEVELS: usize = 2;
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        for user_index in 0..16 {
+            let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
+
+            // The circuit only synthesizes the lowest PARTIAL_LEVELS levels of the tree
+            let entry = merkle_proof.entry.clone();
+            let circuit =
+                MstInclusionCircuit::<PARTIAL_LEVELS, N_CURRENCIES, N_BYTES>::init_partial(
+                    merkle_proof,
+                    entry,
+                    PARTIAL_LEVELS,
+                )
+                .unwrap();
+
+            // public input #2 is the hash of the subtree root rather than the hash of the tree root
+            let expected_subtree_root =
+                &merkle_sum_tree.nodes()[PARTIAL_LEVELS][user_index >> PARTIAL_LEVELS];
+            assert_eq!(circuit.instances()[0][2], expected_subtree_root.hash);
+            assert_eq!(
+                circuit.instances()[0][3..],
+                expected_subtree_root.balances[..]
+            );
+
+            let valid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+
+            valid_prover.assert_satisfied();
+        }
+
+        // A partial circuit verifies at least one level and at most the levels of the merkle proof, for the entry of the merkle proof
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let other_entry = merkle_sum_tree.get_entry(1).clone();
+        for (entry, levels) in [
+            (merkle_proof.entry.clone(), 0),
+            (merkle_proof.entry.clone(), LEVELS + 1),
+            (other_entry, PARTIAL_LEVELS),
+        ] {
+            assert!(matches!(
+                MstInclusionCircuit::<PARTIAL_LEVELS, N_CURRENCIES, N_BYTES>::init_partial(
+                    merkle_proof.clone(),
+                    entry,
+                    levels,
+                ),
+                Err(CircuitError::InvalidWitness(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_valid_merkle_sum_tree_with_zero_balance_entry() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // Replace the first entry with an entry whose balances are all zero
+        let mut entries = merkle_sum_tree.entries().to_vec();
+        entries[0] = Entry::new(
+            "alice".to_string(),
+            [0.to_biguint().unwrap(), 0.to_biguint().unwrap()],
+        );
+
+        let merkle_sum_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            merkle_sum_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let valid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+
+        valid_prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_valid_merkle_sum_tree_from_json_proof() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(5).unwrap();
+
+        // The proof shared as JSON, e.g. with a verifier written in another language, can be used as is to build the circuit
+        let json = merkle_proof.to_json();
+        let deserialized_proof = MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&json).unwrap();
+
+        let circuit =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(deserialized_proof);
+
+        let valid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+
+        valid_prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_valid_merkle_sum_tree_with_salted_entries() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // The first two entries are the same user salted with different salts
+        let mut entries = merkle_sum_tree.entries().to_vec();
+        let (username, balances) = (
+            entries[0].username().to_string(),
+            entries[0].balances().clone(),
+        );
+        entries[0] = Entry::new_salted(
+            username.clone(),
+            balances.clone(),
+            Entry::<N_CURRENCIES>::random_salt(),
+        );
+        entries[1] = Entry::new_salted(username, balances, Entry::<N_CURRENCIES>::random_salt());
+
+        let salted_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            merkle_sum_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+
+        let mut leaf_hashes = vec![];
+        for user_index in 0..2 {
+            let merkle_proof = salted_tree.generate_proof(user_index).unwrap();
+
+            let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+            let valid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+            valid_prover.assert_satisfied();
+
+            leaf_hashes.push(circuit.instances()[0][1]);
+        }
+        assert_ne!(leaf_hashes[0], leaf_hashes[1]);
+    }
+
+    #[test]
+    fn test_constraint_count() {
+        let stats =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::constraint_count().unwrap();
+
+        assert_eq!(stats.n_advice_columns, 3);
+        assert_eq!(stats.n_fixed_columns, 5);
+        assert_eq!(stats.n_instance_columns, 1);
+        assert!(stats.n_gates > 0);
+
+        // K is the one used by the passing MockProver tests
+        assert!(stats.min_k <= K);
+        assert!(
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::recommended_k().unwrap() >= K
+        );
+
+        // A deeper tree shouldn't need fewer rows
+        let deeper_stats =
+            MstInclusionCircuit::<{ LEVELS + 4 }, N_CURRENCIES, N_BYTES>::constraint_count()
+                .unwrap();
+        assert!(deeper_stats.min_k >= stats.min_k);
+    }
+
+    #[test]
+    fn test_circuit_stats_min_k() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let stats =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::constraint_count().unwrap();
+
+        // The lookup table of the range check alone spans 2^8 rows
+        assert!(stats.n_rows >= 1 << 8);
+        assert!(stats.n_rows < 1 << stats.min_k);
+
+        // The circuit fits in 2^min_k rows
+        let valid_prover = MockProver::run(stats.min_k, &circuit, circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+
+        // but not in 2^(min_k - 1) rows
+        assert!(MockProver::run(stats.min_k - 1, &circuit, circuit.instances()).is_err());
+
+        assert!(check_params_k(&stats, stats.min_k).is_ok());
+        assert!(check_params_k(&stats, stats.min_k + 1).is_ok());
+        assert!(matches!(
+            check_params_k(&stats, stats.min_k - 1)
+                .unwrap_err()
+                .downcast_ref::<CircuitError>(),
+            Some(CircuitError::SetupFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_minimum_k() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let minimum_k = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::minimum_k().unwrap();
+
+        // The circuit fits in 2^minimum_k rows but not in 2^(minimum_k - 1) rows
+        let valid_prover = MockProver::run(minimum_k, &circuit, circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+        assert!(MockProver::run(minimum_k - 1, &circuit, circuit.instances()).is_err());
+
+        // The same holds for the solvency circuit
+        let asset_sums = merkle_sum_tree.root().balances.map(fp_to_big_uint);
+        let circuit = SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init(&merkle_sum_tree, asset_sums);
+
+        let minimum_k = SolvencyCircuit::<N_CURRENCIES, N_BYTES>::minimum_k().unwrap();
+
+        let valid_prover = MockProver::run(minimum_k, &circuit, circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+        assert!(MockProver::run(minimum_k - 1, &circuit, circuit.instances()).is_err());
+    }
+
+    #[test]
+    fn test_recommended_k_for_levels() {
+        let k = recommended_k_for_levels::<N_CURRENCIES, N_BYTES>(LEVELS).unwrap();
+        assert_eq!(
+            k,
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::minimum_k().unwrap()
+        );
+
+        // The inclusion circuit of the tree of the csv file passes the mock prover at the recommended k
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let valid_prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+        assert!(MockProver::run(k - 1, &circuit, circuit.instances()).is_err());
+
+        // A deeper tree never needs a smaller k
+        assert_eq!(
+            recommended_k_for_levels::<N_CURRENCIES, N_BYTES>(LEVELS + 4).unwrap(),
+            MstInclusionCircuit::<{ LEVELS + 4 }, N_CURRENCIES, N_BYTES>::minimum_k().unwrap()
+        );
+        assert!(recommended_k_for_levels::<N_CURRENCIES, N_BYTES>(LEVELS + 4).unwrap() >= k);
+
+        // A tree without levels is rejected rather than panicking
+        assert!(matches!(
+            recommended_k_for_levels::<N_CURRENCIES, N_BYTES>(0),
+            Err(CircuitError::SetupFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_utilization_report() {
+        let report =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::utilization_report(K).unwrap();
+
+        assert_eq!(report.total_rows, 1 << K);
+        assert!(report.used_rows <= report.total_rows);
+        // The lookup table of the range check alone spans 2^8 rows
+        assert!(report.used_rows >= 1 << 8);
+        assert_eq!(
+            report.utilization_pct,
+            report.used_rows as f64 * 100.0 / report.total_rows as f64
+        );
+        assert!(!report.bottleneck_gate.is_empty());
+        assert_eq!(report.warning.is_some(), report.utilization_pct < 50.0);
+
+        // The same circuit uses a smaller share of larger params
+        let larger_report =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::utilization_report(K + 1)
+                .unwrap();
+        assert_eq!(larger_report.used_rows, report.used_rows);
+        assert!(larger_report.utilization_pct < report.utilization_pct);
+        assert_eq!(
+            larger_report.warning.is_some(),
+            larger_report.utilization_pct < 50.0
+        );
+
+        // A k whose rows can't be counted is rejected rather than overflowing
+        assert!(matches!(
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::utilization_report(MAX_K + 1),
+            Err(CircuitError::SetupFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_profile_synthesis() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let profile = circuit.profile_synthesis(K);
+
+        // The regions are sorted from the slowest to the fastest, and each region name is reported once
+        assert!(profile
+            .regions
+            .windows(2)
+            .all(|pair| pair[0].duration_ns >= pair[1].duration_ns));
+        for (i, region) in profile.regions.iter().enumerate() {
+            assert!(profile.regions[i + 1..]
+                .iter()
+                .all(|other| other.name != region.name));
+        }
+
+        for name in [
+            "assign nodes hashes per merkle tree level",
+            "sum nodes balances per currency",
+            "assign value to perform range check",
+            "permute state",
+        ] {
+            let region = profile
+                .regions
+                .iter()
+                .find(|region| region.name == name)
+                .unwrap_or_else(|| panic!("the region {} should be profiled", name));
+            assert!(region.rows > 0);
+        }
+
+        // Only the order of the durations is checked, as they depend on the machine, while the regions lie in the 2^k rows of the circuit
+        let profiled_rows: usize = profile.regions.iter().map(|region| region.rows).sum();
+        assert!(profiled_rows <= 1 << K);
+    }
+
+    #[test]
+    fn test_trace_synthesis() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let trace = circuit.trace_synthesis(K);
+
+        // The steps are numbered in order and every gate of a valid circuit is satisfied
+        assert!(!trace.is_empty());
+        assert!(trace.iter().enumerate().all(|(i, step)| step.step == i));
+        assert!(trace.iter().all(|step| step.satisfied));
+
+        // The first swap takes the leaf hash, copied from the entry hash, as input
+        let leaf_hash = merkle_sum_tree.get_entry(0).compute_leaf().hash;
+        let first_swap = trace
+            .iter()
+            .find(|step| step.region_name == "assign nodes hashes per merkle tree level")
+            .unwrap();
+        assert!(first_swap.gate_name.contains("swap constraint"));
+        assert!(first_swap
+            .input_cells
+            .iter()
+            .any(|(_, _, value)| *value == leaf_hash));
+        assert!(!first_swap.output_cells.is_empty());
+
+        // The trace can be exported to JSON
+        let json = serde_json::to_string(&trace).unwrap();
+        let deserialized: Vec<SynthesisStep> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, trace);
+
+        // A non binary swap bit fails the bool constraint of the first swap
+        let mut invalid_circuit = circuit.clone();
+        invalid_circuit.path_indices[0] = Fp::from(2);
+        let invalid_trace = invalid_circuit.trace_synthesis(K);
+        let unsatisfied_step = invalid_trace.iter().find(|step| !step.satisfied).unwrap();
+        assert_eq!(
+            unsatisfied_step.region_name,
+            "assign nodes hashes per merkle tree level"
+        );
+    }
+
+    #[test]
+    fn test_valid_merkle_sum_tree_with_full_prover() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        // Generate a universal trusted setup for testing purposes.
+        //
+        // The verification key (vk) and the proving key (pk) are then generated.
+        // An empty circuit is used here to emphasize that the circuit inputs are not relevant when generating the keys.
+        // Important: The dimensions of the circuit used to generate the keys must match those of the circuit used to generate the proof.
+        // In this case, the dimensions are represented by the height of the Merkle tree.
+        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let user_index = 0;
+
+        let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
+        let user_entry = merkle_sum_tree.get_entry(user_index);
+
+        // Only now we can instantiate the circuit with the actual inputs
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        // Generate the proof
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+
+        // verify the proof to be true
+        assert!(full_verifier(&params, &vk, proof, circuit.instances()));
+
+        // the user should perform the check on the public inputs, namely the version of their layout, the leaf hash, the root hash and the root balances
+        let expected_instances =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::expected_instances(
+                user_entry,
+                merkle_sum_tree.root(),
+            );
+        assert_eq!(circuit.validate_instances(&expected_instances), Ok(()));
+    }
+
+    #[test]
+    fn test_prove_inclusion_parallel() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // The indices are out of order to check that the proofs are returned in the order of the indices
+        let indices = [7, 0, 3, 12, 5, 15, 1, 9];
+        let results = prove_inclusion_parallel::<LEVELS, N_CURRENCIES, N_BYTES>(
+            &params,
+            &pk,
+            &merkle_sum_tree,
+            &indices,
+            4,
+        );
+        assert_eq!(results.len(), indices.len());
+
+        for (result, &user_index) in results.into_iter().zip(indices.iter()) {
+            let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
+            let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+            assert!(full_verifier(
+                &params,
+                &vk,
+                result.unwrap(),
+                circuit.instances()
+            ));
+        }
+
+        // An invalid index fails without discarding the proofs of the other users
+        let results = prove_inclusion_parallel::<LEVELS, N_CURRENCIES, N_BYTES>(
+            &params,
+            &pk,
+            &merkle_sum_tree,
+            &[0, 16],
+            2,
+        );
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(ProverError::Synthesis(_))));
+    }
+
+    #[test]
+    fn test_full_prover_with_rng() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        // Two runs with the same seed yield the same proof, which verifies
+        let prove_with_seed = |seed: u64| {
+            full_prover_with_rng(
+                &params,
+                &pk,
+                circuit.clone(),
+                circuit.instances(),
+                StdRng::seed_from_u64(seed),
+            )
+            .unwrap()
+        };
+        let proof = prove_with_seed(42);
+        assert_eq!(proof, prove_with_seed(42));
+        assert_ne!(proof, prove_with_seed(43));
+        assert!(full_verifier(&params, &vk, proof, circuit.instances()));
+
+        // The same goes for the Solidity calldata
+        let calldata = gen_proof_solidity_calldata_with_rng(
+            &params,
+            &pk,
+            circuit.clone(),
+            StdRng::seed_from_u64(42),
+        )
+        .unwrap();
+        let same_seed_calldata = gen_proof_solidity_calldata_with_rng(
+            &params,
+            &pk,
+            circuit.clone(),
+            StdRng::seed_from_u64(42),
+        )
+        .unwrap();
+        assert_eq!(calldata, same_seed_calldata);
+    }
+
+    /// A Poseidon specification with fewer partial rounds than `PoseidonSpec`, so that it hashes to different values
+    #[derive(Debug, Clone, Copy)]
+    struct ReducedRoundsSpec;
+
+    impl Spec<Fp, 2, 1> for ReducedRoundsSpec {
+        fn full_rounds() -> usize {
+            8
+        }
+
+        fn partial_rounds() -> usize {
+            50
+        }
+
+        fn sbox(val: Fp) -> Fp {
+            PoseidonSpec::sbox(val)
+        }
+
+        fn secure_mds() -> usize {
+            unimplemented!()
+        }
+
+        fn constants() -> (Vec<[Fp; 2]>, [[Fp; 2]; 2], [[Fp; 2]; 2]) {
+            let (round_constants, mds, mds_inv) = PoseidonSpec::constants();
+            (round_constants[..58].to_vec(), mds, mds_inv)
+        }
+    }
+
+    impl_tree_spec!(ReducedRoundsSpec, 2, 1);
+
+    /// A Poseidon specification of width 3 and rate 2, whose constants are generated from its number of rounds
+    #[derive(Debug, Clone, Copy)]
+    struct Width3Spec;
+
+    impl Spec<Fp, 3, 2> for Width3Spec {
+        fn full_rounds() -> usize {
+            8
+        }
+
+        fn partial_rounds() -> usize {
+            57
+        }
+
+        fn sbox(val: Fp) -> Fp {
+            val.pow_vartime([5])
+        }
+
+        fn secure_mds() -> usize {
+            0
+        }
+
+        fn constants() -> (Vec<[Fp; 3]>, [[Fp; 3]; 3], [[Fp; 3]; 3]) {
+            generate_constants::<_, Self, 3, 2>()
+        }
+    }
+
+    impl_tree_spec!(Width3Spec, 3, 2);
+
+    #[test]
+    fn test_inclusion_with_custom_spec() {
+        let default_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_sum_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES, ReducedRoundsSpec>::from_csv(
+            "../csv/entry_16.csv",
+        )
+        .unwrap();
+
+        // The nodes are hashed with the custom spec, while the balances are unchanged
+        assert_ne!(merkle_sum_tree.root().hash, default_tree.root().hash);
+        assert_eq!(
+            merkle_sum_tree.root().balances,
+            default_tree.root().balances
+        );
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        assert!(merkle_sum_tree.verify_proof(&merkle_proof));
+
+        let circuit = MstInclusionCircuit::<
+            LEVELS,
+            N_CURRENCIES,
+            N_BYTES,
+            DEFAULT_LOOKUP_BITS,
+            ReducedRoundsSpec,
+        >::init(merkle_proof.clone());
+
+        let valid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+
+        // The circuit hashing with the default spec doesn't verify the path of the tree
+        let default_spec_circuit =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let invalid_prover =
+            MockProver::run(K, &default_spec_circuit, circuit.instances()).unwrap();
+        assert!(invalid_prover.verify().is_err());
+
+        let (params, pk, vk) = generate_setup_artifacts(
+            K,
+            None,
+            MstInclusionCircuit::<
+                LEVELS,
+                N_CURRENCIES,
+                N_BYTES,
+                DEFAULT_LOOKUP_BITS,
+                ReducedRoundsSpec,
+            >::init_empty(),
+        )
+        .unwrap();
+
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+        assert!(full_verifier(&params, &vk, proof, circuit.instances()));
+    }
+
+    #[test]
+    fn test_inclusion_with_width_3_spec() {
+        let default_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // The first entry is salted, so that its username is hashed with the spec of the tree as well
+        let mut entries = default_tree.entries().to_vec();
+        entries[0] = Entry::new_salted(
+            entries[0].username().to_string(),
+            entries[0].balances().clone(),
+            Entry::<N_CURRENCIES>::random_salt(),
+        );
+        let merkle_sum_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES, Width3Spec>::from_entries(
+            entries,
+            default_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+        assert_ne!(merkle_sum_tree.root().hash, default_tree.root().hash);
+        assert_eq!(
+            merkle_sum_tree.root().balances,
+            default_tree.root().balances
+        );
+        assert_ne!(
+            merkle_sum_tree
+                .get_entry(0)
+                .leaf_username_with_spec::<Width3Spec>(),
+            merkle_sum_tree.get_entry(0).leaf_username()
+        );
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        assert!(merkle_sum_tree.verify_proof(&merkle_proof));
+
+        type Width3Circuit =
+            MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES, DEFAULT_LOOKUP_BITS, Width3Spec>;
+
+        // The circuit has a column more for the state of the permutation of width 3
+        let circuit = Width3Circuit::init(merkle_proof);
+        let stats = circuit_stats(&Width3Circuit::init_empty()).unwrap();
+        assert_eq!(
+            stats.n_advice_columns,
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()
+                .unwrap()
+                .n_advice_columns
+                + 1
+        );
+
+        let k = stats.min_k;
+        let valid_prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+
+        let (params, pk, vk) =
+            generate_setup_artifacts(k, None, Width3Circuit::init_empty()).unwrap();
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+        assert!(full_verifier(&params, &vk, proof, circuit.instances()));
+    }
+
+    #[test]
+    fn test_check_poseidon_params() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+
+        // The default constants, given explicitly
+        let default_params = PoseidonParams::from_spec::<PoseidonSpec, 2, 1>();
+        assert_eq!(default_params.full_rounds, 8);
+        assert_eq!(default_params.partial_rounds, 56);
+
+        let circuit =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof.clone());
+        assert!(circuit.check_poseidon_params(&default_params).is_ok());
+
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+        assert!(full_verifier(&params, &vk, proof, circuit.instances()));
+
+        // Altered constants are reported rather than ignored
+        let mut altered_params = default_params.clone();
+        altered_params.round_constants[0][0] += Fp::one();
+
+        assert!(matches!(
+            circuit.check_poseidon_params(&altered_params),
+            Err(CircuitError::PoseidonParamsMismatch)
+        ));
+
+        // The constants of another specification don't match the circuit hashing with the default one
+        assert!(matches!(
+            circuit.check_poseidon_params(&PoseidonParams::from_spec::<ReducedRoundsSpec, 2, 1>()),
+            Err(CircuitError::PoseidonParamsMismatch)
+        ));
+
+        // Another specification is selected through the type parameters of the tree and the circuit, which then hash to another root
+        let reduced_rounds_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES, ReducedRoundsSpec>::from_csv(
+                "../csv/entry_16.csv",
+            )
+            .unwrap();
+        assert_ne!(reduced_rounds_tree.root().hash, merkle_sum_tree.root().hash);
+
+        let reduced_rounds_circuit = MstInclusionCircuit::<
+            LEVELS,
+            N_CURRENCIES,
+            N_BYTES,
+            DEFAULT_LOOKUP_BITS,
+            ReducedRoundsSpec,
+        >::init(reduced_rounds_tree.generate_proof(0).unwrap());
+        assert!(reduced_rounds_circuit
+            .check_poseidon_params(&PoseidonParams::from_spec::<ReducedRoundsSpec, 2, 1>())
+            .is_ok());
+        assert_ne!(reduced_rounds_circuit.instances(), circuit.instances());
+
+        let valid_prover = MockProver::run(
+            K,
+            &reduced_rounds_circuit,
+            reduced_rounds_circuit.instances(),
+        )
+        .unwrap();
+        valid_prover.assert_satisfied();
+
+        // The circuit hashing with the other specification doesn't verify the root of the default one
+        let invalid_prover =
+            MockProver::run(K, &reduced_rounds_circuit, circuit.instances()).unwrap();
+        assert!(invalid_prover.verify().is_err());
+
+        // Malformed constants are diagnosed as malformed rather than as different from the ones of the circuit
+        let mut non_square_params = default_params.clone();
+        non_square_params.mds_matrix[1].pop();
+        assert!(non_square_params.validate().is_err());
+
+        let mut missing_round_params = default_params.clone();
+        missing_round_params.round_constants.pop();
+        assert!(missing_round_params.validate().is_err());
+
+        let mut odd_full_rounds_params = default_params.clone();
+        odd_full_rounds_params.full_rounds = 7;
+        odd_full_rounds_params.partial_rounds = 57;
+        assert!(odd_full_rounds_params.validate().is_err());
+
+        for invalid_params in [
+            non_square_params,
+            missing_round_params,
+            odd_full_rounds_params,
+        ] {
+            assert!(matches!(
+                circuit.check_poseidon_params(&invalid_params),
+                Err(CircuitError::InvalidPoseidonParams(_))
+            ));
+        }
+
+        // Well formed constants of another width are rejected as well
+        let wider_params = PoseidonParams::from_spec::<Width3Spec, 3, 2>();
+        assert!(wider_params.validate().is_ok());
+        assert!(matches!(
+            circuit.check_poseidon_params(&wider_params),
+            Err(CircuitError::InvalidPoseidonParams(_))
+        ));
+    }
+
+    #[test]
+    fn test_full_prover_with_transcript() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let transcript_kinds = [TranscriptKind::EvmKeccak, TranscriptKind::NativeBlake2b];
+
+        for prover_kind in transcript_kinds {
+            let proof = full_prover_with_transcript(
+                &params,
+                &pk,
+                circuit.clone(),
+                circuit.instances(),
+                prover_kind,
+            )
+            .unwrap();
+
+            // The proof only verifies with the transcript it was generated with, a mismatched transcript is rejected without panicking
+            for verifier_kind in transcript_kinds {
+                assert_eq!(
+                    full_verifier_with_transcript(
+                        &params,
+                        &vk,
+                        proof.clone(),
+                        circuit.instances(),
+                        verifier_kind
+                    ),
+                    prover_kind == verifier_kind
+                );
+            }
+        }
+
+        // The Solidity calldata is generated with the Keccak256 transcript
+        let SolidityCalldata {
+            proof,
+            public_inputs,
+        } = gen_proof_solidity_calldata(&params, &pk, circuit.clone()).unwrap();
+        assert_eq!(public_inputs.len(), circuit.num_instances());
+        assert!(full_verifier_with_transcript(
+            &params,
+            &vk,
+            proof.to_vec(),
+            circuit.instances(),
+            TranscriptKind::EvmKeccak
+        ));
+        assert!(!full_verifier(
+            &params,
+            &vk,
+            proof.to_vec(),
+            circuit.instances()
+        ));
+    }
+
+    #[test]
+    fn test_watermarked_inclusion_proof() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let watermark_a = [0xaa; 32];
+        let watermark_b = [0xbb; 32];
+
+        // The setup artifacts are generated for a circuit with watermark A, which is fixed in the verifying key
+        let empty_circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty()
+            .with_watermark(watermark_a);
+        let k = required_k(&empty_circuit).unwrap().0;
+        let (params, pk, vk) = generate_setup_artifacts(k, None, empty_circuit).unwrap();
+
+        let user_index = 0;
+        let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let leaf_hash = merkle_sum_tree.get_entry(user_index).compute_leaf().hash;
+
+        // The public input #1 is the leaf hash bound to the watermark
+        let watermarked_circuit = circuit.clone().with_watermark(watermark_a);
+        let instances_with = |watermark: &[u8; 32]| {
+            let mut instances = watermarked_circuit.instances();
+            instances[0][1] =
+                MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::watermarked_leaf_hash(
+                    watermark, leaf_hash,
+                );
+            instances
+        };
+        assert_eq!(
+            watermarked_circuit.instances(),
+            instances_with(&watermark_a)
+        );
+        assert_ne!(watermarked_circuit.instances()[0][1], leaf_hash);
+
+        let valid_prover =
+            MockProver::run(k, &watermarked_circuit, watermarked_circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+
+        let invalid_prover = MockProver::run(k, &watermarked_circuit, circuit.instances()).unwrap();
+        assert!(invalid_prover.verify().is_err());
+
+        // A proof with watermark A only verifies against the public inputs of watermark A
+        let SolidityCalldata {
+            proof,
+            public_inputs,
+        } = gen_proof_solidity_calldata_watermarked(&params, &pk, circuit.clone(), watermark_a)
+            .unwrap();
+        assert_eq!(public_inputs.len(), watermarked_circuit.num_instances());
+        assert!(full_verifier_with_transcript(
+            &params,
+            &vk,
+            proof.to_vec(),
+            instances_with(&watermark_a),
+            TranscriptKind::EvmKeccak
+        ));
+        assert!(!full_verifier_with_transcript(
+            &params,
+            &vk,
+            proof.to_vec(),
+            instances_with(&watermark_b),
+            TranscriptKind::EvmKeccak
+        ));
+        assert!(!full_verifier_with_transcript(
+            &params,
+            &vk,
+            proof.to_vec(),
+            circuit.instances(),
+            TranscriptKind::EvmKeccak
+        ));
+
+        // The proving key of watermark A can't produce a proof verifying under watermark B
+        assert!(gen_proof_solidity_calldata_watermarked(
+            &params,
+            &pk,
+            circuit.clone(),
+            watermark_b
+        )
+        .is_err());
+        let forged_circuit = circuit.clone().with_watermark(watermark_b);
+        assert!(matches!(
+            full_prover(
+                &params,
+                &pk,
+                forged_circuit.clone(),
+                forged_circuit.instances(),
+            ),
+            Err(ProverError::Synthesis(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_instances() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let user_index = 0;
+        let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let instances = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::expected_instances(
+            merkle_sum_tree.get_entry(user_index),
+            merkle_sum_tree.root(),
+        );
+        assert_eq!(instances, circuit.instances());
+        assert_eq!(circuit.validate_instances(&instances), Ok(()));
+
+        // Each slot of the instance column is reported when it disagrees
+        let mut invalid_instances = instances.clone();
+        invalid_instances[0][1] = Fp::from(1000);
+        assert_eq!(
+            circuit.validate_instances(&invalid_instances),
+            Err(InstanceMismatch::LeafHash)
+        );
+
+        let mut invalid_instances = instances.clone();
+        invalid_instances[0][2] = Fp::from(1000);
+        assert_eq!(
+            circuit.validate_instances(&invalid_instances),
+            Err(InstanceMismatch::RootHash)
+        );
+
+        for i in 0..N_CURRENCIES {
+            let mut invalid_instances = instances.clone();
+            invalid_instances[0][3 + i] += Fp::one();
+            assert_eq!(
+                circuit.validate_instances(&invalid_instances),
+                Err(InstanceMismatch::RootBalance(i))
+            );
+        }
+
+        let mut invalid_instances = instances;
+        invalid_instances[0].pop();
+        assert_eq!(
+            circuit.validate_instances(&invalid_instances),
+            Err(InstanceMismatch::Length {
+                expected: 3 + N_CURRENCIES,
+                found: 2 + N_CURRENCIES
+            })
+        );
+    }
+
+    #[test]
+    fn test_instance_version() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        // The public input #0 is the version of the layout of the public inputs
+        let instances = circuit.instances();
+        assert_eq!(
+            instances[0][0],
+            Fp::from(MST_INCLUSION_CIRCUIT_VERSION as u64)
+        );
+        assert_eq!(check_instance_version(&instances), Ok(()));
+        assert_eq!(
+            check_instance_version(&[vec![]]),
+            Err(InstanceMismatch::Length {
+                expected: 1,
+                found: 0
+            })
+        );
+
+        // Public inputs laid out for another version are rejected before reading the other slots
+        let mut other_version_instances = instances.clone();
+        other_version_instances[0][0] = Fp::from(MST_INCLUSION_CIRCUIT_VERSION as u64 - 1);
+        assert_eq!(
+            check_instance_version(&other_version_instances),
+            Err(InstanceMismatch::Version)
+        );
+        assert_eq!(
+            circuit.validate_instances(&other_version_instances),
+            Err(InstanceMismatch::Version)
+        );
+
+        // The version is fixed in the circuit, so that a proof can't be checked against another version
+        let invalid_prover = MockProver::run(K, &circuit, other_version_instances).unwrap();
+        assert!(invalid_prover
+            .verify()
+            .unwrap_err()
+            .iter()
+            .all(|failure| matches!(failure, VerifyFailure::Permutation { .. })));
+    }
+
+    #[test]
+    fn test_setup_cache() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("summa-setup-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        // The first call generates the artifacts and caches them
+        let (_, pk, vk) =
+            CachedSetupArtifacts::load_or_generate(&cache_dir, K, None, circuit.clone()).unwrap();
+
+        // The second call loads the same artifacts from the cache, the lock file left behind not being locked by any process
+        let key = CachedSetupArtifacts::cache_key::<
+            MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>,
+        >(K, None)
+        .unwrap();
+        let lock_path = CachedSetupArtifacts::cache_path(&cache_dir, &key).with_extension("lock");
+        assert!(lock_path.exists());
+
+        let (params, cached_pk, cached_vk) =
+            CachedSetupArtifacts::load_or_generate(&cache_dir, K, None, circuit).unwrap();
+
+        assert_eq!(
+            pk.to_bytes(SerdeFormat::RawBytes),
+            cached_pk.to_bytes(SerdeFormat::RawBytes)
+        );
+        assert_eq!(
+            vk.to_bytes(SerdeFormat::RawBytes),
+            cached_vk.to_bytes(SerdeFormat::RawBytes)
+        );
+
+        // The cached artifacts can be used to generate and verify a proof
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let proof = full_prover(&params, &cached_pk, circuit.clone(), circuit.instances()).unwrap();
+        assert!(full_verifier(
+            &params,
+            &cached_vk,
+            proof,
+            circuit.instances()
+        ));
+
+        // A different circuit or a different k is cached under a different key
+        assert_ne!(
+            key,
+            CachedSetupArtifacts::cache_key::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+                K + 1,
+                None
+            )
+            .unwrap()
+        );
+        assert_ne!(
+            key,
+            CachedSetupArtifacts::cache_key::<
+                MstInclusionCircuit<{ LEVELS + 1 }, N_CURRENCIES, N_BYTES>,
+            >(K, None)
+            .unwrap()
+        );
+
+        // The same params file reached through different paths is cached under the same key
+        assert_eq!(
+            CachedSetupArtifacts::cache_key::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+                K,
+                Some("../backend/ptau/hermez-raw-11")
+            )
+            .unwrap(),
+            CachedSetupArtifacts::cache_key::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+                K,
+                Some("../backend/ptau/../ptau/hermez-raw-11")
+            )
+            .unwrap()
+        );
+
+        // The key depends on the contents of the params file rather than on its path
+        let params_copy = cache_dir.join("hermez-raw-11");
+        std::fs::copy("../backend/ptau/hermez-raw-11", &params_copy).unwrap();
+        let params_key = CachedSetupArtifacts::cache_key::<
+            MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>,
+        >(K, Some("../backend/ptau/hermez-raw-11"))
+        .unwrap();
+        let copy_key = |path: &Path| {
+            CachedSetupArtifacts::cache_key::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+                K,
+                path.to_str(),
+            )
+            .unwrap()
+        };
+        assert_eq!(params_key, copy_key(&params_copy));
+
+        // Params replaced at the same path don't share the entry of the former ones
+        let mut contents = std::fs::read(&params_copy).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 1;
+        std::fs::write(&params_copy, contents).unwrap();
+        assert_ne!(params_key, copy_key(&params_copy));
+
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_params_file() {
+        // The params converted from the Hermez ceremony
+        let integrity = verify_params_file(Path::new("../backend/ptau/hermez-raw-11")).unwrap();
+        assert_eq!(
+            integrity,
+            ParamsIntegrity {
+                k: 11,
+                num_g1: 2 * 2048,
+                num_g2: 2,
+                file_size_bytes: 262404,
+            }
+        );
+
+        let params_k = 4;
+        let params_path = std::env::temp_dir().join(format!(
+            "summa_test_params_integrity_{}",
+            std::process::id()
+        ));
+        let mut params_bytes = vec![];
+        ParamsKZG::<Bn256>::setup(params_k, OsRng)
+            .write(&mut params_bytes)
+            .unwrap();
+        std::fs::write(&params_path, &params_bytes).unwrap();
+        assert_eq!(verify_params_file(&params_path).unwrap().k, params_k);
+
+        // A truncated file is rejected before being deserialized
+        std::fs::write(&params_path, &params_bytes[..params_bytes.len() - 100]).unwrap();
+        assert!(matches!(
+            verify_params_file(&params_path),
+            Err(CircuitError::InvalidParamsFile(_))
+        ));
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+        assert!(matches!(
+            generate_setup_artifacts(params_k, Some(params_path.to_str().unwrap()), circuit)
+                .unwrap_err()
+                .downcast_ref::<CircuitError>(),
+            Some(CircuitError::InvalidParamsFile(_))
+        ));
+
+        // A point moved off the curve is rejected
+        let mut corrupted_bytes = params_bytes.clone();
+        corrupted_bytes[4 + 64 * 3 + 10] ^= 1;
+        std::fs::write(&params_path, &corrupted_bytes).unwrap();
+        assert!(matches!(
+            verify_params_file(&params_path),
+            Err(CircuitError::InvalidParamsFile(reason)) if reason.contains("G1 point 3")
+        ));
+
+        // A header that isn't the k of the params
+        let mut header_bytes = params_bytes;
+        header_bytes[..4].copy_from_slice(&5u32.to_le_bytes());
+        std::fs::write(&params_path, &header_bytes).unwrap();
+        assert!(verify_params_file(&params_path).is_err());
+
+        std::fs::remove_file(&params_path).unwrap();
+    }
+
+    #[test]
+    fn test_generate_setup_artifacts_with_oversized_params() {
+        let params_k = 14;
+        let k = 12;
+
+        let params_path = std::env::temp_dir().join(format!(
+            "oversized_params_{}_{}",
+            params_k,
+            std::process::id()
+        ));
+        let params = ParamsKZG::<Bn256>::setup(params_k, OsRng);
+        params
+            .write(&mut std::fs::File::create(&params_path).unwrap())
+            .unwrap();
+        let params_path = params_path.to_str().unwrap();
+
+        assert_eq!(read_params_k(params_path).unwrap(), params_k);
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        // The params are downsized to the requested k
+        let (params, pk, vk) =
+            generate_setup_artifacts(k, Some(params_path), circuit.clone()).unwrap();
+        assert_eq!(params.k(), k);
+        assert_eq!(vk.get_domain().k(), k);
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+        assert!(full_verifier(&params, &vk, proof, circuit.instances()));
+
+        // Params that are too small are rejected
+        assert!(matches!(
+            generate_setup_artifacts(params_k + 1, Some(params_path), circuit)
+                .unwrap_err()
+                .downcast_ref::<CircuitError>(),
+            Some(CircuitError::SetupFailed(_))
+        ));
+
+        std::fs::remove_file(params_path).unwrap();
+    }
+
+    #[test]
+    fn test_generate_setup_artifacts_checks_params_k() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        // The dry synthesis agrees with the mock prover on the smallest k
+        let (min_k, used_rows) = required_k(&circuit).unwrap();
+        assert_eq!(
+            min_k,
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()
+                .unwrap()
+                .min_k
+        );
+
+        let write_params = |params_k: u32| {
+            let params_path = std::env::temp_dir().join(format!(
+                "checked_params_{}_{}",
+                params_k,
+                std::process::id()
+            ));
+            ParamsKZG::<Bn256>::setup(params_k, OsRng)
+                .write(&mut std::fs::File::create(&params_path).unwrap())
+                .unwrap();
+            params_path
+        };
+
+        // Params that are too small for the circuit are rejected before the keygen, with the rows used by the circuit
+        let small_params_path = write_params(min_k - 1);
+        let error = generate_setup_artifacts(
+            min_k,
+            Some(small_params_path.to_str().unwrap()),
+            circuit.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CircuitError>(),
+            Some(CircuitError::SetupFailed(_))
+        ));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "params file supports k={} but circuit requires k>={} (rows used: {})",
+                min_k - 1,
+                min_k,
+                used_rows
+            )
+        );
+        std::fs::remove_file(small_params_path).unwrap();
+
+        // Params of the exact size are used as they are
+        let exact_params_path = write_params(min_k);
+        let exact_params_path_str = exact_params_path.to_str().unwrap();
+        let (params, _, vk) =
+            generate_setup_artifacts(min_k, Some(exact_params_path_str), circuit.clone()).unwrap();
+        assert_eq!(params.k(), min_k);
+        assert_eq!(vk.get_domain().k(), min_k);
+
+        // A k too small for the circuit is rejected even if the params are large enough
+        let error =
+            generate_setup_artifacts(min_k - 1, Some(exact_params_path_str), circuit.clone())
+                .unwrap_err();
+        assert!(error.to_string().contains("but circuit requires"));
+        std::fs::remove_file(exact_params_path).unwrap();
+
+        let error = generate_setup_artifacts(min_k - 1, None, circuit).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CircuitError>(),
+            Some(CircuitError::SetupFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_proof_artifact() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+        let artifact = ProofArtifact::new::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+            K,
+            circuit.instances(),
+            proof,
+            TranscriptKind::NativeBlake2b,
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("mst_inclusion_proof_{}.bin", std::process::id()));
+        artifact.write(&path).unwrap();
+
+        type InclusionCircuit = MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>;
+
+        let reloaded_artifact = ProofArtifact::read(&path).unwrap();
+        assert_eq!(reloaded_artifact, artifact);
+        assert_eq!(reloaded_artifact.circuit_id, InclusionCircuit::circuit_id());
+        assert!(reloaded_artifact
+            .verify::<InclusionCircuit>(&params, &vk)
+            .unwrap());
+
+        // The proof doesn't verify against other instances
+        let mut mismatched_artifact = reloaded_artifact.clone();
+        mismatched_artifact.instances[0][1] += Fp::one();
+        assert!(!mismatched_artifact
+            .verify::<InclusionCircuit>(&params, &vk)
+            .unwrap());
+
+        // Nor as a proof of another circuit
+        assert_eq!(
+            reloaded_artifact
+                .verify::<MstInclusionCircuit<{ LEVELS + 1 }, N_CURRENCIES, N_BYTES>>(&params, &vk),
+            Err(VerifyError::CircuitMismatch {
+                artifact_circuit_id: InclusionCircuit::circuit_id(),
+                circuit_id:
+                    MstInclusionCircuit::<{ LEVELS + 1 }, N_CURRENCIES, N_BYTES>::circuit_id(),
+            })
+        );
+
+        // Nor with an artifact recording another k
+        let mut resized_artifact = reloaded_artifact;
+        resized_artifact.k = K + 1;
+        assert_eq!(
+            resized_artifact.verify::<InclusionCircuit>(&params, &vk),
+            Err(VerifyError::ArtifactKMismatch {
+                artifact_k: K + 1,
+                vk_k: K
+            })
+        );
+
+        // A truncated or corrupted file fails to parse without panicking
+        let bytes = std::fs::read(&path).unwrap();
+
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(ProofArtifact::read(&path).is_err());
+
+        let mut corrupted_bytes = bytes.clone();
+        corrupted_bytes[0] ^= 0xff;
+        std::fs::write(&path, &corrupted_bytes).unwrap();
+        assert!(ProofArtifact::read(&path).is_err());
+
+        // A corrupted length must not be trusted either
+        let mut corrupted_bytes = bytes;
+        corrupted_bytes[13..17].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &corrupted_bytes).unwrap();
+        assert!(ProofArtifact::read(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_verifier_params() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("verifier_params_{}.bin", std::process::id()));
+        write_verifier_params(&params, &path).unwrap();
+
+        // The verifier params don't grow with k, unlike the full params
+        let size = std::fs::metadata(&path).unwrap().len();
+        assert!(size < 4096);
+
+        let verifier_params = read_verifier_params(&path).unwrap();
+        assert_eq!(verifier_params.k, K);
+        assert_eq!(verifier_params.s_g2, params.s_g2());
+
+        let verifier_params = verifier_params.to_params();
+        assert!(full_verifier(
+            &verifier_params,
+            &vk,
+            proof.clone(),
+            circuit.instances()
+        ));
+
+        // The proof doesn't verify against other instances
+        let mut invalid_instances = circuit.instances();
+        invalid_instances[0][1] += Fp::one();
+        assert!(!full_verifier(
+            &verifier_params,
+            &vk,
+            proof,
+            invalid_instances
+        ));
+
+        // A truncated file fails to parse
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(read_verifier_params(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_and_read_setup_artifacts() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit.clone()).unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "mst_inclusion_setup_artifacts_{}.bin",
+            std::process::id()
+        ));
+        write_setup_artifacts::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+            &path, K, &params, &pk, &vk,
+        )
+        .unwrap();
+
+        let (reloaded_params, reloaded_pk, reloaded_vk) =
+            read_setup_artifacts::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(&path, K)
+                .unwrap();
+        assert_eq!(
+            reloaded_vk.to_bytes(SerdeFormat::RawBytes),
+            vk.to_bytes(SerdeFormat::RawBytes)
+        );
+
+        // A proof generated with the reloaded keys verifies against a freshly generated vk
+        let fresh_vk = keygen_vk(&reloaded_params, &circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let proof = full_prover(
+            &reloaded_params,
+            &reloaded_pk,
+            circuit.clone(),
+            circuit.instances(),
+        )
+        .unwrap();
+        assert!(full_verifier(
+            &reloaded_params,
+            &fresh_vk,
+            proof,
+            circuit.instances()
+        ));
+
+        // Loading the artifacts for a different k or a different circuit configuration fails
+        let error = read_setup_artifacts::<MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>>(
+            &path,
+            K + 1,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<CircuitError>(),
+            Some(CircuitError::SetupFailed(_))
+        ));
+        let error = read_setup_artifacts::<
+            MstInclusionCircuit<{ LEVELS + 1 }, N_CURRENCIES, N_BYTES>,
+        >(&path, K)
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<CircuitError>(),
+            Some(CircuitError::SetupFailed(_))
+        ));
+        assert!(error.to_string().contains("was requested"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_setup_artifacts() {
+        type InclusionCircuit = MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES>;
+
+        let (params, pk, vk) =
+            generate_setup_artifacts(K, None, InclusionCircuit::init_empty()).unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "mst_inclusion_setup_artifacts_encrypted_{}.bin",
+            std::process::id()
+        ));
+        write_setup_artifacts_encrypted::<InclusionCircuit>(
+            &path,
+            K,
+            &params,
+            &pk,
+            &vk,
+            "passphrase",
+        )
+        .unwrap();
+
+        // The proving key isn't stored in clear
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.starts_with(b"SUMMAENC"));
+        let pk_bytes = pk.to_bytes(SerdeFormat::RawBytes);
+        assert!(!bytes.windows(64).any(|window| window == &pk_bytes[..64]));
+
+        // The artifacts round trip with the right passphrase
+        let (reloaded_params, reloaded_pk, reloaded_vk) =
+            read_setup_artifacts_encrypted::<InclusionCircuit>(&path, K, "passphrase").unwrap();
+        assert_eq!(reloaded_params.k(), K);
+        assert_eq!(reloaded_pk.to_bytes(SerdeFormat::RawBytes), pk_bytes);
+        assert_eq!(
+            reloaded_vk.to_bytes(SerdeFormat::RawBytes),
+            vk.to_bytes(SerdeFormat::RawBytes)
+        );
+
+        // A wrong passphrase or a tampered file fails to decrypt
+        let error =
+            read_setup_artifacts_encrypted::<InclusionCircuit>(&path, K, "wrong passphrase")
+                .err()
+                .unwrap();
+        assert_eq!(
+            error.downcast_ref::<DecryptionFailed>(),
+            Some(&DecryptionFailed)
+        );
+
+        let mut tampered_bytes = bytes.clone();
+        *tampered_bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, &tampered_bytes).unwrap();
+        let error = read_setup_artifacts_encrypted::<InclusionCircuit>(&path, K, "passphrase")
+            .err()
+            .unwrap();
+        assert!(error.downcast_ref::<DecryptionFailed>().is_some());
+
+        // Encrypted and unencrypted files can't be mistaken for one another
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            read_setup_artifacts::<InclusionCircuit>(&path, K)
+                .err()
+                .unwrap()
+                .downcast_ref::<CircuitError>(),
+            Some(CircuitError::SetupFailed(_))
+        ));
+
+        write_setup_artifacts::<InclusionCircuit>(&path, K, &params, &pk, &vk).unwrap();
+        let error = read_setup_artifacts_encrypted::<InclusionCircuit>(&path, K, "passphrase")
+            .err()
+            .unwrap();
+        assert!(error.downcast_ref::<DecryptionFailed>().is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_setup_artifacts() {
+        const NEW_LEVELS: usize = LEVELS + 1;
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+        let (old_params, old_pk, old_vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        // The 17 users of the tree no longer fit in a tree of LEVELS levels
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_17.csv").unwrap();
+
+        let new_k =
+            MstInclusionCircuit::<NEW_LEVELS, N_CURRENCIES, N_BYTES>::recommended_k().unwrap();
+        let ((new_params, new_pk, new_vk), report) =
+            migrate_setup_artifacts::<LEVELS, NEW_LEVELS, N_CURRENCIES, N_BYTES>(
+                &old_vk,
+                new_k,
+                None,
+                &merkle_sum_tree,
+            )
+            .unwrap();
+
+        assert_eq!(
+            report,
+            MigrationReport {
+                old_k: K,
+                new_k,
+                old_levels: LEVELS,
+                new_levels: NEW_LEVELS,
+                affected_users: 17,
+            }
+        );
+
+        // The new artifacts produce proofs that verify
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<NEW_LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let proof =
+            full_prover(&new_params, &new_pk, circuit.clone(), circuit.instances()).unwrap();
+        assert!(full_verifier(
+            &new_params,
+            &new_vk,
+            proof,
+            circuit.instances()
+        ));
+
+        // A proof generated with the old artifacts doesn't verify against the new verifying key, even for the same public inputs
+        let old_merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let old_merkle_proof = old_merkle_sum_tree.generate_proof(0).unwrap();
+        let old_circuit =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(old_merkle_proof);
+        let old_proof = full_prover(
+            &old_params,
+            &old_pk,
+            old_circuit.clone(),
+            old_circuit.instances(),
+        )
+        .unwrap();
+        assert!(!full_verifier(
+            &new_params,
+            &new_vk,
+            old_proof,
+            old_circuit.instances()
+        ));
+
+        // The tree must have as many levels as the new circuit
+        let error = migrate_setup_artifacts::<LEVELS, NEW_LEVELS, N_CURRENCIES, N_BYTES>(
+            &old_vk,
+            new_k,
+            None,
+            &old_merkle_sum_tree,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<CircuitError>(),
+            Some(CircuitError::InvalidWitness(_))
+        ));
+    }
+
+    #[test]
+    fn test_dynamic_inclusion() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        for levels in SUPPORTED_LEVELS {
+            // Extend the proof of the tree of 4 levels up to `levels` levels with made-up sibling nodes, recomputing the root accordingly
+            let mut merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+            for _ in LEVELS..levels {
+                merkle_proof.sibling_middle_node_hash_preimages.push([
+                    Fp::from(1u64),
+                    Fp::from(2u64),
+                    Fp::from(3u64),
+                    Fp::from(4u64),
+                ]);
+                merkle_proof.path_indices.push(Fp::zero());
+            }
+            let (root_hash, root_balances) = merkle_proof.verify_partial(levels).unwrap();
+            merkle_proof.root = Node {
+                hash: root_hash,
+                balances: root_balances.map(|balance| big_uint_to_fp(&balance)),
+            };
+
+            let circuit =
+                DynamicMstInclusionCircuit::<N_CURRENCIES, N_BYTES>::init(levels, merkle_proof)
+                    .unwrap();
+            assert_eq!(circuit.levels(), levels);
+            assert_eq!(circuit.instances()[0][2], root_hash);
+
+            let k = circuit.constraint_count().unwrap().min_k;
+            let valid_prover = MockProver::run(k, &circuit, circuit.instances()).unwrap();
+
+            valid_prover.assert_satisfied();
+        }
+
+        // The circuit of a supported number of levels has the same verifying key as the corresponding static circuit
+        let (params, _, vk) = generate_setup_artifacts(
+            K,
+            None,
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+        )
+        .unwrap();
+        let dynamic_vk = keygen_vk(
+            &params,
+            &DynamicMstInclusionCircuit::<N_CURRENCIES, N_BYTES>::init_empty(LEVELS).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            dynamic_vk.to_bytes(SerdeFormat::RawBytes),
+            vk.to_bytes(SerdeFormat::RawBytes)
+        );
+
+        // Unsupported levels and proofs of a different number of levels are rejected
+        assert!(matches!(
+            DynamicMstInclusionCircuit::<N_CURRENCIES, N_BYTES>::init_empty(5)
+                .err()
+                .unwrap()
+                .downcast_ref::<CircuitError>(),
+            Some(CircuitError::InvalidWitness(_))
+        ));
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        assert!(matches!(
+            DynamicMstInclusionCircuit::<N_CURRENCIES, N_BYTES>::init(8, merkle_proof)
+                .err()
+                .unwrap()
+                .downcast_ref::<CircuitError>(),
+            Some(CircuitError::InvalidWitness(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_inclusion_proof() {
+        let (params, pk, vk) = generate_setup_artifacts(
+            K,
+            None,
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+        )
+        .unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let instances = circuit.instances()[0].clone();
 
-    const N_CURRENCIES: usize = 2;
-    const LEVELS: usize = 4;
-    const N_BYTES: usize = 8;
-    const K: u32 = 11;
+        let proof = gen_proof_solidity_calldata(&params, &pk, circuit)
+            .unwrap()
+            .proof;
+
+        // A third party only holds the serialized vk, the params, the proof and the public inputs
+        let vk_bytes = vk.to_bytes(SerdeFormat::RawBytes);
+        assert_eq!(
+            verify_inclusion_proof::<N_CURRENCIES, N_BYTES>(&vk_bytes, &params, &proof, &instances),
+            Ok(true)
+        );
+
+        // A proof with a flipped byte doesn't verify
+        let mut corrupted_proof = proof.to_vec();
+        corrupted_proof[42] ^= 1;
+        assert_eq!(
+            verify_inclusion_proof::<N_CURRENCIES, N_BYTES>(
+                &vk_bytes,
+                &params,
+                &corrupted_proof,
+                &instances
+            ),
+            Ok(false)
+        );
+
+        // Neither does a proof checked against other public inputs
+        let mut invalid_instances = instances.clone();
+        invalid_instances[1] = Fp::from(1000u64);
+        assert_eq!(
+            verify_inclusion_proof::<N_CURRENCIES, N_BYTES>(
+                &vk_bytes,
+                &params,
+                &proof,
+                &invalid_instances
+            ),
+            Ok(false)
+        );
+
+        // A truncated vk can't be deserialized
+        assert!(matches!(
+            verify_inclusion_proof::<N_CURRENCIES, N_BYTES>(
+                &vk_bytes[..vk_bytes.len() / 2],
+                &params,
+                &proof,
+                &instances
+            ),
+            Err(VerifyError::InvalidVerifyingKey(_))
+        ));
+    }
 
     #[test]
-    fn test_valid_merkle_sum_tree() {
+    fn test_valid_batch_inclusion() {
+        const BATCH: usize = 4;
+
         let merkle_sum_tree =
             MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
 
-        for user_index in 0..16 {
-            // get proof for entry ˆuser_indexˆ
-            let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
+        let merkle_proofs = [0, 5, 10, 15]
+            .iter()
+            .map(|user_index| merkle_sum_tree.generate_proof(*user_index).unwrap())
+            .collect::<Vec<_>>();
 
-            let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let circuit =
+            MstBatchInclusionCircuit::<BATCH, LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proofs);
 
-            let valid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+        // The public inputs are the root hash, the root balances and the leaf hash of each entry of the batch
+        let instances = circuit.instances();
+        assert_eq!(instances[0].len(), circuit.num_instances());
+        assert_eq!(instances[0].len(), 1 + N_CURRENCIES + BATCH);
+        assert_eq!(instances[0][0], merkle_sum_tree.root().hash);
+        assert_eq!(
+            instances[0][1 + N_CURRENCIES + 1],
+            merkle_sum_tree.get_entry(5).compute_leaf().hash
+        );
 
-            assert_eq!(circuit.instances()[0].len(), circuit.num_instances());
-            assert_eq!(circuit.instances()[0].len(), 2 + N_CURRENCIES);
+        let k =
+            MstBatchInclusionCircuit::<BATCH, LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()
+                .unwrap()
+                .min_k;
 
-            valid_prover.assert_satisfied();
-        }
+        let valid_prover = MockProver::run(k, &circuit, instances).unwrap();
+
+        valid_prover.assert_satisfied();
     }
 
+    // Tampering with the path of a single entry of the batch should fail the permutation check between its computed root and the public root
     #[test]
-    fn test_valid_merkle_sum_tree_with_full_prover() {
-        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+    fn test_batch_inclusion_with_tampered_path() {
+        const BATCH: usize = 4;
 
-        // Generate a universal trusted setup for testing purposes.
-        //
-        // The verification key (vk) and the proving key (pk) are then generated.
-        // An empty circuit is used here to emphasize that the circuit inputs are not relevant when generating the keys.
-        // Important: The dimensions of the circuit used to generate the keys must match those of the circuit used to generate the proof.
-        // In this case, the dimensions are represented by the height of the Merkle tree.
-        let (params, pk, vk) = generate_setup_artifacts(K, None, circuit).unwrap();
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proofs = [0, 5, 10, 15]
+            .iter()
+            .map(|user_index| merkle_sum_tree.generate_proof(*user_index).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut circuit =
+            MstBatchInclusionCircuit::<BATCH, LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proofs);
+
+        let instances = circuit.instances();
+
+        // tamper with the username of the sibling leaf node of the third entry only
+        circuit.paths[2].sibling_leaf_node_hash_preimage[0] += Fp::from(1u64);
+
+        let k =
+            MstBatchInclusionCircuit::<BATCH, LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()
+                .unwrap()
+                .min_k;
+
+        let invalid_prover = MockProver::run(k, &circuit, instances).unwrap();
+
+        let failures = invalid_prover.verify().unwrap_err();
+
+        // the root hash computed from the tampered path no longer matches the public root hash
+        assert!(failures.contains(&VerifyFailure::Permutation {
+            column: (Any::Instance, 0).into(),
+            location: FailureLocation::OutsideRegion { row: 0 }
+        }));
+        // while the root balances and the leaf hashes are untouched
+        assert!(!failures.iter().any(|failure| matches!(
+            failure,
+            VerifyFailure::Permutation {
+                location: FailureLocation::OutsideRegion { row },
+                ..
+            } if *row > 0
+        )));
+    }
 
+    #[test]
+    fn test_batch_inclusion_rows_per_path() {
+        let stats_1 =
+            MstBatchInclusionCircuit::<1, LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()
+                .unwrap();
+        let stats_2 =
+            MstBatchInclusionCircuit::<2, LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()
+                .unwrap();
+        let stats_4 =
+            MstBatchInclusionCircuit::<4, LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()
+                .unwrap();
+
+        // The batch circuit has the same columns as the single inclusion circuit
+        let single_stats =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::constraint_count().unwrap();
+        assert_eq!(stats_4.n_advice_columns, single_stats.n_advice_columns);
+        assert_eq!(stats_4.n_fixed_columns, single_stats.n_fixed_columns);
+
+        // while every path applies the gates of a single inclusion proof once more
+        assert_eq!(stats_1.n_gates, single_stats.n_gates);
+        assert!(stats_2.n_gates > stats_1.n_gates);
+        assert!(stats_4.n_gates > stats_2.n_gates);
+
+        // A batch of a single path fits in the same number of rows as the single inclusion circuit
+        assert_eq!(stats_1.min_k, single_stats.min_k);
+
+        // Every path adds its own rows, while the lookup table is shared
+        assert!(stats_2.min_k >= stats_1.min_k);
+        assert!(stats_4.min_k >= stats_2.min_k);
+        assert!(stats_4.min_k <= stats_1.min_k + 2);
+    }
+
+    #[test]
+    fn test_valid_solvency() {
         let merkle_sum_tree =
             MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
 
-        let user_index = 0;
+        let root_balances = merkle_sum_tree.root().balances.map(fp_to_big_uint);
 
-        let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
-        let user_entry = merkle_sum_tree.get_entry(user_index);
+        // The assets exactly cover the liabilities for the first cryptocurrency and exceed them for the second one
+        let asset_sums = [root_balances[0].clone(), root_balances[1].clone() + 1000u32];
 
-        // Only now we can instantiate the circuit with the actual inputs
-        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let circuit =
+            SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init(&merkle_sum_tree, asset_sums.clone());
 
-        // Generate the proof
-        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances());
+        // The public inputs are the root hash and the asset sums
+        let instances = circuit.instances();
+        assert_eq!(instances[0].len(), circuit.num_instances());
+        assert_eq!(instances[0].len(), 1 + N_CURRENCIES);
+        assert_eq!(instances[0][0], merkle_sum_tree.root().hash);
+        assert_eq!(
+            instances[0][2],
+            merkle_sum_tree.root().balances[1] + Fp::from(1000u64)
+        );
 
-        // verify the proof to be true
+        let valid_prover = MockProver::run(K, &circuit, instances).unwrap();
+
+        valid_prover.assert_satisfied();
+    }
+
+    // Claiming an asset sum lower than the liabilities should fail the range check on the difference between the asset sum and the root balance
+    #[test]
+    fn test_undercollateralized_solvency() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let root_balances = merkle_sum_tree.root().balances.map(fp_to_big_uint);
+
+        let asset_sums = [root_balances[0].clone(), root_balances[1].clone() - 1u32];
+
+        let circuit = SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init(&merkle_sum_tree, asset_sums);
+
+        let invalid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+
+        // The running sum of the difference doesn't end at zero after N_BYTES bytes
+        let failures = invalid_prover.verify().unwrap_err();
+        assert!(failures.iter().any(|failure| failure
+            .to_string()
+            .contains("assign value to perform range check")));
+        // while the root hash and the asset sums match the public inputs
+        assert!(!failures.iter().any(|failure| matches!(
+            failure,
+            VerifyFailure::Permutation {
+                column,
+                location: FailureLocation::OutsideRegion { .. },
+            } if *column == (Any::Instance, 0).into()
+        )));
+    }
+
+    // Passing a root hash that doesn't match the witnessed root should fail the permutation check between the computed root hash and the instance column root hash
+    #[test]
+    fn test_solvency_with_invalid_root_hash() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let asset_sums = merkle_sum_tree.root().balances.map(fp_to_big_uint);
+
+        let circuit = SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init(&merkle_sum_tree, asset_sums);
+
+        let mut instances = circuit.instances();
+        instances[0][0] = Fp::from(1000u64);
+
+        let invalid_prover = MockProver::run(K, &circuit, instances).unwrap();
+
+        assert!(invalid_prover
+            .verify()
+            .unwrap_err()
+            .contains(&VerifyFailure::Permutation {
+                column: (Any::Instance, 0).into(),
+                location: FailureLocation::OutsideRegion { row: 0 }
+            }));
+    }
+
+    #[test]
+    fn test_solvency_solidity_calldata() {
+        let stats = SolvencyCircuit::<N_CURRENCIES, N_BYTES>::constraint_count().unwrap();
+        assert!(stats.min_k <= K);
+
+        let (params, pk, _) = generate_setup_artifacts(
+            K,
+            None,
+            SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init_empty(),
+        )
+        .unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let asset_sums = merkle_sum_tree.root().balances.map(fp_to_big_uint);
+
+        let circuit = SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init(&merkle_sum_tree, asset_sums);
+
+        let SolidityCalldata {
+            proof,
+            public_inputs,
+        } = gen_proof_solidity_calldata(&params, &pk, circuit).unwrap();
+
+        assert!(!proof.is_empty());
+        assert_eq!(public_inputs.len(), 1 + N_CURRENCIES);
+    }
+
+    // Builds a tree whose first user has a balance of 500 and second user a balance of 50 in the first cryptocurrency, both entries being salted
+    fn balance_threshold_tree() -> MerkleSumTree<N_CURRENCIES, N_BYTES> {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let mut entries = merkle_sum_tree.entries().to_vec();
+        for (index, balance) in [(0, 500), (1, 50)] {
+            entries[index] = Entry::new_salted(
+                entries[index].username().to_string(),
+                [
+                    balance.to_biguint().unwrap(),
+                    entries[index].balances()[1].clone(),
+                ],
+                Entry::<N_CURRENCIES>::random_salt(),
+            );
+        }
+
+        MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            merkle_sum_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_valid_balance_threshold() {
+        let merkle_sum_tree = balance_threshold_tree();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+            merkle_proof,
+            0,
+            100.to_biguint().unwrap(),
+        )
+        .unwrap();
+
+        // The public inputs are the leaf hash, the root hash and the thresholds, but not the balances
+        let instances = circuit.instances();
+        assert_eq!(instances[0].len(), circuit.num_instances());
+        assert_eq!(
+            instances[0],
+            vec![
+                merkle_sum_tree.get_entry(0).compute_leaf().hash,
+                merkle_sum_tree.root().hash,
+                Fp::from(100u64),
+                Fp::zero()
+            ]
+        );
+
+        let valid_prover = MockProver::run(K, &circuit, instances).unwrap();
+        valid_prover.assert_satisfied();
+
+        // A balance equal to the threshold is above it as well
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+            merkle_proof,
+            0,
+            500.to_biguint().unwrap(),
+        )
+        .unwrap();
+        let valid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+
+        // The proof verifies against the verifying key of the empty circuit
+        let stats =
+            BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::constraint_count().unwrap();
+        assert!(stats.min_k <= K);
+
+        let (params, pk, vk) = generate_setup_artifacts(
+            K,
+            None,
+            BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+        )
+        .unwrap();
+
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
         assert!(full_verifier(&params, &vk, proof, circuit.instances()));
+    }
+
+    // A balance below the threshold should fail the range check on the difference between the balance and the threshold
+    #[test]
+    fn test_balance_below_threshold() {
+        let merkle_sum_tree = balance_threshold_tree();
 
-        // the user should perform the check on the public inputs
-        // public input #0 is the leaf hash
-        let expected_leaf_hash = user_entry.compute_leaf().hash;
-        assert_eq!(circuit.instances()[0][0], expected_leaf_hash);
+        let merkle_proof = merkle_sum_tree.generate_proof(1).unwrap();
+        let circuit = BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+            merkle_proof,
+            0,
+            100.to_biguint().unwrap(),
+        )
+        .unwrap();
 
-        // public input #1 is the root hash
-        let expected_root_hash = merkle_sum_tree.root().hash;
-        assert_eq!(circuit.instances()[0][1], expected_root_hash);
+        let invalid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
 
-        // public inputs [2, 2+N_CURRENCIES - 1] are the root balances
-        let expected_root_balances = merkle_sum_tree.root().balances;
-        for i in 0..N_CURRENCIES {
-            assert_eq!(circuit.instances()[0][2 + i], expected_root_balances[i]);
+        // The running sum of the difference doesn't end at zero after N_BYTES bytes
+        let failures = invalid_prover.verify().unwrap_err();
+        assert!(failures.iter().any(|failure| failure
+            .to_string()
+            .contains("assign value to perform range check")));
+
+        // Claiming a lower threshold than the one the circuit is built with fails the permutation check with the instance column
+        let merkle_proof = merkle_sum_tree.generate_proof(1).unwrap();
+        let circuit = BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+            merkle_proof,
+            0,
+            10.to_biguint().unwrap(),
+        )
+        .unwrap();
+        let mut instances = circuit.instances();
+        instances[0][2] = Fp::from(100u64);
+
+        let invalid_prover = MockProver::run(K, &circuit, instances).unwrap();
+        assert!(invalid_prover
+            .verify()
+            .unwrap_err()
+            .contains(&VerifyFailure::Permutation {
+                column: (Any::Instance, 0).into(),
+                location: FailureLocation::OutsideRegion { row: 2 }
+            }));
+    }
+
+    #[test]
+    fn test_balance_threshold_init_errors() {
+        let merkle_sum_tree = balance_threshold_tree();
+
+        // The asset index must be the one of a cryptocurrency of the tree
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        assert!(matches!(
+            BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+                merkle_proof,
+                N_CURRENCIES,
+                100.to_biguint().unwrap(),
+            ),
+            Err(CircuitError::InvalidWitness(_))
+        ));
+
+        // The balances of an unsalted entry could be ground out of its public leaf hash
+        let merkle_proof = merkle_sum_tree.generate_proof(2).unwrap();
+        assert!(merkle_proof.entry.salt().is_none());
+        assert!(matches!(
+            BalanceThresholdCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+                merkle_proof,
+                0,
+                0.to_biguint().unwrap(),
+            ),
+            Err(CircuitError::InvalidWitness(_))
+        ));
+    }
+
+    #[test]
+    fn test_solidity_calldata() {
+        // The calldata generated by the `gen_inclusion_proof` example, which the Solidity tests pass to the verifier
+        let json =
+            std::fs::read_to_string("examples/inclusion_proof_solidity_calldata.json").unwrap();
+        let calldata: SolidityCalldata = serde_json::from_str(&json).unwrap();
+        assert_eq!(calldata.public_inputs.len(), 3 + N_CURRENCIES);
+
+        // The JSON representation round trips
+        let serialized = serde_json::to_string(&calldata).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SolidityCalldata>(&serialized).unwrap(),
+            calldata
+        );
+
+        // The public inputs can also be given as decimal strings
+        let decimal_json = serde_json::json!({
+            "proof": calldata.proof,
+            "public_inputs": calldata
+                .public_inputs
+                .iter()
+                .map(|input| input.to_string())
+                .collect::<Vec<_>>(),
+        });
+        assert_eq!(
+            serde_json::from_value::<SolidityCalldata>(decimal_json).unwrap(),
+            calldata
+        );
+        assert!(
+            serde_json::from_value::<SolidityCalldata>(serde_json::json!({
+                "proof": calldata.proof,
+                "public_inputs": ["not a number"],
+            }))
+            .is_err()
+        );
+
+        // The ABI encoding is the calldata expected by the Solidity verifier
+        let instances: Vec<Fp> = calldata
+            .public_inputs
+            .iter()
+            .map(|input| {
+                let mut bytes = [0u8; 32];
+                input.to_little_endian(&mut bytes);
+                Fp::from_bytes(&bytes).unwrap()
+            })
+            .collect();
+        assert_eq!(
+            calldata.to_abi_encoded().to_vec(),
+            encode_calldata(None, &calldata.proof, &instances)
+        );
+    }
+
+    #[test]
+    fn test_check_witness() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let instances = circuit.instances();
+
+        // A valid witness satisfies all the constraints
+        assert_eq!(circuit.check_witness(), Ok(()));
+
+        // An invalid entry balance breaks the equality constraints of the leaf hash, of the root hash and of the root balances
+        let mut invalid_entry_circuit = circuit.clone();
+        invalid_entry_circuit.entry = Entry::new(
+            circuit.entry.username().to_string(),
+            [1000.to_biguint().unwrap(), 1000.to_biguint().unwrap()],
+        );
+        let violations = invalid_entry_circuit
+            .check_witness_with_instances(instances.clone())
+            .unwrap_err();
+        assert!(violations
+            .iter()
+            .all(|violation| violation.kind == ViolationKind::Equality));
+        for public_input_row in 1..3 + N_CURRENCIES {
+            assert!(violations
+                .iter()
+                .any(|violation| violation.region.is_none()
+                    && violation.row == Some(public_input_row)));
         }
+
+        // A non binary index breaks the bool constraint and the swap constraints
+        let mut non_binary_index_circuit = circuit.clone();
+        non_binary_index_circuit.path_indices[0] = Fp::from(2);
+        let violations = non_binary_index_circuit
+            .check_witness_with_instances(instances.clone())
+            .unwrap_err();
+        let bool_violation = violations
+            .iter()
+            .find(|violation| {
+                violation.kind == ViolationKind::Gate
+                    && violation.gate_name.contains("bool constraint")
+            })
+            .unwrap();
+        assert_eq!(bool_violation.cell_values.len(), 1);
+        assert_eq!(bool_violation.cell_values[0].1, "0x2");
+        assert!(violations
+            .iter()
+            .any(|violation| violation.kind == ViolationKind::Gate
+                && violation.gate_name.contains("swap constraint")));
+        assert!(!violations
+            .iter()
+            .any(|violation| violation.kind == ViolationKind::Lookup));
+
+        // Swapping the indices breaks the equality constraint of the root hash only
+        let mut swapped_index_circuit = circuit.clone();
+        swapped_index_circuit.path_indices[0] = Fp::from(1);
+        let violations = swapped_index_circuit
+            .check_witness_with_instances(instances)
+            .unwrap_err();
+        assert!(violations
+            .iter()
+            .all(|violation| violation.kind == ViolationKind::Equality));
+        let root_hash_violation = violations
+            .iter()
+            .find(|violation| violation.region.is_none())
+            .unwrap();
+        assert_eq!(root_hash_violation.row, Some(2));
+        assert!(root_hash_violation.to_string().ends_with("at row 2"));
+
+        // A balance out of range fails the lookup of the range check, as it fails the MockProver
+        let mut entries = merkle_sum_tree.entries().to_vec();
+        entries[0] = Entry::new(
+            entries[0].username().to_string(),
+            [
+                1.to_biguint().unwrap() << (N_BYTES * 8),
+                entries[0].balances()[1].clone(),
+            ],
+        );
+        let out_of_range_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            merkle_sum_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+        let out_of_range_circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+            out_of_range_tree.generate_proof(0).unwrap(),
+        );
+        let violations = out_of_range_circuit.check_witness().unwrap_err();
+        assert!(violations
+            .iter()
+            .any(|violation| violation.kind == ViolationKind::Lookup));
+        assert!(preflight_check(K, &out_of_range_circuit).is_err());
     }
 
     // Passing an invalid root hash in the instance column should fail the permutation check between the computed root hash and the instance column root hash
@@ -100,7 +2418,7 @@ mod test {
 
         let mut instances = circuit.instances();
         let invalid_root_hash = Fp::from(1000u64);
-        instances[0][1] = invalid_root_hash;
+        instances[0][2] = invalid_root_hash;
 
         let invalid_prover = MockProver::run(K, &circuit, instances).unwrap();
 
@@ -110,13 +2428,13 @@ mod test {
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 0).into(),
                     location: FailureLocation::InRegion {
-                        region: (121, "permute state").into(),
+                        region: (122, "permute state").into(),
                         offset: 36
                     }
                 },
                 VerifyFailure::Permutation {
                     column: (Any::Instance, 0).into(),
-                    location: FailureLocation::OutsideRegion { row: 1 }
+                    location: FailureLocation::OutsideRegion { row: 2 }
                 },
             ])
         );
@@ -142,15 +2460,84 @@ mod test {
         let invalid_root_hash = Fp::from(1000u64);
 
         let mut instances = circuit.instances();
-        instances[0][1] = invalid_root_hash;
+        instances[0][2] = invalid_root_hash;
 
-        // Generate the proof
-        let proof = full_prover(&params, &pk, circuit, instances.clone());
+        // The proof generated for the invalid root hash doesn't verify, so that the prover returns an error
+        assert!(matches!(
+            full_prover(&params, &pk, circuit.clone(), instances.clone()),
+            Err(ProverError::Synthesis(_))
+        ));
 
-        // verify the proof to be false
+        // A valid proof doesn't verify against the invalid root hash either
+        let proof = full_prover(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
         assert!(!full_verifier(&params, &vk, proof, instances));
     }
 
+    #[test]
+    fn test_balance_not_in_range_with_full_prover() {
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty();
+
+        let (params, pk, _) = generate_setup_artifacts(K, None, circuit).unwrap();
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // Build a tree whose first entry has a balance exceeding the range check
+        let mut entries = merkle_sum_tree.entries().to_vec();
+        entries[0] = Entry::new(
+            entries[0].username().to_string(),
+            [
+                1.to_biguint().unwrap() << (N_BYTES * 8),
+                entries[0].balances()[1].clone(),
+            ],
+        );
+
+        let merkle_sum_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            merkle_sum_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        // The proof doesn't satisfy the range check, so that an error is returned rather than a proof that no verifier accepts
+        assert!(matches!(
+            full_prover(&params, &pk, circuit.clone(), circuit.instances()),
+            Err(ProverError::Synthesis(_))
+        ));
+        assert!(matches!(
+            gen_proof_solidity_calldata(&params, &pk, circuit.clone()),
+            Err(ProverError::Synthesis(_))
+        ));
+
+        // Params of a different size than the proving key are rejected before proving
+        let (bigger_params, _, _) = generate_setup_artifacts(
+            K + 1,
+            None,
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+        )
+        .unwrap();
+        assert_eq!(
+            full_prover(&bigger_params, &pk, circuit.clone(), circuit.instances()),
+            Err(ProverError::ParamsMismatch {
+                pk_k: K,
+                params_k: K + 1
+            })
+        );
+
+        // The prover errors are the proving failures of the circuit errors
+        let error: CircuitError = gen_proof_solidity_calldata(&params, &pk, circuit)
+            .unwrap_err()
+            .into();
+        assert!(matches!(
+            error,
+            CircuitError::ProvingFailed(ProverError::Synthesis(_))
+        ));
+    }
+
     // Passing an invalid entry balance as input for the witness generation should fail:
     // - the permutation check between the leaf hash and the instance column leaf hash
     // - the permutation check between the computed root hash and the instance column root hash
@@ -183,35 +2570,31 @@ mod test {
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 0).into(),
                     location: FailureLocation::InRegion {
-                        region: (26, "assign nodes hashes per merkle tree level").into(),
+                        region: (27, "assign nodes hashes per merkle tree level").into(),
                         offset: 0
                     }
                 },
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 0).into(),
                     location: FailureLocation::InRegion {
-                        region: (121, "permute state").into(),
+                        region: (122, "permute state").into(),
                         offset: 36
                     }
                 },
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 2).into(),
                     location: FailureLocation::InRegion {
-                        region: (111, "sum nodes balances per currency").into(),
+                        region: (112, "sum nodes balances per currency").into(),
                         offset: 0
                     }
                 },
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 2).into(),
                     location: FailureLocation::InRegion {
-                        region: (112, "sum nodes balances per currency").into(),
+                        region: (113, "sum nodes balances per currency").into(),
                         offset: 0
                     }
                 },
-                VerifyFailure::Permutation {
-                    column: (Any::Instance, 0).into(),
-                    location: FailureLocation::OutsideRegion { row: 0 }
-                },
                 VerifyFailure::Permutation {
                     column: (Any::Instance, 0).into(),
                     location: FailureLocation::OutsideRegion { row: 1 }
@@ -224,6 +2607,10 @@ mod test {
                     column: (Any::Instance, 0).into(),
                     location: FailureLocation::OutsideRegion { row: 3 }
                 },
+                VerifyFailure::Permutation {
+                    column: (Any::Instance, 0).into(),
+                    location: FailureLocation::OutsideRegion { row: 4 }
+                },
             ])
         );
     }
@@ -243,7 +2630,7 @@ mod test {
 
         let mut instances = circuit.instances();
         let invalid_leaf_hash = Fp::from(1000u64);
-        instances[0][0] = invalid_leaf_hash;
+        instances[0][1] = invalid_leaf_hash;
 
         let invalid_prover = MockProver::run(K, &circuit, instances).unwrap();
 
@@ -253,30 +2640,47 @@ mod test {
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 0).into(),
                     location: FailureLocation::InRegion {
-                        region: (26, "assign nodes hashes per merkle tree level").into(),
+                        region: (27, "assign nodes hashes per merkle tree level").into(),
                         offset: 0
                     }
                 },
                 VerifyFailure::Permutation {
                     column: (Any::Instance, 0).into(),
-                    location: FailureLocation::OutsideRegion { row: 0 }
+                    location: FailureLocation::OutsideRegion { row: 1 }
                 },
             ])
         );
     }
 
-    // Building a proof using as input a csv file with an entry that is not in range [0, 2^N_BYTES*8 - 1] should fail the range check constraint on the leaf balance
+    // Building a proof for an entry that is not in range [0, 2^N_BYTES*8 - 1] should fail the range check constraint on the leaf balance.
+    // The CSV parser rejects such an entry, so the unchecked `Entry::new` is used to build it
     #[test]
     fn test_balance_not_in_range() {
         let merkle_sum_tree =
-            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16_overflow.csv")
-                .unwrap();
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let mut entries = merkle_sum_tree.entries().to_vec();
+        entries[0] = Entry::new(
+            entries[0].username().to_string(),
+            [
+                1.to_biguint().unwrap() << (N_BYTES * 8 + 48),
+                entries[0].balances()[1].clone(),
+            ],
+        );
+
+        let merkle_sum_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            merkle_sum_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
 
         let user_index = 0;
 
         let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
 
-        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+        let circuit =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof.clone());
 
         let invalid_prover = MockProver::run(K, &circuit, circuit.instances()).unwrap();
 
@@ -285,17 +2689,102 @@ mod test {
             Err(vec![
                 VerifyFailure::Permutation {
                     column: (Any::Fixed, 2).into(),
-                    location: FailureLocation::OutsideRegion { row: 246 }
+                    location: FailureLocation::OutsideRegion { row: 247 }
                 },
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 0).into(),
                     location: FailureLocation::InRegion {
-                        region: (21, "assign value to perform range check").into(),
+                        region: (22, "assign value to perform range check").into(),
                         offset: 8
                     }
                 },
             ])
         );
+
+        // The balance is rejected as well when the range check looks up 16-bit chunks, in which case the running sum is 4 rows long
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES, 16>::init(merkle_proof);
+
+        let invalid_prover = MockProver::run(17, &circuit, circuit.instances()).unwrap();
+
+        assert!(invalid_prover
+            .verify()
+            .unwrap_err()
+            .contains(&VerifyFailure::Permutation {
+                column: (Any::advice(), 0).into(),
+                location: FailureLocation::InRegion {
+                    region: (22, "assign value to perform range check").into(),
+                    offset: 4
+                }
+            }));
+    }
+
+    #[test]
+    fn test_preflight_check_balance_not_in_range() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let valid_circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+            merkle_sum_tree.generate_proof(0).unwrap(),
+        );
+        assert_eq!(preflight_check(K, &valid_circuit), Ok(()));
+
+        // Same out of range balance as in `test_balance_not_in_range`
+        let mut entries = merkle_sum_tree.entries().to_vec();
+        entries[0] = Entry::new(
+            entries[0].username().to_string(),
+            [
+                1.to_biguint().unwrap() << (N_BYTES * 8 + 48),
+                entries[0].balances()[1].clone(),
+            ],
+        );
+
+        let merkle_sum_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            merkle_sum_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+            merkle_sum_tree.generate_proof(0).unwrap(),
+        );
+
+        let violations = preflight_check(K, &circuit).unwrap_err();
+        assert_eq!(violations.len(), 2);
+
+        // The violation names the region of the range check that fails
+        let message = violations[1].to_string();
+        assert!(message.starts_with("Equality constraint on column"));
+        assert!(message.contains("assign value to perform range check"));
+        assert!(message.ends_with("at offset 8"));
+    }
+
+    #[test]
+    fn test_valid_merkle_sum_tree_with_16bit_lookup() {
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES, 16>::init(merkle_proof);
+
+        let stats =
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES, 16>::constraint_count().unwrap();
+
+        // The lookup table of 2^16 rows needs k >= 17
+        assert_eq!(stats.min_k, 17);
+
+        let valid_prover = MockProver::run(stats.min_k, &circuit, circuit.instances()).unwrap();
+        valid_prover.assert_satisfied();
+
+        // The 16-bit configuration has the same public inputs as the 8-bit one
+        assert_eq!(
+            circuit.instances(),
+            MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(
+                merkle_sum_tree.generate_proof(0).unwrap()
+            )
+            .instances()
+        );
     }
 
     // Passing a non binary index should fail the bool constraint inside "assign nodes hashes per merkle tree level" and "assign nodes balances per currency" region and the permutation check between the computed root hash and the instance column root hash
@@ -324,7 +2813,7 @@ mod test {
                 VerifyFailure::ConstraintNotSatisfied {
                     constraint: ((6, "bool constraint").into(), 0, "").into(),
                     location: FailureLocation::InRegion {
-                        region: (26, "assign nodes hashes per merkle tree level").into(),
+                        region: (27, "assign nodes hashes per merkle tree level").into(),
                         offset: 0
                     },
                     cell_values: vec![(((Any::advice(), 2).into(), 0).into(), "0x2".to_string()),]
@@ -332,7 +2821,7 @@ mod test {
                 VerifyFailure::ConstraintNotSatisfied {
                     constraint: ((7, "swap constraint").into(), 0, "").into(),
                     location: FailureLocation::InRegion {
-                        region: (26, "assign nodes hashes per merkle tree level").into(),
+                        region: (27, "assign nodes hashes per merkle tree level").into(),
                         offset: 0
                     },
                     cell_values: vec![
@@ -357,7 +2846,7 @@ mod test {
                 VerifyFailure::ConstraintNotSatisfied {
                     constraint: ((7, "swap constraint").into(), 1, "").into(),
                     location: FailureLocation::InRegion {
-                        region: (26, "assign nodes hashes per merkle tree level").into(),
+                        region: (27, "assign nodes hashes per merkle tree level").into(),
                         offset: 0
                     },
                     cell_values: vec![
@@ -382,13 +2871,13 @@ mod test {
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 0).into(),
                     location: FailureLocation::InRegion {
-                        region: (121, "permute state").into(),
+                        region: (122, "permute state").into(),
                         offset: 36
                     }
                 },
                 VerifyFailure::Permutation {
                     column: (Any::Instance, 0).into(),
-                    location: FailureLocation::OutsideRegion { row: 1 }
+                    location: FailureLocation::OutsideRegion { row: 2 }
                 },
             ])
         );
@@ -420,18 +2909,110 @@ mod test {
                 VerifyFailure::Permutation {
                     column: (Any::advice(), 0).into(),
                     location: FailureLocation::InRegion {
-                        region: (121, "permute state").into(),
+                        region: (122, "permute state").into(),
                         offset: 36
                     }
                 },
                 VerifyFailure::Permutation {
                     column: (Any::Instance, 0).into(),
-                    location: FailureLocation::OutsideRegion { row: 1 }
+                    location: FailureLocation::OutsideRegion { row: 2 }
                 },
             ])
         );
     }
 
+    #[cfg(feature = "debug")]
+    #[test]
+    fn test_extract_witness() {
+        use crate::circuits::debug::extract_witness;
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let user_index = 0;
+
+        let merkle_proof = merkle_sum_tree.generate_proof(user_index).unwrap();
+        let user_entry = merkle_sum_tree.get_entry(user_index).clone();
+
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let witness = extract_witness(&circuit, K).unwrap();
+
+        // The circuit uses 3 advice columns of 2^K rows
+        assert_eq!(witness.columns.len(), 3);
+        assert!(witness.columns.iter().all(|values| values.len() == 1 << K));
+
+        // The leaf hash is assigned to the first advice column before being swapped at level 0
+        let leaf_hash = user_entry.compute_leaf().hash;
+        let leaf_hash_rows: Vec<usize> = (0..1 << K)
+            .filter(|row| witness.get_value(0, *row) == Some(leaf_hash))
+            .collect();
+        assert!(!leaf_hash_rows.is_empty());
+
+        // Cells outside of the witness have no value
+        assert_eq!(witness.get_value(3, 0), None);
+        assert_eq!(witness.get_value(0, 1 << K), None);
+
+        // The CSV export has a header and a line per row
+        let path = std::env::temp_dir().join("mst_inclusion_witness.csv");
+        witness.to_csv(&path).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(csv.lines().count(), (1 << K) + 1);
+        assert_eq!(
+            csv.lines().next().unwrap(),
+            "row,advice_0,advice_1,advice_2"
+        );
+        assert!(csv.contains(&format!("{:?}", leaf_hash)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "dev-stats")]
+    #[test]
+    fn test_circuit_layout_report() {
+        use crate::circuits::layout_report::{circuit_layout_report, CircuitLayoutReport};
+
+        let merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let merkle_proof = merkle_sum_tree.generate_proof(0).unwrap();
+        let circuit = MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+
+        let path =
+            std::env::temp_dir().join(format!("mst_inclusion_layout_{}.json", std::process::id()));
+        circuit_layout_report(&circuit, K)
+            .write_json(&path)
+            .unwrap();
+
+        let report: CircuitLayoutReport =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(report.k, K);
+        assert_eq!(report.n_instance_columns, circuit.instances().len());
+        assert_eq!(report.n_public_inputs, circuit.num_instances());
+        assert_eq!(report.n_lookups, 1);
+        assert!(report.n_copy_constraints > 0);
+        assert!(report.used_rows <= 1 << K);
+        assert!(report
+            .regions
+            .iter()
+            .any(|region| region.name == "assign value to perform range check"));
+
+        // The regions are reported for the solvency circuit as well
+        let root_balances = merkle_sum_tree.root().balances.map(fp_to_big_uint);
+        let circuit =
+            SolvencyCircuit::<N_CURRENCIES, N_BYTES>::init(&merkle_sum_tree, root_balances);
+
+        circuit_layout_report(&circuit, K)
+            .write_json(&path)
+            .unwrap();
+        let report: CircuitLayoutReport =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(report.n_instance_columns, circuit.instances().len());
+        assert_eq!(report.n_public_inputs, circuit.num_instances());
+        assert!(!report.regions.is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
     #[cfg(feature = "dev-graph")]
     #[test]
     fn print_mst_inclusion() {
@@ -33,10 +33,20 @@ pub trait CircuitBase {
 
     /// Loads the lookup table with values from `0` to `2^8 - 1`
     fn load(&self, layouter: &mut impl Layouter<Fp>, column: Column<Fixed>) -> Result<(), Error> {
-        let range = 1 << 8;
+        self.load_range_table(layouter, column, 8)
+    }
+
+    /// Loads the lookup table with values from `0` to `2^lookup_bits - 1`, as needed by a range check chip configured with chunks of `lookup_bits` bits
+    fn load_range_table(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        column: Column<Fixed>,
+        lookup_bits: usize,
+    ) -> Result<(), Error> {
+        let range = 1 << lookup_bits;
 
         layouter.assign_region(
-            || format!("load range check table of {} bits", 8),
+            || format!("load range check table of {} bits", lookup_bits),
             |mut region| {
                 for i in 0..range {
                     region.assign_fixed(
@@ -51,3 +61,13 @@ pub trait CircuitBase {
         )
     }
 }
+
+/// Identifier of a circuit recorded in the setup artifacts and the proof artifacts generated for it, so that they can't be loaded for another circuit.
+/// Unlike the Rust type name of the circuit, it doesn't change with the compiler or the module path, and it changes when the layout of the circuit does.
+pub trait CircuitId {
+    /// The version of the layout of the circuit, to be bumped whenever its gates or its assignment change for the same type parameters
+    const CIRCUIT_VERSION: u32;
+
+    /// Returns the identifier of the circuit, made of its name, `CIRCUIT_VERSION` and its type parameters, e.g. `mst-inclusion-v1-levels4-currencies2-bytes8`
+    fn circuit_id() -> String;
+}
@@ -1,14 +1,461 @@
-use ethers::types::U256;
-use serde::{Deserialize, Serialize};
+use ethers::{
+    abi::{parse_abi, Token},
+    types::{Bytes, U256},
+};
+use halo2_proofs::{
+    halo2curves::{
+        bn256::{Bn256, G1Affine, G2Affine},
+        group::GroupEncoding,
+    },
+    plonk::Error as PlonkError,
+    poly::kzg::commitment::ParamsKZG,
+    SerdeFormat,
+};
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Serialize, Deserialize)]
-pub struct ProofSolidityCallData {
-    pub proof: String,
+/// The arguments of the `verifyProof(bytes proof, uint256[] instances)` function of the Solidity verifier, as returned by `gen_proof_solidity_calldata`.
+///
+/// The JSON representation is `{"proof": "0x...", "public_inputs": ["0x...", ...]}`, where the proof and the public inputs are 0x-prefixed hex strings.
+/// The public inputs can also be deserialized from decimal strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolidityCalldata {
+    pub proof: Bytes,
+    #[serde(deserialize_with = "deserialize_u256_hex_or_decimal")]
     pub public_inputs: Vec<U256>,
 }
 
+impl SolidityCalldata {
+    /// Returns the calldata of the call to `verifyProof`, namely the function selector followed by the ABI encoded proof and public inputs,
+    /// to be sent as is to the Solidity verifier
+    pub fn to_abi_encoded(&self) -> Bytes {
+        let abi = parse_abi(&[
+            "function verifyProof(bytes calldata proof, uint256[] calldata instances) public returns (bool)",
+        ]).expect("Invalid ABI");
+
+        abi.function("verifyProof")
+            .expect("The ABI should contain verifyProof")
+            .encode_input(&[
+                Token::Bytes(self.proof.to_vec()),
+                Token::Array(
+                    self.public_inputs
+                        .iter()
+                        .map(|&input| Token::Uint(input))
+                        .collect(),
+                ),
+            ])
+            .expect("The proof and the public inputs should match the types of verifyProof")
+            .into()
+    }
+}
+
+/// Deserializes a list of unsigned integers given as 0x-prefixed hex strings or as decimal strings
+fn deserialize_u256_hex_or_decimal<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<U256>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|input| {
+            match input.strip_prefix("0x") {
+                Some(hex) => U256::from_str_radix(hex, 16).ok(),
+                None => U256::from_dec_str(input).ok(),
+            }
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid public input {}", input)))
+        })
+        .collect()
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CommitmentSolidityCallData {
     pub root_hash: U256,
     pub root_balances: Vec<U256>,
 }
+
+/// Summary of a migration of the setup artifacts of the inclusion circuit to a deeper tree, as returned by `migrate_setup_artifacts`.
+///
+/// # Fields
+///
+/// * `old_k`: The `k` of the previous setup artifacts
+/// * `new_k`: The `k` of the new setup artifacts
+/// * `old_levels`: The number of levels of the previous circuit
+/// * `new_levels`: The number of levels of the new circuit
+/// * `affected_users`: The number of users of the tree, not counting the zero entries padding it, whose inclusion proofs must be generated again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub old_k: u32,
+    pub new_k: u32,
+    pub old_levels: usize,
+    pub new_levels: usize,
+    pub affected_users: usize,
+}
+
+/// The layout of a params file checked by `verify_params_file`.
+///
+/// # Fields
+///
+/// * `k`: The `k` of the params, read from the header of the file
+/// * `num_g1`: The number of G1 points of the file, namely the 2^`k` powers of tau and their 2^`k` Lagrange basis
+/// * `num_g2`: The number of G2 points of the file, namely the generator of G2 and the G2 element of the trusted setup
+/// * `file_size_bytes`: The size of the file in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamsIntegrity {
+    pub k: u32,
+    pub num_g1: usize,
+    pub num_g2: usize,
+    pub file_size_bytes: u64,
+}
+
+/// The part of the params of the trusted setup that the SHPLONK verifier uses, as read by `read_verifier_params`.
+///
+/// # Fields
+///
+/// * `k`: The `k` of the params the proofs are generated with
+/// * `g`: The generator of G1
+/// * `g2`: The generator of G2
+/// * `s_g2`: The G2 element of the trusted setup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VerifierParams {
+    pub k: u32,
+    pub g: G1Affine,
+    pub g2: G2Affine,
+    pub s_g2: G2Affine,
+}
+
+impl VerifierParams {
+    /// Returns the `ParamsKZG` holding these elements only, which can be passed to `full_verifier` and `verify_inclusion_proof` but not to a prover.
+    pub fn to_params(&self) -> ParamsKZG<Bn256> {
+        // `ParamsKZG` can only be built from a setup or read, so the params of size 1 made of these elements are serialized,
+        // the Lagrange basis of a domain of size 1 being the generator itself
+        let mut bytes = 0u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(self.g.to_bytes().as_ref());
+        bytes.extend_from_slice(self.g.to_bytes().as_ref());
+        bytes.extend_from_slice(self.g2.to_bytes().as_ref());
+        bytes.extend_from_slice(self.s_g2.to_bytes().as_ref());
+        let params = ParamsKZG::<Bn256>::read_custom(&mut bytes.as_slice(), SerdeFormat::Processed)
+            .expect("The serialized points should be valid");
+
+        // The empty Lagrange basis avoids computing it for a domain of size 2^k out of the single G1 element
+        params.from_parts(self.k, vec![self.g], Some(vec![]), self.g2, self.s_g2)
+    }
+}
+
+/// Size of a circuit, used to choose the `k` parameter before running the setup.
+///
+/// # Fields
+///
+/// * `n_gates`: The number of applications of the custom gates, namely the rows at which each gate is enabled
+/// * `n_advice_columns`: The number of advice columns
+/// * `n_fixed_columns`: The number of fixed columns, not counting the ones added by the selector compression
+/// * `n_instance_columns`: The number of instance columns
+/// * `n_rows`: The number of rows used by the assigned cells, not counting the blinding rows
+/// * `min_k`: The smallest `k` such that the circuit fits in 2^k rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitStats {
+    pub n_gates: usize,
+    pub n_advice_columns: usize,
+    pub n_fixed_columns: usize,
+    pub n_instance_columns: usize,
+    pub n_rows: usize,
+    pub min_k: u32,
+}
+
+/// Share of the 2^k rows of a circuit used by its regions, as returned by `circuit_utilization`.
+///
+/// # Fields
+///
+/// * `total_rows`: The number of rows of the circuit, namely 2^k
+/// * `used_rows`: The number of rows assigned by the circuit, including its lookup tables but not the blinding rows
+/// * `utilization_pct`: The percentage of the rows that are used
+/// * `bottleneck_gate`: The name of the regions spanning the most rows altogether, e.g. the rounds of the Poseidon permutation
+/// * `warning`: A warning that less than half of the rows are used, in which case the circuit would fit with a smaller `k`, none otherwise
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitUtilization {
+    pub total_rows: usize,
+    pub used_rows: usize,
+    pub utilization_pct: f64,
+    pub bottleneck_gate: String,
+    pub warning: Option<String>,
+}
+
+/// Time spent assigning the regions sharing a name during the synthesis of a circuit, see `SynthesisProfile`.
+///
+/// # Fields
+///
+/// * `name`: The name of the regions, as given to `Layouter::assign_region`
+/// * `rows`: The number of rows spanned by the regions altogether
+/// * `duration_ns`: The wall-clock time spent in the regions altogether, including the computation of the witness of their cells, in nanoseconds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionTiming {
+    pub name: String,
+    pub rows: usize,
+    pub duration_ns: u64,
+}
+
+/// Wall-clock time spent in the regions of a circuit while it is synthesized, as returned by `synthesis_profile`.
+///
+/// # Fields
+///
+/// * `regions`: The time spent in each region name, sorted from the slowest to the fastest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SynthesisProfile {
+    pub regions: Vec<RegionTiming>,
+}
+
+/// The public input of the Mst Inclusion circuit that disagrees with the expected one, as returned by `MstInclusionCircuit::validate_instances`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceMismatch {
+    /// The number of instance columns or of public inputs differs from the expected one
+    Length { expected: usize, found: usize },
+    /// The public input #0, namely the version of the layout of the public inputs, differs from `MST_INCLUSION_CIRCUIT_VERSION`
+    Version,
+    /// The public input #1, namely the leaf hash, differs from the expected one
+    LeafHash,
+    /// The public input #2, namely the root hash, differs from the expected one
+    RootHash,
+    /// The root balance of the cryptocurrency with the given index, namely the public input #(3 + index), differs from the expected one
+    RootBalance(usize),
+}
+
+impl std::fmt::Display for InstanceMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstanceMismatch::Length { expected, found } => {
+                write!(f, "Expected {} public inputs but found {}", expected, found)
+            }
+            InstanceMismatch::Version => write!(f, "Circuit version mismatch"),
+            InstanceMismatch::LeafHash => write!(f, "Leaf hash mismatch"),
+            InstanceMismatch::RootHash => write!(f, "Root hash mismatch"),
+            InstanceMismatch::RootBalance(index) => {
+                write!(f, "Root balance mismatch for cryptocurrency {}", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InstanceMismatch {}
+
+/// A constraint of a circuit that is not satisfied by its witness, as returned by `MstInclusionCircuit::check_witness` or `preflight_check`.
+///
+/// # Fields
+///
+/// * `kind`: The kind of the violated constraint
+/// * `gate_name`: The description of the violated constraint, namely a gate constraint, a lookup or an equality constraint on a column
+/// * `region`: The region in which the constraint is violated, if any
+/// * `row`: The row at which the constraint is violated, relative to the start of `region` if any, otherwise absolute
+/// * `cell_values`: The cells queried by the violated gate constraint, along with their values. It is empty for the other constraints
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintViolation {
+    pub kind: ViolationKind,
+    pub gate_name: String,
+    pub region: Option<String>,
+    pub row: Option<usize>,
+    pub cell_values: Vec<(String, String)>,
+}
+
+/// The kind of a constraint violated by a witness, as reported in a `ConstraintViolation`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A custom gate, e.g. the bool constraint of a path index
+    Gate,
+    /// A lookup, e.g. the lookup of the range check
+    Lookup,
+    /// An equality constraint, e.g. between a computed cell and a public input
+    Equality,
+    /// A cell queried by a gate that isn't assigned
+    UnassignedCell,
+    /// The circuit couldn't be synthesized, so that no constraint could be checked
+    Synthesis,
+    /// Any other failure reported by the MockProver
+    Other,
+}
+
+impl std::fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not satisfied", self.gate_name)?;
+        match (&self.region, self.row) {
+            (Some(region), Some(row)) => write!(f, " in {} at offset {}", region, row)?,
+            (None, Some(row)) => write!(f, " at row {}", row)?,
+            _ => {}
+        }
+        for (i, (cell, value)) in self.cell_values.iter().enumerate() {
+            let separator = if i == 0 { ", with cells " } else { ", " };
+            write!(f, "{}{} = {}", separator, cell, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The reason why a proof couldn't be checked by `verify_inclusion_proof`. A proof that is checked but doesn't verify is not an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The verifying key couldn't be deserialized for the given circuit shape
+    InvalidVerifyingKey(String),
+    /// The verifying key has been generated for a circuit of size `vk_k` but the params have size `params_k`
+    KMismatch { vk_k: u32, params_k: u32 },
+    /// The verifying key has been generated for a circuit of size `vk_k` but the proof artifact records size `artifact_k`
+    ArtifactKMismatch { artifact_k: u32, vk_k: u32 },
+    /// The proof artifact has been generated for the circuit identified by `artifact_circuit_id` rather than the circuit identified by `circuit_id`
+    CircuitMismatch {
+        artifact_circuit_id: String,
+        circuit_id: String,
+    },
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::InvalidVerifyingKey(reason) => {
+                write!(f, "Invalid verifying key: {}", reason)
+            }
+            VerifyError::KMismatch { vk_k, params_k } => write!(
+                f,
+                "The verifying key has k = {} but the params have k = {}",
+                vk_k, params_k
+            ),
+            VerifyError::ArtifactKMismatch { artifact_k, vk_k } => write!(
+                f,
+                "The proof artifact has k = {} but the verifying key has k = {}",
+                artifact_k, vk_k
+            ),
+            VerifyError::CircuitMismatch {
+                artifact_circuit_id,
+                circuit_id,
+            } => write!(
+                f,
+                "The proof artifact has been generated for {} but {} was given",
+                artifact_circuit_id, circuit_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// The transcript with which a proof is generated and verified. A proof only verifies with the transcript it was generated with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptKind {
+    /// The Keccak256 transcript, as expected by the Solidity verifier
+    EvmKeccak,
+    /// The Blake2b transcript, for proofs that are only verified natively
+    NativeBlake2b,
+}
+
+/// The reason why a proof couldn't be generated by `full_prover` or `gen_proof_solidity_calldata`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProverError {
+    /// The witness couldn't be assigned to the circuit or doesn't satisfy its constraints, e.g. a balance that exceeds the range check
+    Synthesis(String),
+    /// The proving key has been generated for a circuit of size `pk_k` but the params have size `params_k`
+    ParamsMismatch { pk_k: u32, params_k: u32 },
+    /// The proof couldn't be written to the transcript
+    Transcript(String),
+}
+
+impl std::fmt::Display for ProverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProverError::Synthesis(reason) => {
+                write!(f, "Failed to synthesize the circuit: {}", reason)
+            }
+            ProverError::ParamsMismatch { pk_k, params_k } => write!(
+                f,
+                "The proving key has k = {} but the params have k = {}",
+                pk_k, params_k
+            ),
+            ProverError::Transcript(reason) => write!(f, "Transcript error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProverError {}
+
+impl From<PlonkError> for ProverError {
+    fn from(error: PlonkError) -> Self {
+        match error {
+            PlonkError::Transcript(e) => ProverError::Transcript(e.to_string()),
+            e => ProverError::Synthesis(format!("{:?}", e)),
+        }
+    }
+}
+
+/// Error returned by `read_setup_artifacts_encrypted` when the setup artifacts can't be decrypted, either because the passphrase is wrong or because the file has been tampered with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecryptionFailed;
+
+impl std::fmt::Display for DecryptionFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to decrypt the setup artifacts: wrong passphrase or corrupted file"
+        )
+    }
+}
+
+impl std::error::Error for DecryptionFailed {}
+
+/// The failures of the circuits, from their setup to the generation of their proofs.
+/// The functions of the circuits return them boxed as `Box<dyn Error>`, from which they can be recovered with `downcast_ref::<CircuitError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CircuitError {
+    /// A constraint of the circuit is not satisfied by its witness
+    ConstraintViolation(ConstraintViolation),
+    /// The witness doesn't fit the circuit, e.g. a merkle proof or a tree of another number of levels than the circuit
+    InvalidWitness(String),
+    /// The params or the setup artifacts don't fit the circuit or couldn't be read
+    SetupFailed(String),
+    /// The proof couldn't be generated
+    ProvingFailed(ProverError),
+    /// The params file is truncated, corrupted or not a params file, as found by `verify_params_file`
+    InvalidParamsFile(String),
+    /// The Poseidon constants are malformed, e.g. a MDS matrix that isn't square or a missing round, or have another width than the specification of the circuit, see `PoseidonParams::validate`
+    InvalidPoseidonParams(String),
+    /// The Poseidon constants differ from the ones of the specification the circuit is configured with, see `MstInclusionCircuit::check_poseidon_params`
+    PoseidonParamsMismatch,
+}
+
+impl std::fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitError::ConstraintViolation(violation) => write!(f, "{}", violation),
+            CircuitError::InvalidWitness(reason) | CircuitError::SetupFailed(reason) => {
+                write!(f, "{}", reason)
+            }
+            CircuitError::ProvingFailed(e) => write!(f, "{}", e),
+            CircuitError::InvalidParamsFile(reason) => {
+                write!(f, "Invalid params file: {}", reason)
+            }
+            CircuitError::InvalidPoseidonParams(reason) => {
+                write!(f, "Invalid Poseidon constants: {}", reason)
+            }
+            CircuitError::PoseidonParamsMismatch => write!(
+                f,
+                "The Poseidon constants differ from the ones of the specification of the circuit"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CircuitError {}
+
+impl From<ConstraintViolation> for CircuitError {
+    fn from(violation: ConstraintViolation) -> Self {
+        CircuitError::ConstraintViolation(violation)
+    }
+}
+
+impl From<ProverError> for CircuitError {
+    fn from(error: ProverError) -> Self {
+        CircuitError::ProvingFailed(error)
+    }
+}
+
+impl From<InstanceMismatch> for CircuitError {
+    fn from(mismatch: InstanceMismatch) -> Self {
+        CircuitError::InvalidWitness(mismatch.to_string())
+    }
+}
+
+impl From<VerifyError> for CircuitError {
+    fn from(error: VerifyError) -> Self {
+        CircuitError::SetupFailed(error.to_string())
+    }
+}
@@ -1,17 +1,27 @@
+use std::error::Error;
 use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Instant;
 
-use ark_std::{end_timer, start_timer};
-use ethers::{
-    abi::parse_abi,
-    contract::BaseContract,
-    types::{Bytes, U256},
+use aes_gcm::{
+    aead::{Aead, AeadInPlace, KeyInit},
+    Aes256Gcm, Key, Nonce,
 };
+use ark_std::{end_timer, start_timer};
+use ethers::types::{Bytes, U256};
 use halo2_proofs::{
+    dev::{FailureLocation, MockProver, VerifyFailure},
     halo2curves::{
-        bn256::{Bn256, Fr as Fp, G1Affine},
+        bn256::{Bn256, Fr as Fp, G1Affine, G2Affine},
         ff::PrimeField,
+        group::GroupEncoding,
+        serde::SerdeObject,
+    },
+    plonk::{
+        create_proof, keygen_pk, keygen_vk, verify_proof, Any, Circuit, Column, ConstraintSystem,
+        Expression, ProvingKey, Selector, VerifyingKey,
     },
-    plonk::{create_proof, keygen_pk, keygen_vk, verify_proof, Circuit, ProvingKey, VerifyingKey},
     poly::{
         commitment::{Params, ParamsProver},
         kzg::{
@@ -23,17 +33,57 @@ use halo2_proofs::{
     transcript::{
         Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer,
     },
+    SerdeFormat,
+};
+use halo2_solidity_verifier::Keccak256Transcript;
+use rand::{rngs::OsRng, CryptoRng, RngCore};
+use rayon::prelude::*;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::chips::poseidon::TreeSpec;
+use crate::circuits::{
+    dynamic_inclusion::DynamicMstInclusionCircuit,
+    merkle_sum_tree::MstInclusionCircuit,
+    synthesis_observer::{observe_synthesis, SynthesisObserver},
+    traits::CircuitId,
+    types::{
+        CircuitError, CircuitStats, CircuitUtilization, ConstraintViolation, DecryptionFailed,
+        InstanceMismatch, MigrationReport, ParamsIntegrity, ProverError, RegionTiming,
+        SolidityCalldata, SynthesisProfile, TranscriptKind, VerifierParams, VerifyError,
+        ViolationKind,
+    },
+    WithInstances,
 };
-use halo2_solidity_verifier::{encode_calldata, Keccak256Transcript};
-use rand::{rngs::OsRng, RngCore};
+use crate::merkle_sum_tree::{Entry, Tree};
 
-use crate::circuits::WithInstances;
+/// The maximum `k` supported by the trusted setup of the BN256 curve
+pub const MAX_K: u32 = 28;
+
+/// The version of the layout of the public inputs of `MstInclusionCircuit`, exposed as its first public input and fixed in its verifying key.
+/// It should be increased whenever the public inputs are reordered or a public input is added, so that a verifier reading another layout rejects the proof.
+pub const MST_INCLUSION_CIRCUIT_VERSION: u32 = 2;
+
+/// Checks that the first public input of `instances`, laid out as the public inputs of `MstInclusionCircuit`, is `MST_INCLUSION_CIRCUIT_VERSION`,
+/// so that the other public inputs can be read with the layout of this version.
+/// Returns a `Version` mismatch otherwise, or a `Length` mismatch if there is no public input.
+pub fn check_instance_version(instances: &[Vec<Fp>]) -> Result<(), InstanceMismatch> {
+    match instances.first().and_then(|instance| instance.first()) {
+        Some(version) if *version == Fp::from(MST_INCLUSION_CIRCUIT_VERSION as u64) => Ok(()),
+        Some(_) => Err(InstanceMismatch::Version),
+        None => Err(InstanceMismatch::Length {
+            expected: 1,
+            found: 0,
+        }),
+    }
+}
 
 /// Generate setup artifacts for a circuit of size `k`, where 2^k represents the number of rows in the circuit.
 ///
 /// If the trusted setup parameters are not found, the function performs an unsafe trusted setup to generate the necessary parameters
-/// If the provided `k` value is larger than the `k` value of the loaded parameters, an error is returned, as the provided `k` is too large.
-/// Otherwise, if the `k` value is smaller than the `k` value of the loaded parameters, the parameters are downsized to fit the requested `k`.
+/// Before generating the keys, the rows used by the circuit are counted with a dry synthesis, see `required_k`, and an error is returned if the circuit doesn't fit in 2^`k` rows
+/// or if the `k` value of the loaded parameters is smaller than the requested `k`, rather than failing during the key generation.
+/// Otherwise, if the `k` value is smaller than the `k` value of the loaded parameters, the parameters are downsized to fit the requested `k`, so that a single oversized ptau file can serve circuits of any smaller size. The downsizing is logged as a warning through the `log` crate.
 pub fn generate_setup_artifacts<C: Circuit<Fp>>(
     k: u32,
     params_path: Option<&str>,
@@ -44,22 +94,49 @@ pub fn generate_setup_artifacts<C: Circuit<Fp>>(
         ProvingKey<G1Affine>,
         VerifyingKey<G1Affine>,
     ),
-    &'static str,
+    Box<dyn Error>,
 > {
+    let (min_k, used_rows) = required_k(&circuit)?;
+
+    // Only the header and the size of the params file are checked before loading the params, so that the points are read once, by the deserialization checking them
+    if let Some(path) = params_path {
+        let params_k = check_params_header(Path::new(path))?.1.k;
+        if params_k < k.max(min_k) {
+            return Err(CircuitError::SetupFailed(format!(
+                "params file supports k={} but circuit requires k>={} (rows used: {})",
+                params_k,
+                k.max(min_k),
+                used_rows
+            ))
+            .into());
+        }
+    }
+
+    if k < min_k {
+        return Err(CircuitError::SetupFailed(format!(
+            "requested k={} but circuit requires k>={} (rows used: {})",
+            k, min_k, used_rows
+        ))
+        .into());
+    }
+
     let mut params: ParamsKZG<Bn256>;
 
     match params_path {
         Some(path) => {
             let timer = start_timer!(|| "Creating params");
-            let mut params_fs = File::open(path).expect("couldn't load params");
-            params = ParamsKZG::<Bn256>::read(&mut params_fs).expect("Failed to read params");
+            let mut params_fs = BufReader::new(File::open(path)?);
+            params = ParamsKZG::<Bn256>::read(&mut params_fs)
+                .map_err(|e| CircuitError::InvalidParamsFile(format!("{}: {}", path, e)))?;
             end_timer!(timer);
 
-            if params.k() < k {
-                return Err("k is too large for the given params");
-            }
-
             if params.k() > k {
+                log::warn!(
+                    "the params at {} have k = {}, downsizing them to the requested k = {}",
+                    path,
+                    params.k(),
+                    k
+                );
                 let timer = start_timer!(|| "Downsizing params");
                 params.downsize(k);
                 end_timer!(timer);
@@ -78,121 +155,997 @@ pub fn generate_setup_artifacts<C: Circuit<Fp>>(
     Ok((params, pk, vk))
 }
 
-/// Generates a proof given the public setup, the proving key, the initiated circuit and its public inputs.
+/// Returns the smallest `k` such that a circuit fits in 2^`k` rows, along with the number of rows used by the circuit.
+/// The rows are counted by synthesizing the circuit with its floor planner, as in `circuit_utilization`, without running the mock prover,
+/// so that the circuit doesn't need to be initialized. The rows reserved by halo2 for the blinding factors are added to the rows used.
+/// Returns a `SetupFailed` error if the circuit can't be synthesized.
+pub fn required_k<C: Circuit<Fp>>(circuit: &C) -> Result<(u32, usize), CircuitError> {
+    let (cs, counter) = count_rows(circuit)?;
+
+    let used_rows = counter.last_row.map_or(0, |last_row| last_row + 1);
+
+    Ok((min_k_for_rows(&cs, used_rows), used_rows))
+}
+
+/// Returns the smallest `k` such that the inclusion circuit of a tree of `levels` levels fits in 2^k rows, blinding rows included,
+/// as `MstInclusionCircuit::minimum_k` does for a number of levels known at compile time. See `optimal_levels` to choose `levels` for a number of users.
+/// Returns a `SetupFailed` error if `levels` is zero or if the circuit can't be synthesized.
+pub fn recommended_k_for_levels<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    levels: usize,
+) -> Result<u32, CircuitError>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    if levels == 0 {
+        return Err(CircuitError::SetupFailed(
+            "The tree should have at least one level".to_string(),
+        ));
+    }
+    Ok(required_k(
+        &MstInclusionCircuit::<1, N_CURRENCIES, N_BYTES>::init_empty_with_levels(levels),
+    )?
+    .0)
+}
+
+/// Returns the `k` of the params stored at `params_path`, read from the header of the file without loading the params
+pub fn read_params_k(params_path: &str) -> Result<u32, Box<dyn Error>> {
+    let mut k = [0u8; 4];
+    File::open(params_path)?.read_exact(&mut k)?;
+    Ok(u32::from_le_bytes(k))
+}
+
+/// The size in bytes of a G1 point in a params file, whose points are stored uncompressed as raw bytes
+const PARAMS_G1_POINT_BYTES: u64 = 64;
+/// The size in bytes of a G2 point in a params file
+const PARAMS_G2_POINT_BYTES: u64 = 128;
+/// The number of G2 points of a params file, namely the generator of G2 and the G2 element of the trusted setup
+const PARAMS_G2_POINTS: usize = 2;
+
+/// Checks that the file at `path` holds params as written by `ParamsKZG::write`, such as the params converted from the Hermez ceremony, before they are loaded.
+///
+/// The params have no magic bytes, so that the header is checked instead: `k` must be a `k` of the ceremony and the size of the file must be the one of the params of size 2^`k`,
+/// namely `k` as 4 little-endian bytes, 2^`k` G1 points, their 2^`k` Lagrange basis and 2 G2 points. Each point must then lie on the BN254 curve.
+/// Returns an `InvalidParamsFile` error naming the first check that fails, e.g. for a truncated file.
+pub fn verify_params_file(path: &Path) -> Result<ParamsIntegrity, CircuitError> {
+    let invalid =
+        |reason: String| CircuitError::InvalidParamsFile(format!("{}: {}", path.display(), reason));
+
+    let (mut reader, integrity) = check_params_header(path)?;
+
+    let mut g1_bytes = [0u8; PARAMS_G1_POINT_BYTES as usize];
+    for index in 0..integrity.num_g1 {
+        reader
+            .read_exact(&mut g1_bytes)
+            .map_err(|e| invalid(e.to_string()))?;
+        if G1Affine::from_raw_bytes(&g1_bytes).is_none() {
+            return Err(invalid(format!(
+                "the G1 point {} is not on the curve",
+                index
+            )));
+        }
+    }
+
+    let mut g2_bytes = [0u8; PARAMS_G2_POINT_BYTES as usize];
+    for index in 0..PARAMS_G2_POINTS {
+        reader
+            .read_exact(&mut g2_bytes)
+            .map_err(|e| invalid(e.to_string()))?;
+        if G2Affine::from_raw_bytes(&g2_bytes).is_none() {
+            return Err(invalid(format!(
+                "the G2 point {} is not on the curve",
+                index
+            )));
+        }
+    }
+
+    Ok(integrity)
+}
+
+/// Reads the header of the params file at `path` and checks that `k` is at most `MAX_K` and that the size of the file is the one of the params of size 2^`k`, as `verify_params_file` does,
+/// without reading the points. Returns the layout of the file and the reader positioned after the header.
+fn check_params_header(path: &Path) -> Result<(BufReader<File>, ParamsIntegrity), CircuitError> {
+    let invalid =
+        |reason: String| CircuitError::InvalidParamsFile(format!("{}: {}", path.display(), reason));
+
+    let file = File::open(path).map_err(|e| invalid(e.to_string()))?;
+    let file_size_bytes = file.metadata().map_err(|e| invalid(e.to_string()))?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut k = [0u8; 4];
+    reader
+        .read_exact(&mut k)
+        .map_err(|_| invalid("the file is too short to hold a header".to_string()))?;
+    let k = u32::from_le_bytes(k);
+    if k == 0 || k > MAX_K {
+        return Err(invalid(format!(
+            "the header has k = {}, which isn't between 1 and {}",
+            k, MAX_K
+        )));
+    }
+
+    let num_g1 = 2 * (1usize << k);
+    let expected_size =
+        4 + num_g1 as u64 * PARAMS_G1_POINT_BYTES + PARAMS_G2_POINTS as u64 * PARAMS_G2_POINT_BYTES;
+    if file_size_bytes != expected_size {
+        return Err(invalid(format!(
+            "the params of k = {} take {} bytes but the file has {} bytes",
+            k, expected_size, file_size_bytes
+        )));
+    }
+
+    Ok((
+        reader,
+        ParamsIntegrity {
+            k,
+            num_g1,
+            num_g2: PARAMS_G2_POINTS,
+            file_size_bytes,
+        },
+    ))
+}
+
+/// Writes to `path` the part of `params` that the SHPLONK verifier uses, namely `k`, the generator of G1, the generator of G2 and the G2 element of the trusted setup,
+/// so that a verifier can load a file of a few hundred bytes rather than the full params, whose size grows with 2^`k`.
+///
+/// The points are stored compressed, after `k` as 4 little-endian bytes.
+pub fn write_verifier_params(params: &ParamsKZG<Bn256>, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&params.k().to_le_bytes())?;
+    writer.write_all(params.get_g()[0].to_bytes().as_ref())?;
+    writer.write_all(params.g2().to_bytes().as_ref())?;
+    writer.write_all(params.s_g2().to_bytes().as_ref())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads the verifier params written by `write_verifier_params`.
+///
+/// The returned params hold neither the G1 elements of the trusted setup beyond the generator nor their Lagrange basis.
+/// `VerifierParams::to_params` turns them into params that can be passed to `full_verifier` and `verify_inclusion_proof` but not to a prover.
+pub fn read_verifier_params(path: &Path) -> Result<VerifierParams, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut k = [0u8; 4];
+    reader.read_exact(&mut k)?;
+    let k = u32::from_le_bytes(k);
+
+    let mut g = <G1Affine as GroupEncoding>::Repr::default();
+    reader.read_exact(g.as_mut())?;
+    let g = Option::<G1Affine>::from(G1Affine::from_bytes(&g))
+        .ok_or("Invalid generator of G1 in the verifier params")?;
+
+    let mut g2_points = [G2Affine::default(); 2];
+    for point in g2_points.iter_mut() {
+        let mut repr = <G2Affine as GroupEncoding>::Repr::default();
+        reader.read_exact(repr.as_mut())?;
+        *point = Option::<G2Affine>::from(G2Affine::from_bytes(&repr))
+            .ok_or("Invalid G2 point in the verifier params")?;
+    }
+    let [g2, s_g2] = g2_points;
+
+    Ok(VerifierParams { k, g, g2, s_g2 })
+}
+
+/// Writes the setup artifacts of the circuit `C` of size `k` to `path`, so that they can be reloaded by `read_setup_artifacts` without running the key generation again.
+///
+/// The file starts with a header recording `k` and the identifier of the circuit given by `CircuitId`, followed by the params, the verifying key and the proving key.
+pub fn write_setup_artifacts<C: Circuit<Fp> + CircuitId>(
+    path: &Path,
+    k: u32,
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    vk: &VerifyingKey<G1Affine>,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_setup_artifacts_to::<C, _>(&mut writer, k, params, pk, vk)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the setup artifacts to `writer` in the format of `write_setup_artifacts`
+fn write_setup_artifacts_to<C: Circuit<Fp> + CircuitId, W: Write>(
+    writer: &mut W,
+    k: u32,
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    vk: &VerifyingKey<G1Affine>,
+) -> Result<(), Box<dyn Error>> {
+    let circuit_id = C::circuit_id();
+
+    writer.write_all(&k.to_le_bytes())?;
+    writer.write_all(&(circuit_id.len() as u32).to_le_bytes())?;
+    writer.write_all(circuit_id.as_bytes())?;
+    params.write(writer)?;
+    vk.write(writer, SerdeFormat::RawBytes)?;
+    pk.write(writer, SerdeFormat::RawBytes)?;
+
+    Ok(())
+}
+
+/// Reads the setup artifacts written by `write_setup_artifacts` for the circuit `C` of size `k`.
+///
+/// Returns an error if the artifacts have been generated for a different `k` or a different circuit, including the same circuit with different type parameters or another `CIRCUIT_VERSION`.
+pub fn read_setup_artifacts<C: Circuit<Fp> + CircuitId>(
+    path: &Path,
+    k: u32,
+) -> Result<
+    (
+        ParamsKZG<Bn256>,
+        ProvingKey<G1Affine>,
+        VerifyingKey<G1Affine>,
+    ),
+    Box<dyn Error>,
+> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader
+        .fill_buf()?
+        .starts_with(ENCRYPTED_SETUP_ARTIFACTS_MAGIC)
+    {
+        return Err(CircuitError::SetupFailed(format!(
+            "Setup artifacts at {} are encrypted, they must be read with read_setup_artifacts_encrypted",
+            path.display()
+        )).into());
+    }
+
+    read_setup_artifacts_from::<C, _>(&mut reader, path, k)
+}
+
+/// Reads the setup artifacts stored at `path` from `reader`, in the format of `write_setup_artifacts`
+fn read_setup_artifacts_from<C: Circuit<Fp> + CircuitId, R: Read>(
+    reader: &mut R,
+    path: &Path,
+    k: u32,
+) -> Result<
+    (
+        ParamsKZG<Bn256>,
+        ProvingKey<G1Affine>,
+        VerifyingKey<G1Affine>,
+    ),
+    Box<dyn Error>,
+> {
+    let circuit_id = C::circuit_id();
+
+    let mut stored_k = [0u8; 4];
+    reader.read_exact(&mut stored_k)?;
+    let stored_k = u32::from_le_bytes(stored_k);
+
+    let mut circuit_id_len = [0u8; 4];
+    reader.read_exact(&mut circuit_id_len)?;
+    let mut stored_circuit_id = vec![0u8; u32::from_le_bytes(circuit_id_len) as usize];
+    reader.read_exact(&mut stored_circuit_id)?;
+    let stored_circuit_id = String::from_utf8(stored_circuit_id)?;
+
+    if stored_k != k || stored_circuit_id != circuit_id {
+        return Err(CircuitError::SetupFailed(format!(
+            "Setup artifacts at {} were generated for {} with k = {}, but {} with k = {} was requested",
+            path.display(),
+            stored_circuit_id,
+            stored_k,
+            circuit_id,
+            k
+        )).into());
+    }
+
+    let params = ParamsKZG::<Bn256>::read(reader)?;
+    let vk = VerifyingKey::<G1Affine>::read::<_, C>(reader, SerdeFormat::RawBytes)?;
+    let pk = ProvingKey::<G1Affine>::read::<_, C>(reader, SerdeFormat::RawBytes)?;
+
+    Ok((params, pk, vk))
+}
+
+/// The magic bytes with which the files written by `write_setup_artifacts_encrypted` start, so that they can't be mistaken for unencrypted setup artifacts
+const ENCRYPTED_SETUP_ARTIFACTS_MAGIC: &[u8; 8] = b"SUMMAENC";
+
+/// The length of the authentication tag appended by AES-256-GCM to the ciphertext
+const AES_GCM_TAG_LEN: usize = 16;
+
+/// The number of PBKDF2-HMAC-SHA256 iterations deriving the encryption key of the setup artifacts from the passphrase, as recommended by OWASP for PBKDF2-HMAC-SHA256
+const PASSPHRASE_KDF_ITERATIONS: u32 = 600_000;
+
+/// Derives the AES-256 key of the setup artifacts from `passphrase` and `salt`, wiped from memory once dropped
+fn derive_setup_artifacts_key(passphrase: &str, salt: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2::pbkdf2_hmac::<Sha256>(
+        passphrase.as_bytes(),
+        salt,
+        PASSPHRASE_KDF_ITERATIONS,
+        &mut *key,
+    );
+    key
+}
+
+/// Writes the setup artifacts as `write_setup_artifacts` does, encrypted with AES-256-GCM under a key derived from `passphrase` with PBKDF2,
+/// so that the proving key can't be used by whoever gets hold of the file without the passphrase.
+///
+/// The file starts with the magic bytes `SUMMAENC`, followed by the random 16-byte salt of the key derivation, the random 12-byte nonce and the ciphertext.
+pub fn write_setup_artifacts_encrypted<C: Circuit<Fp> + CircuitId>(
+    path: &Path,
+    k: u32,
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    vk: &VerifyingKey<G1Affine>,
+    passphrase: &str,
+) -> Result<(), Box<dyn Error>> {
+    // The artifacts are serialized into a buffer sized upfront, with room for the tag, and encrypted in place,
+    // so that no reallocation leaves a copy of the proving key behind and the buffer is wiped from memory once dropped
+    let mut counter = ByteCounter(0);
+    write_setup_artifacts_to::<C, _>(&mut counter, k, params, pk, vk)?;
+    let mut buffer = Zeroizing::new(Vec::with_capacity(counter.0 + AES_GCM_TAG_LEN));
+    write_setup_artifacts_to::<C, _>(&mut *buffer, k, params, pk, vk)?;
+
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_setup_artifacts_key(passphrase, &salt);
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&*key))
+        .encrypt_in_place(Nonce::from_slice(&nonce), b"", &mut *buffer)
+        .map_err(|_| "Failed to encrypt the setup artifacts")?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(ENCRYPTED_SETUP_ARTIFACTS_MAGIC)?;
+    writer.write_all(&salt)?;
+    writer.write_all(&nonce)?;
+    writer.write_all(&buffer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// A writer discarding the bytes written to it, counting them to size the buffer of `write_setup_artifacts_encrypted`
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the setup artifacts written by `write_setup_artifacts_encrypted` for the circuit `C` of size `k`, decrypting them with `passphrase`.
+///
+/// Returns a `DecryptionFailed` error if the passphrase is wrong or the file has been tampered with, and an error as `read_setup_artifacts` does
+/// if the file isn't encrypted or the artifacts have been generated for a different `k` or a different circuit.
+pub fn read_setup_artifacts_encrypted<C: Circuit<Fp> + CircuitId>(
+    path: &Path,
+    k: u32,
+    passphrase: &str,
+) -> Result<
+    (
+        ParamsKZG<Bn256>,
+        ProvingKey<G1Affine>,
+        VerifyingKey<G1Affine>,
+    ),
+    Box<dyn Error>,
+> {
+    let bytes = std::fs::read(path)?;
+
+    let header_len = ENCRYPTED_SETUP_ARTIFACTS_MAGIC.len() + 16 + 12;
+    if bytes.len() < header_len || !bytes.starts_with(ENCRYPTED_SETUP_ARTIFACTS_MAGIC) {
+        return Err(format!(
+            "{} doesn't contain encrypted setup artifacts",
+            path.display()
+        )
+        .into());
+    }
+    let (salt, rest) = bytes[ENCRYPTED_SETUP_ARTIFACTS_MAGIC.len()..].split_at(16);
+    let (nonce, ciphertext) = rest.split_at(12);
+
+    let key = derive_setup_artifacts_key(passphrase, salt);
+    let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&*key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map(Zeroizing::new)
+        .map_err(|_| DecryptionFailed)?;
+
+    read_setup_artifacts_from::<C, _>(&mut plaintext.as_slice(), path, k)
+}
+
+/// Generates the setup artifacts of the inclusion circuit of `NEW_LEVELS` levels and size `new_k`, replacing the artifacts of the circuit of `OLD_LEVELS` levels whose verifying key is `old_vk`,
+/// e.g. when the tree of an exchange outgrows the 2^`OLD_LEVELS` users supported by its circuit. The params are loaded from `params_path` as in `generate_setup_artifacts`.
+///
+/// The public inputs of the circuit don't depend on its number of levels, but the proofs generated with the old proving key don't verify against the new verifying key,
+/// so that every user of `tree` must be proven again and the new verifying key deployed. The returned `MigrationReport` records what changed and how many users are affected.
+/// Returns an error if the depth of `tree` doesn't match `NEW_LEVELS` or if the new circuit doesn't fit in 2^`new_k` rows.
+pub fn migrate_setup_artifacts<
+    const OLD_LEVELS: usize,
+    const NEW_LEVELS: usize,
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+>(
+    old_vk: &VerifyingKey<G1Affine>,
+    new_k: u32,
+    params_path: Option<&str>,
+    tree: &dyn Tree<N_CURRENCIES>,
+) -> Result<
+    (
+        (
+            ParamsKZG<Bn256>,
+            ProvingKey<G1Affine>,
+            VerifyingKey<G1Affine>,
+        ),
+        MigrationReport,
+    ),
+    Box<dyn Error>,
+>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    if *tree.depth() != NEW_LEVELS {
+        return Err(CircuitError::InvalidWitness(format!(
+            "The tree has {} levels but the new circuit has {}",
+            tree.depth(),
+            NEW_LEVELS
+        ))
+        .into());
+    }
+
+    check_params_k(
+        &MstInclusionCircuit::<NEW_LEVELS, N_CURRENCIES, N_BYTES>::constraint_count()?,
+        new_k,
+    )?;
+
+    let artifacts = generate_setup_artifacts(
+        new_k,
+        params_path,
+        MstInclusionCircuit::<NEW_LEVELS, N_CURRENCIES, N_BYTES>::init_empty(),
+    )?;
+
+    let affected_users = (0..1 << NEW_LEVELS)
+        .filter(|&index| *tree.get_entry(index) != Entry::zero_entry())
+        .count();
+
+    let report = MigrationReport {
+        old_k: old_vk.get_domain().k(),
+        new_k,
+        old_levels: OLD_LEVELS,
+        new_levels: NEW_LEVELS,
+        affected_users,
+    };
+
+    Ok((artifacts, report))
+}
+
+/// Returns an error if the proving key hasn't been generated for params of the same size as `params`
+fn check_pk_k(params: &ParamsKZG<Bn256>, pk: &ProvingKey<G1Affine>) -> Result<(), ProverError> {
+    let pk_k = pk.get_vk().get_domain().k();
+    if pk_k != params.k() {
+        return Err(ProverError::ParamsMismatch {
+            pk_k,
+            params_k: params.k(),
+        });
+    }
+    Ok(())
+}
+
+/// Generates a proof given the public setup, the proving key, the initiated circuit and its public inputs, with the native Blake2b transcript.
+/// The proof can be verified by `full_verifier` but not by the Solidity verifier, see `gen_proof_solidity_calldata` for a proof to be verified on-chain.
+///
+/// The proof is verified against the verifying key of `pk` before being returned, as a prover given a witness violating the constraints still outputs a proof, which no verifier accepts.
+/// Returns an error if the params don't match the proving key, if the circuit can't be synthesized or if the proof doesn't verify, e.g. for a balance exceeding the range check.
 pub fn full_prover<C: Circuit<Fp>>(
     params: &ParamsKZG<Bn256>,
     pk: &ProvingKey<G1Affine>,
     circuit: C,
     public_inputs: Vec<Vec<Fp>>,
-) -> Vec<u8> {
+) -> Result<Vec<u8>, ProverError> {
+    full_prover_with_transcript(
+        params,
+        pk,
+        circuit,
+        public_inputs,
+        TranscriptKind::NativeBlake2b,
+    )
+}
+
+/// Generates a proof as `full_prover` does, drawing the blinding factors from `rng` instead of the OS randomness.
+/// A seeded `rng`, e.g. `StdRng::seed_from_u64`, yields the same proof bytes on every run, so that a verification failure can be reproduced.
+/// The seed must only be used for testing and debugging, as the blinding factors are what keep the witness of the proof private.
+pub fn full_prover_with_rng<C: Circuit<Fp>>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: C,
+    public_inputs: Vec<Vec<Fp>>,
+    rng: impl RngCore + CryptoRng,
+) -> Result<Vec<u8>, ProverError> {
+    create_proof_checked(
+        params,
+        pk,
+        circuit,
+        public_inputs,
+        TranscriptKind::NativeBlake2b,
+        rng,
+    )
+}
+
+/// Generates a proof as `full_prover` does, with the transcript given by `transcript_kind`.
+/// The proof can only be verified with the same transcript, see `full_verifier_with_transcript`.
+pub fn full_prover_with_transcript<C: Circuit<Fp>>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: C,
+    public_inputs: Vec<Vec<Fp>>,
+    transcript_kind: TranscriptKind,
+) -> Result<Vec<u8>, ProverError> {
+    create_proof_checked(params, pk, circuit, public_inputs, transcript_kind, OsRng)
+}
+
+/// Generates a proof with the transcript given by `transcript_kind` and the blinding factors drawn from `rng`
+fn prove<C: Circuit<Fp>>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: C,
+    public_inputs: Vec<Vec<Fp>>,
+    transcript_kind: TranscriptKind,
+    rng: impl RngCore + CryptoRng,
+) -> Result<Vec<u8>, ProverError> {
+    check_pk_k(params, pk)?;
+
     let pf_time = start_timer!(|| "Creating proof");
 
     let instance: Vec<&[Fp]> = public_inputs.iter().map(|input| &input[..]).collect();
     let instances = &[&instance[..]];
 
-    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
-    let result = create_proof::<
-        KZGCommitmentScheme<Bn256>,
-        ProverSHPLONK<'_, Bn256>,
-        Challenge255<G1Affine>,
-        _,
-        Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
-        _,
-    >(params, pk, &[circuit], instances, OsRng, &mut transcript)
-    .expect("prover should not fail");
-    assert!(result.0.is_ok());
-    let proof = transcript.finalize();
+    let proof = match transcript_kind {
+        TranscriptKind::EvmKeccak => {
+            let mut transcript = Keccak256Transcript::new(Vec::new());
+            let result = create_proof::<_, ProverSHPLONK<_>, _, _, _, _>(
+                params,
+                pk,
+                &[circuit],
+                instances,
+                rng,
+                &mut transcript,
+            )?;
+            result.0?;
+            transcript.finalize()
+        }
+        TranscriptKind::NativeBlake2b => {
+            let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+            let result = create_proof::<
+                KZGCommitmentScheme<Bn256>,
+                ProverSHPLONK<'_, Bn256>,
+                Challenge255<G1Affine>,
+                _,
+                Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>,
+                _,
+            >(params, pk, &[circuit], instances, rng, &mut transcript)?;
+            result.0?;
+            transcript.finalize()
+        }
+    };
     end_timer!(pf_time);
-    proof
+    Ok(proof)
 }
 
-/// Verifies a proof given the public setup, the verification key, the proof and the public inputs of the circuit.
+/// Verifies a proof generated by `full_prover`, with the native Blake2b transcript, given the public setup, the verification key, the proof and the public inputs of the circuit.
 pub fn full_verifier(
     params: &ParamsKZG<Bn256>,
     vk: &VerifyingKey<G1Affine>,
     proof: Vec<u8>,
     public_inputs: Vec<Vec<Fp>>,
+) -> bool {
+    full_verifier_with_transcript(
+        params,
+        vk,
+        proof,
+        public_inputs,
+        TranscriptKind::NativeBlake2b,
+    )
+}
+
+/// Verifies a proof as `full_verifier` does, with the transcript given by `transcript_kind`.
+/// A proof generated with another transcript doesn't verify.
+pub fn full_verifier_with_transcript(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: Vec<u8>,
+    public_inputs: Vec<Vec<Fp>>,
+    transcript_kind: TranscriptKind,
 ) -> bool {
     let verifier_params = params.verifier_params();
     let strategy = SingleStrategy::new(params);
-    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
 
     let instance: Vec<&[Fp]> = public_inputs.iter().map(|input| &input[..]).collect();
     let instances = &[&instance[..]];
 
-    verify_proof::<
-        KZGCommitmentScheme<Bn256>,
-        VerifierSHPLONK<'_, Bn256>,
-        Challenge255<G1Affine>,
-        Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
-        SingleStrategy<'_, Bn256>,
-    >(verifier_params, vk, strategy, instances, &mut transcript)
-    .is_ok()
+    match transcript_kind {
+        TranscriptKind::EvmKeccak => {
+            let mut transcript = Keccak256Transcript::new(proof.as_slice());
+            verify_proof::<_, VerifierSHPLONK<_>, _, _, SingleStrategy<_>>(
+                verifier_params,
+                vk,
+                strategy,
+                instances,
+                &mut transcript,
+            )
+            .is_ok()
+        }
+        TranscriptKind::NativeBlake2b => {
+            let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
+            verify_proof::<
+                KZGCommitmentScheme<Bn256>,
+                VerifierSHPLONK<'_, Bn256>,
+                Challenge255<G1Affine>,
+                Blake2bRead<&[u8], G1Affine, Challenge255<G1Affine>>,
+                SingleStrategy<'_, Bn256>,
+            >(verifier_params, vk, strategy, instances, &mut transcript)
+            .is_ok()
+        }
+    }
 }
 
-/// Generate the proof Solidity calldata for a circuit
-pub fn gen_proof_solidity_calldata<C: Circuit<Fp> + WithInstances>(
+/// Generates the inclusion proofs of the users at `indices` of `tree` with `full_prover`, running up to `threads` provers at once.
+/// The params and the proving key are shared by all the provers, whereas each prover allocates its own buffers, so that `threads` bounds the memory used.
+/// If `threads` is 0, as many provers as CPUs are run at once.
+///
+/// Returns the result of each proof in the order of `indices`, so that a user whose proof fails doesn't prevent the proofs of the other users.
+pub fn prove_inclusion_parallel<
+    const LEVELS: usize,
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+>(
     params: &ParamsKZG<Bn256>,
     pk: &ProvingKey<G1Affine>,
-    circuit: C,
-) -> (Bytes, Vec<U256>) {
-    let instances_clone = circuit.instances().clone();
-    let pf_time = start_timer!(|| "Creating proof");
-    let proof = create_proof_checked(params, pk, circuit, &instances_clone[0], OsRng);
-    end_timer!(pf_time);
+    tree: &dyn Tree<N_CURRENCIES>,
+    indices: &[usize],
+    threads: usize,
+) -> Vec<Result<Vec<u8>, ProverError>>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool,
+        Err(e) => {
+            return indices
+                .iter()
+                .map(|_| {
+                    Err(ProverError::Synthesis(format!(
+                        "Failed to build the thread pool: {}",
+                        e
+                    )))
+                })
+                .collect()
+        }
+    };
+
+    pool.install(|| {
+        indices
+            .par_iter()
+            .map(|&user_index| {
+                let merkle_proof = tree.generate_proof(user_index).map_err(|e| {
+                    ProverError::Synthesis(format!(
+                        "Failed to generate the Merkle proof of user {}: {}",
+                        user_index, e
+                    ))
+                })?;
+                let circuit =
+                    MstInclusionCircuit::<LEVELS, N_CURRENCIES, N_BYTES>::init(merkle_proof);
+                let instances = circuit.instances();
+                full_prover(params, pk, circuit, instances)
+            })
+            .collect()
+    })
+}
+
+/// Verifies an inclusion proof generated by `gen_proof_solidity_calldata`, given only the serialized verifying key, the params and the public inputs.
+///
+/// The verifying key must be serialized in raw bytes. It is deserialized for an inclusion circuit of `N_CURRENCIES` currencies and `N_BYTES` bytes,
+/// whose configuration doesn't depend on the number of levels, so that neither the circuit nor the proving key are needed.
+/// Returns `Ok(false)` if the proof doesn't verify, and an error if the verifying key can't be used with the params.
+pub fn verify_inclusion_proof<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    vk_bytes: &[u8],
+    params: &ParamsKZG<Bn256>,
+    proof: &[u8],
+    instances: &[Fp],
+) -> Result<bool, VerifyError>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    let vk = VerifyingKey::<G1Affine>::from_bytes::<
+        DynamicMstInclusionCircuit<N_CURRENCIES, N_BYTES>,
+    >(vk_bytes, SerdeFormat::RawBytes)
+    .map_err(|e| VerifyError::InvalidVerifyingKey(e.to_string()))?;
+
+    let vk_k = vk.get_domain().k();
+    if vk_k != params.k() {
+        return Err(VerifyError::KMismatch {
+            vk_k,
+            params_k: params.k(),
+        });
+    }
+
+    let mut transcript = Keccak256Transcript::new(proof);
+    Ok(
+        verify_proof::<_, VerifierSHPLONK<_>, _, _, SingleStrategy<_>>(
+            params,
+            &vk,
+            SingleStrategy::new(params),
+            &[&[instances]],
+            &mut transcript,
+        )
+        .is_ok(),
+    )
+}
+
+/// The magic bytes with which the files written by `ProofArtifact::write` start
+const PROOF_ARTIFACT_MAGIC: &[u8; 8] = b"SUMMAPRF";
+
+/// The version of the format of the files written by `ProofArtifact::write`, stored after the magic bytes
+pub const PROOF_ARTIFACT_VERSION: u8 = 1;
+
+/// A proof together with the public inputs it has been generated for, the size `k` of the circuit and an identifier of the circuit,
+/// so that they travel as a single file rather than as separate files that can be mismatched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofArtifact {
+    pub k: u32,
+    /// The identifier of the circuit given by `CircuitId`, as recorded by `write_setup_artifacts`
+    pub circuit_id: String,
+    pub instances: Vec<Vec<Fp>>,
+    pub proof_bytes: Vec<u8>,
+    pub transcript_kind: TranscriptKind,
+}
+
+impl ProofArtifact {
+    /// Creates the artifact of a proof of the circuit `C` of size `k`, generated with the transcript given by `transcript_kind`
+    pub fn new<C: CircuitId>(
+        k: u32,
+        instances: Vec<Vec<Fp>>,
+        proof_bytes: Vec<u8>,
+        transcript_kind: TranscriptKind,
+    ) -> Self {
+        Self {
+            k,
+            circuit_id: C::circuit_id(),
+            instances,
+            proof_bytes,
+            transcript_kind,
+        }
+    }
+
+    /// Writes the artifact to `path`.
+    ///
+    /// The file starts with the magic bytes `SUMMAPRF` and the format version, followed by `k`, the circuit identifier, the transcript kind, the instance columns and the proof.
+    /// Integers are little-endian, field elements are their 32-byte representation and variable-length fields are prefixed by their length as a `u32`.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(PROOF_ARTIFACT_MAGIC)?;
+        writer.write_all(&[PROOF_ARTIFACT_VERSION])?;
+        writer.write_all(&self.k.to_le_bytes())?;
+        writer.write_all(&(self.circuit_id.len() as u32).to_le_bytes())?;
+        writer.write_all(self.circuit_id.as_bytes())?;
+        writer.write_all(&[match self.transcript_kind {
+            TranscriptKind::EvmKeccak => 0,
+            TranscriptKind::NativeBlake2b => 1,
+        }])?;
+        writer.write_all(&(self.instances.len() as u32).to_le_bytes())?;
+        for column in &self.instances {
+            writer.write_all(&(column.len() as u32).to_le_bytes())?;
+            for instance in column {
+                writer.write_all(instance.to_repr().as_ref())?;
+            }
+        }
+        writer.write_all(&(self.proof_bytes.len() as u32).to_le_bytes())?;
+        writer.write_all(&self.proof_bytes)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads an artifact written by `write`.
+    ///
+    /// Returns an error, rather than panicking, if the file isn't an artifact, has an unsupported version or is truncated or corrupted.
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        let mut bytes = bytes.as_slice();
 
-    let calldata = encode_calldata(None, &proof, &instances_clone[0]);
+        if take_bytes(&mut bytes, PROOF_ARTIFACT_MAGIC.len())? != PROOF_ARTIFACT_MAGIC {
+            return Err(format!("{} is not a proof artifact", path.display()).into());
+        }
+        let version = take_bytes(&mut bytes, 1)?[0];
+        if version != PROOF_ARTIFACT_VERSION {
+            return Err(format!("Unsupported proof artifact version {}", version).into());
+        }
+
+        let k = take_u32(&mut bytes)?;
+        let circuit_id_len = take_u32(&mut bytes)? as usize;
+        let circuit_id = String::from_utf8(take_bytes(&mut bytes, circuit_id_len)?.to_vec())?;
+        let transcript_kind = match take_bytes(&mut bytes, 1)?[0] {
+            0 => TranscriptKind::EvmKeccak,
+            1 => TranscriptKind::NativeBlake2b,
+            kind => return Err(format!("Invalid transcript kind {}", kind).into()),
+        };
+
+        let column_count = take_u32(&mut bytes)?;
+        let mut instances = Vec::new();
+        for _ in 0..column_count {
+            let instance_count = take_u32(&mut bytes)?;
+            let mut column = Vec::new();
+            for _ in 0..instance_count {
+                let mut repr = [0u8; 32];
+                repr.copy_from_slice(take_bytes(&mut bytes, 32)?);
+                let instance: Option<Fp> = Fp::from_repr(repr).into();
+                column.push(instance.ok_or("Invalid field element in the instances")?);
+            }
+            instances.push(column);
+        }
 
-    let abi = parse_abi(&[
-        "function verifyProof(bytes calldata proof, uint256[] calldata instances) public returns (bool)",
-    ]).expect("Invalid ABI");
+        let proof_len = take_u32(&mut bytes)? as usize;
+        let proof_bytes = take_bytes(&mut bytes, proof_len)?.to_vec();
+
+        if !bytes.is_empty() {
+            return Err(format!("{} trailing bytes after the proof", bytes.len()).into());
+        }
+
+        Ok(Self {
+            k,
+            circuit_id,
+            instances,
+            proof_bytes,
+            transcript_kind,
+        })
+    }
+
+    /// Verifies the proof against its instances, given the params and the verifying key of the circuit `C`.
+    /// Returns `Ok(false)` if the proof doesn't verify, and an error if the artifact has been generated for another circuit than `C`
+    /// or if the verifying key or the params don't have the size of the artifact.
+    pub fn verify<C: CircuitId>(
+        &self,
+        params: &ParamsKZG<Bn256>,
+        vk: &VerifyingKey<G1Affine>,
+    ) -> Result<bool, VerifyError> {
+        let circuit_id = C::circuit_id();
+        if self.circuit_id != circuit_id {
+            return Err(VerifyError::CircuitMismatch {
+                artifact_circuit_id: self.circuit_id.clone(),
+                circuit_id,
+            });
+        }
+        let vk_k = vk.get_domain().k();
+        if vk_k != self.k {
+            return Err(VerifyError::ArtifactKMismatch {
+                artifact_k: self.k,
+                vk_k,
+            });
+        }
+        if vk_k != params.k() {
+            return Err(VerifyError::KMismatch {
+                vk_k,
+                params_k: params.k(),
+            });
+        }
+
+        Ok(full_verifier_with_transcript(
+            params,
+            vk,
+            self.proof_bytes.clone(),
+            self.instances.clone(),
+            self.transcript_kind,
+        ))
+    }
+}
+
+/// Splits the first `len` bytes off `bytes`, failing if there are fewer
+fn take_bytes<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+    if bytes.len() < len {
+        return Err("Truncated proof artifact".into());
+    }
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn take_u32(bytes: &mut &[u8]) -> Result<u32, Box<dyn Error>> {
+    let mut le_bytes = [0u8; 4];
+    le_bytes.copy_from_slice(take_bytes(bytes, 4)?);
+    Ok(u32::from_le_bytes(le_bytes))
+}
 
-    let base_contract = BaseContract::from(abi);
+/// Generate the proof Solidity calldata for a circuit.
+/// The proof is always generated with the Keccak256 transcript, as expected by the Solidity verifier, and checked before being returned.
+/// Returns an error if the proof can't be generated, as for `full_prover`, or if the generated proof doesn't verify.
+pub fn gen_proof_solidity_calldata<C: Circuit<Fp> + WithInstances>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: C,
+) -> Result<SolidityCalldata, ProverError> {
+    gen_proof_solidity_calldata_with_rng(params, pk, circuit, OsRng)
+}
 
-    type VerifyProofInput = (Bytes, Vec<U256>);
+/// Generate the proof Solidity calldata for a circuit as `gen_proof_solidity_calldata` does, drawing the blinding factors from `rng`.
+/// As for `full_prover_with_rng`, a seeded `rng` yields the same calldata on every run and must only be used for testing and debugging.
+pub fn gen_proof_solidity_calldata_with_rng<C: Circuit<Fp> + WithInstances>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: C,
+    rng: impl RngCore + CryptoRng,
+) -> Result<SolidityCalldata, ProverError> {
+    let instances = circuit.instances();
+    let proof = create_proof_checked(
+        params,
+        pk,
+        circuit,
+        instances.clone(),
+        TranscriptKind::EvmKeccak,
+        rng,
+    )?;
 
-    // Decode the function input
-    let decoded: VerifyProofInput = base_contract
-        .decode_input(calldata)
-        .expect("Failed to decode data");
+    // The arguments of `verifyProof(bytes proof, uint256[] instances)`, the public inputs being passed as their canonical values
+    Ok(SolidityCalldata {
+        proof: Bytes::from(proof),
+        public_inputs: instances
+            .iter()
+            .flatten()
+            .map(|instance| field_element_to_solidity_calldata(*instance))
+            .collect(),
+    })
+}
 
-    (decoded.0, decoded.1)
+/// Generate the proof Solidity calldata for an inclusion circuit bound to the exchange identified by `watermark`, see `MstInclusionCircuit::with_watermark`.
+/// The public input #1 of the calldata is `H(watermark, leaf_hash)`, so that the proof only verifies against the public inputs recomputed with the same watermark.
+/// `pk` must have been generated for a circuit with the same watermark.
+pub fn gen_proof_solidity_calldata_watermarked<
+    const LEVELS: usize,
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+    const LOOKUP_BITS: usize,
+    S: TreeSpec,
+>(
+    params: &ParamsKZG<Bn256>,
+    pk: &ProvingKey<G1Affine>,
+    circuit: MstInclusionCircuit<LEVELS, N_CURRENCIES, N_BYTES, LOOKUP_BITS, S>,
+    watermark: [u8; 32],
+) -> Result<SolidityCalldata, ProverError>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    gen_proof_solidity_calldata(params, pk, circuit.with_watermark(watermark))
 }
 
+/// Generates a proof with the transcript given by `transcript_kind` and checks that it verifies against the verifying key of `pk`
 fn create_proof_checked(
     params: &ParamsKZG<Bn256>,
     pk: &ProvingKey<G1Affine>,
     circuit: impl Circuit<Fp>,
-    instances: &[Fp],
-    mut rng: impl RngCore,
-) -> Vec<u8> {
-    let proof = {
-        let mut transcript = Keccak256Transcript::new(Vec::new());
-        let proof_creation_result = create_proof::<_, ProverSHPLONK<_>, _, _, _, _>(
-            params,
-            pk,
-            &[circuit],
-            &[&[instances]],
-            &mut rng,
-            &mut transcript,
-        );
-        assert!(proof_creation_result.is_ok());
-        transcript.finalize()
-    };
+    public_inputs: Vec<Vec<Fp>>,
+    transcript_kind: TranscriptKind,
+    rng: impl RngCore + CryptoRng,
+) -> Result<Vec<u8>, ProverError> {
+    let proof = prove(
+        params,
+        pk,
+        circuit,
+        public_inputs.clone(),
+        transcript_kind,
+        rng,
+    )?;
 
-    let result = {
-        let mut transcript = Keccak256Transcript::new(proof.as_slice());
-        verify_proof::<_, VerifierSHPLONK<_>, _, _, SingleStrategy<_>>(
-            params,
-            pk.get_vk(),
-            SingleStrategy::new(params),
-            &[&[instances]],
-            &mut transcript,
-        )
-    };
-    assert!(result.is_ok());
+    if !full_verifier_with_transcript(
+        params,
+        pk.get_vk(),
+        proof.clone(),
+        public_inputs,
+        transcript_kind,
+    ) {
+        return Err(ProverError::Synthesis(
+            "the generated proof doesn't verify, the witness doesn't satisfy the constraints"
+                .to_string(),
+        ));
+    }
 
-    proof
+    Ok(proof)
 }
 
 /// Converts a field element to a Solidity calldata
@@ -201,3 +1154,371 @@ pub fn field_element_to_solidity_calldata(field_element: Fp) -> U256 {
     let u = U256::from_little_endian(bytes.as_slice());
     u
 }
+
+/// Runs the `MockProver` on the circuit of size `k` with its own public inputs, so that a witness that doesn't satisfy the constraints is caught in seconds rather than after running the real prover, e.g. at the `k` of the proving key.
+/// See `preflight_check_with_instances`.
+pub fn preflight_check<C: Circuit<Fp> + WithInstances>(
+    k: u32,
+    circuit: &C,
+) -> Result<(), Vec<ConstraintViolation>> {
+    preflight_check_with_instances(k, circuit, circuit.instances())
+}
+
+/// Checks with the `MockProver` that the witness of the circuit of size `k` satisfies all the gate constraints, lookups and equality constraints of the circuit, given the public inputs `instances`.
+/// The MockProver synthesizes the witness and evaluates every constraint at each of the 2^`k` rows, so that its time and memory grow with 2^`k` as the prover's do.
+/// It skips the commitments and the opening proof, which makes it faster than generating and verifying a proof, but not free for a large `k`.
+///
+/// Returns the list of the violated constraints otherwise, with their kinds and the names of their gates and regions. A circuit that can't be synthesized is reported as a single `Synthesis` violation.
+pub fn preflight_check_with_instances<C: Circuit<Fp>>(
+    k: u32,
+    circuit: &C,
+    instances: Vec<Vec<Fp>>,
+) -> Result<(), Vec<ConstraintViolation>> {
+    let prover = MockProver::run(k, circuit, instances).map_err(|e| {
+        vec![ConstraintViolation {
+            kind: ViolationKind::Synthesis,
+            gate_name: format!("synthesis ({:?})", e),
+            region: None,
+            row: None,
+            cell_values: vec![],
+        }]
+    })?;
+
+    prover.verify().map_err(|failures| {
+        failures
+            .into_iter()
+            .map(|failure| {
+                let (region, row) = match &failure {
+                    VerifyFailure::ConstraintNotSatisfied { location, .. }
+                    | VerifyFailure::Lookup { location, .. }
+                    | VerifyFailure::Permutation { location, .. } => match location {
+                        FailureLocation::InRegion { region, offset } => {
+                            (Some(region.to_string()), Some(*offset))
+                        }
+                        FailureLocation::OutsideRegion { row } => (None, Some(*row)),
+                    },
+                    _ => (None, None),
+                };
+
+                match failure {
+                    VerifyFailure::ConstraintNotSatisfied {
+                        constraint,
+                        cell_values,
+                        ..
+                    } => ConstraintViolation {
+                        kind: ViolationKind::Gate,
+                        gate_name: constraint.to_string(),
+                        region,
+                        row,
+                        cell_values: cell_values
+                            .into_iter()
+                            .map(|(cell, value)| (format!("{:?}", cell), value))
+                            .collect(),
+                    },
+                    VerifyFailure::Permutation { column, .. } => ConstraintViolation {
+                        kind: ViolationKind::Equality,
+                        gate_name: format!("Equality constraint on column {:?}", column),
+                        region,
+                        row,
+                        cell_values: vec![],
+                    },
+                    other => ConstraintViolation {
+                        kind: match other {
+                            VerifyFailure::Lookup { .. } => ViolationKind::Lookup,
+                            VerifyFailure::CellNotAssigned { .. } => ViolationKind::UnassignedCell,
+                            _ => ViolationKind::Other,
+                        },
+                        gate_name: other.to_string(),
+                        region,
+                        row,
+                        cell_values: vec![],
+                    },
+                }
+            })
+            .collect()
+    })
+}
+
+/// Returns the size of a circuit. The columns are counted from the constraint system of the circuit,
+/// while the rows used and the smallest `k` are found by synthesizing the circuit once with its floor planner, without running the mock prover, see `required_k`.
+/// The gates are counted per application rather than per definition: a gate counts once for each row at which one of its selectors is enabled,
+/// and a gate without selector once for each row used.
+///
+/// Returns an error if the circuit can't be synthesized.
+pub fn circuit_stats<C: Circuit<Fp>>(circuit: &C) -> Result<CircuitStats, CircuitError> {
+    let (cs, counter) = count_rows(circuit)?;
+
+    let n_rows = counter.last_row.map_or(0, |last_row| last_row + 1);
+
+    let n_gates = cs
+        .gates()
+        .iter()
+        .map(|gate| {
+            let mut selectors = vec![];
+            for polynomial in gate.polynomials() {
+                queried_selectors(polynomial, &mut selectors);
+            }
+            if selectors.is_empty() {
+                return n_rows;
+            }
+            counter
+                .enabled_selectors
+                .iter()
+                .filter(|selector| selectors.contains(selector))
+                .count()
+        })
+        .sum();
+
+    Ok(CircuitStats {
+        n_gates,
+        n_advice_columns: cs.num_advice_columns(),
+        n_fixed_columns: cs.num_fixed_columns(),
+        n_instance_columns: cs.num_instance_columns(),
+        n_rows,
+        min_k: min_k_for_rows(&cs, n_rows),
+    })
+}
+
+/// Synthesizes the circuit with its floor planner without computing its witness, and returns its constraint system along with the rows spanned by its regions
+fn count_rows<C: Circuit<Fp>>(
+    circuit: &C,
+) -> Result<(ConstraintSystem<Fp>, RegionCounter), CircuitError> {
+    observe_synthesis(circuit, None, RegionCounter::default()).map_err(|e| {
+        CircuitError::SetupFailed(format!("The circuit can't be synthesized: {:?}", e))
+    })
+}
+
+/// Returns the smallest `k` such that `used_rows` fit in 2^`k` rows, along with the rows reserved by halo2 for the blinding factors
+fn min_k_for_rows(cs: &ConstraintSystem<Fp>, used_rows: usize) -> u32 {
+    // The last blinding_factors + 1 rows of the circuit can't be assigned
+    let required_rows = (used_rows + cs.blinding_factors() + 1).max(cs.minimum_rows());
+    required_rows.next_power_of_two().trailing_zeros()
+}
+
+/// Collects the selectors queried by `expression` into `selectors`
+pub(crate) fn queried_selectors(expression: &Expression<Fp>, selectors: &mut Vec<Selector>) {
+    match expression {
+        Expression::Selector(selector) => {
+            if !selectors.contains(selector) {
+                selectors.push(*selector);
+            }
+        }
+        Expression::Negated(a) | Expression::Scaled(a, _) => queried_selectors(a, selectors),
+        Expression::Sum(a, b) | Expression::Product(a, b) => {
+            queried_selectors(a, selectors);
+            queried_selectors(b, selectors);
+        }
+        _ => {}
+    }
+}
+
+/// Returns the share of the 2^`k` rows used by a circuit, along with the regions spanning the most rows.
+/// The rows spanned by each region are recorded by synthesizing the circuit with its floor planner, without running the mock prover.
+/// The report carries a warning if less than half of the rows are used, in which case the circuit would fit with a smaller `k`.
+/// Returns a `SetupFailed` error if `k` is larger than `MAX_K` or if the circuit can't be synthesized.
+pub fn circuit_utilization<C: Circuit<Fp>>(
+    circuit: &C,
+    k: u32,
+) -> Result<CircuitUtilization, CircuitError> {
+    if k > MAX_K {
+        return Err(CircuitError::SetupFailed(format!(
+            "k = {} is larger than the maximum k of {}",
+            k, MAX_K
+        )));
+    }
+    let (_, counter) = count_rows(circuit)?;
+
+    let used_rows = counter.last_row.map_or(0, |last_row| last_row + 1);
+
+    // The regions sharing a name, e.g. the rounds of the Poseidon permutation, are counted together
+    let mut rows_per_name: Vec<(String, usize)> = vec![];
+    for region in counter.regions.iter() {
+        if let Some((first_row, last_row)) = region.rows {
+            let rows = last_row - first_row + 1;
+            match rows_per_name
+                .iter_mut()
+                .find(|(name, _)| *name == region.name)
+            {
+                Some((_, total)) => *total += rows,
+                None => rows_per_name.push((region.name.clone(), rows)),
+            }
+        }
+    }
+    let bottleneck_gate = rows_per_name
+        .into_iter()
+        .max_by_key(|(_, rows)| *rows)
+        .map_or(String::new(), |(name, _)| name);
+
+    let total_rows = 1usize << k;
+    let utilization_pct = used_rows as f64 * 100.0 / total_rows as f64;
+    let warning = (utilization_pct < 50.0).then(|| {
+        format!(
+            "the circuit only uses {} of its {} rows ({:.1}%), k could be reduced",
+            used_rows, total_rows, utilization_pct
+        )
+    });
+
+    Ok(CircuitUtilization {
+        total_rows,
+        used_rows,
+        utilization_pct,
+        bottleneck_gate,
+        warning,
+    })
+}
+
+/// A region entered during the synthesis, along with the first and last rows of its cells, if any
+pub(crate) struct RegionRows {
+    pub(crate) name: String,
+    pub(crate) rows: Option<(usize, usize)>,
+}
+
+/// Records the rows spanned by the regions of a circuit while it is synthesized, see `circuit_utilization`.
+/// The last row includes the cells assigned outside of any region, such as the lookup tables.
+/// The copy constraints are counted as well.
+#[derive(Default)]
+pub(crate) struct RegionCounter {
+    pub(crate) regions: Vec<RegionRows>,
+    in_region: bool,
+    pub(crate) last_row: Option<usize>,
+    #[cfg_attr(not(feature = "dev-stats"), allow(dead_code))]
+    pub(crate) copies: usize,
+    // The selector enabled at each row at which a selector is enabled, one item per enabled row
+    pub(crate) enabled_selectors: Vec<Selector>,
+}
+
+impl RegionCounter {
+    fn record_row(&mut self, row: usize) {
+        self.last_row = Some(self.last_row.map_or(row, |last_row| last_row.max(row)));
+        if !self.in_region {
+            return;
+        }
+        if let Some(region) = self.regions.last_mut() {
+            region.rows = Some(match region.rows {
+                Some((first_row, last_row)) => (first_row.min(row), last_row.max(row)),
+                None => (row, row),
+            });
+        }
+    }
+}
+
+impl SynthesisObserver for RegionCounter {
+    const COMPUTE_VALUES: bool = false;
+
+    fn entered_region(&mut self, name: String) {
+        self.regions.push(RegionRows { name, rows: None });
+        self.in_region = true;
+    }
+
+    fn exited_region(&mut self) {
+        self.in_region = false;
+    }
+
+    fn enabled_selector(&mut self, selector: &Selector, row: usize) {
+        self.record_row(row);
+        self.enabled_selectors.push(*selector);
+    }
+
+    fn assigned(&mut self, _column: Column<Any>, row: usize, _value: Option<Fp>) {
+        self.record_row(row);
+    }
+
+    fn copied(&mut self, _left: (Column<Any>, usize), _right: (Column<Any>, usize)) {
+        self.copies += 1;
+    }
+}
+
+/// Synthesizes the circuit of size `k` with its floor planner, computing the witness of every cell, and returns the wall-clock time spent in each region name.
+/// The regions sharing a name, e.g. the Poseidon permutations, are counted together.
+///
+/// Panics if the circuit doesn't fit in 2^`k` rows.
+pub fn synthesis_profile<C: Circuit<Fp>>(circuit: &C, k: u32) -> SynthesisProfile {
+    let (_, timer) = observe_synthesis(circuit, Some(k), RegionTimer::default())
+        .expect("the circuit should be synthesized");
+
+    let mut regions: Vec<RegionTiming> = vec![];
+    for (name, rows, duration_ns) in timer.regions {
+        match regions.iter_mut().find(|region| region.name == name) {
+            Some(region) => {
+                region.rows += rows;
+                region.duration_ns += duration_ns;
+            }
+            None => regions.push(RegionTiming {
+                name,
+                rows,
+                duration_ns,
+            }),
+        }
+    }
+    regions.sort_by(|a, b| b.duration_ns.cmp(&a.duration_ns));
+
+    SynthesisProfile { regions }
+}
+
+/// A region being assigned during the synthesis, see `RegionTimer`
+struct TimedRegion {
+    name: String,
+    start: Instant,
+    rows: Option<(usize, usize)>,
+}
+
+/// Records the name, the rows spanned and the time spent in each region of a circuit while it is synthesized, see `synthesis_profile`.
+/// Unlike `RegionCounter`, the values assigned to the cells are computed, so that the time spent computing the witness is included.
+#[derive(Default)]
+struct RegionTimer {
+    regions: Vec<(String, usize, u64)>,
+    current: Option<TimedRegion>,
+}
+
+impl RegionTimer {
+    fn record_row(&mut self, row: usize) {
+        if let Some(region) = self.current.as_mut() {
+            region.rows = Some(match region.rows {
+                Some((first_row, last_row)) => (first_row.min(row), last_row.max(row)),
+                None => (row, row),
+            });
+        }
+    }
+}
+
+impl SynthesisObserver for RegionTimer {
+    const COMPUTE_VALUES: bool = true;
+
+    fn entered_region(&mut self, name: String) {
+        self.current = Some(TimedRegion {
+            name,
+            start: Instant::now(),
+            rows: None,
+        });
+    }
+
+    fn exited_region(&mut self) {
+        if let Some(region) = self.current.take() {
+            let duration_ns = region.start.elapsed().as_nanos() as u64;
+            let rows = region
+                .rows
+                .map_or(0, |(first_row, last_row)| last_row - first_row + 1);
+            self.regions.push((region.name, rows, duration_ns));
+        }
+    }
+
+    fn enabled_selector(&mut self, _selector: &Selector, row: usize) {
+        self.record_row(row);
+    }
+
+    fn assigned(&mut self, _column: Column<Any>, row: usize, _value: Option<Fp>) {
+        self.record_row(row);
+    }
+}
+
+/// Checks that params of size 2^`k` are large enough for a circuit of the given size, e.g. when a backend starts with a configured ptau file
+pub fn check_params_k(stats: &CircuitStats, k: u32) -> Result<(), Box<dyn Error>> {
+    if k < stats.min_k {
+        return Err(CircuitError::SetupFailed(format!(
+            "The params have k = {} but the circuit needs k >= {} to fit its {} rows",
+            k, stats.min_k, stats.n_rows
+        ))
+        .into());
+    }
+    Ok(())
+}
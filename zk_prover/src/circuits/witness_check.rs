@@ -0,0 +1,383 @@
+//! Check of the witness of a circuit against its gates, lookups and equality constraints, evaluated on the cells assigned by a single synthesis
+//! rather than by the `MockProver`, which evaluates every constraint at each of the 2^k rows of the circuit.
+use crate::circuits::synthesis_observer::{observe_synthesis, SynthesisObserver};
+use crate::circuits::types::{ConstraintViolation, ViolationKind};
+use crate::circuits::utils::queried_selectors;
+use crate::merkle_sum_tree::utils::fp_to_big_uint;
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use halo2_proofs::plonk::{Any, Circuit, Column, Expression, Selector};
+use std::collections::{HashMap, HashSet};
+
+/// Checks that the witness of `circuit` satisfies its gate constraints, lookups and equality constraints, given the public inputs `instances`.
+///
+/// The circuit is synthesized once with its floor planner, recording the assigned cells, the enabled selectors and the copy constraints,
+/// without building the 2^k rows of every column as the `MockProver` does. Each gate is then evaluated at the rows where one of its selectors is enabled,
+/// each lookup at the rows where one of the selectors of its inputs is enabled, and each copy constraint on the two cells it links,
+/// so that the cost of the check grows with the rows used by the circuit rather than with 2^k.
+/// A gate or a lookup without selector is evaluated at every row used by the circuit. A cell that is queried but not assigned is taken as zero.
+///
+/// Returns the list of the violated constraints otherwise, the equality constraints being reported once per cell whose value differs from the cell it is copied from.
+/// A circuit that can't be synthesized is reported as a single `Synthesis` violation.
+pub(crate) fn check_witness<C: Circuit<Fp>>(
+    circuit: &C,
+    instances: &[Vec<Fp>],
+) -> Result<(), Vec<ConstraintViolation>> {
+    let (cs, recorder) =
+        observe_synthesis(circuit, None, WitnessRecorder::default()).map_err(|e| {
+            vec![ConstraintViolation {
+                kind: ViolationKind::Synthesis,
+                gate_name: format!("synthesis ({:?})", e),
+                region: None,
+                row: None,
+                cell_values: vec![],
+            }]
+        })?;
+
+    let used_rows = recorder.last_row.map_or(0, |last_row| last_row + 1);
+    let enabled: HashSet<(Selector, usize)> = recorder.selectors.keys().copied().collect();
+    // The rows at which one of `selectors` is enabled, or every row used if there is none
+    let rows_of = |selectors: &[Selector]| -> Vec<usize> {
+        if selectors.is_empty() {
+            return (0..used_rows).collect();
+        }
+        let mut rows: Vec<usize> = enabled
+            .iter()
+            .filter(|(selector, _)| selectors.contains(selector))
+            .map(|(_, row)| *row)
+            .collect();
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    };
+
+    let mut violations = vec![];
+
+    for (gate_index, gate) in cs.gates().iter().enumerate() {
+        for (polynomial_index, polynomial) in gate.polynomials().iter().enumerate() {
+            let mut selectors = vec![];
+            queried_selectors(polynomial, &mut selectors);
+
+            for row in rows_of(&selectors) {
+                if recorder.evaluate(polynomial, row, &enabled, instances) == Fp::zero() {
+                    continue;
+                }
+                let (region, offset) = recorder.locate(&selectors, row);
+                violations.push(ConstraintViolation {
+                    kind: ViolationKind::Gate,
+                    gate_name: format!(
+                        "Constraint {} in gate {} ('{}')",
+                        polynomial_index,
+                        gate_index,
+                        gate.name()
+                    ),
+                    region,
+                    row: Some(offset),
+                    cell_values: recorder.queried_cells(polynomial, row),
+                });
+            }
+        }
+    }
+
+    for (lookup_index, lookup) in cs.lookups().iter().enumerate() {
+        let mut table_columns = vec![];
+        for expression in lookup.table_expressions() {
+            queried_fixed_columns(expression, &mut table_columns);
+        }
+        let table: HashSet<Vec<Fp>> = recorder
+            .fixed
+            .keys()
+            .filter(|(column, _)| table_columns.contains(column))
+            .map(|(_, row)| *row)
+            .collect::<HashSet<usize>>()
+            .into_iter()
+            .map(|row| {
+                lookup
+                    .table_expressions()
+                    .iter()
+                    .map(|expression| recorder.evaluate(expression, row, &enabled, instances))
+                    .collect()
+            })
+            .collect();
+
+        let mut selectors = vec![];
+        for expression in lookup.input_expressions() {
+            queried_selectors(expression, &mut selectors);
+        }
+        for row in rows_of(&selectors) {
+            let input: Vec<Fp> = lookup
+                .input_expressions()
+                .iter()
+                .map(|expression| recorder.evaluate(expression, row, &enabled, instances))
+                .collect();
+            if table.contains(&input) {
+                continue;
+            }
+            let (region, offset) = recorder.locate(&selectors, row);
+            violations.push(ConstraintViolation {
+                kind: ViolationKind::Lookup,
+                gate_name: format!("Lookup {}", lookup_index),
+                region,
+                row: Some(offset),
+                cell_values: vec![],
+            });
+        }
+    }
+
+    let mut reported_cells = HashSet::new();
+    for (left, right) in recorder.copies.iter() {
+        if recorder.value_of(*left, instances) == recorder.value_of(*right, instances) {
+            continue;
+        }
+        for (column, row) in [*left, *right] {
+            if !reported_cells.insert((column, row)) {
+                continue;
+            }
+            let (region, row) = match column.column_type() {
+                Any::Instance => (None, row),
+                _ => recorder.locate_cell(column, row),
+            };
+            violations.push(ConstraintViolation {
+                kind: ViolationKind::Equality,
+                gate_name: format!("Equality constraint on column {:?}", column),
+                region,
+                row: Some(row),
+                cell_values: vec![],
+            });
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/// Collects the indexes of the fixed columns queried by `expression` into `columns`, e.g. the columns of a lookup table
+fn queried_fixed_columns(expression: &Expression<Fp>, columns: &mut Vec<usize>) {
+    match expression {
+        Expression::Fixed(query) => {
+            if !columns.contains(&query.column_index()) {
+                columns.push(query.column_index());
+            }
+        }
+        Expression::Negated(a) | Expression::Scaled(a, _) => queried_fixed_columns(a, columns),
+        Expression::Sum(a, b) | Expression::Product(a, b) => {
+            queried_fixed_columns(a, columns);
+            queried_fixed_columns(b, columns);
+        }
+        _ => {}
+    }
+}
+
+/// Formats `value` as a hex number, the values closer to the modulus than to zero being formatted as negative numbers, as the `MockProver` does
+fn format_value(value: Fp) -> String {
+    let (positive, negative) = (fp_to_big_uint(value), fp_to_big_uint(-value));
+    if negative < positive {
+        format!("-0x{:x}", negative)
+    } else {
+        format!("0x{:x}", positive)
+    }
+}
+
+/// Returns the public input at `row` of the instance column `column`, the missing public inputs being zero
+fn instance_at(instances: &[Vec<Fp>], column: usize, row: usize) -> Fp {
+    instances
+        .get(column)
+        .and_then(|instance| instance.get(row))
+        .copied()
+        .unwrap_or(Fp::zero())
+}
+
+/// Records the cells, the selectors and the copy constraints of a circuit while it is synthesized, along with the regions they belong to, see `check_witness`
+#[derive(Default)]
+struct WitnessRecorder {
+    // The names of the regions entered so far and the first row they use, if any
+    regions: Vec<(String, Option<usize>)>,
+    current_region: Option<usize>,
+    last_row: Option<usize>,
+    selectors: HashMap<(Selector, usize), Option<usize>>,
+    advice: HashMap<(usize, usize), Fp>,
+    fixed: HashMap<(usize, usize), Fp>,
+    // The region of each advice or fixed cell assigned in a region
+    cell_regions: HashMap<(Column<Any>, usize), usize>,
+    copies: Vec<((Column<Any>, usize), (Column<Any>, usize))>,
+}
+
+impl WitnessRecorder {
+    /// Records that `row` is used by the current region, if any
+    fn use_row(&mut self, row: usize) {
+        self.last_row = self.last_row.max(Some(row));
+        if let Some(region) = self.current_region {
+            let start = &mut self.regions[region].1;
+            *start = Some(start.map_or(row, |start| start.min(row)));
+        }
+    }
+
+    /// Returns the value of the cell at `row` of `column`, the cells that are not assigned being zero
+    fn value_of(&self, (column, row): (Column<Any>, usize), instances: &[Vec<Fp>]) -> Fp {
+        match column.column_type() {
+            Any::Advice(_) => self.advice_at(column.index(), row),
+            Any::Fixed => self.fixed_at(column.index(), row),
+            Any::Instance => instance_at(instances, column.index(), row),
+        }
+    }
+
+    fn advice_at(&self, column: usize, row: usize) -> Fp {
+        self.advice
+            .get(&(column, row))
+            .copied()
+            .unwrap_or(Fp::zero())
+    }
+
+    fn fixed_at(&self, column: usize, row: usize) -> Fp {
+        self.fixed
+            .get(&(column, row))
+            .copied()
+            .unwrap_or(Fp::zero())
+    }
+
+    /// Evaluates `expression` at `row`, the cells that are not assigned being zero
+    fn evaluate(
+        &self,
+        expression: &Expression<Fp>,
+        row: usize,
+        enabled: &HashSet<(Selector, usize)>,
+        instances: &[Vec<Fp>],
+    ) -> Fp {
+        let at = |rotation: i32, value: &dyn Fn(usize) -> Fp| {
+            row.checked_add_signed(rotation as isize)
+                .map_or(Fp::zero(), value)
+        };
+
+        expression.evaluate(
+            &|constant| constant,
+            &|selector| {
+                if enabled.contains(&(selector, row)) {
+                    Fp::one()
+                } else {
+                    Fp::zero()
+                }
+            },
+            &|query| {
+                at(query.rotation().0, &|row| {
+                    self.fixed_at(query.column_index(), row)
+                })
+            },
+            &|query| {
+                at(query.rotation().0, &|row| {
+                    self.advice_at(query.column_index(), row)
+                })
+            },
+            &|query| {
+                at(query.rotation().0, &|row| {
+                    instance_at(instances, query.column_index(), row)
+                })
+            },
+            &|_| Fp::zero(),
+            &|value| -value,
+            &|a, b| a + b,
+            &|a, b| a * b,
+            &|value, scalar| value * scalar,
+        )
+    }
+
+    /// Returns the advice and fixed cells queried by `expression` at `row`, along with their values
+    fn queried_cells(&self, expression: &Expression<Fp>, row: usize) -> Vec<(String, String)> {
+        let cell = |name: &str, column: usize, rotation: i32, value: &dyn Fn(usize) -> Fp| {
+            let value = row
+                .checked_add_signed(rotation as isize)
+                .map_or(Fp::zero(), |row| value(row));
+            vec![(
+                format!("{}[{}]@{}", name, column, rotation),
+                format_value(value),
+            )]
+        };
+
+        let mut cells: Vec<(String, String)> = expression.evaluate(
+            &|_| vec![],
+            &|_| vec![],
+            &|query| {
+                cell("Fixed", query.column_index(), query.rotation().0, &|row| {
+                    self.fixed_at(query.column_index(), row)
+                })
+            },
+            &|query| {
+                cell("Advice", query.column_index(), query.rotation().0, &|row| {
+                    self.advice_at(query.column_index(), row)
+                })
+            },
+            &|_| vec![],
+            &|_| vec![],
+            &|cells| cells,
+            &|mut a, b| {
+                a.extend(b);
+                a
+            },
+            &|mut a, b| {
+                a.extend(b);
+                a
+            },
+            &|cells, _| cells,
+        );
+        cells.sort();
+        cells.dedup();
+        cells
+    }
+
+    /// Returns the region of the first of `selectors` enabled at `row`, if any, along with the offset of `row` in the region, otherwise `row` itself
+    fn locate(&self, selectors: &[Selector], row: usize) -> (Option<String>, usize) {
+        let region = selectors
+            .iter()
+            .find_map(|selector| self.selectors.get(&(*selector, row)).copied().flatten());
+        self.in_region(region, row)
+    }
+
+    /// Returns the region in which the cell at `row` of `column` is assigned, if any, along with the offset of `row` in the region, otherwise `row` itself
+    fn locate_cell(&self, column: Column<Any>, row: usize) -> (Option<String>, usize) {
+        self.in_region(self.cell_regions.get(&(column, row)).copied(), row)
+    }
+
+    fn in_region(&self, region: Option<usize>, row: usize) -> (Option<String>, usize) {
+        match region.map(|region| &self.regions[region]) {
+            Some((name, Some(start))) => (Some(name.clone()), row - start),
+            _ => (None, row),
+        }
+    }
+}
+
+impl SynthesisObserver for WitnessRecorder {
+    const COMPUTE_VALUES: bool = true;
+
+    fn entered_region(&mut self, name: String) {
+        self.current_region = Some(self.regions.len());
+        self.regions.push((name, None));
+    }
+
+    fn exited_region(&mut self) {
+        self.current_region = None;
+    }
+
+    fn enabled_selector(&mut self, selector: &Selector, row: usize) {
+        self.use_row(row);
+        self.selectors.insert((*selector, row), self.current_region);
+    }
+
+    fn assigned(&mut self, column: Column<Any>, row: usize, value: Option<Fp>) {
+        self.use_row(row);
+        if let Some(region) = self.current_region {
+            self.cell_regions.insert((column, row), region);
+        }
+        if let Some(value) = value {
+            match column.column_type() {
+                Any::Advice(_) => self.advice.insert((column.index(), row), value),
+                Any::Fixed => self.fixed.insert((column.index(), row), value),
+                Any::Instance => None,
+            };
+        }
+    }
+
+    fn copied(&mut self, left: (Column<Any>, usize), right: (Column<Any>, usize)) {
+        self.copies.push((left, right));
+    }
+}
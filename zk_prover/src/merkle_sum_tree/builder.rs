@@ -0,0 +1,117 @@
+use crate::merkle_sum_tree::utils::parse_csv_to_entries_with_progress;
+use crate::merkle_sum_tree::{Cryptocurrency, Entry, MerkleSumTree};
+
+/// The phases of the construction of a Merkle Sum Tree reported to the progress callback.
+///
+/// * `CsvParsing`: the entries are being parsed from the CSV file. The counts are the number of bytes read so far and the size of the file in bytes.
+/// * `LeafHashing`: the leaves are being computed from the entries. The counts are the number of leaves computed so far and the number of leaves of the tree.
+/// * `MiddleNodeHashing`: the middle levels are being built. The counts are the number of levels built so far and the depth of the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildStage {
+    CsvParsing,
+    LeafHashing,
+    MiddleNodeHashing,
+}
+
+/// Builder of a Merkle Sum Tree that reports the progress of the construction.
+///
+/// The progress callback is called with the current `BuildStage`, the amount of work done and the total amount of work of the stage.
+/// The stages are reported in order and the amount of work done is monotonically increasing within a stage.
+/// The callback is always invoked from the thread that builds the tree, so it is not required to be `Send` nor `Sync`.
+pub struct MerkleSumTreeBuilder<'a, const N_CURRENCIES: usize, const N_BYTES: usize> {
+    progress: Box<dyn FnMut(BuildStage, usize, usize) + 'a>,
+}
+
+impl<'a, const N_CURRENCIES: usize, const N_BYTES: usize> Default
+    for MerkleSumTreeBuilder<'a, N_CURRENCIES, N_BYTES>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, const N_CURRENCIES: usize, const N_BYTES: usize>
+    MerkleSumTreeBuilder<'a, N_CURRENCIES, N_BYTES>
+{
+    /// Returns a builder that doesn't report any progress
+    pub fn new() -> Self {
+        Self {
+            progress: Box::new(|_, _, _| {}),
+        }
+    }
+
+    /// Sets the callback to which the progress of the construction is reported
+    pub fn with_progress(mut self, progress: impl FnMut(BuildStage, usize, usize) + 'a) -> Self {
+        self.progress = Box::new(progress);
+        self
+    }
+
+    /// Builds a Merkle Sum Tree from a CSV file stored at `path`. See `MerkleSumTree::from_csv`.
+    pub fn from_csv(
+        mut self,
+        path: &str,
+    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let (cryptocurrencies, entries) = self.parse_csv(path)?;
+        MerkleSumTree::from_entries_with_progress(
+            entries,
+            cryptocurrencies,
+            false,
+            &mut *self.progress,
+        )
+    }
+
+    /// Builds a Merkle Sum Tree from a CSV file stored at `path` with the leaves sorted by the username byte values. See `MerkleSumTree::from_csv_sorted`.
+    pub fn from_csv_sorted(
+        mut self,
+        path: &str,
+    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let (cryptocurrencies, mut entries) = self.parse_csv(path)?;
+
+        entries.sort_by(|a, b| a.username().cmp(b.username()));
+
+        MerkleSumTree::from_entries_with_progress(
+            entries,
+            cryptocurrencies,
+            true,
+            &mut *self.progress,
+        )
+    }
+
+    /// Builds a Merkle Sum Tree from a vector of entries. See `MerkleSumTree::from_entries`.
+    pub fn from_entries(
+        mut self,
+        entries: Vec<Entry<N_CURRENCIES>>,
+        cryptocurrencies: Vec<Cryptocurrency>,
+        is_sorted: bool,
+    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        MerkleSumTree::from_entries_with_progress(
+            entries,
+            cryptocurrencies,
+            is_sorted,
+            &mut *self.progress,
+        )
+    }
+
+    fn parse_csv(
+        &mut self,
+        path: &str,
+    ) -> Result<(Vec<Cryptocurrency>, Vec<Entry<N_CURRENCIES>>), Box<dyn std::error::Error>> {
+        let progress = &mut self.progress;
+        parse_csv_to_entries_with_progress::<&str, N_CURRENCIES, N_BYTES>(
+            path,
+            &mut |done, total| progress(BuildStage::CsvParsing, done, total),
+        )
+    }
+}
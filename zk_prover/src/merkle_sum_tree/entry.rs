@@ -1,14 +1,83 @@
-use crate::merkle_sum_tree::Node;
+use crate::chips::poseidon::{poseidon_spec::PoseidonSpec, TreeSpec};
+use crate::merkle_sum_tree::utils::{
+    big_uint_to_fp, fp_to_big_uint, normalize_balance, serde_helpers,
+};
+use crate::merkle_sum_tree::{Node, TreeError};
 use ethers::utils::keccak256;
+use halo2_proofs::arithmetic::Field;
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
 use num_bigint::BigUint;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
 
 /// An entry in the Merkle Sum Tree from the database of the CEX.
 /// It contains the username and the balances of the user.
-#[derive(Clone, Debug, std::cmp::PartialEq)]
+/// It is serialized as `{ "hashed_username": "..", "balances": ["..", ...], "username": ".." }`, where the hashed username and the balances are decimal strings.
+///
+/// An entry whose balances are all zero is a valid entry, namely a user of the CEX with no holdings.
+/// Its leaf is hashed from the hashed username as any other entry, so it differs from the leaf of the zero entry used to pad the tree,
+/// whose username is also zero. A zero balance always lies in the range enforced by the range check of the circuit.
+///
+/// An entry can be salted with a random field element, serialized as an additional `"salt": "0x.."` field. The username element of the leaf preimage is then `H(hashed_username, salt)` instead of
+/// the hashed username, so that a third party knowing the username can't grind the balances of the user out of the leaf hash. The salt has to be shared with the user along with the proof,
+/// so that the user can recompute the leaf. An entry built by `new` is unsalted and its leaf is unchanged.
+///
+/// The hashed username of a deserialized entry is recomputed from its username, and the entry is rejected if it doesn't match the serialized one.
+#[derive(Clone, Debug, std::cmp::PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "EntryJson<N_CURRENCIES>")]
 pub struct Entry<const N_CURRENCIES: usize> {
+    #[serde(with = "serde_helpers::big_uint_dec")]
     hashed_username: BigUint,
+    #[serde(with = "serde_helpers::big_uint_dec_array")]
     balances: [BigUint; N_CURRENCIES],
     username: String,
+    #[serde(
+        default,
+        with = "serde_helpers::fp_hex_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    salt: Option<Fp>,
+}
+
+/// The serialized layout of `Entry`, checked against the username before being turned into an entry
+#[derive(Deserialize)]
+struct EntryJson<const N_CURRENCIES: usize> {
+    #[serde(with = "serde_helpers::big_uint_dec")]
+    hashed_username: BigUint,
+    #[serde(with = "serde_helpers::big_uint_dec_array")]
+    balances: [BigUint; N_CURRENCIES],
+    username: String,
+    #[serde(default, with = "serde_helpers::fp_hex_option")]
+    salt: Option<Fp>,
+}
+
+impl<const N_CURRENCIES: usize> TryFrom<EntryJson<N_CURRENCIES>> for Entry<N_CURRENCIES> {
+    type Error = String;
+
+    fn try_from(json: EntryJson<N_CURRENCIES>) -> Result<Self, Self::Error> {
+        let entry = if json.hashed_username == BigUint::from(0u32) && json.username == "0" {
+            // The zero entry padding the tree isn't hashed from its username
+            Entry {
+                balances: json.balances,
+                ..Self::zero_entry()
+            }
+        } else {
+            Self::new(json.username, json.balances)
+        };
+
+        if entry.hashed_username != json.hashed_username {
+            return Err(format!(
+                "The hashed username {} doesn't match the username {}",
+                json.hashed_username, entry.username
+            ));
+        }
+
+        Ok(Entry {
+            salt: json.salt,
+            ..entry
+        })
+    }
 }
 
 impl<const N_CURRENCIES: usize> Entry<N_CURRENCIES> {
@@ -23,10 +92,85 @@ impl<const N_CURRENCIES: usize> Entry<N_CURRENCIES> {
             hashed_username,
             balances,
             username,
+            salt: None,
         }
     }
 
-    /// Returns a zero entry where the username is 0 and the balances are all 0
+    /// Builds an entry as `new` does, salting its leaf with `salt`
+    pub fn new_salted(username: String, balances: [BigUint; N_CURRENCIES], salt: Fp) -> Self {
+        Entry {
+            salt: Some(salt),
+            ..Self::new(username, balances)
+        }
+    }
+
+    /// Returns a random salt to be passed to `new_salted`
+    pub fn random_salt() -> Fp {
+        Fp::random(OsRng)
+    }
+
+    /// Builds an entry as `new` does, checking that each balance lies in the range [0, 2^(N_BYTES*8) - 1] enforced by the range check of the circuit.
+    /// Returns an error naming the first cryptocurrency whose balance is out of range.
+    pub fn new_checked<const N_BYTES: usize>(
+        username: String,
+        balances: [BigUint; N_CURRENCIES],
+    ) -> Result<Self, Box<dyn Error>> {
+        let entry = Self::new(username, balances);
+        entry.check_range::<N_BYTES>()?;
+        Ok(entry)
+    }
+
+    /// Checks that each balance of the entry lies in the range [0, 2^(N_BYTES*8) - 1] enforced by the range check of the circuit.
+    /// Returns a `BalanceOverflow` error naming the first cryptocurrency whose balance is out of range.
+    pub fn check_range<const N_BYTES: usize>(&self) -> Result<(), TreeError> {
+        let bound = BigUint::from(1u32) << (N_BYTES * 8);
+
+        match self
+            .balances
+            .iter()
+            .enumerate()
+            .find(|(_, balance)| *balance >= &bound)
+        {
+            Some((index, balance)) => Err(TreeError::BalanceOverflow {
+                username: self.username.clone(),
+                asset_index: index,
+                balance: balance.clone(),
+                n_bytes: N_BYTES,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Builds an entry as `new` does from balances given as `(raw_amount, decimals)` pairs, one per cryptocurrency, e.g. 18 decimals for ETH and 6 for USDC.
+    /// The balances are normalized to the largest number of decimals among them, see `normalize_balance`, so that they share a common precision.
+    /// Returns a `BalanceCountMismatch` error if there isn't one balance per cryptocurrency.
+    pub fn new_normalized(
+        username: &str,
+        balances: &[(BigUint, u8)],
+    ) -> Result<Self, Box<dyn Error>> {
+        if balances.len() != N_CURRENCIES {
+            return Err(TreeError::BalanceCountMismatch {
+                expected: N_CURRENCIES,
+                found: balances.len(),
+            }
+            .into());
+        }
+
+        let max_decimals = balances
+            .iter()
+            .map(|(_, decimals)| *decimals)
+            .max()
+            .unwrap_or(0);
+        let normalized_balances: [BigUint; N_CURRENCIES] = std::array::from_fn(|index| {
+            let (balance, decimals) = &balances[index];
+            normalize_balance(balance, *decimals, max_decimals)
+        });
+
+        Ok(Self::new(username.to_string(), normalized_balances))
+    }
+
+    /// Returns a zero entry where the username is 0 and the balances are all 0.
+    /// It is used to pad the leaves of the tree up to a power of 2 and doesn't belong to any user.
     pub fn zero_entry() -> Self {
         let empty_balances: [BigUint; N_CURRENCIES] = std::array::from_fn(|_| BigUint::from(0u32));
 
@@ -34,6 +178,7 @@ impl<const N_CURRENCIES: usize> Entry<N_CURRENCIES> {
             hashed_username: BigUint::from(0u32),
             balances: empty_balances,
             username: "0".to_string(),
+            salt: None,
         }
     }
 
@@ -41,7 +186,19 @@ impl<const N_CURRENCIES: usize> Entry<N_CURRENCIES> {
     where
         [usize; N_CURRENCIES + 1]: Sized,
     {
-        Node::leaf(&self.hashed_username, &self.balances)
+        self.compute_leaf_with_spec::<PoseidonSpec>()
+    }
+
+    /// Computes the leaf of the entry as `compute_leaf` does, hashed with the Poseidon specification `S`.
+    /// The username element of a salted entry is hashed with `S` as well, see `leaf_username_with_spec`.
+    pub fn compute_leaf_with_spec<S: TreeSpec>(&self) -> Node<N_CURRENCIES>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+    {
+        Node::leaf_with_spec::<S>(
+            &fp_to_big_uint(self.leaf_username_with_spec::<S>()),
+            &self.balances,
+        )
     }
 
     /// Stores the new balance values
@@ -51,11 +208,41 @@ impl<const N_CURRENCIES: usize> Entry<N_CURRENCIES> {
         &mut self,
         updated_balances: &[BigUint; N_CURRENCIES],
     ) -> Node<N_CURRENCIES>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+    {
+        self.recompute_leaf_with_spec::<PoseidonSpec>(updated_balances)
+    }
+
+    /// Stores the new balance values as `recompute_leaf` does, returning the updated node hashed with the Poseidon specification `S`
+    pub fn recompute_leaf_with_spec<S: TreeSpec>(
+        &mut self,
+        updated_balances: &[BigUint; N_CURRENCIES],
+    ) -> Node<N_CURRENCIES>
     where
         [usize; N_CURRENCIES + 1]: Sized,
     {
         self.balances = updated_balances.clone();
-        Node::leaf(&self.hashed_username, updated_balances)
+        self.compute_leaf_with_spec::<S>()
+    }
+
+    /// Returns the username element of the leaf hash preimage, namely the hashed username or `H(hashed_username, salt)` if the entry is salted
+    pub fn leaf_username(&self) -> Fp {
+        self.leaf_username_with_spec::<PoseidonSpec>()
+    }
+
+    /// Returns the username element of the leaf hash preimage as `leaf_username` does, the salt being hashed with the Poseidon specification `S`
+    pub fn leaf_username_with_spec<S: TreeSpec>(&self) -> Fp {
+        let hashed_username = big_uint_to_fp(&self.hashed_username);
+        match self.salt {
+            Some(salt) => S::hash([hashed_username, salt]),
+            None => hashed_username,
+        }
+    }
+
+    /// Returns the salt of the entry, if it is salted
+    pub fn salt(&self) -> Option<Fp> {
+        self.salt
     }
 
     pub fn balances(&self) -> &[BigUint; N_CURRENCIES] {
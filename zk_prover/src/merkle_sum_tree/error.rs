@@ -0,0 +1,78 @@
+use num_bigint::BigUint;
+
+/// The failures of the merkle sum tree, from the parsing of its entries to the generation of its proofs.
+/// The functions of the tree return them boxed as `Box<dyn Error>`, from which they can be recovered with `downcast_ref::<TreeError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeError {
+    /// The record at `line` of the input file couldn't be turned into an entry, e.g. a missing balance or a balance out of range
+    InvalidEntry { line: usize, reason: String },
+    /// The entries at `first_line` and `line` of the input file share the same username
+    DuplicateUsername {
+        username: String,
+        first_line: usize,
+        line: usize,
+    },
+    /// The index doesn't point to any of the `len` leaves of the tree, or of the nodes of the requested level
+    IndexOutOfBounds { index: usize, len: usize },
+    /// The balance of the cryptocurrency at `asset_index` doesn't lie in the range [0, 2^(`n_bytes` * 8) - 1] enforced by the range check of the circuit
+    BalanceOverflow {
+        username: String,
+        asset_index: usize,
+        balance: BigUint,
+        n_bytes: usize,
+    },
+    /// The number of balances given for an entry differs from the number of cryptocurrencies of the tree
+    BalanceCountMismatch { expected: usize, found: usize },
+    /// The tree can't be written as a CSV file that rebuilds the same tree, e.g. one of its entries is salted
+    CsvExport(String),
+    /// The JSON representation of a proof is inconsistent, e.g. a path index is neither 0 nor 1 or a path element doesn't match its preimage
+    InvalidProofJson(String),
+}
+
+impl std::fmt::Display for TreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeError::InvalidEntry { line, reason } => {
+                write!(f, "Invalid entry at line {}: {}", line, reason)
+            }
+            TreeError::DuplicateUsername {
+                username,
+                first_line,
+                line,
+            } => write!(
+                f,
+                "Duplicate entry for user {} at lines {} and {}",
+                username, first_line, line
+            ),
+            TreeError::IndexOutOfBounds { index, len } => {
+                write!(f, "Index {} out of bounds, there are {} nodes", index, len)
+            }
+            TreeError::BalanceOverflow {
+                username,
+                asset_index,
+                balance,
+                n_bytes,
+            } => write!(
+                f,
+                "Balance {} of cryptocurrency {} for user {} is not in range [0, 2^{} - 1]",
+                balance,
+                asset_index,
+                username,
+                n_bytes * 8
+            ),
+            TreeError::BalanceCountMismatch { expected, found } => write!(
+                f,
+                "Expected {} balances, one per cryptocurrency, but found {}",
+                expected, found
+            ),
+            TreeError::CsvExport(reason) => {
+                write!(f, "The tree can't be exported as a CSV file: {}", reason)
+            }
+            TreeError::InvalidProofJson(reason) => {
+                write!(f, "Invalid JSON representation of the proof: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
@@ -0,0 +1,270 @@
+use crate::merkle_sum_tree::utils::big_uint_to_fp;
+use crate::merkle_sum_tree::{Entry, MerkleProof, MerkleSumTree, Node, Tree, TreeError};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+
+/// A forest of Merkle Sum Trees, committed to by a top-level tree whose leaves are the roots of the sub-trees.
+/// The top-level tree is padded with the roots of sub-trees of `LEVELS` levels filled with zero entries, and its nodes are built as the middle nodes of a Merkle Sum Tree.
+/// The root of the forest is therefore the root of a single Merkle Sum Tree of `LEVELS + top_depth` levels holding the entries of all the sub-trees, each sub-tree being padded to `2^LEVELS` entries.
+///
+/// # Type Parameters
+///
+/// * `LEVELS`: The depth of each sub-tree
+/// * `N_CURRENCIES`: The number of cryptocurrencies for each user account
+/// * `N_BYTES`: Range in which each node balance should lie
+///
+/// The leaf at `global_index` of the forest is the leaf at `global_index % 2^LEVELS` of the sub-tree at `global_index / 2^LEVELS`.
+#[derive(Debug, Clone)]
+pub struct ForestMerkleSumTree<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+{
+    sub_trees: Vec<MerkleSumTree<N_CURRENCIES, N_BYTES>>,
+    // The levels of the top-level tree, from the padded sub-tree roots up to the root of the forest
+    top_levels: Vec<Vec<Node<N_CURRENCIES>>>,
+    // The nodes of a sub-tree filled with zero entries, from its leaf level up to its root
+    empty_sub_tree_nodes: Vec<Node<N_CURRENCIES>>,
+}
+
+/// A proof of inclusion of an entry in a `ForestMerkleSumTree`, made of the proof of the entry within its sub-tree and of the proof of the sub-tree root within the top-level tree.
+///
+/// # Fields
+///
+/// * `sub_tree_proof`: The proof of the entry within its sub-tree, whose `root` is the root of the sub-tree
+/// * `sub_tree_index`: The index of the sub-tree among the leaves of the top-level tree
+/// * `sibling_middle_node_hash_preimages`: The hash preimages of the siblings of the nodes on the path from the sub-tree root up to the root of the forest. The sibling at the first level is the root of another sub-tree, so that all of them are middle nodes
+/// * `path_indices`: The indices of the path from the sub-tree root to the root of the forest. 0 indicates that the node on the path is the left child, 1 that it is the right child
+/// * `root`: The root of the forest
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForestProof<const N_CURRENCIES: usize>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    pub sub_tree_proof: MerkleProof<N_CURRENCIES>,
+    pub sub_tree_index: usize,
+    pub sibling_middle_node_hash_preimages: Vec<[Fp; N_CURRENCIES + 2]>,
+    pub path_indices: Vec<Fp>,
+    pub root: Node<N_CURRENCIES>,
+}
+
+impl<const N_CURRENCIES: usize> ForestProof<N_CURRENCIES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Flattens the two levels of the proof into a single `MerkleProof` of the entry against the root of the forest.
+    /// As the forest is a Merkle Sum Tree of `LEVELS + top_depth` levels, the flattened proof can be verified by `MstInclusionCircuit` with as many levels.
+    pub fn to_merkle_proof(&self) -> MerkleProof<N_CURRENCIES> {
+        let mut sibling_middle_node_hash_preimages = self
+            .sub_tree_proof
+            .sibling_middle_node_hash_preimages
+            .clone();
+        sibling_middle_node_hash_preimages
+            .extend_from_slice(&self.sibling_middle_node_hash_preimages);
+
+        let mut path_indices = self.sub_tree_proof.path_indices.clone();
+        path_indices.extend_from_slice(&self.path_indices);
+
+        MerkleProof {
+            entry: self.sub_tree_proof.entry.clone(),
+            root: self.root.clone(),
+            sibling_leaf_node_hash_preimage: self.sub_tree_proof.sibling_leaf_node_hash_preimage,
+            sibling_middle_node_hash_preimages,
+            path_indices,
+            cryptocurrencies: self.sub_tree_proof.cryptocurrencies.clone(),
+        }
+    }
+
+    /// Verifies both levels of the proof: the entry against the root of its sub-tree, and the root of the sub-tree against the root of the forest
+    pub fn verify(&self) -> bool {
+        let sub_tree_levels = self.sub_tree_proof.path_indices.len();
+        let sub_tree_root = match self.sub_tree_proof.verify_partial(sub_tree_levels) {
+            Ok(sub_tree_root) => sub_tree_root,
+            Err(_) => return false,
+        };
+        if sub_tree_root.0 != self.sub_tree_proof.root.hash
+            || sub_tree_root.1.map(|balance| big_uint_to_fp(&balance))
+                != self.sub_tree_proof.root.balances
+        {
+            return false;
+        }
+
+        match self
+            .to_merkle_proof()
+            .verify_partial(sub_tree_levels + self.path_indices.len())
+        {
+            Ok((hash, balances)) => {
+                hash == self.root.hash
+                    && balances.map(|balance| big_uint_to_fp(&balance)) == self.root.balances
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl<const LEVELS: usize, const N_CURRENCIES: usize, const N_BYTES: usize>
+    ForestMerkleSumTree<LEVELS, N_CURRENCIES, N_BYTES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Builds the forest of `sub_trees`, which must all be of depth `LEVELS` and hold the same cryptocurrencies.
+    /// The top-level tree has the smallest depth, at least 1, whose leaves fit all the sub-tree roots.
+    pub fn from_sub_trees(
+        sub_trees: Vec<MerkleSumTree<N_CURRENCIES, N_BYTES>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if LEVELS == 0 {
+            return Err(Box::from("The sub-trees must have at least one level"));
+        }
+        let first_sub_tree = match sub_trees.first() {
+            Some(sub_tree) => sub_tree,
+            None => return Err(Box::from("The forest must have at least one sub-tree")),
+        };
+        for (i, sub_tree) in sub_trees.iter().enumerate() {
+            if *sub_tree.depth() != LEVELS {
+                return Err(Box::from(format!(
+                    "Sub-tree {} has depth {}, expected {}",
+                    i,
+                    sub_tree.depth(),
+                    LEVELS
+                )));
+            }
+            if sub_tree.cryptocurrencies() != first_sub_tree.cryptocurrencies() {
+                return Err(Box::from(format!(
+                    "Sub-tree {} doesn't hold the same cryptocurrencies as sub-tree 0",
+                    i
+                )));
+            }
+        }
+
+        let mut empty_sub_tree_nodes = vec![Entry::<N_CURRENCIES>::zero_entry().compute_leaf()];
+        for _ in 0..LEVELS {
+            let child = empty_sub_tree_nodes.last().unwrap();
+            let node = Node::middle(child, child);
+            empty_sub_tree_nodes.push(node);
+        }
+
+        let top_depth = (sub_trees.len().next_power_of_two().trailing_zeros() as usize).max(1);
+        let mut top_leaves: Vec<Node<N_CURRENCIES>> = sub_trees
+            .iter()
+            .map(|sub_tree| sub_tree.root().clone())
+            .collect();
+        top_leaves.resize(1 << top_depth, empty_sub_tree_nodes[LEVELS].clone());
+
+        let mut top_levels = vec![top_leaves];
+        for _ in 0..top_depth {
+            let parents = top_levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|children| Node::middle(&children[0], &children[1]))
+                .collect();
+            top_levels.push(parents);
+        }
+
+        Ok(ForestMerkleSumTree {
+            sub_trees,
+            top_levels,
+            empty_sub_tree_nodes,
+        })
+    }
+
+    /// Returns the root of the forest, namely the root of the top-level tree
+    pub fn root(&self) -> &Node<N_CURRENCIES> {
+        &self.top_levels[self.top_depth()][0]
+    }
+
+    /// Returns the sub-trees of the forest, in the order of their roots in the top-level tree
+    pub fn sub_trees(&self) -> &[MerkleSumTree<N_CURRENCIES, N_BYTES>] {
+        &self.sub_trees
+    }
+
+    /// Returns the depth of the top-level tree
+    pub fn top_depth(&self) -> usize {
+        self.top_levels.len() - 1
+    }
+
+    /// Returns the depth of the forest seen as a single Merkle Sum Tree, namely `LEVELS + top_depth`
+    pub fn depth(&self) -> usize {
+        LEVELS + self.top_depth()
+    }
+
+    /// Generates the proof of inclusion of the entry at `global_index` of the forest, made of its proof within its sub-tree and the proof of the sub-tree root within the top-level tree
+    pub fn generate_proof(
+        &self,
+        global_index: usize,
+    ) -> Result<ForestProof<N_CURRENCIES>, Box<dyn std::error::Error>> {
+        let sub_tree_index = global_index >> LEVELS;
+        if sub_tree_index >= self.sub_trees.len() {
+            return Err(Box::new(TreeError::IndexOutOfBounds {
+                index: global_index,
+                len: self.sub_trees.len() << LEVELS,
+            }));
+        }
+        let sub_tree_proof =
+            self.sub_trees[sub_tree_index].generate_proof(global_index % (1 << LEVELS))?;
+
+        let mut sibling_middle_node_hash_preimages = Vec::with_capacity(self.top_depth());
+        let mut path_indices = Vec::with_capacity(self.top_depth());
+        let mut index = sub_tree_index;
+        for level in 0..self.top_depth() {
+            let sibling_index = index ^ 1;
+            sibling_middle_node_hash_preimages
+                .push(self.top_node_hash_preimage(level, sibling_index)?);
+            path_indices.push(Fp::from((index % 2) as u64));
+            index /= 2;
+        }
+
+        Ok(ForestProof {
+            sub_tree_proof,
+            sub_tree_index,
+            sibling_middle_node_hash_preimages,
+            path_indices,
+            root: self.root().clone(),
+        })
+    }
+
+    /// Verifies a proof of inclusion against the root of the forest
+    pub fn verify_proof(&self, proof: &ForestProof<N_CURRENCIES>) -> bool {
+        proof.root == *self.root() && proof.verify()
+    }
+
+    /// Returns the hash preimage of the node at `index` of the `level` of the top-level tree.
+    /// The children of a node at level 0 are the children of the root of the sub-tree, or of an empty sub-tree for the padding nodes.
+    fn top_node_hash_preimage(
+        &self,
+        level: usize,
+        index: usize,
+    ) -> Result<[Fp; N_CURRENCIES + 2], Box<dyn std::error::Error>> {
+        if level > 0 {
+            let children = &self.top_levels[level - 1];
+            return Ok(middle_node_hash_preimage(
+                &children[2 * index],
+                &children[2 * index + 1],
+            ));
+        }
+
+        match self.sub_trees.get(index) {
+            Some(sub_tree) => sub_tree.get_middle_node_hash_preimage(LEVELS, 0),
+            None => {
+                let child = &self.empty_sub_tree_nodes[LEVELS - 1];
+                Ok(middle_node_hash_preimage(child, child))
+            }
+        }
+    }
+}
+
+// The hash preimage of the middle node whose children are `child_l` and `child_r`, as built by `Node::middle`
+fn middle_node_hash_preimage<const N_CURRENCIES: usize>(
+    child_l: &Node<N_CURRENCIES>,
+    child_r: &Node<N_CURRENCIES>,
+) -> [Fp; N_CURRENCIES + 2]
+where
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    let mut preimage = [Fp::zero(); N_CURRENCIES + 2];
+    for (i, balance) in preimage.iter_mut().enumerate().take(N_CURRENCIES) {
+        *balance = child_l.balances[i] + child_r.balances[i];
+    }
+    preimage[N_CURRENCIES] = child_l.hash;
+    preimage[N_CURRENCIES + 1] = child_r.hash;
+    preimage
+}
@@ -1,10 +1,19 @@
+mod builder;
 mod entry;
+mod error;
+mod forest;
 mod mst;
 mod node;
+mod proof_cache;
+mod proof_json;
 mod tests;
 mod tree;
 pub mod utils;
+use crate::chips::poseidon::{poseidon_spec::PoseidonSpec, TreeSpec};
+use crate::merkle_sum_tree::proof_json::MerkleProofJson;
+use crate::merkle_sum_tree::utils::fp_to_big_uint;
 use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use num_bigint::BigUint;
 
 /// A struct representing a Merkle Proof.
 ///
@@ -13,7 +22,11 @@ use halo2_proofs::halo2curves::bn256::Fr as Fp;
 /// * `root`: The root of the Merkle Sum Tree
 /// * `sibling_leaf_node_hash_preimage`: The hash preimage of the sibling leaf node. The hash preimage is equal to `[sibling_username, sibling.balance[0], sibling.balance[1], ... sibling.balance[N_CURRENCIES - 1]]`
 /// * `sibling_middle_node_hash_preimages`: The hash preimages of the sibling middle nodes. The hash preimage is equal to `[sibling_left_child.balance[0] + sibling_right_child.balance[0], sibling_left_child.balance[1] + sibling_right_child.balance[1], ..., sibling_left_child.balance[N_CURRENCIES - 1] + sibling_right_child.balance[N_CURRENCIES - 1], sibling_left_child.hash, sibling_right_child.hash]`
-#[derive(Clone, Debug)]
+/// * `path_indices`: The indices of the path from the leaf to the root. 0 indicates that the node on the path is the left child, 1 that it is the right child
+/// * `cryptocurrencies`: The cryptocurrencies labelling the balances of the entry and of the root, in the same order. It is empty if the tree has no labels
+///
+/// The proof is shared with the users through the JSON format of `to_json`, which can be parsed and verified outside of Rust.
+#[derive(Clone, Debug, PartialEq)]
 pub struct MerkleProof<const N_CURRENCIES: usize>
 where
     [usize; N_CURRENCIES + 1]: Sized,
@@ -24,10 +37,114 @@ where
     pub sibling_leaf_node_hash_preimage: [Fp; N_CURRENCIES + 1],
     pub sibling_middle_node_hash_preimages: Vec<[Fp; N_CURRENCIES + 2]>,
     pub path_indices: Vec<Fp>,
+    pub cryptocurrencies: Vec<Cryptocurrency>,
+}
+
+impl<const N_CURRENCIES: usize> MerkleProof<N_CURRENCIES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Serializes the proof into its canonical JSON format:
+    /// ```json
+    /// {
+    ///   "entry": { "hashed_username": "<decimal>", "balances": ["<decimal>", ...], "username": "<string>", "salt": "<hex>" },
+    ///   "leaf_hash": "<hex>",
+    ///   "root": { "hash": "<hex>", "balances": ["<decimal>", ...] },
+    ///   "path_elements": [{ "hash": "<hex>", "balances": ["<decimal>", ...], "preimage": ["<hex>", ...] }, ...],
+    ///   "path_indices": [0 | 1, ...],
+    ///   "cryptocurrencies": [{ "name": "<string>", "chain": "<string>" }, ...]
+    /// }
+    /// ```
+    /// where `<hex>` is a field element encoded as a 0x-prefixed, big-endian, 32-byte hex string and `<decimal>` is an unsigned integer encoded as a decimal string.
+    /// `path_elements` are the sibling nodes from the leaf to the root, the first one being the sibling leaf, along with the hash preimages from which the circuit recomputes them.
+    /// `path_indices` are 0 when the node on the path is the left child and 1 when it is the right child.
+    /// The `cryptocurrencies` field may be omitted, in which case the proof has no labels. The `salt` field of the entry is only present if the entry is salted.
+    /// The format is published as a JSON schema in `zk_prover/schemas/merkle_proof.schema.json`.
+    pub fn to_json(&self) -> String {
+        self.to_json_with_spec::<PoseidonSpec>()
+    }
+
+    /// Serializes the proof as `to_json` does, the leaf hash and the path elements being hashed with the Poseidon specification `S` of the tree
+    pub fn to_json_with_spec<S: TreeSpec>(&self) -> String {
+        serde_json::to_string(&MerkleProofJson::from_proof::<S>(self))
+            .expect("The JSON representation of a proof is always serializable")
+    }
+
+    /// Deserializes a proof from the JSON format of `to_json`.
+    /// Returns an error if the hashed username of the entry doesn't match its username, or if one of its balances doesn't lie in the range of `N_BYTES` bytes, see `Entry::check_range`,
+    /// and a `TreeError::InvalidProofJson` if a path index is neither 0 nor 1, or if the leaf hash or a path element doesn't match the entry or its preimage.
+    pub fn from_json<const N_BYTES: usize>(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_json_with_spec::<N_BYTES, PoseidonSpec>(json)
+    }
+
+    /// Deserializes a proof as `from_json` does, the leaf hash and the path elements being checked with the Poseidon specification `S` of the tree
+    pub fn from_json_with_spec<const N_BYTES: usize, S: TreeSpec>(
+        json: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let proof =
+            serde_json::from_str::<MerkleProofJson<N_CURRENCIES>>(json)?.into_proof::<S>()?;
+        proof.entry.check_range::<N_BYTES>()?;
+        Ok(proof)
+    }
+
+    /// Recomputes the node reached after `levels` hashing operations from the leaf of the proof, without requiring the rest of the path.
+    /// `levels = 0` returns the leaf itself, while `levels = path_indices.len()` returns the root of the tree.
+    ///
+    /// Returns the hash and the balances of the computed intermediate node.
+    pub fn verify_partial(
+        &self,
+        levels: usize,
+    ) -> Result<(Fp, [BigUint; N_CURRENCIES]), Box<dyn std::error::Error>> {
+        self.verify_partial_with_spec::<PoseidonSpec>(levels)
+    }
+
+    /// Recomputes the intermediate node as `verify_partial` does, hashing the nodes with the Poseidon specification `S` of the tree
+    pub fn verify_partial_with_spec<S: TreeSpec>(
+        &self,
+        levels: usize,
+    ) -> Result<(Fp, [BigUint; N_CURRENCIES]), Box<dyn std::error::Error>> {
+        if levels > self.path_indices.len()
+            || levels > self.sibling_middle_node_hash_preimages.len() + 1
+        {
+            return Err(Box::from("Invalid depth"));
+        }
+
+        let mut node = self.entry.compute_leaf_with_spec::<S>();
+
+        for level in 0..levels {
+            let sibling_node = if level == 0 {
+                Node::<N_CURRENCIES>::leaf_node_from_preimage_with_spec::<S>(
+                    &self.sibling_leaf_node_hash_preimage,
+                )
+            } else {
+                Node::<N_CURRENCIES>::middle_node_from_preimage_with_spec::<S>(
+                    &self.sibling_middle_node_hash_preimages[level - 1],
+                )
+            };
+
+            node = if self.path_indices[level] == Fp::zero() {
+                Node::middle_with_spec::<S>(&node, &sibling_node)
+            } else {
+                Node::middle_with_spec::<S>(&sibling_node, &node)
+            };
+        }
+
+        Ok((node.hash, node.balances.map(fp_to_big_uint)))
+    }
 }
 
+pub use builder::{BuildStage, MerkleSumTreeBuilder};
 pub use entry::Entry;
+pub use error::TreeError;
+pub use forest::{ForestMerkleSumTree, ForestProof};
 pub use mst::Cryptocurrency;
+pub use mst::MergedEntry;
 pub use mst::MerkleSumTree;
+#[cfg(feature = "compact")]
+pub use mst::FULL_NODE_LEVEL_INTERVAL;
+#[cfg(feature = "compact")]
+pub use node::CompactNode;
 pub use node::Node;
+pub use proof_cache::DEFAULT_PROOF_CACHE_CAPACITY;
 pub use tree::Tree;
@@ -1,9 +1,25 @@
+use crate::chips::poseidon::{poseidon_spec::PoseidonSpec, TreeSpec};
+use crate::merkle_sum_tree::proof_cache::{ProofCache, DEFAULT_PROOF_CACHE_CAPACITY};
+use crate::merkle_sum_tree::tree::compute_proof;
+#[cfg(not(feature = "compact"))]
+use crate::merkle_sum_tree::utils::build_merkle_tree_from_leaves_with_spec;
+#[cfg(feature = "compact")]
+use crate::merkle_sum_tree::utils::{big_uint_to_fp, build_merkle_tree_levels_with_spec};
 use crate::merkle_sum_tree::utils::{
-    build_leaves_from_entries, build_merkle_tree_from_leaves, parse_csv_to_entries,
+    build_leaves_from_entries_with_spec, parse_csv_to_entries,
+    parse_csv_to_entries_merging_duplicates, parse_jsonl_to_entries,
 };
-use crate::merkle_sum_tree::{Entry, Node, Tree};
+#[cfg(feature = "compact")]
+use crate::merkle_sum_tree::CompactNode;
+use crate::merkle_sum_tree::{BuildStage, Entry, MerkleProof, Node, Tree, TreeError};
+#[cfg(feature = "compact")]
 use halo2_proofs::halo2curves::bn256::Fr as Fp;
 use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::marker::PhantomData;
+use std::path::Path;
 
 /// Merkle Sum Tree Data Structure.
 ///
@@ -17,18 +33,60 @@ use num_bigint::BigUint;
 ///
 /// * `N_CURRENCIES`: The number of cryptocurrencies for each user account
 /// * `N_BYTES`: Range in which each node balance should lie
+/// * `S`: The Poseidon specification with which the nodes are hashed, which must be the one of the circuit verifying the proofs of the tree
+///
+/// The leaf level is not stored, as the leaves can be recomputed from the entries. The middle nodes are stored level by level in a single flat vector, starting from level 1 up to the root.
+/// With the `compact` feature, the middle nodes are stored as `CompactNodes`, which keep the balances of one level out of `FULL_NODE_LEVEL_INTERVAL` only.
 #[derive(Debug, Clone)]
-pub struct MerkleSumTree<const N_CURRENCIES: usize, const N_BYTES: usize> {
+pub struct MerkleSumTree<
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+    S: TreeSpec = PoseidonSpec,
+> {
     root: Node<N_CURRENCIES>,
-    nodes: Vec<Vec<Node<N_CURRENCIES>>>,
+    #[cfg(not(feature = "compact"))]
+    nodes: Vec<Node<N_CURRENCIES>>,
+    #[cfg(feature = "compact")]
+    nodes: CompactNodes<N_CURRENCIES>,
     depth: usize,
     entries: Vec<Entry<N_CURRENCIES>>,
     cryptocurrencies: Vec<Cryptocurrency>,
     is_sorted: bool,
+    // The Merkle paths computed by `generate_proof`, dropped when an update to the tree affects them
+    proof_cache: ProofCache,
+    _spec: PhantomData<S>,
+}
+
+/// The levels of middle nodes whose full nodes are stored by a tree built with the `compact` feature, namely the multiples of this interval
+#[cfg(feature = "compact")]
+pub const FULL_NODE_LEVEL_INTERVAL: usize = 4;
+
+/// The middle nodes stored by a `MerkleSumTree` built with the `compact` feature: the hash of every middle node, and the full nodes of the levels that are multiples of `FULL_NODE_LEVEL_INTERVAL`.
+/// The balances of a node of another level are the sums of the balances of its descendants at the closest full level below, or of its entries,
+/// so that reading a node sums the balances of at most 2^(`FULL_NODE_LEVEL_INTERVAL` - 1) nodes while the tree holds the balances of about one node out of 2^`FULL_NODE_LEVEL_INTERVAL`.
+#[cfg(feature = "compact")]
+#[derive(Debug, Clone, Default)]
+struct CompactNodes<const N_CURRENCIES: usize> {
+    // The hashes of the middle nodes, level by level
+    hashes: Vec<CompactNode>,
+    // The full nodes of the levels that are multiples of `FULL_NODE_LEVEL_INTERVAL`, level by level
+    full_nodes: Vec<Node<N_CURRENCIES>>,
+}
+
+#[cfg(feature = "compact")]
+impl<const N_CURRENCIES: usize> CompactNodes<N_CURRENCIES> {
+    /// Stores the nodes of `level`, the levels being pushed in order starting from level 1
+    fn push_level(&mut self, level: usize, nodes: &[Node<N_CURRENCIES>]) {
+        self.hashes
+            .extend(nodes.iter().map(|node| CompactNode { hash: node.hash }));
+        if level % FULL_NODE_LEVEL_INTERVAL == 0 {
+            self.full_nodes.extend_from_slice(nodes);
+        }
+    }
 }
 
-impl<const N_CURRENCIES: usize, const N_BYTES: usize> Tree<N_CURRENCIES>
-    for MerkleSumTree<N_CURRENCIES, N_BYTES>
+impl<const N_CURRENCIES: usize, const N_BYTES: usize, S: TreeSpec> Tree<N_CURRENCIES, S>
+    for MerkleSumTree<N_CURRENCIES, N_BYTES, S>
 {
     fn root(&self) -> &Node<N_CURRENCIES> {
         &self.root
@@ -38,8 +96,30 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> Tree<N_CURRENCIES>
         &self.depth
     }
 
-    fn nodes(&self) -> &[Vec<Node<N_CURRENCIES>>] {
-        &self.nodes
+    fn get_node(
+        &self,
+        level: usize,
+        index: usize,
+    ) -> Result<Node<N_CURRENCIES>, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+    {
+        if level > self.depth {
+            return Err(Box::from("Node not found"));
+        }
+        if index >= 2usize.pow((self.depth - level) as u32) {
+            return Err(TreeError::IndexOutOfBounds {
+                index,
+                len: 2usize.pow((self.depth - level) as u32),
+            }
+            .into());
+        }
+
+        if level == 0 {
+            return Ok(self.entries[index].compute_leaf_with_spec::<S>());
+        }
+
+        Ok(self.stored_node(level, index))
     }
 
     fn get_entry(&self, index: usize) -> &Entry<N_CURRENCIES> {
@@ -49,19 +129,79 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> Tree<N_CURRENCIES>
     fn cryptocurrencies(&self) -> &[Cryptocurrency] {
         &self.cryptocurrencies
     }
+
+    /// Generates a MerkleProof for the user with the given index, reusing the path of a previous proof of the same leaf if it is still cached.
+    /// The entry, the root and the cryptocurrencies of the proof are always read from the tree.
+    fn generate_proof(
+        &self,
+        index: usize,
+    ) -> Result<MerkleProof<N_CURRENCIES>, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if let Some((
+            sibling_leaf_node_hash_preimage,
+            sibling_middle_node_hash_preimages,
+            path_indices,
+        )) = self.proof_cache.get::<N_CURRENCIES>(index)
+        {
+            return Ok(MerkleProof {
+                entry: self.entries[index].clone(),
+                root: self.root.clone(),
+                sibling_leaf_node_hash_preimage,
+                sibling_middle_node_hash_preimages,
+                path_indices,
+                cryptocurrencies: self.cryptocurrencies.clone(),
+            });
+        }
+
+        let proof = compute_proof::<N_CURRENCIES, S, Self>(self, index)?;
+        self.proof_cache.put(index, &proof);
+        Ok(proof)
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Number of leaves hashed in parallel between two progress reports
+const LEAF_HASHING_CHUNK_SIZE: usize = 1 << 12;
+
+/// A cryptocurrency whose balances are in the tree, labelled by its name and the chain it lives on.
+/// The labels don't take part in the computation of the root, they only describe the position of the balances of the entries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Cryptocurrency {
     pub name: String,
     pub chain: String,
 }
 
-impl<const N_CURRENCIES: usize, const N_BYTES: usize> MerkleSumTree<N_CURRENCIES, N_BYTES> {
-    /// Returns the leaves of the tree
-    pub fn leaves(&self) -> &[Node<N_CURRENCIES>] {
-        &self.nodes[0]
+/// A group of entries sharing the same username that have been merged into a single entry by `MerkleSumTree::merge_duplicate_entries`.
+///
+/// # Fields
+///
+/// * `username`: The username shared by the merged entries
+/// * `old_entries`: The balances of the merged entries, in the order of their index in the tree before the merge
+/// * `merged_balances`: The balances of the single entry replacing them, namely the sum of the old balances per each cryptocurrency
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedEntry<const N_CURRENCIES: usize> {
+    pub username: String,
+    pub old_entries: Vec<[BigUint; N_CURRENCIES]>,
+    pub merged_balances: [BigUint; N_CURRENCIES],
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize, S: TreeSpec>
+    MerkleSumTree<N_CURRENCIES, N_BYTES, S>
+{
+    /// Returns the leaves of the tree.
+    /// The leaves are not stored by the tree, they are recomputed from the entries on each call.
+    pub fn leaves(&self) -> Vec<Node<N_CURRENCIES>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+    {
+        self.entries
+            .iter()
+            .map(|entry| entry.compute_leaf_with_spec::<S>())
+            .collect()
     }
+
     /// Returns the entries of the tree
     pub fn entries(&self) -> &[Entry<N_CURRENCIES>] {
         &self.entries
@@ -81,6 +221,85 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> MerkleSumTree<N_CURRENCIES
         Self::from_entries(entries, cryptocurrencies, false)
     }
 
+    /// Writes the entries of the tree to a CSV file stored at `path`, in the format read by `from_csv`:
+    ///
+    /// `username,balance_<cryptocurrency>_<chain>,balance_<cryptocurrency>_<chain>,...`
+    ///
+    /// The entries are written in the order of the leaves of the tree, leaving out the zero entries padding the tree, so that `from_csv` rebuilds a tree with the same root.
+    /// If the balances of the tree aren't labelled, e.g. for a tree built by `from_jsonl`, the columns are named `balance_asset<i>_unknown` after the index of the balance.
+    /// Returns a `TreeError::CsvExport` if the file couldn't be read back into the same tree, namely if the balances of the tree are labelled with another number of cryptocurrencies
+    /// or with cryptocurrencies whose names and chains can't be told apart in the header, if an entry is salted, or if a zero entry lies before the last entry of a user.
+    pub fn to_csv(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let cryptocurrencies = match self.cryptocurrencies.len() {
+            0 => (0..N_CURRENCIES)
+                .map(|i| Cryptocurrency {
+                    name: format!("asset{}", i),
+                    chain: "unknown".to_string(),
+                })
+                .collect(),
+            n if n == N_CURRENCIES => self.cryptocurrencies.clone(),
+            n => {
+                return Err(TreeError::CsvExport(format!(
+                    "Expected {} cryptocurrencies to name the balance columns but found {}",
+                    N_CURRENCIES, n
+                ))
+                .into())
+            }
+        };
+
+        let mut header = vec!["username".to_string()];
+        for cryptocurrency in &cryptocurrencies {
+            if cryptocurrency.name.contains('_') || cryptocurrency.chain.contains('_') {
+                return Err(TreeError::CsvExport(format!(
+                    "The cryptocurrency {} on chain {} can't be named in the header",
+                    cryptocurrency.name, cryptocurrency.chain
+                ))
+                .into());
+            }
+            header.push(format!(
+                "balance_{}_{}",
+                cryptocurrency.name, cryptocurrency.chain
+            ));
+        }
+
+        let zero_entry = Entry::<N_CURRENCIES>::zero_entry();
+        let n_user_entries = self
+            .entries
+            .iter()
+            .rposition(|entry| *entry != zero_entry)
+            .map_or(0, |last_index| last_index + 1);
+
+        let user_entries = &self.entries[..n_user_entries];
+        // The entries are checked before the file is created, so that no partial file is left behind
+        for (index, entry) in user_entries.iter().enumerate() {
+            if *entry == zero_entry {
+                return Err(TreeError::CsvExport(format!(
+                    "The zero entry at index {} can't be written as a user",
+                    index
+                ))
+                .into());
+            }
+            if entry.salt().is_some() {
+                return Err(TreeError::CsvExport(format!(
+                    "The entry of user {} is salted",
+                    entry.username()
+                ))
+                .into());
+            }
+        }
+
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(&header)?;
+        for entry in user_entries {
+            let mut record = vec![entry.username().to_string()];
+            record.extend(entry.balances().iter().map(|balance| balance.to_string()));
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
     /// Builds a Merkle Sum Tree from a CSV file stored at `path`. The MST leaves are sorted by the username byte values. The CSV file must be formatted as follows:
     ///
     /// `username,balance_<cryptocurrency>_<chain>,balance_<cryptocurrency>_<chain>,...`
@@ -99,17 +318,108 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> MerkleSumTree<N_CURRENCIES
         Self::from_entries(entries, cryptocurrencies, true)
     }
 
+    /// Builds a Merkle Sum Tree from a JSONL file stored at `path`, with one entry per line. The JSONL file must be formatted as follows:
+    ///
+    /// `{"username":"dxGaEAii","balances":[11888,41163]}`
+    ///
+    /// The entries are validated as in `from_csv`. As the file has no header, the balances of the tree are not labelled with the cryptocurrencies.
+    pub fn from_jsonl(path: &str) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let entries = parse_jsonl_to_entries::<&str, N_CURRENCIES, N_BYTES>(path)?;
+        Self::from_entries(entries, vec![], false)
+    }
+
+    /// Builds a Merkle Sum Tree from a CSV file stored at `path` as `from_csv` does, merging the records that share the same username into a single entry whose balances are the sum of the balances of the records.
+    /// While `from_csv` rejects a CSV file with duplicate usernames, this is intended for reconciliation tooling where the same user may appear in multiple exports.
+    pub fn from_csv_merge_duplicates(path: &str) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let (cryptocurrencies, entries) =
+            parse_csv_to_entries_merging_duplicates::<&str, N_CURRENCIES, N_BYTES>(path)?;
+        Self::from_entries(entries, cryptocurrencies, false)
+    }
+
     /// Builds a Merkle Sum Tree from a vector of entries
     pub fn from_entries(
+        entries: Vec<Entry<N_CURRENCIES>>,
+        cryptocurrencies: Vec<Cryptocurrency>,
+        is_sorted: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        Self::from_entries_with_progress(entries, cryptocurrencies, is_sorted, &mut |_, _, _| {})
+    }
+
+    /// Labels the balances of the tree with the names of the cryptocurrencies, in the same order as the balances of the entries.
+    /// The chains of the cryptocurrencies are left empty. Returns an error if the number of names doesn't match `N_CURRENCIES`.
+    pub fn with_asset_names<T: AsRef<str>>(
+        mut self,
+        names: &[T],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let cryptocurrencies = names
+            .iter()
+            .map(|name| Cryptocurrency {
+                name: name.as_ref().to_owned(),
+                chain: String::new(),
+            })
+            .collect::<Vec<_>>();
+        check_cryptocurrencies_count::<N_CURRENCIES>(&cryptocurrencies)?;
+
+        self.cryptocurrencies = cryptocurrencies;
+        Ok(self)
+    }
+
+    /// Sets the number of Merkle paths cached by `generate_proof`, `DEFAULT_PROOF_CACHE_CAPACITY` by default, dropping the paths cached so far.
+    /// The least recently used path is dropped when the cache is full, and a capacity of 0 disables the cache.
+    pub fn with_proof_cache_capacity(mut self, capacity: usize) -> Self {
+        self.proof_cache = ProofCache::new(capacity);
+        self
+    }
+
+    /// Returns the number of Merkle paths cached by `generate_proof`
+    pub fn proof_cache_capacity(&self) -> usize {
+        self.proof_cache.capacity()
+    }
+
+    /// Returns whether the Merkle path of the leaf at `index` is cached, so that its next proof is generated without walking the tree
+    pub fn is_proof_cached(&self, index: usize) -> bool {
+        self.proof_cache.contains(index)
+    }
+
+    /// Returns the names of the cryptocurrencies whose balances are in the tree, in the same order as the balances of the entries.
+    /// The names are empty if the tree has been built without labelling the balances.
+    pub fn asset_names(&self) -> Vec<&str> {
+        self.cryptocurrencies
+            .iter()
+            .map(|cryptocurrency| cryptocurrency.name.as_str())
+            .collect()
+    }
+
+    /// Builds a Merkle Sum Tree from a vector of entries, reporting the progress of the leaf hashing and of the middle node hashing to `progress`.
+    pub(crate) fn from_entries_with_progress(
         mut entries: Vec<Entry<N_CURRENCIES>>,
         cryptocurrencies: Vec<Cryptocurrency>,
         is_sorted: bool,
-    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn std::error::Error>>
+        progress: &mut dyn FnMut(BuildStage, usize, usize),
+    ) -> Result<Self, Box<dyn std::error::Error>>
     where
         [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        let depth = (entries.len() as f64).log2().ceil() as usize;
+        // The tree has at least one level of middle nodes, so that an empty tree or a tree with a single entry
+        // still has a root computed as a middle node and every entry has a sibling to generate a proof with
+        if !cryptocurrencies.is_empty() {
+            check_cryptocurrencies_count::<N_CURRENCIES>(&cryptocurrencies)?;
+        }
+
+        let depth = ((entries.len() as f64).log2().ceil() as usize).max(1);
 
         // Pad the entries with empty entries to make the number of entries equal to 2^depth
         if entries.len() < 2usize.pow(depth as u32) {
@@ -119,9 +429,34 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> MerkleSumTree<N_CURRENCIES
             ]);
         }
 
-        let leaves = build_leaves_from_entries(&entries);
+        // The leaves are hashed in chunks so that the progress can be reported between two chunks
+        let mut leaves = Vec::with_capacity(entries.len());
+        for chunk in entries.chunks(LEAF_HASHING_CHUNK_SIZE) {
+            leaves.extend(build_leaves_from_entries_with_spec::<N_CURRENCIES, S>(
+                chunk,
+            ));
+            progress(BuildStage::LeafHashing, leaves.len(), entries.len());
+        }
+
+        #[cfg(not(feature = "compact"))]
+        let (root, nodes) = build_merkle_tree_from_leaves_with_spec::<N_CURRENCIES, S>(
+            &leaves,
+            depth,
+            &mut |done, total| progress(BuildStage::MiddleNodeHashing, done, total),
+        )?;
 
-        let (root, nodes) = build_merkle_tree_from_leaves(&leaves, depth)?;
+        // The levels are stored as they are built, so that the full middle nodes are never all held at once
+        #[cfg(feature = "compact")]
+        let (root, nodes) = {
+            let mut nodes = CompactNodes::default();
+            let root = build_merkle_tree_levels_with_spec::<N_CURRENCIES, S>(
+                leaves,
+                depth,
+                &mut |done, total| progress(BuildStage::MiddleNodeHashing, done, total),
+                &mut |level, level_nodes| nodes.push_level(level, level_nodes),
+            );
+            (root, nodes)
+        };
 
         Ok(MerkleSumTree {
             root,
@@ -130,13 +465,16 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> MerkleSumTree<N_CURRENCIES
             entries,
             cryptocurrencies,
             is_sorted,
+            proof_cache: ProofCache::new(DEFAULT_PROOF_CACHE_CAPACITY),
+            _spec: PhantomData,
         })
     }
 
     /// Builds a Merkle Sum Tree from a root node, a vector of nodes, a depth, a vector of entries, a vector of cryptocurrencies and a boolean indicating whether the leaves are sorted by the username byte values.
+    /// The `nodes` vector must contain the middle nodes level by level, starting from level 1 up to the root, as returned by `build_merkle_tree_from_leaves`.
     pub fn from_params(
         root: Node<N_CURRENCIES>,
-        nodes: Vec<Vec<Node<N_CURRENCIES>>>,
+        nodes: Vec<Node<N_CURRENCIES>>,
         depth: usize,
         entries: Vec<Entry<N_CURRENCIES>>,
         cryptocurrencies: Vec<Cryptocurrency>,
@@ -146,13 +484,15 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> MerkleSumTree<N_CURRENCIES
         [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        Ok(MerkleSumTree::<N_CURRENCIES, N_BYTES> {
+        Ok(MerkleSumTree {
             root,
-            nodes,
+            nodes: Self::store_nodes(nodes, depth),
             depth,
             entries,
             cryptocurrencies,
             is_sorted,
+            proof_cache: ProofCache::new(DEFAULT_PROOF_CACHE_CAPACITY),
+            _spec: PhantomData,
         })
     }
 
@@ -178,29 +518,299 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> MerkleSumTree<N_CURRENCIES
         let index = self.index_of_username(username)?;
 
         // Update the leaf node.
-        let updated_leaf = self.entries[index].recompute_leaf(new_balances);
-        self.nodes[0][index] = updated_leaf;
+        let mut current_node = self.entries[index].recompute_leaf_with_spec::<S>(new_balances);
 
         // Recompute the hashes and balances up the tree.
         let mut current_index = index;
-        for depth in 1..=self.depth {
-            let parent_index = current_index / 2;
-            let left_child = &self.nodes[depth - 1][2 * parent_index];
-            let right_child = &self.nodes[depth - 1][2 * parent_index + 1];
-
-            let mut hash_preimage = [Fp::zero(); N_CURRENCIES + 2];
-            for (i, balance) in hash_preimage.iter_mut().enumerate().take(N_CURRENCIES) {
-                *balance = left_child.balances[i] + right_child.balances[i];
+        for level in 1..=self.depth {
+            let sibling_node = self.get_node(level - 1, current_index ^ 1)?;
+
+            current_node = if current_index % 2 == 0 {
+                Node::middle_with_spec::<S>(&current_node, &sibling_node)
+            } else {
+                Node::middle_with_spec::<S>(&sibling_node, &current_node)
+            };
+
+            current_index /= 2;
+            self.set_node(level, current_index, &current_node);
+        }
+
+        self.root = current_node.clone();
+        self.proof_cache.invalidate(&[index]);
+        Ok(current_node)
+    }
+
+    /// Replaces the entries of the tree with `entries`, recomputing only the middle nodes lying on the path of the entries that changed.
+    /// The untouched subtrees are reused, so that the resulting tree is the same as the one built from scratch by `from_entries`.
+    /// If the padded number of entries differs from the current one, the depth of the tree changes and the whole tree is rebuilt.
+    ///
+    /// # Returns
+    ///
+    /// The number of middle nodes that have been recomputed
+    pub fn rebuild_with(
+        &mut self,
+        mut entries: Vec<Entry<N_CURRENCIES>>,
+    ) -> Result<usize, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if self.is_sorted {
+            entries.sort_by(|a, b| a.username().cmp(b.username()));
+        }
+
+        let depth = ((entries.len() as f64).log2().ceil() as usize).max(1);
+        if depth != self.depth {
+            *self = Self::from_entries(entries, self.cryptocurrencies.clone(), self.is_sorted)?
+                .with_proof_cache_capacity(self.proof_cache.capacity());
+            return Ok(2usize.pow(self.depth as u32) - 1);
+        }
+
+        // Pad the entries with empty entries to make the number of entries equal to 2^depth
+        entries.resize(2usize.pow(depth as u32), Entry::zero_entry());
+
+        // Indices of the nodes of the current level whose subtree contains a changed entry
+        let mut changed_indices: Vec<usize> = entries
+            .iter()
+            .zip(self.entries.iter())
+            .enumerate()
+            .filter(|(_, (new_entry, old_entry))| new_entry != old_entry)
+            .map(|(index, _)| index)
+            .collect();
+
+        self.entries = entries;
+        self.proof_cache.invalidate(&changed_indices);
+
+        let mut recomputed_nodes = 0;
+        for level in 1..=self.depth {
+            changed_indices = changed_indices.into_iter().map(|index| index / 2).collect();
+            changed_indices.dedup();
+
+            for &index in &changed_indices {
+                let left_child = self.get_node(level - 1, 2 * index)?;
+                let right_child = self.get_node(level - 1, 2 * index + 1)?;
+                let node = Node::middle_with_spec::<S>(&left_child, &right_child);
+                self.set_node(level, index, &node);
+            }
+            recomputed_nodes += changed_indices.len();
+        }
+
+        self.root = self.get_node(self.depth, 0)?;
+        Ok(recomputed_nodes)
+    }
+
+    /// Replaces the entries sharing the same username with a single entry whose balances are the sums of their balances per each cryptocurrency, and rebuilds the tree with `rebuild_with`.
+    /// The merged entry takes the position and the salt of the first occurrence of the username, and the zero entries padding the tree are left out of the merge.
+    /// Returns an error if a merged balance doesn't lie in the range enforced by the range check of the circuit, in which case the tree is left unchanged.
+    ///
+    /// # Returns
+    ///
+    /// The merged groups of entries, in the order of the first occurrence of their username. The tree isn't rebuilt if there is no duplicate username
+    pub fn merge_duplicate_entries(
+        &mut self,
+    ) -> Result<Vec<MergedEntry<N_CURRENCIES>>, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let zero_entry = Entry::<N_CURRENCIES>::zero_entry();
+
+        let mut positions: HashMap<&str, usize> = HashMap::with_capacity(self.entries.len());
+        let mut groups: Vec<Vec<&Entry<N_CURRENCIES>>> = Vec::with_capacity(self.entries.len());
+        for entry in self.entries.iter().filter(|entry| **entry != zero_entry) {
+            match positions.get(entry.username()) {
+                Some(&position) => groups[position].push(entry),
+                None => {
+                    positions.insert(entry.username(), groups.len());
+                    groups.push(vec![entry]);
+                }
+            }
+        }
+
+        if groups.iter().all(|group| group.len() == 1) {
+            return Ok(vec![]);
+        }
+
+        let mut entries = Vec::with_capacity(groups.len());
+        let mut merged_entries = vec![];
+        for group in groups {
+            if group.len() == 1 {
+                entries.push(group[0].clone());
+                continue;
+            }
+
+            let first = group[0];
+            let merged_balances: [BigUint; N_CURRENCIES] = std::array::from_fn(|i| {
+                group
+                    .iter()
+                    .map(|entry| &entry.balances()[i])
+                    .sum::<BigUint>()
+            });
+            let merged = Entry::new_checked::<N_BYTES>(
+                first.username().to_string(),
+                merged_balances.clone(),
+            )?;
+            entries.push(match first.salt() {
+                Some(salt) => {
+                    Entry::new_salted(first.username().to_string(), merged_balances.clone(), salt)
+                }
+                None => merged,
+            });
+
+            merged_entries.push(MergedEntry {
+                username: first.username().to_string(),
+                old_entries: group.iter().map(|entry| entry.balances().clone()).collect(),
+                merged_balances,
+            });
+        }
+
+        self.rebuild_with(entries)?;
+        Ok(merged_entries)
+    }
+
+    /// Returns the entries of the tree along with their index, sorted by descending balance of the cryptocurrency at `asset_index`,
+    /// or by descending sum of the balances of all the cryptocurrencies if `asset_index` is `None`.
+    /// The entries with the same balance are kept in the order of their index. The zero entries padding the tree are included.
+    ///
+    /// Panics if `asset_index` is not lower than `N_CURRENCIES`.
+    pub fn entries_ranked_by_balance(
+        &self,
+        asset_index: Option<usize>,
+    ) -> Vec<(usize, &Entry<N_CURRENCIES>)> {
+        let mut ranked_entries = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (ranking_balance(entry, asset_index), index, entry))
+            .collect::<Vec<_>>();
+
+        // The sort is stable, so that the entries with the same balance stay in the order of their index
+        ranked_entries.sort_by(|a, b| b.0.cmp(&a.0));
+
+        ranked_entries
+            .into_iter()
+            .map(|(_, index, entry)| (index, entry))
+            .collect()
+    }
+
+    /// Returns the `n` first entries of `entries_ranked_by_balance`, without sorting all the entries of the tree.
+    /// Only `n` entries are kept at once, so that retrieving the largest balances of a large tree takes `O(entries * log(n))` time.
+    pub fn top_n_entries(
+        &self,
+        n: usize,
+        asset_index: Option<usize>,
+    ) -> Vec<(usize, &Entry<N_CURRENCIES>)> {
+        if n == 0 {
+            return vec![];
+        }
+
+        // A min-heap of the n largest balances seen so far, in which the entry of the lowest balance and highest index is popped first
+        let mut top_entries = BinaryHeap::with_capacity(n + 1);
+        for (index, entry) in self.entries.iter().enumerate() {
+            top_entries.push(Reverse((
+                ranking_balance(entry, asset_index),
+                Reverse(index),
+            )));
+            if top_entries.len() > n {
+                top_entries.pop();
             }
-            hash_preimage[N_CURRENCIES] = left_child.hash;
-            hash_preimage[N_CURRENCIES + 1] = right_child.hash;
+        }
+
+        top_entries
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((_, Reverse(index)))| (index, &self.entries[index]))
+            .collect()
+    }
 
-            self.nodes[depth][parent_index] = Node::middle_node_from_preimage(&hash_preimage);
-            current_index = parent_index;
+    /// Converts the middle nodes, as returned by `build_merkle_tree_from_leaves`, to the ones stored by the tree
+    #[cfg(not(feature = "compact"))]
+    fn store_nodes(nodes: Vec<Node<N_CURRENCIES>>, _depth: usize) -> Vec<Node<N_CURRENCIES>> {
+        nodes
+    }
+
+    /// Converts the middle nodes of a tree of `depth` levels, as returned by `build_merkle_tree_from_leaves`, to the ones stored by the tree
+    #[cfg(feature = "compact")]
+    fn store_nodes(nodes: Vec<Node<N_CURRENCIES>>, depth: usize) -> CompactNodes<N_CURRENCIES> {
+        let mut stored_nodes = CompactNodes::default();
+        let mut level_start = 0;
+        for level in 1..=depth {
+            let level_end = level_start + 2usize.pow((depth - level) as u32);
+            stored_nodes.push_level(level, &nodes[level_start..level_end]);
+            level_start = level_end;
         }
+        stored_nodes
+    }
 
-        let root = self.nodes[self.depth][0].clone();
-        Ok(root)
+    /// Returns the middle node at `index` of `level`, which must be between 1 and the depth of the tree
+    #[cfg(not(feature = "compact"))]
+    fn stored_node(&self, level: usize, index: usize) -> Node<N_CURRENCIES> {
+        self.nodes[self.level_offset(level) + index].clone()
+    }
+
+    /// Returns the middle node at `index` of `level`, which must be between 1 and the depth of the tree.
+    /// Unless the level is a full one, its balances are the sums of the balances of its descendants at the closest full level below, or of its entries.
+    #[cfg(feature = "compact")]
+    fn stored_node(&self, level: usize, index: usize) -> Node<N_CURRENCIES> {
+        if level % FULL_NODE_LEVEL_INTERVAL == 0 {
+            return self.nodes.full_nodes[self.full_level_offset(level) + index].clone();
+        }
+
+        let full_level = level - level % FULL_NODE_LEVEL_INTERVAL;
+        let first = index << (level - full_level);
+        let last = (index + 1) << (level - full_level);
+
+        let mut balances = [Fp::zero(); N_CURRENCIES];
+        if full_level == 0 {
+            for entry in &self.entries[first..last] {
+                for (balance, entry_balance) in balances.iter_mut().zip(entry.balances()) {
+                    *balance += big_uint_to_fp(entry_balance);
+                }
+            }
+        } else {
+            let offset = self.full_level_offset(full_level);
+            for node in &self.nodes.full_nodes[offset + first..offset + last] {
+                for (balance, node_balance) in balances.iter_mut().zip(node.balances.iter()) {
+                    *balance += node_balance;
+                }
+            }
+        }
+
+        Node {
+            hash: self.nodes.hashes[self.level_offset(level) + index].hash,
+            balances,
+        }
+    }
+
+    /// Replaces the middle node at `index` of `level` with `node`
+    fn set_node(&mut self, level: usize, index: usize, node: &Node<N_CURRENCIES>) {
+        let position = self.level_offset(level) + index;
+        #[cfg(not(feature = "compact"))]
+        {
+            self.nodes[position] = node.clone();
+        }
+        #[cfg(feature = "compact")]
+        {
+            self.nodes.hashes[position] = CompactNode { hash: node.hash };
+            if level % FULL_NODE_LEVEL_INTERVAL == 0 {
+                let full_position = self.full_level_offset(level) + index;
+                self.nodes.full_nodes[full_position] = node.clone();
+            }
+        }
+    }
+
+    /// Returns the position of the first node of `level`, which must be a multiple of `FULL_NODE_LEVEL_INTERVAL`, inside the full nodes of the compact storage
+    #[cfg(feature = "compact")]
+    fn full_level_offset(&self, level: usize) -> usize {
+        (1..level / FULL_NODE_LEVEL_INTERVAL)
+            .map(|multiple| 2usize.pow((self.depth - multiple * FULL_NODE_LEVEL_INTERVAL) as u32))
+            .sum()
+    }
+
+    /// Returns the position of the first node of `level` inside the flat `nodes` vector.
+    /// Level 1 starts at 0 and each level `l` holds `2^(depth - l)` nodes.
+    fn level_offset(&self, level: usize) -> usize {
+        2usize.pow(self.depth as u32) - 2usize.pow((self.depth + 1 - level) as u32)
     }
 
     /// Returns the index of the leaf with the matching username
@@ -222,3 +832,29 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> MerkleSumTree<N_CURRENCIES
         }
     }
 }
+
+/// Returns the balance of `entry` by which it is ranked, namely the balance of the cryptocurrency at `asset_index` or the sum of its balances if `asset_index` is `None`
+fn ranking_balance<const N_CURRENCIES: usize>(
+    entry: &Entry<N_CURRENCIES>,
+    asset_index: Option<usize>,
+) -> BigUint {
+    match asset_index {
+        Some(asset_index) => entry.balances()[asset_index].clone(),
+        None => entry.balances().iter().sum(),
+    }
+}
+
+/// Checks that there is a cryptocurrency label per balance of the entries
+fn check_cryptocurrencies_count<const N_CURRENCIES: usize>(
+    cryptocurrencies: &[Cryptocurrency],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if cryptocurrencies.len() != N_CURRENCIES {
+        return Err(format!(
+            "Expected {} cryptocurrencies but found {}",
+            N_CURRENCIES,
+            cryptocurrencies.len()
+        )
+        .into());
+    }
+    Ok(())
+}
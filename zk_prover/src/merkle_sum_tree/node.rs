@@ -1,19 +1,43 @@
-use crate::chips::poseidon::poseidon_spec::PoseidonSpec;
-use crate::merkle_sum_tree::utils::big_uint_to_fp;
-use halo2_gadgets::poseidon::primitives::{self as poseidon, ConstantLength};
+use crate::chips::poseidon::{poseidon_spec::PoseidonSpec, TreeSpec};
+use crate::merkle_sum_tree::utils::{big_uint_to_fp, serde_helpers};
 use halo2_proofs::halo2curves::bn256::Fr as Fp;
 use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq)]
+/// A node of the Merkle Sum Tree, made of a hash and #N_CURRENCIES balances.
+/// It is serialized as `{ "hash": "0x..", "balances": ["0x..", ...] }`, where each field element is a 0x-prefixed, big-endian, 32-byte hex string.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Node<const N_CURRENCIES: usize> {
+    #[serde(with = "serde_helpers::fp_hex")]
     pub hash: Fp,
+    #[serde(with = "serde_helpers::fp_hex_array")]
     pub balances: [Fp; N_CURRENCIES],
 }
+
+/// A middle node of the Merkle Sum Tree stored without its balances, as done by `MerkleSumTree` for the levels that don't hold full nodes when the `compact` feature is enabled.
+/// The balances of the node are recomputed from its descendants at the closest full level below when the node is read, trading the time to generate a proof for the memory held by the tree.
+#[cfg(feature = "compact")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactNode {
+    pub hash: Fp,
+}
+
 impl<const N_CURRENCIES: usize> Node<N_CURRENCIES> {
     /// Builds a leaf-level node of the MST
     /// The leaf node hash is equal to `H(username, balance[0], balance[1], ... balance[N_CURRENCIES - 1])`
     /// The balances are equal to `balance[0], balance[1], ... balance[N_CURRENCIES - 1]`
     pub fn leaf(username: &BigUint, balances: &[BigUint; N_CURRENCIES]) -> Node<N_CURRENCIES>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+    {
+        Node::leaf_with_spec::<PoseidonSpec>(username, balances)
+    }
+
+    /// Builds a leaf-level node of the MST as `leaf` does, hashed with the Poseidon specification `S`
+    pub fn leaf_with_spec<S: TreeSpec>(
+        username: &BigUint,
+        balances: &[BigUint; N_CURRENCIES],
+    ) -> Node<N_CURRENCIES>
     where
         [usize; N_CURRENCIES + 1]: Sized,
     {
@@ -23,13 +47,24 @@ impl<const N_CURRENCIES: usize> Node<N_CURRENCIES> {
             *balance = big_uint_to_fp(&balances[i - 1]);
         }
 
-        Node::leaf_node_from_preimage(&hash_preimage)
+        Node::leaf_node_from_preimage_with_spec::<S>(&hash_preimage)
     }
 
     /// Builds a "middle" (non-leaf-level) node of the MST
     /// The middle node hash is equal to `H(LeftChild.balance[0] + RightChild.balance[0], LeftChild.balance[1] + RightChild.balance[1], ..., LeftChild.balance[N_CURRENCIES - 1] + RightChild.balance[N_CURRENCIES - 1], LeftChild.hash, RightChild.hash)`
     /// The balances are equal to `LeftChild.balance[0] + RightChild.balance[0], LeftChild.balance[1] + RightChild.balance[1], ..., LeftChild.balance[N_CURRENCIES - 1] + RightChild.balance[N_CURRENCIES - 1]`
     pub fn middle(child_l: &Node<N_CURRENCIES>, child_r: &Node<N_CURRENCIES>) -> Node<N_CURRENCIES>
+    where
+        [(); N_CURRENCIES + 2]: Sized,
+    {
+        Node::middle_with_spec::<PoseidonSpec>(child_l, child_r)
+    }
+
+    /// Builds a "middle" (non-leaf-level) node of the MST as `middle` does, hashed with the Poseidon specification `S`
+    pub fn middle_with_spec<S: TreeSpec>(
+        child_l: &Node<N_CURRENCIES>,
+        child_r: &Node<N_CURRENCIES>,
+    ) -> Node<N_CURRENCIES>
     where
         [(); N_CURRENCIES + 2]: Sized,
     {
@@ -40,7 +75,7 @@ impl<const N_CURRENCIES: usize> Node<N_CURRENCIES> {
         hash_preimage[N_CURRENCIES] = child_l.hash;
         hash_preimage[N_CURRENCIES + 1] = child_r.hash;
 
-        Node::middle_node_from_preimage(&hash_preimage)
+        Node::middle_node_from_preimage_with_spec::<S>(&hash_preimage)
     }
 
     /// Returns an empty node where the hash is 0 and the balances are all 0
@@ -58,9 +93,17 @@ impl<const N_CURRENCIES: usize> Node<N_CURRENCIES> {
     where
         [usize; N_CURRENCIES + 1]: Sized,
     {
-        let hash =
-            poseidon::Hash::<Fp, PoseidonSpec, ConstantLength<{ N_CURRENCIES + 1 }>, 2, 1>::init()
-                .hash(preimage.clone());
+        Node::leaf_node_from_preimage_with_spec::<PoseidonSpec>(preimage)
+    }
+
+    /// Builds a leaf-level node of the MST from its hash preimage as `leaf_node_from_preimage` does, hashed with the Poseidon specification `S`
+    pub fn leaf_node_from_preimage_with_spec<S: TreeSpec>(
+        preimage: &[Fp; N_CURRENCIES + 1],
+    ) -> Node<N_CURRENCIES>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+    {
+        let hash = S::hash(preimage.clone());
         Node {
             hash,
             balances: preimage[1..].try_into().unwrap(),
@@ -74,9 +117,17 @@ impl<const N_CURRENCIES: usize> Node<N_CURRENCIES> {
     where
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        let hash =
-            poseidon::Hash::<Fp, PoseidonSpec, ConstantLength<{ N_CURRENCIES + 2 }>, 2, 1>::init()
-                .hash(preimage.clone());
+        Node::middle_node_from_preimage_with_spec::<PoseidonSpec>(preimage)
+    }
+
+    /// Builds a middle-level node of the MST from its hash preimage as `middle_node_from_preimage` does, hashed with the Poseidon specification `S`
+    pub fn middle_node_from_preimage_with_spec<S: TreeSpec>(
+        preimage: &[Fp; N_CURRENCIES + 2],
+    ) -> Node<N_CURRENCIES>
+    where
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let hash = S::hash(preimage.clone());
         Node {
             hash,
             balances: preimage[0..N_CURRENCIES].try_into().unwrap(),
@@ -0,0 +1,125 @@
+use crate::merkle_sum_tree::MerkleProof;
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+/// The number of Merkle paths cached by a tree unless set otherwise by `MerkleSumTree::with_proof_cache_capacity`
+pub const DEFAULT_PROOF_CACHE_CAPACITY: usize = 1024;
+
+/// The part of a `MerkleProof` computed by walking the tree from a leaf up to the root.
+/// The entry, the root and the cryptocurrencies are read from the tree when the proof is rebuilt, so that they are always up to date.
+#[derive(Debug, Clone)]
+struct MerklePath {
+    sibling_leaf_node_hash_preimage: Vec<Fp>,
+    sibling_middle_node_hash_preimages: Vec<Vec<Fp>>,
+    path_indices: Vec<Fp>,
+}
+
+/// The least recently used Merkle paths computed by `MerkleSumTree::generate_proof`, keyed by the index of their leaf.
+/// A capacity of 0 disables the cache.
+#[derive(Debug)]
+pub(crate) struct ProofCache {
+    capacity: usize,
+    paths: Option<Mutex<LruCache<usize, MerklePath>>>,
+}
+
+impl ProofCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        ProofCache {
+            capacity,
+            paths: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the cached path of the leaf at `index`, if any, as the sibling leaf node hash preimage, the sibling middle node hash preimages and the path indices of its proof
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn get<const N_CURRENCIES: usize>(
+        &self,
+        index: usize,
+    ) -> Option<([Fp; N_CURRENCIES + 1], Vec<[Fp; N_CURRENCIES + 2]>, Vec<Fp>)>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let path = self.paths.as_ref()?.lock().unwrap().get(&index)?.clone();
+
+        // The cached preimages have been taken from a proof of the same tree, so that their lengths match
+        let sibling_leaf_node_hash_preimage =
+            path.sibling_leaf_node_hash_preimage.try_into().unwrap();
+        let sibling_middle_node_hash_preimages = path
+            .sibling_middle_node_hash_preimages
+            .into_iter()
+            .map(|preimage| preimage.try_into().unwrap())
+            .collect();
+
+        Some((
+            sibling_leaf_node_hash_preimage,
+            sibling_middle_node_hash_preimages,
+            path.path_indices,
+        ))
+    }
+
+    /// Caches the path of `proof`, generated for the leaf at `index`
+    pub(crate) fn put<const N_CURRENCIES: usize>(
+        &self,
+        index: usize,
+        proof: &MerkleProof<N_CURRENCIES>,
+    ) where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if let Some(paths) = &self.paths {
+            paths.lock().unwrap().put(
+                index,
+                MerklePath {
+                    sibling_leaf_node_hash_preimage: proof.sibling_leaf_node_hash_preimage.to_vec(),
+                    sibling_middle_node_hash_preimages: proof
+                        .sibling_middle_node_hash_preimages
+                        .iter()
+                        .map(|preimage| preimage.to_vec())
+                        .collect(),
+                    path_indices: proof.path_indices.clone(),
+                },
+            );
+        }
+    }
+
+    /// Returns whether the path of the leaf at `index` is cached, without marking it as recently used
+    pub(crate) fn contains(&self, index: usize) -> bool {
+        self.paths
+            .as_ref()
+            .map_or(false, |paths| paths.lock().unwrap().contains(&index))
+    }
+
+    /// Drops the paths going through a sibling of the leaves at `changed_indices`.
+    /// The path of a leaf is made of the preimages of the siblings of the nodes from the leaf up to the root, and each other leaf lies under one of them,
+    /// so that a change to a leaf affects the path of every leaf but itself. Only the path of a leaf changed alone is kept.
+    pub(crate) fn invalidate(&self, changed_indices: &[usize]) {
+        if changed_indices.is_empty() {
+            return;
+        }
+        if let Some(paths) = &self.paths {
+            let mut paths = paths.lock().unwrap();
+            let kept_path = match changed_indices {
+                [index] => paths.pop(index).map(|path| (*index, path)),
+                _ => None,
+            };
+            paths.clear();
+            if let Some((index, path)) = kept_path {
+                paths.put(index, path);
+            }
+        }
+    }
+}
+
+impl Clone for ProofCache {
+    /// Returns an empty cache of the same capacity, so that cloning a tree doesn't copy its cached paths
+    fn clone(&self) -> Self {
+        ProofCache::new(self.capacity)
+    }
+}
@@ -0,0 +1,199 @@
+use crate::chips::poseidon::TreeSpec;
+use crate::merkle_sum_tree::utils::{big_uint_to_fp, fp_to_big_uint, serde_helpers};
+use crate::merkle_sum_tree::{Cryptocurrency, Entry, MerkleProof, Node, TreeError};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+
+/// The JSON representation of a node of the tree, its balances being decimal strings
+#[derive(Serialize, Deserialize)]
+struct NodeJson<const N_CURRENCIES: usize> {
+    #[serde(with = "serde_helpers::fp_hex")]
+    hash: Fp,
+    #[serde(with = "serde_helpers::big_uint_dec_array")]
+    balances: [BigUint; N_CURRENCIES],
+}
+
+impl<const N_CURRENCIES: usize> From<&Node<N_CURRENCIES>> for NodeJson<N_CURRENCIES> {
+    fn from(node: &Node<N_CURRENCIES>) -> Self {
+        NodeJson {
+            hash: node.hash,
+            balances: node.balances.map(fp_to_big_uint),
+        }
+    }
+}
+
+impl<const N_CURRENCIES: usize> NodeJson<N_CURRENCIES> {
+    /// Returns the node, or an error if one of its balances is not lower than the modulus of the field
+    fn to_node(&self) -> Result<Node<N_CURRENCIES>, TreeError> {
+        let mut balances = [Fp::zero(); N_CURRENCIES];
+        for (balance, decimal) in balances.iter_mut().zip(self.balances.iter()) {
+            *balance = big_uint_to_fp(decimal);
+            if fp_to_big_uint(*balance) != *decimal {
+                return Err(TreeError::InvalidProofJson(format!(
+                    "Balance {} is not in the field",
+                    decimal
+                )));
+            }
+        }
+        Ok(Node {
+            hash: self.hash,
+            balances,
+        })
+    }
+}
+
+/// The JSON representation of a sibling node on the path from the leaf to the root.
+/// Its hash preimage is carried along, as the inclusion circuit recomputes the node from it.
+#[derive(Serialize, Deserialize)]
+struct PathElementJson<const N_CURRENCIES: usize> {
+    #[serde(flatten)]
+    node: NodeJson<N_CURRENCIES>,
+    #[serde(with = "serde_helpers::fp_hex_vec")]
+    preimage: Vec<Fp>,
+}
+
+/// The canonical JSON representation of a `MerkleProof`, see `MerkleProof::to_json` for its layout
+#[derive(Serialize, Deserialize)]
+pub(super) struct MerkleProofJson<const N_CURRENCIES: usize> {
+    entry: Entry<N_CURRENCIES>,
+    #[serde(with = "serde_helpers::fp_hex")]
+    leaf_hash: Fp,
+    root: NodeJson<N_CURRENCIES>,
+    path_elements: Vec<PathElementJson<N_CURRENCIES>>,
+    path_indices: Vec<u8>,
+    #[serde(default)]
+    cryptocurrencies: Vec<Cryptocurrency>,
+}
+
+impl<const N_CURRENCIES: usize> MerkleProofJson<N_CURRENCIES>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    /// Builds the JSON representation of `proof`, whose nodes are hashed with the Poseidon specification `S`
+    pub(super) fn from_proof<S: TreeSpec>(proof: &MerkleProof<N_CURRENCIES>) -> Self {
+        let sibling_leaf_node = PathElementJson {
+            node: NodeJson::from(&Node::leaf_node_from_preimage_with_spec::<S>(
+                &proof.sibling_leaf_node_hash_preimage,
+            )),
+            preimage: proof.sibling_leaf_node_hash_preimage.to_vec(),
+        };
+        let sibling_middle_nodes =
+            proof
+                .sibling_middle_node_hash_preimages
+                .iter()
+                .map(|preimage| PathElementJson {
+                    node: NodeJson::from(&Node::middle_node_from_preimage_with_spec::<S>(preimage)),
+                    preimage: preimage.to_vec(),
+                });
+
+        MerkleProofJson {
+            entry: proof.entry.clone(),
+            leaf_hash: proof.entry.compute_leaf_with_spec::<S>().hash,
+            root: NodeJson::from(&proof.root),
+            path_elements: std::iter::once(sibling_leaf_node)
+                .chain(sibling_middle_nodes)
+                .collect(),
+            path_indices: proof
+                .path_indices
+                .iter()
+                .map(|index| u8::from(*index != Fp::zero()))
+                .collect(),
+            cryptocurrencies: proof.cryptocurrencies.clone(),
+        }
+    }
+
+    /// Returns the proof represented, checking that the leaf hash and the path elements match the entry and the preimages hashed with the Poseidon specification `S`.
+    ///
+    /// Returns a `TreeError::InvalidProofJson` if a path index is neither 0 nor 1, if there isn't one path element per path index,
+    /// if a preimage has the wrong length or if a hash or balances don't match the ones recomputed from the entry or the preimages.
+    pub(super) fn into_proof<S: TreeSpec>(self) -> Result<MerkleProof<N_CURRENCIES>, TreeError> {
+        let path_indices = self
+            .path_indices
+            .iter()
+            .map(|index| match index {
+                0 => Ok(Fp::zero()),
+                1 => Ok(Fp::one()),
+                _ => Err(TreeError::InvalidProofJson(format!(
+                    "Path index {} is neither 0 nor 1",
+                    index
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if self.path_elements.is_empty() || self.path_elements.len() != path_indices.len() {
+            return Err(TreeError::InvalidProofJson(format!(
+                "Expected one path element per path index, found {} path elements and {} path indices",
+                self.path_elements.len(),
+                path_indices.len()
+            )));
+        }
+
+        if self.leaf_hash != self.entry.compute_leaf_with_spec::<S>().hash {
+            return Err(TreeError::InvalidProofJson(
+                "The leaf hash is not the hash of the entry".to_string(),
+            ));
+        }
+
+        let preimage_len_error = |level: usize, expected: usize, found: usize| {
+            TreeError::InvalidProofJson(format!(
+                "Expected a preimage of {} elements for the path element at level {} but found {}",
+                expected, level, found
+            ))
+        };
+        let check_node = |level: usize,
+                          element: &PathElementJson<N_CURRENCIES>,
+                          node: Node<N_CURRENCIES>|
+         -> Result<(), TreeError> {
+            let expected = NodeJson::from(&node);
+            if expected.hash != element.node.hash || expected.balances != element.node.balances {
+                return Err(TreeError::InvalidProofJson(format!(
+                    "The path element at level {} doesn't match its preimage",
+                    level
+                )));
+            }
+            Ok(())
+        };
+
+        let mut path_elements = self.path_elements.into_iter();
+
+        let sibling_leaf_node = path_elements.next().unwrap();
+        let sibling_leaf_node_hash_preimage: [Fp; N_CURRENCIES + 1] = sibling_leaf_node
+            .preimage
+            .clone()
+            .try_into()
+            .map_err(|preimage: Vec<Fp>| preimage_len_error(0, N_CURRENCIES + 1, preimage.len()))?;
+        check_node(
+            0,
+            &sibling_leaf_node,
+            Node::leaf_node_from_preimage_with_spec::<S>(&sibling_leaf_node_hash_preimage),
+        )?;
+
+        let mut sibling_middle_node_hash_preimages = Vec::with_capacity(path_elements.len());
+        for (level, sibling_middle_node) in (1..).zip(path_elements) {
+            let preimage: [Fp; N_CURRENCIES + 2] = sibling_middle_node
+                .preimage
+                .clone()
+                .try_into()
+                .map_err(|preimage: Vec<Fp>| {
+                    preimage_len_error(level, N_CURRENCIES + 2, preimage.len())
+                })?;
+            check_node(
+                level,
+                &sibling_middle_node,
+                Node::middle_node_from_preimage_with_spec::<S>(&preimage),
+            )?;
+            sibling_middle_node_hash_preimages.push(preimage);
+        }
+
+        Ok(MerkleProof {
+            entry: self.entry,
+            root: self.root.to_node()?,
+            sibling_leaf_node_hash_preimage,
+            sibling_middle_node_hash_preimages,
+            path_indices,
+            cryptocurrencies: self.cryptocurrencies,
+        })
+    }
+}
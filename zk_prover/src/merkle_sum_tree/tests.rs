@@ -1,8 +1,15 @@
 #[cfg(test)]
 mod test {
 
-    use crate::merkle_sum_tree::utils::big_uint_to_fp;
-    use crate::merkle_sum_tree::{Entry, MerkleSumTree, Node, Tree};
+    use crate::merkle_sum_tree::utils::serde_helpers::fp_from_hex;
+    use crate::merkle_sum_tree::utils::{
+        big_uint_to_fp, csv_asset_columns, fp_to_big_uint, optimal_levels,
+    };
+    use crate::merkle_sum_tree::{
+        BuildStage, Entry, ForestMerkleSumTree, MerkleProof, MerkleSumTree, MerkleSumTreeBuilder,
+        Node, Tree, TreeError, DEFAULT_PROOF_CACHE_CAPACITY,
+    };
+    use halo2_proofs::halo2curves::bn256::Fr as Fp;
     use num_bigint::{BigUint, ToBigUint};
     use rand::Rng as _;
 
@@ -47,7 +54,13 @@ mod test {
         }
 
         // shouldn't create a proof for an entry that doesn't exist in the tree
-        assert!(merkle_tree.generate_proof(16).is_err());
+        assert_eq!(
+            merkle_tree
+                .generate_proof(16)
+                .unwrap_err()
+                .downcast_ref::<TreeError>(),
+            Some(&TreeError::IndexOutOfBounds { index: 16, len: 16 })
+        );
 
         // shouldn't verify a proof with a wrong leaf
         let invalid_entry = Entry::new(
@@ -65,6 +78,332 @@ mod test {
         assert!(!merkle_tree.verify_proof(&proof_invalid_2));
     }
 
+    #[test]
+    fn test_partial_proof_verification() {
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let depth = *merkle_tree.depth();
+
+        for user_index in 0..16 {
+            let proof = merkle_tree.generate_proof(user_index).unwrap();
+
+            // zero levels should give back the leaf itself
+            let (leaf_hash, _) = proof.verify_partial(0).unwrap();
+            assert_eq!(leaf_hash, merkle_tree.leaves()[user_index].hash);
+
+            // one level should give the parent of the leaf
+            let (parent_hash, parent_balances) = proof.verify_partial(1).unwrap();
+            let parent = &merkle_tree.nodes()[1][user_index / 2];
+            assert_eq!(parent_hash, parent.hash);
+            assert_eq!(parent_balances.map(|b| big_uint_to_fp(&b)), parent.balances);
+
+            // depth - 1 levels should give the child of the root that lies on the path
+            let (subtree_root_hash, _) = proof.verify_partial(depth - 1).unwrap();
+            assert_eq!(
+                subtree_root_hash,
+                merkle_tree.nodes()[depth - 1][user_index >> (depth - 1)].hash
+            );
+
+            // depth levels should give the full root
+            let (root_hash, root_balances) = proof.verify_partial(depth).unwrap();
+            assert_eq!(root_hash, merkle_tree.root().hash);
+            assert_eq!(
+                root_balances.map(|b| big_uint_to_fp(&b)),
+                merkle_tree.root().balances
+            );
+
+            // shouldn't verify more levels than the proof contains
+            assert!(proof.verify_partial(depth + 1).is_err());
+        }
+    }
+
+    #[test]
+    fn test_proof_json_round_trip() {
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        for index in [0, 7, 15] {
+            let proof = merkle_tree.generate_proof(index).unwrap();
+
+            let json = proof.to_json();
+            let deserialized_proof =
+                MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&json).unwrap();
+
+            assert_eq!(proof, deserialized_proof);
+            assert!(merkle_tree.verify_proof(&deserialized_proof));
+        }
+
+        // Field elements are encoded as 0x-prefixed 32-byte hex strings, balances as decimal strings and path indices as 0 or 1
+        let proof = merkle_tree.generate_proof(0).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&proof.to_json()).unwrap();
+        assert_eq!(json["entry"]["username"], "dxGaEAii");
+        assert_eq!(
+            json["entry"]["balances"],
+            serde_json::json!(["11888", "41163"])
+        );
+        assert_eq!(
+            fp_from_hex(json["leaf_hash"].as_str().unwrap()).unwrap(),
+            proof.entry.compute_leaf().hash
+        );
+        assert_eq!(json["path_indices"], serde_json::json!([0, 0, 0, 0]));
+        let root_hash = json["root"]["hash"].as_str().unwrap();
+        assert_eq!(root_hash.len(), 66);
+        assert_eq!(fp_from_hex(root_hash).unwrap(), merkle_tree.root().hash);
+        assert_eq!(
+            json["root"]["balances"][0],
+            fp_to_big_uint(merkle_tree.root().balances[0]).to_string()
+        );
+
+        // The path elements are the sibling nodes from the leaf to the root, along with their preimages
+        let sibling_leaf = merkle_tree.get_entry(1).compute_leaf();
+        assert_eq!(json["path_elements"].as_array().unwrap().len(), 4);
+        assert_eq!(
+            fp_from_hex(json["path_elements"][0]["hash"].as_str().unwrap()).unwrap(),
+            sibling_leaf.hash
+        );
+        assert_eq!(
+            json["path_elements"][0]["balances"],
+            serde_json::json!(merkle_tree
+                .get_entry(1)
+                .balances()
+                .iter()
+                .map(|balance| balance.to_string())
+                .collect::<Vec<_>>())
+        );
+        assert_eq!(
+            json["path_elements"][0]["preimage"]
+                .as_array()
+                .unwrap()
+                .len(),
+            N_CURRENCIES + 1
+        );
+        assert_eq!(
+            json["path_elements"][1]["preimage"]
+                .as_array()
+                .unwrap()
+                .len(),
+            N_CURRENCIES + 2
+        );
+
+        // A path index other than 0 or 1 is rejected
+        let mut invalid_json = json.clone();
+        invalid_json["path_indices"][2] = serde_json::json!(2);
+        let error = MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&invalid_json.to_string())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TreeError>(),
+            Some(TreeError::InvalidProofJson(_))
+        ));
+
+        // A path element that doesn't match its preimage is rejected
+        let mut invalid_json = json.clone();
+        invalid_json["path_elements"][1]["balances"][0] = serde_json::json!("1");
+        let error = MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&invalid_json.to_string())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TreeError>(),
+            Some(TreeError::InvalidProofJson(_))
+        ));
+
+        // A leaf hash that isn't the hash of the entry is rejected
+        let mut invalid_json = json.clone();
+        invalid_json["leaf_hash"] = json["root"]["hash"].clone();
+        let error = MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&invalid_json.to_string())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TreeError>(),
+            Some(TreeError::InvalidProofJson(_))
+        ));
+
+        // A field element that is not in the field is rejected
+        let mut invalid_json = json.clone();
+        invalid_json["root"]["hash"] = serde_json::json!(format!("0x{}", "f".repeat(64)));
+        assert!(
+            MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&invalid_json.to_string()).is_err()
+        );
+
+        // A proof with a wrong number of balances is rejected
+        let mut invalid_json = json.clone();
+        invalid_json["entry"]["balances"] = serde_json::json!(["11888"]);
+        assert!(
+            MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&invalid_json.to_string()).is_err()
+        );
+
+        // A hashed username that isn't the hash of the username is rejected
+        let mut invalid_json = json.clone();
+        invalid_json["entry"]["hashed_username"] = serde_json::json!("1");
+        assert!(
+            MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&invalid_json.to_string()).is_err()
+        );
+
+        // A balance out of the range of the circuit is rejected
+        let mut invalid_json = json;
+        let bound = BigUint::from(1u32) << (N_BYTES * 8);
+        invalid_json["entry"]["balances"][1] = serde_json::json!(bound.to_string());
+        let error = MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&invalid_json.to_string())
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TreeError>(),
+            Some(TreeError::BalanceOverflow { asset_index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_proof_json_schema() {
+        let schema: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string("schemas/merkle_proof.schema.json").unwrap(),
+        )
+        .unwrap();
+
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let proof = merkle_tree.generate_proof(3).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&proof.to_json()).unwrap();
+
+        // The fields of the serialized proof and of its entry are the ones described by the schema
+        let keys = |value: &serde_json::Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(&json), keys(&schema["properties"]));
+        for key in keys(&json["entry"]) {
+            assert!(schema["properties"]["entry"]["properties"]
+                .get(&key)
+                .is_some());
+        }
+        for required in schema["required"].as_array().unwrap() {
+            assert!(json.get(required.as_str().unwrap()).is_some());
+        }
+        for required in schema["properties"]["entry"]["required"]
+            .as_array()
+            .unwrap()
+        {
+            assert!(json["entry"].get(required.as_str().unwrap()).is_some());
+        }
+
+        // The path elements are described by the schema as well
+        let path_element_schema = &schema["$defs"]["path_element"];
+        for path_element in json["path_elements"].as_array().unwrap() {
+            assert_eq!(keys(path_element), keys(&path_element_schema["properties"]));
+        }
+        assert_eq!(
+            schema["properties"]["path_indices"]["items"]["enum"],
+            serde_json::json!([0, 1])
+        );
+    }
+
+    #[test]
+    fn test_salted_entry() {
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let entry = merkle_tree.entries()[0].clone();
+
+        let salted_entry_1 = Entry::<N_CURRENCIES>::new_salted(
+            entry.username().to_string(),
+            entry.balances().clone(),
+            Entry::<N_CURRENCIES>::random_salt(),
+        );
+        let salted_entry_2 = Entry::<N_CURRENCIES>::new_salted(
+            entry.username().to_string(),
+            entry.balances().clone(),
+            Entry::<N_CURRENCIES>::random_salt(),
+        );
+
+        // Entries differing only in their salt have different leaves, none of them being the unsalted leaf
+        assert_ne!(
+            salted_entry_1.compute_leaf().hash,
+            salted_entry_2.compute_leaf().hash
+        );
+        assert_ne!(
+            salted_entry_1.compute_leaf().hash,
+            entry.compute_leaf().hash
+        );
+        assert_eq!(
+            salted_entry_1.compute_leaf().balances,
+            entry.compute_leaf().balances
+        );
+
+        // The same salt gives the same leaf
+        let resalted_entry = Entry::<N_CURRENCIES>::new_salted(
+            entry.username().to_string(),
+            entry.balances().clone(),
+            salted_entry_1.salt().unwrap(),
+        );
+        assert_eq!(resalted_entry.compute_leaf(), salted_entry_1.compute_leaf());
+
+        // The salt is shared with the user within the proof, so that the leaf can be recomputed
+        let mut entries = merkle_tree.entries().to_vec();
+        entries[0] = salted_entry_1.clone();
+        let salted_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            merkle_tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+        let proof = salted_tree.generate_proof(0).unwrap();
+        assert!(salted_tree.verify_proof(&proof));
+
+        let json = proof.to_json();
+        let deserialized_proof = MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&json).unwrap();
+        assert_eq!(deserialized_proof.entry.salt(), salted_entry_1.salt());
+        assert!(salted_tree.verify_proof(&deserialized_proof));
+
+        // The unsalted entries are serialized without salt
+        let unsalted_proof = salted_tree.generate_proof(1).unwrap();
+        assert!(!unsalted_proof.to_json().contains("salt"));
+    }
+
+    #[test]
+    fn test_asset_names() {
+        // The names are inferred from the CSV header
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        assert_eq!(merkle_tree.asset_names(), vec!["ETH", "USDT"]);
+
+        // The labels are embedded in the proofs and survive a JSON round trip
+        let proof = merkle_tree.generate_proof(3).unwrap();
+        assert_eq!(proof.cryptocurrencies, merkle_tree.cryptocurrencies());
+        let deserialized_proof =
+            MerkleProof::<N_CURRENCIES>::from_json::<N_BYTES>(&proof.to_json()).unwrap();
+        assert_eq!(deserialized_proof.cryptocurrencies[1].name, "USDT");
+        assert_eq!(deserialized_proof.cryptocurrencies[1].chain, "ETH");
+
+        // The names can be set on a tree built without labels, without changing the root
+        let unlabelled_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            merkle_tree.entries().to_vec(),
+            vec![],
+            false,
+        )
+        .unwrap();
+        assert!(unlabelled_tree.asset_names().is_empty());
+        assert!(unlabelled_tree
+            .generate_proof(0)
+            .unwrap()
+            .cryptocurrencies
+            .is_empty());
+
+        let labelled_tree = unlabelled_tree.with_asset_names(&["BTC", "DAI"]).unwrap();
+        assert_eq!(labelled_tree.asset_names(), vec!["BTC", "DAI"]);
+        assert_eq!(labelled_tree.root(), merkle_tree.root());
+
+        // A mismatching number of labels is rejected
+        assert_eq!(
+            labelled_tree
+                .with_asset_names(&["BTC"])
+                .unwrap_err()
+                .to_string(),
+            "Expected 2 cryptocurrencies but found 1"
+        );
+        assert!(MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            merkle_tree.entries().to_vec(),
+            merkle_tree.cryptocurrencies()[..1].to_vec(),
+            false,
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_update_mst_leaf() {
         let merkle_tree_1 =
@@ -89,6 +428,674 @@ mod test {
             .unwrap();
         //The roots should match
         assert!(root_hash_1 == new_root.hash);
+        assert!(root_hash_1 == merkle_tree_2.root().hash);
+
+        // Every middle node should be equal to the ones of a tree built from scratch
+        for level in 1..=*merkle_tree_1.depth() {
+            for index in 0..2usize.pow((*merkle_tree_1.depth() - level) as u32) {
+                assert_eq!(
+                    merkle_tree_1.get_node(level, index).unwrap(),
+                    merkle_tree_2.get_node(level, index).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_proof_cache() {
+        let mut merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        // The same tree without cache, whose proofs are always computed by walking the tree
+        let mut uncached_merkle_tree = merkle_tree.clone().with_proof_cache_capacity(0);
+        assert_eq!(
+            merkle_tree.proof_cache_capacity(),
+            DEFAULT_PROOF_CACHE_CAPACITY
+        );
+
+        // A cache hit returns the same proof as a cache miss
+        for index in 0..16 {
+            assert!(!merkle_tree.is_proof_cached(index));
+            let proof = merkle_tree.generate_proof(index).unwrap();
+            assert!(merkle_tree.is_proof_cached(index));
+            assert_eq!(merkle_tree.generate_proof(index).unwrap(), proof);
+            assert_eq!(uncached_merkle_tree.generate_proof(index).unwrap(), proof);
+            assert!(!uncached_merkle_tree.is_proof_cached(index));
+        }
+
+        // Each other leaf lies under a sibling of the path of the updated leaf, so that only the path of the updated leaf is kept
+        let username = merkle_tree.get_entry(6).username().to_string();
+        let new_balances = [1000.to_biguint().unwrap(), 2000.to_biguint().unwrap()];
+        merkle_tree.update_leaf(&username, &new_balances).unwrap();
+        uncached_merkle_tree
+            .update_leaf(&username, &new_balances)
+            .unwrap();
+        for index in 0..16 {
+            assert_eq!(merkle_tree.is_proof_cached(index), index == 6);
+        }
+
+        // The kept path gives the proof of the updated entry against the new root
+        for index in 0..16 {
+            let proof = merkle_tree.generate_proof(index).unwrap();
+            assert_eq!(proof, uncached_merkle_tree.generate_proof(index).unwrap());
+            assert!(merkle_tree.verify_proof(&proof));
+        }
+        assert_eq!(
+            merkle_tree.generate_proof(6).unwrap().entry.balances(),
+            &new_balances
+        );
+
+        // The least recently used path is dropped when the cache is full
+        let merkle_tree = merkle_tree.with_proof_cache_capacity(2);
+        for index in 0..3 {
+            merkle_tree.generate_proof(index).unwrap();
+        }
+        assert!(!merkle_tree.is_proof_cached(0));
+        assert!(merkle_tree.is_proof_cached(1));
+        assert!(merkle_tree.is_proof_cached(2));
+    }
+
+    #[test]
+    fn test_rebuild_with() {
+        const N_ENTRIES: usize = 1 << 12;
+        let mut rng = rand::thread_rng();
+
+        let entries: Vec<Entry<N_CURRENCIES>> = (0..N_ENTRIES)
+            .map(|i| {
+                Entry::new(
+                    format!("user_{}", i),
+                    std::array::from_fn(|_| rng.gen_range(0..100_000u64).to_biguint().unwrap()),
+                )
+            })
+            .collect();
+
+        let mut merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(entries.clone(), vec![], false)
+                .unwrap();
+
+        // Change the balances of 3% of the entries
+        let mut updated_entries = entries;
+        for _ in 0..N_ENTRIES * 3 / 100 {
+            let index = rng.gen_range(0..N_ENTRIES);
+            updated_entries[index] = Entry::new(
+                updated_entries[index].username().to_string(),
+                std::array::from_fn(|_| rng.gen_range(0..100_000u64).to_biguint().unwrap()),
+            );
+        }
+
+        let recomputed_nodes = merkle_tree.rebuild_with(updated_entries.clone()).unwrap();
+
+        let fresh_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(updated_entries, vec![], false)
+                .unwrap();
+
+        // Only the paths of the changed entries should be recomputed, out of the 2^12 - 1 middle nodes
+        assert!(recomputed_nodes > 0);
+        assert!(recomputed_nodes <= (N_ENTRIES * 3 / 100) * 12);
+        assert!(recomputed_nodes < N_ENTRIES - 1);
+
+        assert_eq!(merkle_tree.root(), fresh_tree.root());
+        for _ in 0..8 {
+            let index = rng.gen_range(0..N_ENTRIES);
+            let proof = merkle_tree.generate_proof(index).unwrap();
+            let fresh_proof = fresh_tree.generate_proof(index).unwrap();
+
+            assert_eq!(proof.entry, fresh_proof.entry);
+            assert_eq!(proof.root, fresh_proof.root);
+            assert_eq!(
+                proof.sibling_leaf_node_hash_preimage,
+                fresh_proof.sibling_leaf_node_hash_preimage
+            );
+            assert_eq!(
+                proof.sibling_middle_node_hash_preimages,
+                fresh_proof.sibling_middle_node_hash_preimages
+            );
+            assert_eq!(proof.path_indices, fresh_proof.path_indices);
+            assert!(merkle_tree.verify_proof(&proof));
+        }
+
+        // Rebuilding with a number of entries that changes the depth rebuilds the whole tree
+        let entries = merkle_tree.entries()[..N_ENTRIES / 2].to_vec();
+        assert_eq!(
+            merkle_tree.rebuild_with(entries.clone()).unwrap(),
+            N_ENTRIES / 2 - 1
+        );
+        let fresh_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(entries, vec![], false).unwrap();
+        assert_eq!(merkle_tree.root(), fresh_tree.root());
+    }
+
+    #[test]
+    fn test_get_node() {
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        let depth = *merkle_tree.depth();
+
+        // Every middle node should be computed from its children
+        for level in 1..=depth {
+            for index in 0..2usize.pow((depth - level) as u32) {
+                let left_child = merkle_tree.get_node(level - 1, 2 * index).unwrap();
+                let right_child = merkle_tree.get_node(level - 1, 2 * index + 1).unwrap();
+                assert_eq!(
+                    merkle_tree.get_node(level, index).unwrap(),
+                    Node::middle(&left_child, &right_child)
+                );
+            }
+        }
+
+        // The node at the top level should be the root
+        assert_eq!(merkle_tree.get_node(depth, 0).unwrap(), *merkle_tree.root());
+
+        // Leaves should be computed from the entries
+        assert_eq!(
+            merkle_tree.get_node(0, 3).unwrap(),
+            merkle_tree.entries()[3].compute_leaf()
+        );
+
+        // shouldn't fetch a node outside of the tree
+        assert_eq!(
+            merkle_tree
+                .get_node(0, 16)
+                .unwrap_err()
+                .downcast_ref::<TreeError>(),
+            Some(&TreeError::IndexOutOfBounds { index: 16, len: 16 })
+        );
+        assert_eq!(
+            merkle_tree
+                .get_node(1, 8)
+                .unwrap_err()
+                .downcast_ref::<TreeError>(),
+            Some(&TreeError::IndexOutOfBounds { index: 8, len: 8 })
+        );
+        assert!(merkle_tree.get_node(depth + 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_builder_progress() {
+        let mut reports: Vec<(BuildStage, usize, usize)> = vec![];
+
+        let merkle_tree = MerkleSumTreeBuilder::<N_CURRENCIES, N_BYTES>::new()
+            .with_progress(|stage, done, total| reports.push((stage, done, total)))
+            .from_csv("../csv/entry_16.csv")
+            .unwrap();
+
+        // The tree should be the same as the one built without progress reporting
+        let expected_merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        assert_eq!(merkle_tree.root(), expected_merkle_tree.root());
+
+        // The stages should be reported in order
+        let stages = [
+            BuildStage::CsvParsing,
+            BuildStage::LeafHashing,
+            BuildStage::MiddleNodeHashing,
+        ];
+        let stage_positions: Vec<usize> = reports
+            .iter()
+            .map(|(stage, _, _)| stages.iter().position(|s| s == stage).unwrap())
+            .collect();
+        assert!(stage_positions.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        for stage in stages {
+            let stage_reports: Vec<(usize, usize)> = reports
+                .iter()
+                .filter(|(s, _, _)| *s == stage)
+                .map(|(_, done, total)| (*done, *total))
+                .collect();
+
+            assert!(!stage_reports.is_empty());
+            // The counts should be monotonically increasing and never exceed the total
+            assert!(stage_reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            assert!(stage_reports.iter().all(|(done, total)| done <= total));
+        }
+
+        // The hashing stages should be reported as completed
+        assert_eq!(
+            reports[reports.len() - 1],
+            (BuildStage::MiddleNodeHashing, 4, 4)
+        );
+        assert!(reports.contains(&(BuildStage::LeafHashing, 16, 16)));
+
+        // There is a report per parsed record and per level of the tree
+        assert_eq!(
+            reports
+                .iter()
+                .filter(|(s, _, _)| *s == BuildStage::CsvParsing)
+                .count(),
+            16
+        );
+        assert_eq!(
+            reports
+                .iter()
+                .filter(|(s, _, _)| *s == BuildStage::MiddleNodeHashing)
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_csv_asset_columns() {
+        let asset_columns = csv_asset_columns("../csv/entry_16.csv").unwrap();
+        assert_eq!(asset_columns, vec!["balance_ETH_ETH", "balance_USDT_ETH"]);
+
+        // The number of balance columns matches N_CURRENCIES
+        assert!(MerkleSumTree::<2, N_BYTES>::from_csv("../csv/entry_16.csv").is_ok());
+
+        // The CSV file has more balance columns than N_CURRENCIES
+        let too_many_columns = MerkleSumTree::<1, N_BYTES>::from_csv("../csv/entry_16.csv");
+        assert_eq!(
+            too_many_columns.unwrap_err().to_string(),
+            "Expected 1 balance columns but found 2, extra columns: balance_USDT_ETH"
+        );
+
+        // The CSV file has fewer balance columns than N_CURRENCIES
+        let too_few_columns = MerkleSumTree::<3, N_BYTES>::from_csv("../csv/entry_16.csv");
+        assert_eq!(
+            too_few_columns.unwrap_err().to_string(),
+            "Expected 3 balance columns but found 2, missing 1 balance columns besides: balance_ETH_ETH, balance_USDT_ETH"
+        );
+    }
+
+    #[test]
+    fn test_entry_balance_range() {
+        let bound = BigUint::from(1u32) << (N_BYTES * 8);
+        let max_balance = &bound - 1u32;
+
+        // A balance equal to 2^(N_BYTES*8) - 1 is in range
+        assert!(Entry::<N_CURRENCIES>::new_checked::<N_BYTES>(
+            "alice".to_string(),
+            [max_balance.clone(), 0.to_biguint().unwrap()],
+        )
+        .is_ok());
+
+        // A balance equal to 2^(N_BYTES*8) is not in range
+        let out_of_range = Entry::<N_CURRENCIES>::new_checked::<N_BYTES>(
+            "alice".to_string(),
+            [0.to_biguint().unwrap(), bound.clone()],
+        );
+        let error = out_of_range.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TreeError>(),
+            Some(&TreeError::BalanceOverflow {
+                username: "alice".to_string(),
+                asset_index: 1,
+                balance: bound.clone(),
+                n_bytes: N_BYTES
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Balance {} of cryptocurrency 1 for user alice is not in range [0, 2^64 - 1]",
+                bound
+            )
+        );
+
+        // The CSV parser reports the line of the entry that is not in range
+        let overflow_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16_overflow.csv");
+        let error = overflow_tree.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TreeError>(),
+            Some(TreeError::InvalidEntry { line: 2, .. })
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Invalid entry at line 2: Balance 5192296858534827628530496329220096 of cryptocurrency 0 for user dxGaEAii is not in range [0, 2^64 - 1]"
+        );
+
+        // A tree built from checked entries matches the one built from the CSV file
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let entries = merkle_tree
+            .entries()
+            .iter()
+            .map(|entry| {
+                Entry::new_checked::<N_BYTES>(
+                    entry.username().to_string(),
+                    entry.balances().clone(),
+                )
+                .unwrap()
+            })
+            .collect();
+        let checked_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(entries, vec![], false).unwrap();
+        assert_eq!(merkle_tree.root(), checked_tree.root());
+    }
+
+    #[test]
+    fn test_entry_new_normalized() {
+        // 1 ETH with 18 decimals and 1 USDC with 6 decimals, namely 1 000 000 of its smallest unit
+        let one_eth = BigUint::from(10u32).pow(18);
+        let one_usdc = BigUint::from(1_000_000u32);
+
+        let entry = Entry::<N_CURRENCIES>::new_normalized(
+            "alice",
+            &[(one_eth.clone(), 18), (one_usdc.clone(), 6)],
+        )
+        .unwrap();
+        assert_eq!(entry.balances()[0], one_eth);
+        assert_eq!(entry.balances()[1], one_eth);
+        assert_eq!(entry.username(), "alice");
+        assert_eq!(
+            entry.compute_leaf().hash,
+            Entry::<N_CURRENCIES>::new("alice".to_string(), [one_eth.clone(), one_eth.clone()])
+                .compute_leaf()
+                .hash
+        );
+
+        // The balances are normalized to the largest decimals present, not to a fixed precision
+        let entry = Entry::<N_CURRENCIES>::new_normalized(
+            "bob",
+            &[(one_usdc.clone(), 6), (1u32.into(), 8)],
+        )
+        .unwrap();
+        assert_eq!(entry.balances()[0], BigUint::from(100_000_000u32));
+        assert_eq!(entry.balances()[1], BigUint::from(1u32));
+
+        // There must be one balance per cryptocurrency
+        let error = Entry::<N_CURRENCIES>::new_normalized("carl", &[(one_eth, 18)]).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TreeError>(),
+            Some(&TreeError::BalanceCountMismatch {
+                expected: N_CURRENCIES,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_duplicate_entries() {
+        // The last record of the CSV file has the same username as the third one
+        let duplicate_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16_duplicate.csv");
+        let error = duplicate_tree.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TreeError>(),
+            Some(&TreeError::DuplicateUsername {
+                username: "lAhWlEWZ".to_string(),
+                first_line: 4,
+                line: 17
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "Duplicate entry for user lAhWlEWZ at lines 4 and 17"
+        );
+
+        // The duplicate records are merged into the entry of the first occurrence
+        let merged_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv_merge_duplicates(
+            "../csv/entry_16_duplicate.csv",
+        )
+        .unwrap();
+
+        let index = merged_tree.index_of_username("lAhWlEWZ").unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(
+            merged_tree.entries()[index].balances(),
+            &[54130.to_biguint().unwrap(), 33786.to_biguint().unwrap()]
+        );
+
+        // The merged tree has 15 entries padded with a zero entry, and the same total balances as the tree without duplicates
+        assert_eq!(
+            merged_tree.entries()[15],
+            Entry::<N_CURRENCIES>::zero_entry()
+        );
+        assert_eq!(merged_tree.root().balances, [556862.into(), 556862.into()]);
+
+        let proof = merged_tree.generate_proof(index).unwrap();
+        assert!(merged_tree.verify_proof(&proof));
+    }
+
+    #[test]
+    fn test_merge_duplicate_entries() {
+        let csv_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        // Two more records for lAhWlEWZ and one more for dxGaEAii, e.g. coming from another data source
+        let mut entries = csv_tree.entries().to_vec();
+        entries.push(Entry::new(
+            "lAhWlEWZ".to_string(),
+            [100.to_biguint().unwrap(), 200.to_biguint().unwrap()],
+        ));
+        entries.push(Entry::new(
+            "dxGaEAii".to_string(),
+            [300.to_biguint().unwrap(), 0.to_biguint().unwrap()],
+        ));
+        entries.push(Entry::new(
+            "lAhWlEWZ".to_string(),
+            [1.to_biguint().unwrap(), 2.to_biguint().unwrap()],
+        ));
+
+        let mut merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(entries, vec![], false).unwrap();
+        assert_eq!(*merkle_tree.depth(), 5);
+        let root_balances = merkle_tree.root().balances;
+
+        let merged_entries = merkle_tree.merge_duplicate_entries().unwrap();
+        assert_eq!(merged_entries.len(), 2);
+
+        // The groups are listed in the order of the first occurrence of their username
+        assert_eq!(merged_entries[0].username, "dxGaEAii");
+        assert_eq!(
+            merged_entries[0].old_entries,
+            vec![
+                [11888.to_biguint().unwrap(), 41163.to_biguint().unwrap()],
+                [300.to_biguint().unwrap(), 0.to_biguint().unwrap()],
+            ]
+        );
+        assert_eq!(
+            merged_entries[0].merged_balances,
+            [12188.to_biguint().unwrap(), 41163.to_biguint().unwrap()]
+        );
+        assert_eq!(merged_entries[1].username, "lAhWlEWZ");
+        assert_eq!(merged_entries[1].old_entries.len(), 3);
+        assert_eq!(
+            merged_entries[1].merged_balances,
+            [18752.to_biguint().unwrap(), 2289.to_biguint().unwrap()]
+        );
+
+        // The 16 users fit again in 16 leaves, and the root keeps the sum of all the original balances
+        assert_eq!(*merkle_tree.depth(), 4);
+        assert_eq!(merkle_tree.entries().len(), 16);
+        assert_eq!(merkle_tree.root().balances, root_balances);
+
+        let index = merkle_tree.index_of_username("lAhWlEWZ").unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(
+            merkle_tree.entries()[index].balances(),
+            &merged_entries[1].merged_balances
+        );
+
+        // The tree is the same as the one built from the merged entries
+        let rebuilt_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            merkle_tree.entries().to_vec(),
+            vec![],
+            false,
+        )
+        .unwrap();
+        assert_eq!(merkle_tree.root(), rebuilt_tree.root());
+
+        let proof = merkle_tree.generate_proof(index).unwrap();
+        assert!(merkle_tree.verify_proof(&proof));
+
+        // Merging again finds no duplicate
+        assert!(merkle_tree.merge_duplicate_entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mst_from_jsonl() {
+        let csv_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let jsonl_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_jsonl("../csv/entry_16.jsonl").unwrap();
+
+        assert_eq!(jsonl_tree.root(), csv_tree.root());
+        assert_eq!(jsonl_tree.entries(), csv_tree.entries());
+
+        // The duplicate usernames are rejected as in the CSV file, the lines being counted from the first record
+        let duplicate_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_jsonl("../csv/entry_16_duplicate.jsonl");
+        assert_eq!(
+            duplicate_tree.unwrap_err().downcast_ref::<TreeError>(),
+            Some(&TreeError::DuplicateUsername {
+                username: "lAhWlEWZ".to_string(),
+                first_line: 3,
+                line: 16
+            })
+        );
+
+        let path = std::env::temp_dir().join(format!("entries_{}.jsonl", std::process::id()));
+
+        // Balances can be given as decimal strings, and are range checked
+        std::fs::write(
+            &path,
+            "{\"username\":\"alice\",\"balances\":[\"100\",200]}\n{\"username\":\"bob\",\"balances\":[\"18446744073709551616\",0]}\n",
+        )
+        .unwrap();
+        let out_of_range_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_jsonl(path.to_str().unwrap());
+        assert!(matches!(
+            out_of_range_tree.unwrap_err().downcast_ref::<TreeError>(),
+            Some(TreeError::InvalidEntry { line: 2, .. })
+        ));
+
+        std::fs::write(
+            &path,
+            "{\"username\":\"alice\",\"balances\":[\"100\",200]}\n{\"username\":\"bob\",\"balances\":[1]}\n",
+        )
+        .unwrap();
+        let missing_balance_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_jsonl(path.to_str().unwrap());
+        let error = missing_balance_tree.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<TreeError>(),
+            Some(&TreeError::InvalidEntry {
+                line: 2,
+                reason: "Expected 2 balances but found 1".to_string()
+            })
+        );
+        assert_eq!(
+            error.to_string(),
+            "Invalid entry at line 2: Expected 2 balances but found 1"
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let path = std::env::temp_dir().join(format!("entries_{}.csv", std::process::id()));
+
+        // The tree is padded with zero entries, which are left out of the file
+        for tree in [
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_13.csv").unwrap(),
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap(),
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv_sorted("../csv/entry_16.csv").unwrap(),
+        ] {
+            tree.to_csv(&path).unwrap();
+            let written_tree =
+                MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(path.to_str().unwrap()).unwrap();
+
+            assert_eq!(written_tree.root(), tree.root());
+            assert_eq!(written_tree.entries(), tree.entries());
+            assert_eq!(written_tree.cryptocurrencies(), tree.cryptocurrencies());
+        }
+
+        let tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        tree.to_csv(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            std::fs::read_to_string("../csv/entry_16.csv").unwrap()
+        );
+
+        // The balance columns of a tree without cryptocurrency labels are named after their index
+        let unlabelled_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_jsonl("../csv/entry_16.jsonl").unwrap();
+        unlabelled_tree.to_csv(&path).unwrap();
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("username,balance_asset0_unknown,balance_asset1_unknown\n"));
+
+        let written_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(path.to_str().unwrap()).unwrap();
+        assert_eq!(written_tree.root().hash, unlabelled_tree.root().hash);
+        assert_eq!(written_tree.entries(), unlabelled_tree.entries());
+
+        // The salt of an entry has no column
+        let mut entries = tree.entries().to_vec();
+        entries[0] = Entry::new_salted(
+            entries[0].username().to_string(),
+            entries[0].balances().clone(),
+            Entry::<N_CURRENCIES>::random_salt(),
+        );
+        let salted_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries,
+            tree.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+        assert!(matches!(
+            salted_tree
+                .to_csv(&path)
+                .unwrap_err()
+                .downcast_ref::<TreeError>(),
+            Some(TreeError::CsvExport(_))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_zero_balance_entry() {
+        let zero_balance_entry = Entry::new(
+            "alice".to_string(),
+            [0.to_biguint().unwrap(), 0.to_biguint().unwrap()],
+        );
+
+        // An entry with all zero balances is not a zero entry, as its username is not zero
+        assert_ne!(zero_balance_entry, Entry::<N_CURRENCIES>::zero_entry());
+        assert_ne!(
+            zero_balance_entry.compute_leaf().hash,
+            Entry::<N_CURRENCIES>::zero_entry().compute_leaf().hash
+        );
+
+        let mut entries = vec![zero_balance_entry.clone()];
+        entries.extend((0..3).map(|i| {
+            Entry::new(
+                format!("user_{}", i),
+                [(i + 1).to_biguint().unwrap(), (i + 2).to_biguint().unwrap()],
+            )
+        }));
+
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(entries, vec![], false).unwrap();
+
+        assert_eq!(merkle_tree.entries()[0], zero_balance_entry);
+        assert_eq!(merkle_tree.index_of_username("alice").unwrap(), 0);
+        assert_eq!(merkle_tree.root().balances, [6.into(), 9.into()]);
+
+        let proof = merkle_tree.generate_proof(0).unwrap();
+        assert!(merkle_tree.verify_proof(&proof));
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(vec![], vec![], false).unwrap();
+
+        // The empty tree is padded with zero entries up to a single level of middle nodes
+        assert_eq!(*merkle_tree.depth(), 1);
+        assert_eq!(merkle_tree.entries().len(), 2);
+
+        let zero_leaf = Entry::<N_CURRENCIES>::zero_entry().compute_leaf();
+        let root = merkle_tree.root();
+        assert_eq!(root.balances, [0.into(), 0.into()]);
+        assert_ne!(root.hash, 0.into());
+        assert_eq!(root.hash, Node::middle(&zero_leaf, &zero_leaf).hash);
+
+        let proof = merkle_tree.generate_proof(0).unwrap();
+        assert!(merkle_tree.verify_proof(&proof));
     }
 
     #[test]
@@ -126,6 +1133,58 @@ mod test {
         assert!(old_root_hash != new_root_hash);
     }
 
+    #[test]
+    fn test_entries_ranked_by_balance() {
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+
+        for asset_index in [Some(0), Some(1), None] {
+            let balance = |entry: &Entry<N_CURRENCIES>| match asset_index {
+                Some(asset_index) => entry.balances()[asset_index].clone(),
+                None => entry.balances().iter().sum::<BigUint>(),
+            };
+
+            let ranked_entries = merkle_tree.entries_ranked_by_balance(asset_index);
+            assert_eq!(ranked_entries.len(), merkle_tree.entries().len());
+
+            // The first entry has the highest balance and the balances are descending
+            let highest_balance = merkle_tree.entries().iter().map(balance).max().unwrap();
+            assert_eq!(balance(ranked_entries[0].1), highest_balance);
+            for pair in ranked_entries.windows(2) {
+                assert!(balance(pair[0].1) >= balance(pair[1].1));
+            }
+            for (index, entry) in ranked_entries.iter() {
+                assert_eq!(merkle_tree.get_entry(*index), *entry);
+            }
+
+            // The top entries are the first ranked entries
+            let top_entries = merkle_tree.top_n_entries(5, asset_index);
+            assert_eq!(top_entries, ranked_entries[..5]);
+
+            assert_eq!(merkle_tree.top_n_entries(100, asset_index), ranked_entries);
+            assert!(merkle_tree.top_n_entries(0, asset_index).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_optimal_levels() {
+        assert_eq!(optimal_levels(16), 4);
+        assert_eq!(optimal_levels(17), 5);
+        assert_eq!(optimal_levels(1 << 20), 20);
+
+        // A tree has at least one level
+        assert_eq!(optimal_levels(1), 1);
+        assert_eq!(optimal_levels(2), 1);
+
+        // The tree of the csv file fits its 16 entries
+        let merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        assert_eq!(
+            optimal_levels(merkle_tree.entries().len()),
+            *merkle_tree.depth()
+        );
+    }
+
     #[test]
     fn test_big_uint_conversion() {
         let big_uint = 3.to_biguint().unwrap();
@@ -227,7 +1286,13 @@ mod test {
         }
 
         // shouldn't create a proof for an entry that doesn't exist in the tree
-        assert!(merkle_tree.generate_proof(16).is_err());
+        assert_eq!(
+            merkle_tree
+                .generate_proof(16)
+                .unwrap_err()
+                .downcast_ref::<TreeError>(),
+            Some(&TreeError::IndexOutOfBounds { index: 16, len: 16 })
+        );
     }
 
     #[test]
@@ -259,6 +1324,143 @@ mod test {
         }
 
         // shouldn't create a proof for an entry that doesn't exist in the tree
-        assert!(merkle_tree.generate_proof(32).is_err());
+        assert_eq!(
+            merkle_tree
+                .generate_proof(32)
+                .unwrap_err()
+                .downcast_ref::<TreeError>(),
+            Some(&TreeError::IndexOutOfBounds { index: 32, len: 32 })
+        );
+    }
+
+    #[cfg(feature = "compact")]
+    #[test]
+    fn test_compact_tree_matches_full_tree() {
+        use crate::merkle_sum_tree::utils::{
+            build_leaves_from_entries, build_merkle_tree_from_leaves,
+        };
+        use crate::merkle_sum_tree::FULL_NODE_LEVEL_INTERVAL;
+
+        // The 17 entries span 5 levels of middle nodes, so that the balances are read from the entries below level 4 and from the full nodes of level 4 above it
+        let mut merkle_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_17.csv").unwrap();
+        assert_eq!(*merkle_tree.depth(), FULL_NODE_LEVEL_INTERVAL + 1);
+
+        // The middle nodes with their balances, as built without the compact storage
+        let full_nodes = |merkle_tree: &MerkleSumTree<N_CURRENCIES, N_BYTES>| {
+            let leaves = build_leaves_from_entries(merkle_tree.entries());
+            build_merkle_tree_from_leaves(&leaves, *merkle_tree.depth()).unwrap()
+        };
+
+        let assert_matches_full_tree = |merkle_tree: &MerkleSumTree<N_CURRENCIES, N_BYTES>| {
+            let depth = *merkle_tree.depth();
+            let (root, nodes) = full_nodes(merkle_tree);
+            assert_eq!(*merkle_tree.root(), root);
+
+            let mut offset = 0;
+            for level in 1..=depth {
+                let level_size = 1 << (depth - level);
+                for index in 0..level_size {
+                    assert_eq!(
+                        merkle_tree.get_node(level, index).unwrap(),
+                        nodes[offset + index]
+                    );
+                }
+                offset += level_size;
+            }
+
+            // The proofs are built out of the nodes, so that they are identical to the ones of the full tree
+            for index in 0..1 << depth {
+                let proof = merkle_tree.generate_proof(index).unwrap();
+                assert_eq!(proof.root, root);
+                assert!(merkle_tree.verify_proof(&proof));
+            }
+        };
+
+        assert_matches_full_tree(&merkle_tree);
+
+        // The hashes are kept up to date when an entry is updated
+        let username = merkle_tree.entries()[5].username().to_string();
+        merkle_tree
+            .update_leaf(
+                &username,
+                &[1000u32.to_biguint().unwrap(), 2000u32.to_biguint().unwrap()],
+            )
+            .unwrap();
+        assert_matches_full_tree(&merkle_tree);
+    }
+
+    #[test]
+    fn test_forest_merkle_sum_tree() {
+        const LEVELS: usize = 4;
+
+        let sub_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16.csv").unwrap();
+        let sub_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_16_switched_order.csv")
+                .unwrap();
+
+        let forest = ForestMerkleSumTree::<LEVELS, N_CURRENCIES, N_BYTES>::from_sub_trees(vec![
+            sub_tree_1.clone(),
+            sub_tree_2.clone(),
+        ])
+        .unwrap();
+
+        // The top-level tree has the two sub-tree roots as leaves
+        assert_eq!(forest.top_depth(), 1);
+        assert_eq!(forest.depth(), 5);
+        assert_eq!(
+            *forest.root(),
+            Node::middle(sub_tree_1.root(), sub_tree_2.root())
+        );
+
+        // should create a valid proof for a leaf of each sub-tree
+        for (global_index, sub_tree, local_index) in [(3, &sub_tree_1, 3), (16 + 9, &sub_tree_2, 9)]
+        {
+            let proof = forest.generate_proof(global_index).unwrap();
+            assert_eq!(proof.sub_tree_index, global_index / 16);
+            assert_eq!(proof.sub_tree_proof.root, *sub_tree.root());
+            assert_eq!(proof.sub_tree_proof.entry, *sub_tree.get_entry(local_index));
+            assert!(forest.verify_proof(&proof));
+
+            // the flattened proof is a proof of the entry against the root of the forest
+            let merkle_proof = proof.to_merkle_proof();
+            assert_eq!(merkle_proof.path_indices.len(), 5);
+            assert_eq!(merkle_proof.sibling_middle_node_hash_preimages.len(), 4);
+            assert_eq!(merkle_proof.root, *forest.root());
+
+            // should fail to verify a proof whose sub-tree root isn't committed in the forest
+            let mut invalid_proof = proof.clone();
+            invalid_proof.path_indices[0] = Fp::one() - invalid_proof.path_indices[0];
+            assert!(!forest.verify_proof(&invalid_proof));
+        }
+
+        // shouldn't create a proof for a leaf outside of the sub-trees
+        assert_eq!(
+            forest
+                .generate_proof(32)
+                .unwrap_err()
+                .downcast_ref::<TreeError>(),
+            Some(&TreeError::IndexOutOfBounds { index: 32, len: 32 })
+        );
+
+        // a forest of three sub-trees is padded with an empty sub-tree
+        let forest = ForestMerkleSumTree::<LEVELS, N_CURRENCIES, N_BYTES>::from_sub_trees(vec![
+            sub_tree_1.clone(),
+            sub_tree_2,
+            sub_tree_1,
+        ])
+        .unwrap();
+        assert_eq!(forest.top_depth(), 2);
+        let proof = forest.generate_proof(32 + 5).unwrap();
+        assert!(forest.verify_proof(&proof));
+
+        // should fail to build a forest of sub-trees of a different depth
+        let sub_tree_3 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("../csv/entry_17.csv").unwrap();
+        assert!(
+            ForestMerkleSumTree::<LEVELS, N_CURRENCIES, N_BYTES>::from_sub_trees(vec![sub_tree_3])
+                .is_err()
+        );
     }
 }
@@ -1,18 +1,46 @@
+use crate::chips::poseidon::{poseidon_spec::PoseidonSpec, TreeSpec};
 use crate::merkle_sum_tree::utils::big_uint_to_fp;
 use crate::merkle_sum_tree::Cryptocurrency;
-use crate::merkle_sum_tree::{Entry, MerkleProof, Node};
+use crate::merkle_sum_tree::{Entry, MerkleProof, Node, TreeError};
 use halo2_proofs::halo2curves::bn256::Fr as Fp;
 
 /// A trait representing the basic operations for a Merkle-Sum-like Tree.
-pub trait Tree<const N_CURRENCIES: usize> {
+/// Trees are `Send + Sync` so that a tree can be shared by the threads generating proofs.
+/// The nodes are hashed with the Poseidon specification `S`, which must be the one of the circuit verifying the proofs of the tree.
+pub trait Tree<const N_CURRENCIES: usize, S: TreeSpec = PoseidonSpec>: Send + Sync {
     /// Returns a reference to the root node.
     fn root(&self) -> &Node<N_CURRENCIES>;
 
     /// Returns the depth of the tree.
     fn depth(&self) -> &usize;
 
-    /// Returns a slice of the nodes.
-    fn nodes(&self) -> &[Vec<Node<N_CURRENCIES>>];
+    /// Returns the node at the given `level` and `index`. Level 0 is the leaf level and level `depth` is the root level.
+    fn get_node(
+        &self,
+        level: usize,
+        index: usize,
+    ) -> Result<Node<N_CURRENCIES>, Box<dyn std::error::Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized;
+
+    /// Returns the nodes of the tree level by level, from the leaves at level 0 up to the root.
+    /// The nodes are read one by one with `get_node`, so that the whole tree is materialized on each call.
+    fn nodes(&self) -> Vec<Vec<Node<N_CURRENCIES>>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+    {
+        let depth = *self.depth();
+        (0..=depth)
+            .map(|level| {
+                (0..2usize.pow((depth - level) as u32))
+                    .map(|index| {
+                        self.get_node(level, index)
+                            .expect("the index lies inside the level")
+                    })
+                    .collect()
+            })
+            .collect()
+    }
 
     /// Returns the cryptocurrencies whose balances are in the tree. The order of cryptocurrencies and balances is supposed to agree for all the entries.
     fn cryptocurrencies(&self) -> &[Cryptocurrency];
@@ -26,20 +54,18 @@ pub trait Tree<const N_CURRENCIES: usize> {
         index: usize,
     ) -> Result<[Fp; N_CURRENCIES + 2], Box<dyn std::error::Error>>
     where
+        [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
         if level == 0 || level > *self.depth() {
             return Err(Box::from("Invalid depth"));
         }
 
-        self.nodes()
-            .get(level)
-            .and_then(|layer| layer.get(index))
-            .ok_or_else(|| Box::<dyn std::error::Error>::from("Node not found"))?;
+        self.get_node(level, index)?;
 
         // Assuming the left and right children are stored in order
-        let left_child = &self.nodes()[level - 1][2 * index];
-        let right_child = &self.nodes()[level - 1][2 * index + 1];
+        let left_child = self.get_node(level - 1, 2 * index)?;
+        let right_child = self.get_node(level - 1, 2 * index + 1)?;
 
         // Constructing preimage
         let mut preimage = [Fp::zero(); N_CURRENCIES + 2];
@@ -71,7 +97,7 @@ pub trait Tree<const N_CURRENCIES: usize> {
         let mut preimage = [Fp::zero(); N_CURRENCIES + 1];
 
         // Add username to preimage
-        preimage[0] = big_uint_to_fp(&entry.username_as_big_uint());
+        preimage[0] = entry.leaf_username_with_spec::<S>();
 
         // Add balances to preimage
         for (i, balance) in preimage.iter_mut().enumerate().skip(1).take(N_CURRENCIES) {
@@ -90,50 +116,7 @@ pub trait Tree<const N_CURRENCIES: usize> {
         [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        let nodes = self.nodes();
-        let depth = *self.depth();
-        let root = self.root();
-
-        if index >= nodes[0].len() {
-            return Err(Box::from("Index out of bounds"));
-        }
-        assert_eq!(nodes[0].len(), 2usize.pow(depth as u32));
-
-        let mut sibling_middle_node_hash_preimages = Vec::with_capacity(depth - 1);
-
-        let sibling_leaf_index = if index % 2 == 0 { index + 1 } else { index - 1 };
-
-        let sibling_leaf_node_hash_preimage: [Fp; N_CURRENCIES + 1] =
-            self.get_leaf_node_hash_preimage(sibling_leaf_index)?;
-        let mut path_indices = vec![Fp::zero(); depth];
-        let mut current_index = index;
-
-        for level in 0..depth {
-            let position = current_index % 2;
-            let sibling_index = current_index - position + (1 - position);
-
-            // we asserted that the leaves vec length is a power of 2
-            // so the index shouldn't overflow the level's length
-            if level > 0 {
-                // Fetch hash preimage for sibling middle nodes
-                let sibling_node_preimage =
-                    self.get_middle_node_hash_preimage(level, sibling_index)?;
-                sibling_middle_node_hash_preimages.push(sibling_node_preimage);
-            }
-
-            path_indices[level] = Fp::from(position as u64);
-            current_index /= 2;
-        }
-
-        let entry = self.get_entry(index).clone();
-
-        Ok(MerkleProof {
-            entry,
-            root: root.clone(),
-            sibling_leaf_node_hash_preimage,
-            sibling_middle_node_hash_preimages,
-            path_indices,
-        })
+        compute_proof::<N_CURRENCIES, S, Self>(self, index)
     }
 
     /// Verifies a MerkleProof.
@@ -142,10 +125,11 @@ pub trait Tree<const N_CURRENCIES: usize> {
         [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        let mut node = proof.entry.compute_leaf();
+        let mut node = proof.entry.compute_leaf_with_spec::<S>();
 
-        let sibling_leaf_node =
-            Node::<N_CURRENCIES>::leaf_node_from_preimage(&proof.sibling_leaf_node_hash_preimage);
+        let sibling_leaf_node = Node::<N_CURRENCIES>::leaf_node_from_preimage_with_spec::<S>(
+            &proof.sibling_leaf_node_hash_preimage,
+        );
 
         let mut hash_preimage = [Fp::zero(); N_CURRENCIES + 2];
         for (i, balance) in hash_preimage.iter_mut().enumerate().take(N_CURRENCIES) {
@@ -159,10 +143,10 @@ pub trait Tree<const N_CURRENCIES: usize> {
             hash_preimage[N_CURRENCIES] = sibling_leaf_node.hash;
             hash_preimage[N_CURRENCIES + 1] = node.hash;
         }
-        node = Node::middle_node_from_preimage(&hash_preimage);
+        node = Node::middle_node_from_preimage_with_spec::<S>(&hash_preimage);
 
         for (i, path_index) in proof.path_indices.iter().enumerate().skip(1) {
-            let sibling_node = Node::<N_CURRENCIES>::middle_node_from_preimage(
+            let sibling_node = Node::<N_CURRENCIES>::middle_node_from_preimage_with_spec::<S>(
                 &proof.sibling_middle_node_hash_preimages[i - 1],
             );
 
@@ -178,9 +162,71 @@ pub trait Tree<const N_CURRENCIES: usize> {
                 hash_preimage[N_CURRENCIES] = sibling_node.hash;
                 hash_preimage[N_CURRENCIES + 1] = node.hash;
             }
-            node = Node::middle_node_from_preimage(&hash_preimage);
+            node = Node::middle_node_from_preimage_with_spec::<S>(&hash_preimage);
         }
 
         proof.root.hash == node.hash && proof.root.balances == node.balances
     }
 }
+
+/// Generates the MerkleProof of the user at `index` by walking `tree` from its leaf up to the root, as `Tree::generate_proof` does by default.
+/// Trees caching their proofs fall back on it when the proof isn't cached.
+pub(crate) fn compute_proof<
+    const N_CURRENCIES: usize,
+    S: TreeSpec,
+    T: Tree<N_CURRENCIES, S> + ?Sized,
+>(
+    tree: &T,
+    index: usize,
+) -> Result<MerkleProof<N_CURRENCIES>, Box<dyn std::error::Error>>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    let depth = *tree.depth();
+    let root = tree.root();
+
+    if index >= 2usize.pow(depth as u32) {
+        return Err(TreeError::IndexOutOfBounds {
+            index,
+            len: 2usize.pow(depth as u32),
+        }
+        .into());
+    }
+
+    let mut sibling_middle_node_hash_preimages = Vec::with_capacity(depth - 1);
+
+    let sibling_leaf_index = if index % 2 == 0 { index + 1 } else { index - 1 };
+
+    let sibling_leaf_node_hash_preimage: [Fp; N_CURRENCIES + 1] =
+        tree.get_leaf_node_hash_preimage(sibling_leaf_index)?;
+    let mut path_indices = vec![Fp::zero(); depth];
+    let mut current_index = index;
+
+    for level in 0..depth {
+        let position = current_index % 2;
+        let sibling_index = current_index - position + (1 - position);
+
+        // the number of leaves is a power of 2
+        // so the index shouldn't overflow the level's length
+        if level > 0 {
+            // Fetch hash preimage for sibling middle nodes
+            let sibling_node_preimage = tree.get_middle_node_hash_preimage(level, sibling_index)?;
+            sibling_middle_node_hash_preimages.push(sibling_node_preimage);
+        }
+
+        path_indices[level] = Fp::from(position as u64);
+        current_index /= 2;
+    }
+
+    let entry = tree.get_entry(index).clone();
+
+    Ok(MerkleProof {
+        entry,
+        root: root.clone(),
+        sibling_leaf_node_hash_preimage,
+        sibling_middle_node_hash_preimages,
+        path_indices,
+        cryptocurrencies: tree.cryptocurrencies().to_vec(),
+    })
+}
@@ -1,39 +1,118 @@
+use crate::chips::poseidon::{poseidon_spec::PoseidonSpec, TreeSpec};
 use crate::merkle_sum_tree::{Entry, Node};
-use halo2_proofs::halo2curves::bn256::Fr as Fp;
 use rayon::prelude::*;
 
+/// Builds the middle levels of the tree on top of `leaves`.
+///
+/// Returns the root and the middle nodes stored level by level in a single flat vector, starting from level 1 up to the root.
+/// The leaf level is not part of the returned vector as it can be recomputed from the entries.
 pub fn build_merkle_tree_from_leaves<const N_CURRENCIES: usize>(
     leaves: &[Node<N_CURRENCIES>],
     depth: usize,
-) -> Result<(Node<N_CURRENCIES>, Vec<Vec<Node<N_CURRENCIES>>>), Box<dyn std::error::Error>>
+) -> Result<(Node<N_CURRENCIES>, Vec<Node<N_CURRENCIES>>), Box<dyn std::error::Error>>
 where
     [usize; N_CURRENCIES + 1]: Sized,
     [usize; N_CURRENCIES + 2]: Sized,
 {
-    let mut tree: Vec<Vec<Node<N_CURRENCIES>>> = Vec::with_capacity(depth + 1);
+    build_merkle_tree_from_leaves_with_progress(leaves, depth, &mut |_, _| {})
+}
 
+/// Builds the middle levels of the tree as `build_merkle_tree_from_leaves` does.
+/// `progress` is called after each level is built with the number of levels built so far and the depth of the tree.
+pub fn build_merkle_tree_from_leaves_with_progress<const N_CURRENCIES: usize>(
+    leaves: &[Node<N_CURRENCIES>],
+    depth: usize,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(Node<N_CURRENCIES>, Vec<Node<N_CURRENCIES>>), Box<dyn std::error::Error>>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    build_merkle_tree_from_leaves_with_spec::<N_CURRENCIES, PoseidonSpec>(leaves, depth, progress)
+}
+
+/// Builds the middle levels of the tree as `build_merkle_tree_from_leaves_with_progress` does, hashing the middle nodes with the Poseidon specification `S`.
+pub fn build_merkle_tree_from_leaves_with_spec<const N_CURRENCIES: usize, S: TreeSpec>(
+    leaves: &[Node<N_CURRENCIES>],
+    depth: usize,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(Node<N_CURRENCIES>, Vec<Node<N_CURRENCIES>>), Box<dyn std::error::Error>>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
     // the size of a leaf layer must be a power of 2
     // if not, the `leaves` Vec should be completed with "zero entries" until a power of 2
     assert_eq!(leaves.len(), 2usize.pow(depth as u32));
 
-    tree.push(leaves.to_vec());
+    // a full binary tree with 2^depth leaves has 2^depth - 1 middle nodes
+    let mut nodes: Vec<Node<N_CURRENCIES>> = Vec::with_capacity(leaves.len() - 1);
+    let mut previous_level_start = 0;
 
     for level in 1..=depth {
-        build_middle_level(level, &mut tree)
+        let level_start = nodes.len();
+        let level_nodes = if level == 1 {
+            build_middle_level::<N_CURRENCIES, S>(leaves)
+        } else {
+            build_middle_level::<N_CURRENCIES, S>(&nodes[previous_level_start..])
+        };
+        nodes.extend(level_nodes);
+        previous_level_start = level_start;
+
+        progress(level, depth);
     }
 
-    let root = tree[depth][0].clone();
-    Ok((root, tree))
+    let root = match nodes.last() {
+        Some(root) => root.clone(),
+        None => leaves[0].clone(),
+    };
+    Ok((root, nodes))
+}
+
+/// Builds the middle levels of the tree on top of `leaves` as `build_merkle_tree_from_leaves_with_spec` does, handing each level to `store_level` along with its number
+/// instead of collecting the middle nodes, so that no more than two levels of nodes are held at once. Returns the root.
+#[cfg(feature = "compact")]
+pub(crate) fn build_merkle_tree_levels_with_spec<const N_CURRENCIES: usize, S: TreeSpec>(
+    leaves: Vec<Node<N_CURRENCIES>>,
+    depth: usize,
+    progress: &mut dyn FnMut(usize, usize),
+    store_level: &mut dyn FnMut(usize, &[Node<N_CURRENCIES>]),
+) -> Node<N_CURRENCIES>
+where
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    assert_eq!(leaves.len(), 2usize.pow(depth as u32));
+
+    // each level replaces its children, which are dropped once it is built
+    let mut level_nodes = leaves;
+    for level in 1..=depth {
+        level_nodes = build_middle_level::<N_CURRENCIES, S>(&level_nodes);
+        store_level(level, &level_nodes);
+
+        progress(level, depth);
+    }
+
+    level_nodes.swap_remove(0)
 }
 
 pub fn build_leaves_from_entries<const N_CURRENCIES: usize>(
     entries: &[Entry<N_CURRENCIES>],
 ) -> Vec<Node<N_CURRENCIES>>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+{
+    build_leaves_from_entries_with_spec::<N_CURRENCIES, PoseidonSpec>(entries)
+}
+
+/// Builds the leaves of the tree as `build_leaves_from_entries` does, hashing them with the Poseidon specification `S`
+pub fn build_leaves_from_entries_with_spec<const N_CURRENCIES: usize, S: TreeSpec>(
+    entries: &[Entry<N_CURRENCIES>],
+) -> Vec<Node<N_CURRENCIES>>
 where
     [usize; N_CURRENCIES + 1]: Sized,
 {
     // Precompute the zero leaf (this will only be used if we encounter a zero entry)
-    let zero_leaf = Entry::<N_CURRENCIES>::zero_entry().compute_leaf();
+    let zero_leaf = Entry::<N_CURRENCIES>::zero_entry().compute_leaf_with_spec::<S>();
 
     let leaves = entries
         .par_iter()
@@ -43,7 +122,7 @@ where
             if entry == &Entry::<N_CURRENCIES>::zero_entry() {
                 zero_leaf.clone()
             } else {
-                entry.compute_leaf()
+                entry.compute_leaf_with_spec::<S>()
             }
         })
         .collect::<Vec<_>>();
@@ -51,28 +130,15 @@ where
     leaves
 }
 
-fn build_middle_level<const N_CURRENCIES: usize>(
-    level: usize,
-    tree: &mut Vec<Vec<Node<N_CURRENCIES>>>,
-) where
+/// Computes the parent level of `children`, where each pair of consecutive children is hashed into a middle node.
+fn build_middle_level<const N_CURRENCIES: usize, S: TreeSpec>(
+    children: &[Node<N_CURRENCIES>],
+) -> Vec<Node<N_CURRENCIES>>
+where
     [usize; N_CURRENCIES + 2]: Sized,
 {
-    let results: Vec<Node<N_CURRENCIES>> = (0..tree[level - 1].len())
-        .into_par_iter()
-        .step_by(2)
-        .map(|index| {
-            let mut hash_preimage = [Fp::zero(); N_CURRENCIES + 2];
-
-            for (i, balance) in hash_preimage.iter_mut().enumerate().take(N_CURRENCIES) {
-                *balance =
-                    tree[level - 1][index].balances[i] + tree[level - 1][index + 1].balances[i];
-            }
-
-            hash_preimage[N_CURRENCIES] = tree[level - 1][index].hash;
-            hash_preimage[N_CURRENCIES + 1] = tree[level - 1][index + 1].hash;
-            Node::middle_node_from_preimage(&hash_preimage)
-        })
-        .collect();
-
-    tree.push(results);
+    children
+        .par_chunks(2)
+        .map(|pair| Node::middle_with_spec::<S>(&pair[0], &pair[1]))
+        .collect()
 }
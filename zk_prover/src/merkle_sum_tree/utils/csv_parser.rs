@@ -1,14 +1,115 @@
-use crate::merkle_sum_tree::{Cryptocurrency, Entry};
+use crate::merkle_sum_tree::{Cryptocurrency, Entry, TreeError};
 use num_bigint::BigUint;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
 use std::path::Path;
 
+/// Returns the names of the balance columns found in the header of the CSV file stored at `path`, namely every column but the `username` one.
+/// It can be used to check the number of cryptocurrencies of a CSV file before choosing the `N_CURRENCIES` of the Merkle Sum Tree.
+pub fn csv_asset_columns<P: AsRef<Path>>(path: P) -> Result<Vec<String>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let mut rdr = csv::ReaderBuilder::new().from_reader(file);
+
+    Ok(rdr
+        .headers()?
+        .iter()
+        .skip(1)
+        .map(|header| header.to_owned())
+        .collect())
+}
+
+/// The line of the CSV file on which the first record lies, the header being on the first line
+const FIRST_RECORD_LINE: usize = 2;
+
+/// Parses the CSV file stored at `path` into a vector of entries.
+/// Returns an error if two records of the file share the same username, naming the lines of the first two occurrences.
 pub fn parse_csv_to_entries<P: AsRef<Path>, const N_CURRENCIES: usize, const N_BYTES: usize>(
     path: P,
+) -> Result<(Vec<Cryptocurrency>, Vec<Entry<N_CURRENCIES>>), Box<dyn Error>> {
+    parse_csv_to_entries_with_progress::<P, N_CURRENCIES, N_BYTES>(path, &mut |_, _| {})
+}
+
+/// Parses the CSV file stored at `path` as `parse_csv_to_entries` does.
+/// `progress` is called after each parsed record with the number of bytes read so far and the size of the file in bytes.
+pub fn parse_csv_to_entries_with_progress<
+    P: AsRef<Path>,
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+>(
+    path: P,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(Vec<Cryptocurrency>, Vec<Entry<N_CURRENCIES>>), Box<dyn Error>> {
+    let (cryptocurrencies, entries) =
+        parse_csv_records::<P, N_CURRENCIES, N_BYTES>(path, progress)?;
+
+    check_unique_usernames(&entries, FIRST_RECORD_LINE)?;
+
+    Ok((cryptocurrencies, entries))
+}
+
+/// Returns an error if two entries share the same username, naming the lines of the first two occurrences.
+/// The entries are expected to lie on consecutive lines of the input file, starting at `first_record_line`.
+pub(crate) fn check_unique_usernames<const N_CURRENCIES: usize>(
+    entries: &[Entry<N_CURRENCIES>],
+    first_record_line: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut first_occurrences: HashMap<&str, usize> = HashMap::with_capacity(entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        let line = index + first_record_line;
+        if let Some(first_line) = first_occurrences.insert(entry.username(), line) {
+            return Err(TreeError::DuplicateUsername {
+                username: entry.username().to_string(),
+                first_line,
+                line,
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the CSV file stored at `path` as `parse_csv_to_entries` does, merging the records that share the same username into a single entry.
+/// The balances of the merged entry are the sum of the balances of the records, and the entry takes the position of the first record.
+/// It is intended for reconciliation tooling, where the same user may appear in multiple exports.
+pub fn parse_csv_to_entries_merging_duplicates<
+    P: AsRef<Path>,
+    const N_CURRENCIES: usize,
+    const N_BYTES: usize,
+>(
+    path: P,
+) -> Result<(Vec<Cryptocurrency>, Vec<Entry<N_CURRENCIES>>), Box<dyn Error>> {
+    let (cryptocurrencies, entries) =
+        parse_csv_records::<P, N_CURRENCIES, N_BYTES>(path, &mut |_, _| {})?;
+
+    let mut positions: HashMap<String, usize> = HashMap::with_capacity(entries.len());
+    let mut merged_entries: Vec<Entry<N_CURRENCIES>> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match positions.get(entry.username()) {
+            Some(&position) => {
+                let merged = &merged_entries[position];
+                let balances: [BigUint; N_CURRENCIES] =
+                    std::array::from_fn(|i| &merged.balances()[i] + &entry.balances()[i]);
+                merged_entries[position] =
+                    Entry::new_checked::<N_BYTES>(entry.username().to_string(), balances)?;
+            }
+            None => {
+                positions.insert(entry.username().to_string(), merged_entries.len());
+                merged_entries.push(entry);
+            }
+        }
+    }
+
+    Ok((cryptocurrencies, merged_entries))
+}
+
+fn parse_csv_records<P: AsRef<Path>, const N_CURRENCIES: usize, const N_BYTES: usize>(
+    path: P,
+    progress: &mut dyn FnMut(usize, usize),
 ) -> Result<(Vec<Cryptocurrency>, Vec<Entry<N_CURRENCIES>>), Box<dyn Error>> {
     let file = File::open(path)?;
+    let file_size = file.metadata()?.len() as usize;
     let mut rdr = csv::ReaderBuilder::new().from_reader(file);
 
     let headers = rdr.headers()?.clone();
@@ -29,30 +130,67 @@ pub fn parse_csv_to_entries<P: AsRef<Path>, const N_CURRENCIES: usize, const N_B
         }
     }
 
+    // Throw an error if the number of balance columns doesn't match the number of cryptocurrencies of the tree
+    let balance_columns: Vec<&str> = headers.iter().skip(1).collect();
+    if balance_columns.len() > N_CURRENCIES {
+        return Err(format!(
+            "Expected {} balance columns but found {}, extra columns: {}",
+            N_CURRENCIES,
+            balance_columns.len(),
+            balance_columns[N_CURRENCIES..].join(", ")
+        )
+        .into());
+    }
+    if balance_columns.len() < N_CURRENCIES {
+        return Err(format!(
+            "Expected {} balance columns but found {}, missing {} balance columns besides: {}",
+            N_CURRENCIES,
+            balance_columns.len(),
+            N_CURRENCIES - balance_columns.len(),
+            balance_columns.join(", ")
+        )
+        .into());
+    }
+
     let mut entries = Vec::new();
 
-    for result in rdr.deserialize() {
-        let record: HashMap<String, String> = result?;
-        let username = record.get("username").ok_or("Username not found")?.clone();
+    let mut records = rdr.deserialize::<HashMap<String, String>>();
+
+    let mut line = FIRST_RECORD_LINE;
+    while let Some(result) = records.next() {
+        let record = result?;
+        let invalid_entry = |reason: String| TreeError::InvalidEntry { line, reason };
+        let username = record
+            .get("username")
+            .ok_or_else(|| invalid_entry("Username not found".to_string()))?
+            .clone();
 
         let mut balances_big_int = Vec::new();
         for cryptocurrency in &cryptocurrencies {
             let balance_str = record
                 .get(format!("balance_{}_{}", cryptocurrency.name, cryptocurrency.chain).as_str())
-                .ok_or(format!(
-                    "Balance for {} on {} not found",
+                .ok_or_else(|| {
+                    invalid_entry(format!(
+                        "Balance for {} on {} not found",
+                        cryptocurrency.name, cryptocurrency.chain
+                    ))
+                })?;
+            let balance = BigUint::parse_bytes(balance_str.as_bytes(), 10).ok_or_else(|| {
+                invalid_entry(format!(
+                    "Invalid balance for {} on {}",
                     cryptocurrency.name, cryptocurrency.chain
-                ))?;
-            let balance = BigUint::parse_bytes(balance_str.as_bytes(), 10).ok_or(format!(
-                "Invalid balance for {} on {}",
-                cryptocurrency.name, cryptocurrency.chain
-            ))?;
+                ))
+            })?;
             balances_big_int.push(balance);
         }
 
-        let entry = Entry::new(username, balances_big_int.try_into().unwrap());
+        let entry = Entry::new_checked::<N_BYTES>(username, balances_big_int.try_into().unwrap())
+            .map_err(|e| invalid_entry(e.to_string()))?;
 
         entries.push(entry);
+        line += 1;
+
+        progress(records.reader().position().byte() as usize, file_size);
     }
 
     Ok((cryptocurrencies, entries))
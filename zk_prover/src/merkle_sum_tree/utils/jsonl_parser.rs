@@ -0,0 +1,80 @@
+use crate::merkle_sum_tree::utils::csv_parser::check_unique_usernames;
+use crate::merkle_sum_tree::{Entry, TreeError};
+use num_bigint::BigUint;
+use serde::Deserialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// The line of the JSONL file on which the first record lies, as there is no header
+const FIRST_RECORD_LINE: usize = 1;
+
+/// A line of a JSONL file, as read by `parse_jsonl_to_entries`
+#[derive(Deserialize)]
+struct JsonlRecord {
+    username: String,
+    balances: Vec<Value>,
+}
+
+/// Parses the JSONL file stored at `path` into a vector of entries. Each line of the file must be a JSON object formatted as follows:
+///
+/// `{"username":"dxGaEAii","balances":[11888,41163]}`
+///
+/// The balances are given in the order of the cryptocurrencies of the tree, either as integers or as decimal strings for the balances that don't fit in a `u64`.
+/// As for `parse_csv_to_entries`, the balances must lie within `N_BYTES` and an error is returned if two lines share the same username.
+pub fn parse_jsonl_to_entries<P: AsRef<Path>, const N_CURRENCIES: usize, const N_BYTES: usize>(
+    path: P,
+) -> Result<Vec<Entry<N_CURRENCIES>>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    let mut entries = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + FIRST_RECORD_LINE;
+        let line = line?;
+
+        let invalid_entry = |reason: String| TreeError::InvalidEntry {
+            line: line_number,
+            reason,
+        };
+
+        let record: JsonlRecord =
+            serde_json::from_str(&line).map_err(|e| invalid_entry(e.to_string()))?;
+
+        if record.balances.len() != N_CURRENCIES {
+            return Err(invalid_entry(format!(
+                "Expected {} balances but found {}",
+                N_CURRENCIES,
+                record.balances.len()
+            ))
+            .into());
+        }
+
+        let balances = record
+            .balances
+            .iter()
+            .map(parse_balance)
+            .collect::<Option<Vec<BigUint>>>()
+            .ok_or_else(|| invalid_entry("Invalid balance".to_string()))?;
+
+        let entry = Entry::new_checked::<N_BYTES>(record.username, balances.try_into().unwrap())
+            .map_err(|e| invalid_entry(e.to_string()))?;
+
+        entries.push(entry);
+    }
+
+    check_unique_usernames(&entries, FIRST_RECORD_LINE)?;
+
+    Ok(entries)
+}
+
+/// Parses a balance given either as a non-negative integer or as a decimal string
+fn parse_balance(balance: &Value) -> Option<BigUint> {
+    match balance {
+        Value::Number(number) => number.as_u64().map(BigUint::from),
+        Value::String(string) => BigUint::parse_bytes(string.as_bytes(), 10),
+        _ => None,
+    }
+}
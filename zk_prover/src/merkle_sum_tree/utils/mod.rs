@@ -1,7 +1,18 @@
 mod build_tree;
 mod csv_parser;
+mod jsonl_parser;
 mod operation_helpers;
+pub mod serde_helpers;
 
-pub use build_tree::{build_leaves_from_entries, build_merkle_tree_from_leaves};
-pub use csv_parser::parse_csv_to_entries;
+#[cfg(feature = "compact")]
+pub(crate) use build_tree::build_merkle_tree_levels_with_spec;
+pub use build_tree::{
+    build_leaves_from_entries, build_leaves_from_entries_with_spec, build_merkle_tree_from_leaves,
+    build_merkle_tree_from_leaves_with_progress, build_merkle_tree_from_leaves_with_spec,
+};
+pub use csv_parser::{
+    csv_asset_columns, parse_csv_to_entries, parse_csv_to_entries_merging_duplicates,
+    parse_csv_to_entries_with_progress,
+};
+pub use jsonl_parser::parse_jsonl_to_entries;
 pub use operation_helpers::*;
@@ -15,3 +15,21 @@ pub fn big_uint_to_fp(big_uint: &BigUint) -> Fp {
 pub fn fp_to_big_uint(f: Fp) -> BigUint {
     BigUint::from_bytes_le(f.to_bytes().as_slice())
 }
+
+/// Returns the number of levels of the smallest merkle sum tree fitting `user_count` users, namely the smallest `L` such that `2^L >= user_count`.
+/// A tree has at least one level, so that a single user still gets a sibling leaf.
+pub fn optimal_levels(user_count: usize) -> usize {
+    (user_count.next_power_of_two().trailing_zeros() as usize).max(1)
+}
+
+/// Scales `balance`, expressed with `decimals` decimal places, to `target_decimals` decimal places by multiplying it by `10^(target_decimals - decimals)`,
+/// so that the balances of cryptocurrencies with different decimals can be compared or summed up. `target_decimals` must not be lower than `decimals`.
+pub fn normalize_balance(balance: &BigUint, decimals: u8, target_decimals: u8) -> BigUint {
+    assert!(
+        decimals <= target_decimals,
+        "Can't normalize a balance with {} decimals to {} decimals",
+        decimals,
+        target_decimals
+    );
+    balance * BigUint::from(10u32).pow((target_decimals - decimals) as u32)
+}
@@ -0,0 +1,184 @@
+//! Serde helpers used to serialize the Merkle Sum Tree types into a stable JSON schema.
+//!
+//! Field elements are encoded as 0x-prefixed, big-endian, 32-byte hex strings, while `BigUint` values are encoded as decimal strings.
+
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use num_bigint::BigUint;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Encodes a field element as a 0x-prefixed, big-endian, 32-byte hex string
+pub fn fp_to_hex(fp: &Fp) -> String {
+    let mut bytes = fp.to_bytes();
+    bytes.reverse();
+    format!("0x{}", hex::encode(bytes))
+}
+
+/// Decodes a field element from a 0x-prefixed, big-endian, 32-byte hex string.
+/// Returns an error if the string is malformed or if the value is not lower than the modulus of the field.
+pub fn fp_from_hex(hex_str: &str) -> Result<Fp, String> {
+    let digits = hex_str
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("Field element {} is not 0x-prefixed", hex_str))?;
+    let mut bytes: [u8; 32] = hex::decode(digits)
+        .map_err(|e| format!("Invalid field element {}: {}", hex_str, e))?
+        .try_into()
+        .map_err(|_| format!("Field element {} is not 32 bytes long", hex_str))?;
+    bytes.reverse();
+
+    Option::from(Fp::from_bytes(&bytes))
+        .ok_or_else(|| format!("Field element {} is not in the field", hex_str))
+}
+
+fn big_uint_from_dec(dec_str: &str) -> Result<BigUint, String> {
+    BigUint::parse_bytes(dec_str.as_bytes(), 10)
+        .ok_or_else(|| format!("Invalid decimal integer {}", dec_str))
+}
+
+fn try_into_array<T, const N: usize>(values: Vec<T>) -> Result<[T; N], String> {
+    let len = values.len();
+    values
+        .try_into()
+        .map_err(|_| format!("Expected {} elements but found {}", N, len))
+}
+
+/// Serializes a field element as a hex string
+pub mod fp_hex {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(fp: &Fp, serializer: S) -> Result<S::Ok, S::Error> {
+        fp_to_hex(fp).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Fp, D::Error> {
+        let hex_str = String::deserialize(deserializer)?;
+        fp_from_hex(&hex_str).map_err(D::Error::custom)
+    }
+}
+
+/// Serializes an optional field element as a hex string, or as `null` if it is absent
+pub mod fp_hex_option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(fp: &Option<Fp>, serializer: S) -> Result<S::Ok, S::Error> {
+        fp.as_ref().map(fp_to_hex).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Fp>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|hex_str| fp_from_hex(&hex_str))
+            .transpose()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Serializes an array of field elements as an array of hex strings
+pub mod fp_hex_array {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(fps: &[Fp], serializer: S) -> Result<S::Ok, S::Error> {
+        fps.iter()
+            .map(fp_to_hex)
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[Fp; N], D::Error> {
+        let fps = Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|hex_str| fp_from_hex(hex_str))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(D::Error::custom)?;
+        try_into_array(fps).map_err(D::Error::custom)
+    }
+}
+
+/// Serializes a vector of field elements as an array of hex strings
+pub mod fp_hex_vec {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(fps: &[Fp], serializer: S) -> Result<S::Ok, S::Error> {
+        fps.iter()
+            .map(fp_to_hex)
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Fp>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|hex_str| fp_from_hex(hex_str))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Serializes a vector of arrays of field elements as an array of arrays of hex strings
+pub mod fp_hex_array_vec {
+    use super::*;
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        fps: &[[Fp; N]],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        fps.iter()
+            .map(|array| array.iter().map(fp_to_hex).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<Vec<[Fp; N]>, D::Error> {
+        Vec::<Vec<String>>::deserialize(deserializer)?
+            .iter()
+            .map(|array| {
+                let fps = array
+                    .iter()
+                    .map(|hex_str| fp_from_hex(hex_str))
+                    .collect::<Result<Vec<_>, _>>()?;
+                try_into_array(fps)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Serializes a `BigUint` as a decimal string
+pub mod big_uint_dec {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+        value.to_str_radix(10).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+        let dec_str = String::deserialize(deserializer)?;
+        big_uint_from_dec(&dec_str).map_err(D::Error::custom)
+    }
+}
+
+/// Serializes an array of `BigUint` as an array of decimal strings
+pub mod big_uint_dec_array {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(values: &[BigUint], serializer: S) -> Result<S::Ok, S::Error> {
+        values
+            .iter()
+            .map(|value| value.to_str_radix(10))
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<[BigUint; N], D::Error> {
+        let values = Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|dec_str| big_uint_from_dec(dec_str))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(D::Error::custom)?;
+        try_into_array(values).map_err(D::Error::custom)
+    }
+}